@@ -73,7 +73,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("🎉 All IPC tests passed!");
     println!("========================================");
     println!("✅ Gateway can communicate with vault-daemon");
-    println!("✅ Line-delimited JSON protocol working");
+    println!("✅ Shared identra-ipc protocol working");
     println!("✅ All CRUD operations functional");
     
     Ok(())
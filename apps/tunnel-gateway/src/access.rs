@@ -0,0 +1,154 @@
+use crate::database::MemoryDatabase;
+use crate::services::memory::MemoryModel;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How often recorded reads are written to `memories`
+const FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Memories with reads waiting to be written; reads of others are dropped
+/// until the next flush rather than letting the buffer grow without bound
+const MAX_PENDING: usize = 100_000;
+
+/// Reads after which a memory's frequency stops counting for more
+const SATURATING_READS: f64 = 100.0;
+
+/// Days for the recency of a memory's last read to fall to half
+const RECENCY_HALF_LIFE_DAYS: f64 = 7.0;
+
+/// Reads of one memory since the last flush
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Reads {
+    count: i64,
+    last: i64,
+}
+
+/// Reads of memories waiting to be added to `access_count` and `last_accessed_at`
+///
+/// `record` only touches an in-process map, so reads never wait on a
+/// database write; `spawn_flush_task` writes the totals in one batch every
+/// `FLUSH_INTERVAL`. Counts are best effort: reads buffered when the
+/// process dies are lost.
+#[derive(Default)]
+pub struct AccessTracker {
+    // (user_id, memory_id) -> reads
+    pending: Mutex<HashMap<(String, String), Reads>>,
+}
+
+impl AccessTracker {
+    /// Count one read at `at` of each of `memory_ids`
+    pub fn record<'a>(&self, user_id: &str, memory_ids: impl IntoIterator<Item = &'a str>, at: i64) {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        for memory_id in memory_ids {
+            let key = (user_id.to_string(), memory_id.to_string());
+            if pending.len() >= MAX_PENDING && !pending.contains_key(&key) {
+                continue;
+            }
+            let reads = pending.entry(key).or_insert(Reads { count: 0, last: at });
+            reads.count += 1;
+            reads.last = reads.last.max(at);
+        }
+    }
+
+    /// Write the reads recorded so far, returning how many memories they touched
+    ///
+    /// Reads that fail to write are put back for the next flush.
+    pub async fn flush(&self, db: &MemoryDatabase) -> Result<usize, sqlx::Error> {
+        let taken = std::mem::take(&mut *self.pending.lock().unwrap_or_else(|e| e.into_inner()));
+        if taken.is_empty() {
+            return Ok(0);
+        }
+        let accesses: Vec<(String, String, i64, i64)> = taken.iter()
+            .map(|((user_id, memory_id), reads)| (user_id.clone(), memory_id.clone(), reads.count, reads.last))
+            .collect();
+
+        match db.record_accesses(&accesses).await {
+            Ok(()) => Ok(accesses.len()),
+            Err(e) => {
+                let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+                for (key, reads) in taken {
+                    let merged = pending.entry(key).or_insert(Reads { count: 0, last: reads.last });
+                    merged.count += reads.count;
+                    merged.last = merged.last.max(reads.last);
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Write recorded reads every `FLUSH_INTERVAL`
+pub fn spawn_flush_task(tracker: Arc<AccessTracker>, db: Arc<MemoryDatabase>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = tracker.flush(&db).await {
+                tracing::error!("Failed to record memory reads: {}", e);
+            }
+        }
+    });
+}
+
+/// How much `memory` has been read, and how lately, between 0 and 1
+///
+/// The mean of its frequency, growing logarithmically with `access_count`
+/// up to `SATURATING_READS`, and the recency of `last_accessed_at`, halving
+/// every `RECENCY_HALF_LIFE_DAYS`. Never-read memories score 0.
+pub fn access_score(memory: &MemoryModel, now: i64) -> f32 {
+    let frequency = ((memory.access_count.max(0) as f64).ln_1p() / SATURATING_READS.ln_1p()).min(1.0);
+    let recency = memory.last_accessed_at.map_or(0.0, |last| {
+        let age_days = (now - last).max(0) as f64 / 86_400.0;
+        0.5f64.powf(age_days / RECENCY_HALF_LIFE_DAYS)
+    });
+    ((frequency + recency) / 2.0) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory(access_count: i64, last_accessed_at: Option<i64>) -> MemoryModel {
+        MemoryModel {
+            id: String::new(),
+            content: String::new(),
+            metadata: HashMap::new(),
+            embedding: vec![],
+            tags: vec![],
+            created_at: 0,
+            updated_at: 0,
+            pinned: false,
+            importance: 0.0,
+            access_count,
+            last_accessed_at,
+            content_type: String::new(),
+            lang: None,
+        }
+    }
+
+    #[test]
+    fn test_access_score() {
+        let now = 100 * 86_400;
+        assert_eq!(access_score(&memory(0, None), now), 0.0);
+        assert_eq!(access_score(&memory(100, Some(now)), now), 1.0);
+        assert_eq!(access_score(&memory(1_000, Some(now + 60)), now), 1.0, "clamped to 1");
+
+        let week_old = access_score(&memory(0, Some(now - 7 * 86_400)), now);
+        assert!((week_old - 0.25).abs() < 1e-6, "{}", week_old);
+        assert!(access_score(&memory(10, Some(now)), now) > access_score(&memory(2, Some(now)), now));
+    }
+
+    #[test]
+    fn test_record_merges_reads() {
+        let tracker = AccessTracker::default();
+        tracker.record("alice", ["a", "b"], 10);
+        tracker.record("alice", ["a"], 5);
+        tracker.record("bob", ["a"], 7);
+
+        let pending = tracker.pending.lock().unwrap();
+        assert_eq!(pending.len(), 3);
+        assert_eq!(pending[&("alice".to_string(), "a".to_string())], Reads { count: 2, last: 10 });
+        assert_eq!(pending[&("bob".to_string(), "a".to_string())], Reads { count: 1, last: 7 });
+    }
+}
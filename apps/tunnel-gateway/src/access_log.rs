@@ -0,0 +1,192 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tonic::codegen::http::{HeaderValue, Request, Response};
+use tonic::Code;
+use tower::{Layer, Service};
+use tracing::Instrument;
+
+/// Header carrying the request id, accepted from callers and echoed back
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest caller-supplied request id we reuse; longer ones are replaced
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+/// Tower layer writing one access-log line per gRPC call
+///
+/// Each call runs in a `grpc` span carrying the method, a request id and,
+/// once `AuthInterceptor` has verified the caller, their user id. Only
+/// those fields, the duration and the status code are logged; request and
+/// response bodies (passwords, key material) never are. The same method
+/// and status feed the request counters in `crate::metrics`.
+#[derive(Clone, Default)]
+pub struct AccessLogLayer;
+
+impl<S> Layer<S> for AccessLogLayer {
+    type Service = AccessLogService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AccessLogService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct AccessLogService<S> {
+    inner: S,
+}
+
+type BoxFuture<T, E> = Pin<Box<dyn Future<Output = Result<T, E>> + Send>>;
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for AccessLogService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+    S::Error: std::fmt::Display,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<S::Response, S::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let request_id = request_id(&req);
+        let method = req.uri().path().to_string();
+        let span = tracing::info_span!(
+            "grpc",
+            method = %method,
+            request_id = %request_id,
+            user_id = tracing::field::Empty,
+        );
+
+        let start = Instant::now();
+        let future = span.in_scope(|| self.inner.call(req));
+
+        Box::pin(
+            async move {
+                let result = future.await;
+                let elapsed = start.elapsed();
+                let elapsed_ms = elapsed.as_secs_f64() * 1000.0;
+
+                match result {
+                    Ok(mut response) => {
+                        let code = grpc_code(&response);
+                        crate::metrics::record_rpc(&method, code, elapsed);
+                        if code == Code::Ok {
+                            tracing::info!(code = ?code, elapsed_ms, "request completed");
+                        } else {
+                            tracing::warn!(code = ?code, elapsed_ms, "request failed");
+                        }
+                        if let Ok(value) = HeaderValue::from_str(&request_id) {
+                            response.headers_mut().insert(REQUEST_ID_HEADER, value);
+                        }
+                        Ok(response)
+                    }
+                    Err(e) => {
+                        tracing::error!(elapsed_ms, "request errored: {}", e);
+                        crate::metrics::record_rpc(&method, Code::Internal, elapsed);
+                        Err(e)
+                    }
+                }
+            }
+            .instrument(span),
+        )
+    }
+}
+
+/// Record the authenticated caller on the current request's span
+pub fn record_user_id(user_id: &str) {
+    tracing::Span::current().record("user_id", user_id);
+}
+
+/// The caller's `x-request-id` if it looks sane, otherwise a fresh UUID
+fn request_id<B>(req: &Request<B>) -> String {
+    req.headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN)
+        .filter(|id| id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+}
+
+/// Status of a response as far as the headers tell
+///
+/// Errors come back trailers-only with `grpc-status` in the headers; a
+/// response without it is a successful call whose status follows the body.
+fn grpc_code<B>(response: &Response<B>) -> Code {
+    response.headers()
+        .get("grpc-status")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<i32>().ok())
+        .map(Code::from_i32)
+        .unwrap_or(Code::Ok)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+
+    /// Service answering every request with the given `grpc-status`, if any
+    #[derive(Clone)]
+    struct Respond(Option<&'static str>);
+
+    impl Service<Request<()>> for Respond {
+        type Response = Response<()>;
+        type Error = Infallible;
+        type Future = BoxFuture<Response<()>, Infallible>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _req: Request<()>) -> Self::Future {
+            let mut response = Response::new(());
+            if let Some(status) = self.0 {
+                response.headers_mut().insert("grpc-status", HeaderValue::from_static(status));
+            }
+            Box::pin(async move { Ok(response) })
+        }
+    }
+
+    fn request(request_id: Option<&str>) -> Request<()> {
+        let mut builder = Request::builder().uri("/identra.auth.AuthService/Login");
+        if let Some(id) = request_id {
+            builder = builder.header(REQUEST_ID_HEADER, id);
+        }
+        builder.body(()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_generates_request_id() {
+        let mut service = AccessLogLayer.layer(Respond(None));
+
+        let response = service.call(request(None)).await.unwrap();
+        let id = response.headers()[REQUEST_ID_HEADER].to_str().unwrap();
+        assert!(uuid::Uuid::parse_str(id).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_reuses_sane_caller_request_id() {
+        let mut service = AccessLogLayer.layer(Respond(None));
+
+        let response = service.call(request(Some("trace-123"))).await.unwrap();
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "trace-123");
+
+        let response = service.call(request(Some("bad id\twith junk"))).await.unwrap();
+        assert_ne!(response.headers()[REQUEST_ID_HEADER], "bad id\twith junk");
+    }
+
+    #[test]
+    fn test_grpc_code_from_headers() {
+        let mut response = Response::new(());
+        assert_eq!(grpc_code(&response), Code::Ok);
+
+        response.headers_mut().insert("grpc-status", HeaderValue::from_static("16"));
+        assert_eq!(grpc_code(&response), Code::Unauthenticated);
+    }
+}
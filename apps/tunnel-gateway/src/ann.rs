@@ -0,0 +1,295 @@
+use crate::quantize::{quantize, DistCosineI8};
+use hnsw_rs::prelude::Hnsw;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// Graph neighbours kept per point; more improves recall but costs memory
+const MAX_CONNECTIONS: usize = 16;
+
+/// Candidates explored while inserting a point
+const EF_CONSTRUCTION: usize = 200;
+
+const MAX_LAYERS: usize = 16;
+
+/// Removed points a user's graph may carry before it is rebuilt without them
+const MIN_STALE_FOR_COMPACTION: usize = 64;
+
+/// When and how thoroughly `AnnIndex` searches
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnnConfig {
+    /// Graph candidates explored per search, never fewer than asked for
+    ///
+    /// This is the recall knob: raising it finds more of the true nearest
+    /// neighbours at the cost of latency, lowering it does the opposite.
+    pub ef_search: usize,
+    /// Users with fewer memories are searched by brute force, which is
+    /// exact and still fast at that size
+    pub min_rows: usize,
+}
+
+impl Default for AnnConfig {
+    fn default() -> Self {
+        Self { ef_search: 64, min_rows: 1000 }
+    }
+}
+
+/// One user's HNSW graph; points are never taken out of it, only forgotten
+///
+/// Points are int8-quantized embeddings, a quarter of the memory of `f32`.
+/// The precision lost only affects which candidates are found, since they
+/// are rescored exactly in SQL.
+struct UserIndex {
+    graph: Hnsw<'static, i8, DistCosineI8>,
+    /// Memory id for each point, `None` once removed or re-embedded
+    points: Vec<Option<String>>,
+    /// Current point of each memory
+    live: HashMap<String, usize>,
+}
+
+impl UserIndex {
+    fn new(capacity: usize) -> Self {
+        Self {
+            graph: Hnsw::new(MAX_CONNECTIONS, capacity.max(1), MAX_LAYERS, EF_CONSTRUCTION, DistCosineI8),
+            points: Vec::new(),
+            live: HashMap::new(),
+        }
+    }
+
+    /// Index `(memory_id, quantized embedding)` pairs with distinct ids, in parallel
+    fn from_memories(memories: Vec<(String, Vec<i8>)>) -> Self {
+        let mut index = Self::new(memories.len());
+        let data: Vec<(&[i8], usize)> = memories.iter()
+            .enumerate()
+            .map(|(point, (_, embedding))| (embedding.as_slice(), point))
+            .collect();
+        index.graph.parallel_insert_slice(&data);
+
+        for (point, (memory_id, _)) in memories.into_iter().enumerate() {
+            index.points.push(Some(memory_id.clone()));
+            index.live.insert(memory_id, point);
+        }
+        index
+    }
+
+    fn insert(&mut self, memory_id: &str, embedding: &[i8]) {
+        self.remove(memory_id);
+        let point = self.points.len();
+        self.graph.insert_slice((embedding, point));
+        self.points.push(Some(memory_id.to_string()));
+        self.live.insert(memory_id.to_string(), point);
+    }
+
+    fn remove(&mut self, memory_id: &str) {
+        if let Some(point) = self.live.remove(memory_id) {
+            self.points[point] = None;
+        }
+        let stale = self.points.len() - self.live.len();
+        if stale >= MIN_STALE_FOR_COMPACTION && stale > self.live.len() {
+            self.compact();
+        }
+    }
+
+    /// Rebuild the graph from live points, so removed ones stop taking
+    /// search slots
+    fn compact(&mut self) {
+        let memories = self.graph.get_point_indexation()
+            .into_iter()
+            .filter_map(|point| {
+                let memory_id = self.points[point.get_origin_id()].clone()?;
+                Some((memory_id, point.get_v().to_vec()))
+            })
+            .collect();
+        *self = Self::from_memories(memories);
+    }
+}
+
+/// Approximate nearest-neighbour candidates for `search_memories`
+///
+/// Keeps one HNSW graph per user, fed by the same writes that reach the
+/// database and rebuilt from it on startup. Results are only candidates:
+/// the caller rescores them exactly in SQL, which also drops any the
+/// database no longer has (e.g. purged with their owner's account).
+///
+/// Only embeddings of `dimension` are indexed; others (e.g. stored under a
+/// previous embedding provider) are left to brute-force search.
+pub struct AnnIndex {
+    config: AnnConfig,
+    dimension: usize,
+    users: RwLock<HashMap<String, UserIndex>>,
+}
+
+impl AnnIndex {
+    /// Index `(user_id, memory_id, embedding)` rows in one go, as loaded by
+    /// `MemoryDatabase::live_embeddings`
+    pub fn build(config: AnnConfig, dimension: usize, embeddings: Vec<(String, String, Vec<f32>)>) -> Self {
+        let mut grouped: HashMap<String, Vec<(String, Vec<i8>)>> = HashMap::new();
+        for (user_id, memory_id, embedding) in embeddings {
+            if embedding.len() == dimension {
+                grouped.entry(user_id).or_default().push((memory_id, quantize(&embedding)));
+            }
+        }
+
+        let users = grouped.into_iter()
+            .map(|(user_id, memories)| (user_id, UserIndex::from_memories(memories)))
+            .collect();
+        Self { config, dimension, users: RwLock::new(users) }
+    }
+
+    /// Number of memories indexed across all users
+    pub fn memory_count(&self) -> usize {
+        let users = self.users.read().unwrap_or_else(|e| e.into_inner());
+        users.values().map(|index| index.live.len()).sum()
+    }
+
+    /// Add a memory, or replace its embedding after the content changed
+    pub fn insert(&self, user_id: &str, memory_id: &str, embedding: &[f32]) {
+        if embedding.len() != self.dimension {
+            // Keep the memory out of the graph; searches still find it in SQL
+            self.remove(user_id, memory_id);
+            return;
+        }
+        let mut users = self.users.write().unwrap_or_else(|e| e.into_inner());
+        users.entry(user_id.to_string())
+            .or_insert_with(|| UserIndex::new(self.config.min_rows))
+            .insert(memory_id, &quantize(embedding));
+    }
+
+    pub fn remove(&self, user_id: &str, memory_id: &str) {
+        let mut users = self.users.write().unwrap_or_else(|e| e.into_inner());
+        if let Some(index) = users.get_mut(user_id) {
+            index.remove(memory_id);
+        }
+    }
+
+    /// Ids of roughly the `count` memories nearest to `query`, nearest first
+    ///
+    /// `None` means the user has too few memories for the index to pay off;
+    /// search them by brute force instead.
+    pub fn search(&self, user_id: &str, query: &[f32], count: usize) -> Option<AnnCandidates> {
+        if query.len() != self.dimension {
+            return None;
+        }
+        let users = self.users.read().unwrap_or_else(|e| e.into_inner());
+        let index = users.get(user_id).filter(|index| index.live.len() >= self.config.min_rows.max(1))?;
+
+        // Removed points still take slots in the graph's answer, so ask for
+        // proportionally more
+        let wanted = (count * index.points.len()).div_ceil(index.live.len()).min(index.points.len());
+        let query = quantize(query);
+        let ids = index.graph
+            .search(&query, wanted, self.config.ef_search.max(wanted))
+            .into_iter()
+            .filter_map(|neighbour| index.points[neighbour.d_id].clone())
+            .take(count)
+            .collect::<Vec<_>>();
+
+        Some(AnnCandidates { exhaustive: ids.len() >= index.live.len(), ids })
+    }
+}
+
+/// Memories the graph considers nearest to a query
+pub struct AnnCandidates {
+    pub ids: Vec<String>,
+    /// Every live memory of the user is among `ids`
+    pub exhaustive: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random_vector(state: &mut u64, dim: usize) -> Vec<f32> {
+        (0..dim)
+            .map(|_| {
+                // xorshift keeps the test deterministic without a rand dependency
+                *state ^= *state << 13;
+                *state ^= *state >> 7;
+                *state ^= *state << 17;
+                (*state % 2001) as f32 / 1000.0 - 1.0
+            })
+            .collect()
+    }
+
+    fn cosine(a: &[f32], b: &[f32]) -> f32 {
+        let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+        let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+        dot / (norm(a) * norm(b))
+    }
+
+    fn brute_force(rows: &[(String, String, Vec<f32>)], query: &[f32], count: usize) -> Vec<String> {
+        let mut scored: Vec<_> = rows.iter().map(|(_, id, v)| (cosine(query, v), id.clone())).collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.into_iter().take(count).map(|(_, id)| id).collect()
+    }
+
+    #[test]
+    fn test_recall_against_brute_force() {
+        let mut state = 0x2545_f491_4f6c_dd1d;
+        let rows: Vec<_> = (0..1000)
+            .map(|i| ("alice".to_string(), format!("m{}", i), random_vector(&mut state, 32)))
+            .collect();
+        let index = AnnIndex::build(AnnConfig { ef_search: 64, min_rows: 100 }, 32, rows.clone());
+
+        let (mut found, mut expected) = (0, 0);
+        for _ in 0..50 {
+            let query = random_vector(&mut state, 32);
+            let exact = brute_force(&rows, &query, 10);
+            let approximate = index.search("alice", &query, 10).unwrap().ids;
+            found += exact.iter().filter(|id| approximate.contains(id)).count();
+            expected += exact.len();
+        }
+
+        let recall = found as f64 / expected as f64;
+        assert!(recall >= 0.9, "recall@10 was {:.3}", recall);
+    }
+
+    #[test]
+    fn test_small_users_fall_back_to_brute_force() {
+        let index = AnnIndex::build(AnnConfig { ef_search: 64, min_rows: 3 }, 2, Vec::new());
+        index.insert("alice", "a", &[1.0, 0.0]);
+        index.insert("alice", "b", &[0.0, 1.0]);
+        assert!(index.search("alice", &[1.0, 0.0], 1).is_none());
+        assert!(index.search("bob", &[1.0, 0.0], 1).is_none());
+
+        index.insert("alice", "c", &[1.0, 1.0]);
+        let candidates = index.search("alice", &[1.0, 0.0], 5).unwrap();
+        assert_eq!(candidates.ids[0], "a");
+        assert!(candidates.exhaustive);
+    }
+
+    #[test]
+    fn test_removed_and_reembedded_memories() {
+        let index = AnnIndex::build(AnnConfig { ef_search: 64, min_rows: 1 }, 2, Vec::new());
+        index.insert("alice", "a", &[1.0, 0.0]);
+        index.insert("alice", "b", &[0.0, 1.0]);
+
+        index.remove("alice", "a");
+        assert_eq!(index.search("alice", &[1.0, 0.0], 5).unwrap().ids, vec!["b"]);
+
+        // A new embedding replaces the old point instead of adding a second
+        index.insert("alice", "b", &[1.0, 0.1]);
+        assert_eq!(index.search("alice", &[1.0, 0.0], 5).unwrap().ids, vec!["b"]);
+    }
+
+    #[test]
+    fn test_compaction_keeps_live_memories() {
+        let index = AnnIndex::build(AnnConfig { ef_search: 64, min_rows: 1 }, 8, Vec::new());
+        let mut state = 7;
+        for i in 0..200 {
+            index.insert("alice", &format!("m{}", i), &random_vector(&mut state, 8));
+        }
+        for i in 0..150 {
+            index.remove("alice", &format!("m{}", i));
+        }
+
+        let users = index.users.read().unwrap();
+        let alice = &users["alice"];
+        assert_eq!(alice.live.len(), 50);
+        assert!(alice.points.len() < 200, "removed points should have been compacted away");
+        drop(users);
+
+        let ids = index.search("alice", &random_vector(&mut state, 8), 50).unwrap().ids;
+        assert_eq!(ids.len(), 50);
+        assert!(ids.iter().all(|id| id[1..].parse::<usize>().unwrap() >= 150));
+    }
+}
@@ -0,0 +1,191 @@
+use crate::auth::middleware::AuthClaims;
+use crate::auth::supabase_client::{AuthResponse, SignUpResponse, SupabaseClient};
+use crate::config::AuthSettings;
+use crate::http::HttpClient;
+use std::sync::Arc;
+use tonic::Status;
+
+/// Backend errors are reported in Supabase's terms, the only backend so far
+pub use crate::auth::supabase_client::SupabaseError as AuthError;
+
+/// Tokens issued for a signed-in user
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub user_id: String,
+    pub access_token: String,
+    pub refresh_token: String,
+    /// Seconds until `access_token` expires
+    pub expires_in: u64,
+}
+
+/// Outcome of a sign-up
+#[derive(Debug, Clone)]
+pub enum Registration {
+    /// The account can be used right away
+    Active(Session),
+    /// Nobody can sign in until the address is confirmed with `verify_email`
+    PendingVerification { user_id: String },
+}
+
+impl Registration {
+    pub fn user_id(&self) -> &str {
+        match self {
+            Self::Active(session) => &session.user_id,
+            Self::PendingVerification { user_id } => user_id,
+        }
+    }
+}
+
+/// Which sessions a sign-out revokes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignOutScope {
+    /// Only the session the access token belongs to
+    Local,
+    /// Every session of the user, revoking all their refresh tokens
+    Global,
+}
+
+/// Issues and verifies the tokens the gateway accepts
+///
+/// `AuthServiceImpl` and `AuthInterceptor` share one backend, so a token
+/// issued by `login` is always checked by the same system that issued it.
+#[tonic::async_trait]
+pub trait AuthBackend: Send + Sync {
+    async fn register(&self, email: &str, password: &str, username: &str) -> Result<Registration, AuthError>;
+
+    /// Fails with `EmailNotConfirmed` for a right password on an unconfirmed account
+    async fn login(&self, email: &str, password: &str) -> Result<Session, AuthError>;
+
+    /// Confirm an email address with the token sent to it and sign the user in
+    ///
+    /// Tokens are single use and expire; both are rejected as `BadRequest`.
+    async fn verify_email(&self, token: &str) -> Result<Session, AuthError>;
+
+    /// Email a single-use password reset token to `email`
+    ///
+    /// Must succeed whether or not the address has an account.
+    async fn request_password_reset(&self, email: &str) -> Result<(), AuthError>;
+
+    /// Exchange a password reset token for a session that may set a new password
+    ///
+    /// Same single-use and expiry rules as `verify_email`.
+    async fn verify_reset_token(&self, token: &str) -> Result<Session, AuthError>;
+
+    /// Exchange a refresh token for a new session; the old token is spent
+    async fn refresh(&self, refresh_token: &str) -> Result<Session, AuthError>;
+
+    /// Check an access token and return who it belongs to
+    async fn verify(&self, access_token: &str) -> Result<AuthClaims, AuthError>;
+
+    /// Revoke refresh tokens for the session(s) behind `access_token`
+    async fn logout(&self, access_token: &str, scope: SignOutScope) -> Result<(), AuthError>;
+
+    /// Set a new password for the user `access_token` belongs to
+    async fn update_password(&self, access_token: &str, new_password: &str) -> Result<(), AuthError>;
+
+    /// Remove the user for good; their tokens stop verifying once they expire
+    async fn delete_user(&self, user_id: &str) -> Result<(), AuthError>;
+}
+
+/// Backend delegating to Supabase Auth
+pub struct SupabaseAuthBackend {
+    client: SupabaseClient,
+}
+
+impl SupabaseAuthBackend {
+    pub fn new(client: SupabaseClient) -> Self {
+        Self { client }
+    }
+}
+
+impl From<AuthResponse> for Session {
+    fn from(response: AuthResponse) -> Self {
+        Self {
+            user_id: response.user.id,
+            access_token: response.access_token,
+            refresh_token: response.refresh_token,
+            expires_in: response.expires_in,
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl AuthBackend for SupabaseAuthBackend {
+    async fn register(&self, email: &str, password: &str, username: &str) -> Result<Registration, AuthError> {
+        // Supabase sends the confirmation email itself, from its templates/SMTP settings
+        Ok(match self.client.sign_up(email, password, username).await? {
+            SignUpResponse::Session(response) => Registration::Active(response.into()),
+            SignUpResponse::Unconfirmed(user) => Registration::PendingVerification { user_id: user.id },
+        })
+    }
+
+    async fn login(&self, email: &str, password: &str) -> Result<Session, AuthError> {
+        self.client.sign_in(email, password).await.map(Session::from)
+    }
+
+    async fn verify_email(&self, token: &str) -> Result<Session, AuthError> {
+        self.client.verify_email(token).await.map(Session::from)
+    }
+
+    async fn request_password_reset(&self, email: &str) -> Result<(), AuthError> {
+        self.client.recover(email).await
+    }
+
+    async fn verify_reset_token(&self, token: &str) -> Result<Session, AuthError> {
+        self.client.verify_recovery(token).await.map(Session::from)
+    }
+
+    async fn refresh(&self, refresh_token: &str) -> Result<Session, AuthError> {
+        self.client.refresh_token(refresh_token).await.map(Session::from)
+    }
+
+    async fn verify(&self, access_token: &str) -> Result<AuthClaims, AuthError> {
+        let verified = self.client.verify_token(access_token).await?;
+        Ok(AuthClaims {
+            sub: verified.sub,
+            email: verified.email,
+            role: verified.role,
+            exp: verified.exp,
+        })
+    }
+
+    async fn logout(&self, access_token: &str, scope: SignOutScope) -> Result<(), AuthError> {
+        self.client.sign_out(access_token, scope).await
+    }
+
+    async fn update_password(&self, access_token: &str, new_password: &str) -> Result<(), AuthError> {
+        self.client.update_password(access_token, new_password).await
+    }
+
+    async fn delete_user(&self, user_id: &str) -> Result<(), AuthError> {
+        self.client.delete_user(user_id).await
+    }
+}
+
+/// Status for failures that say nothing about the caller's credentials
+///
+/// `None` means the backend rejected the request itself (bad token, wrong
+/// password); anything else is the backend being down or overloaded.
+pub fn upstream_status(error: &AuthError) -> Option<Status> {
+    match error {
+        AuthError::Network(_) => Some(Status::unavailable("Authentication service unavailable")),
+        AuthError::RateLimited { retry_after } => Some(Status::resource_exhausted(match retry_after {
+            Some(wait) => format!("Authentication service busy, retry in {} seconds", wait.as_secs()),
+            None => "Authentication service busy, retry later".to_string(),
+        })),
+        AuthError::Parse(_) => Some(Status::internal("Unexpected response from authentication service")),
+        AuthError::Unauthorized(_)
+        | AuthError::BadRequest(_)
+        | AuthError::EmailNotConfirmed
+        | AuthError::AlreadyRegistered => None,
+    }
+}
+
+/// Build the backend selected by `settings` (only supabase), calling out through `http`
+///
+/// `GatewayConfig::validate` has already turned away other backends.
+pub fn backend_from_config(settings: &AuthSettings, http: &HttpClient) -> Result<Arc<dyn AuthBackend>, String> {
+    let client = SupabaseClient::from_config(&settings.supabase, &settings.jwt, http)
+        .map_err(|e| format!("Invalid Supabase configuration: {}", e))?;
+    Ok(Arc::new(SupabaseAuthBackend::new(client)))
+}
@@ -0,0 +1,196 @@
+use crate::migrations::{self, Migration};
+use sqlx::postgres::PgPool;
+use sqlx::Row;
+use std::time::Duration;
+
+/// Consecutive failed logins before an account is locked
+const DEFAULT_MAX_FAILED_ATTEMPTS: i32 = 10;
+
+/// How long a locked account stays locked
+const DEFAULT_LOCKOUT_SECS: u64 = 30 * 60;
+
+// Supabase owns `auth.users`, so failure state lives in a table of our own
+// keyed by the login name. Columns are added separately so existing rows
+// pick up their defaults.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create login_failures",
+        statements: &[
+            "CREATE TABLE IF NOT EXISTS login_failures (username TEXT PRIMARY KEY)",
+            "ALTER TABLE login_failures ADD COLUMN IF NOT EXISTS failed_attempts INTEGER NOT NULL DEFAULT 0",
+            "ALTER TABLE login_failures ADD COLUMN IF NOT EXISTS locked_until BIGINT",
+            "ALTER TABLE login_failures ADD COLUMN IF NOT EXISTS last_failed_at BIGINT",
+        ],
+    },
+];
+
+// A failure after an expired lock starts a fresh count
+const RECORD_FAILURE: &str = r#"
+    INSERT INTO login_failures (username, failed_attempts, last_failed_at)
+    VALUES ($1, 1, $2)
+    ON CONFLICT (username) DO UPDATE SET
+        failed_attempts = CASE
+            WHEN login_failures.locked_until IS NOT NULL AND login_failures.locked_until <= $2 THEN 1
+            ELSE login_failures.failed_attempts + 1
+        END,
+        locked_until = CASE
+            WHEN login_failures.locked_until IS NOT NULL AND login_failures.locked_until <= $2 THEN NULL
+            ELSE login_failures.locked_until
+        END,
+        last_failed_at = $2
+    RETURNING failed_attempts
+"#;
+
+#[derive(Debug, Clone)]
+pub struct LockoutConfig {
+    pub max_failed_attempts: i32,
+    pub lockout: Duration,
+}
+
+impl Default for LockoutConfig {
+    fn default() -> Self {
+        Self {
+            max_failed_attempts: DEFAULT_MAX_FAILED_ATTEMPTS,
+            lockout: Duration::from_secs(DEFAULT_LOCKOUT_SECS),
+        }
+    }
+}
+
+/// Persistent per-account lockout after consecutive failed logins
+///
+/// Unlike `LoginRateLimiter` this survives restarts and is shared by every
+/// gateway on the same database. Usernames are normalised the same way for
+/// every call, and unknown usernames are tracked like real ones.
+#[derive(Clone)]
+pub struct AccountLockout {
+    pool: PgPool,
+    config: LockoutConfig,
+}
+
+impl AccountLockout {
+    pub fn new(pool: PgPool, config: LockoutConfig) -> Self {
+        Self { pool, config }
+    }
+
+    /// Create `login_failures`, or add columns missing from an older table
+    pub async fn migrate(&self) -> Result<(), sqlx::Error> {
+        migrations::migrate(&self.pool, "login_failures", MIGRATIONS).await?;
+        Ok(())
+    }
+
+    /// Count a failed login, locking the account once it hits the limit
+    ///
+    /// Returns true if the account is now locked.
+    pub async fn record_failed_login(&self, username: &str) -> Result<bool, sqlx::Error> {
+        let username = normalize(username);
+        let now = chrono::Utc::now().timestamp();
+
+        let mut tx = self.pool.begin().await?;
+        let failed_attempts: i32 = sqlx::query(RECORD_FAILURE)
+            .bind(&username)
+            .bind(now)
+            .fetch_one(&mut *tx)
+            .await?
+            .try_get("failed_attempts")?;
+
+        let locked = failed_attempts >= self.config.max_failed_attempts;
+        if locked {
+            let locked_until = now + self.config.lockout.as_secs() as i64;
+            sqlx::query("UPDATE login_failures SET locked_until = $2 WHERE username = $1 AND locked_until IS NULL")
+                .bind(&username)
+                .bind(locked_until)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(locked)
+    }
+
+    /// Clear the failure count, e.g. after a successful login
+    pub async fn reset_failed_logins(&self, username: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM login_failures WHERE username = $1")
+            .bind(normalize(username))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn is_locked(&self, username: &str) -> Result<bool, sqlx::Error> {
+        let locked_until: Option<i64> = sqlx::query("SELECT locked_until FROM login_failures WHERE username = $1")
+            .bind(normalize(username))
+            .fetch_optional(&self.pool)
+            .await?
+            .map(|row| row.try_get("locked_until"))
+            .transpose()?
+            .flatten();
+
+        Ok(locked_until.is_some_and(|until| until > chrono::Utc::now().timestamp()))
+    }
+}
+
+fn normalize(username: &str) -> String {
+    username.trim().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Integration tests: need DATABASE_URL pointing at Postgres.
+    // Run with `just test-integration`.
+    async fn test_lockout(max_failed_attempts: i32, lockout: Duration) -> AccountLockout {
+        dotenvy::dotenv().ok();
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let pool = PgPool::connect(&url).await.expect("Failed to connect");
+
+        let lockout = AccountLockout::new(pool, LockoutConfig { max_failed_attempts, lockout });
+        lockout.migrate().await.expect("Failed to migrate login_failures");
+        lockout
+    }
+
+    fn username() -> String {
+        format!("lockout-{}@example.com", uuid::Uuid::new_v4())
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_locks_after_consecutive_failures() {
+        let lockout = test_lockout(3, Duration::from_secs(60)).await;
+        let user = username();
+
+        assert!(!lockout.record_failed_login(&user).await.unwrap());
+        assert!(!lockout.record_failed_login(&user).await.unwrap());
+        assert!(!lockout.is_locked(&user).await.unwrap());
+
+        assert!(lockout.record_failed_login(&user).await.unwrap());
+        assert!(lockout.is_locked(&user).await.unwrap());
+        // Same account, different spelling
+        assert!(lockout.is_locked(&user.to_uppercase()).await.unwrap());
+
+        lockout.reset_failed_logins(&user).await.unwrap();
+        assert!(!lockout.is_locked(&user).await.unwrap());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_expired_lock_starts_new_count() {
+        let lockout = test_lockout(1, Duration::ZERO).await;
+        let user = username();
+
+        assert!(lockout.record_failed_login(&user).await.unwrap());
+        assert!(!lockout.is_locked(&user).await.unwrap());
+
+        assert!(lockout.record_failed_login(&user).await.unwrap());
+        let failed_attempts: i32 = sqlx::query("SELECT failed_attempts FROM login_failures WHERE username = $1")
+            .bind(&user)
+            .fetch_one(&lockout.pool)
+            .await
+            .unwrap()
+            .get("failed_attempts");
+        assert_eq!(failed_attempts, 1);
+
+        lockout.reset_failed_logins(&user).await.unwrap();
+    }
+}
@@ -1,4 +1,4 @@
-use crate::auth::supabase_client::SupabaseClient;
+use crate::auth::backend::{upstream_status, AuthBackend};
 use std::sync::Arc;
 use tonic::{Request, Status};
 use serde::{Deserialize, Serialize};
@@ -8,20 +8,23 @@ pub struct AuthClaims {
     pub sub: String,
     pub email: String,
     pub role: String,
+    /// Expiry as a Unix timestamp, or 0 if the backend doesn't report it
+    #[serde(default)]
+    pub exp: u64,
 }
 
-/// gRPC interceptor for Supabase JWT authentication
+/// gRPC interceptor for bearer-token authentication
 #[derive(Clone)]
 pub struct AuthInterceptor {
-    supabase: Arc<SupabaseClient>,
+    backend: Arc<dyn AuthBackend>,
 }
 
 impl AuthInterceptor {
-    pub fn new(supabase: Arc<SupabaseClient>) -> Self {
-        Self { supabase }
+    pub fn new(backend: Arc<dyn AuthBackend>) -> Self {
+        Self { backend }
     }
     
-    /// Intercept and validate Supabase JWT token from metadata
+    /// Intercept and validate the bearer token from metadata
     pub async fn intercept<T>(&self, mut req: Request<T>) -> Result<Request<T>, Status> {
         // Get authorization header
         let token = match req.metadata().get("authorization") {
@@ -37,20 +40,23 @@ impl AuthInterceptor {
         let token = extract_bearer_token(token)
             .ok_or_else(|| Status::unauthenticated("Invalid token format. Use: Bearer <token>"))?;
         
-        // Validate token with Supabase
-        let verify_response = self.supabase.verify_token(&token)
+        // Validate token with the same backend that issued it
+        let claims = self.backend.verify(&token)
             .await
-            .map_err(|e| {
-                tracing::warn!("Token validation failed: {}", e);
-                Status::unauthenticated("Invalid or expired token")
+            .map_err(|e| match upstream_status(&e) {
+                // Backend trouble isn't the caller's fault; don't tell them to log in again
+                Some(status) => {
+                    tracing::error!("Token validation unavailable: {}", e);
+                    status
+                }
+                None => {
+                    tracing::warn!("Token validation failed: {}", e);
+                    Status::unauthenticated("Invalid or expired token")
+                }
             })?;
         
         // Add user info to request extensions for downstream services
-        let claims = AuthClaims {
-            sub: verify_response.sub,
-            email: verify_response.email,
-            role: verify_response.role,
-        };
+        crate::access_log::record_user_id(&claims.sub);
         req.extensions_mut().insert(claims);
         
         Ok(req)
@@ -58,26 +64,45 @@ impl AuthInterceptor {
 }
 
 /// Extract token from "Bearer <token>" format
-fn extract_bearer_token(auth_header: &str) -> Option<String> {
-    if auth_header.starts_with("Bearer ") {
-        Some(auth_header[7..].to_string())
-    } else {
-        None
-    }
+pub(crate) fn extract_bearer_token(auth_header: &str) -> Option<String> {
+    auth_header.strip_prefix("Bearer ").map(str::to_string)
 }
 
 /// Helper function to extract user ID from request extensions
+///
+/// `ADMIN_ROLE` callers aren't users and are refused: their `sub` names no
+/// account, so user-scoped RPCs would act on a pseudo-user's data.
+#[allow(clippy::result_large_err)]
 pub fn get_user_id_from_request<T>(req: &Request<T>) -> Result<String, Status> {
-    req.extensions()
-        .get::<AuthClaims>()
-        .map(|claims| claims.sub.clone())
-        .ok_or_else(|| Status::unauthenticated("User not authenticated"))
+    match req.extensions().get::<AuthClaims>() {
+        Some(claims) if claims.role == ADMIN_ROLE => {
+            Err(Status::permission_denied("The service role may only call admin RPCs"))
+        }
+        Some(claims) => Ok(claims.sub.clone()),
+        None => Err(Status::unauthenticated("User not authenticated")),
+    }
 }
 
 /// Helper function to extract email from request extensions
+#[allow(clippy::result_large_err)]
 pub fn get_email_from_request<T>(req: &Request<T>) -> Result<String, Status> {
     req.extensions()
         .get::<AuthClaims>()
         .map(|claims| claims.email.clone())
         .ok_or_else(|| Status::unauthenticated("User not authenticated"))
 }
+
+/// Role whose tokens may call admin RPCs such as `Reindex`; Supabase gives
+/// it to trusted backends, and the gateway grants it to callers presenting
+/// its configured service role key. It opens no user-scoped RPCs
+pub const ADMIN_ROLE: &str = "service_role";
+
+/// Refuse callers whose token lacks `ADMIN_ROLE`
+#[allow(clippy::result_large_err)]
+pub fn require_admin<T>(req: &Request<T>) -> Result<(), Status> {
+    match req.extensions().get::<AuthClaims>() {
+        Some(claims) if claims.role == ADMIN_ROLE => Ok(()),
+        Some(_) => Err(Status::permission_denied("Admin role required")),
+        None => Err(Status::unauthenticated("User not authenticated")),
+    }
+}
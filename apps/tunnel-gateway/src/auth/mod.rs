@@ -1,6 +1,10 @@
+pub mod backend;
+pub mod jwt;
+pub mod lockout;
+pub mod password_policy;
+pub mod rate_limit;
 pub mod service;
 pub mod middleware;
 pub mod supabase_client;
 
 pub use service::AuthServiceImpl;
-pub use supabase_client::SupabaseClient;
@@ -0,0 +1,171 @@
+use std::collections::HashSet;
+use std::sync::OnceLock;
+use thiserror::Error;
+
+/// Shortest password accepted by default
+const DEFAULT_MIN_LENGTH: usize = 8;
+
+/// Longest password accepted; Supabase hashes with bcrypt, which stops at 72 bytes
+const DEFAULT_MAX_LENGTH: usize = 72;
+
+/// Character classes (lower, upper, digit, symbol) required by default
+const DEFAULT_MIN_CHARACTER_CLASSES: usize = 2;
+
+static COMMON_PASSWORDS: &str = include_str!("common_passwords.txt");
+
+/// Which password rule was broken
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum PolicyError {
+    #[error("Password must be at least {0} characters")]
+    TooShort(usize),
+
+    #[error("Password must be at most {0} bytes")]
+    TooLong(usize),
+
+    #[error("Password must mix at least {0} of lowercase, uppercase, digits and symbols")]
+    TooFewCharacterClasses(usize),
+
+    #[error("Password is too common")]
+    Common,
+}
+
+impl PolicyError {
+    /// Stable identifier of the rule, for clients to key their UI on
+    pub fn rule(&self) -> &'static str {
+        match self {
+            Self::TooShort(_) => "min_length",
+            Self::TooLong(_) => "max_length",
+            Self::TooFewCharacterClasses(_) => "character_classes",
+            Self::Common => "common_password",
+        }
+    }
+}
+
+/// Rules a new password has to satisfy, shared by registration and
+/// password changes
+#[derive(Debug, Clone)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub max_length: usize,
+    pub min_character_classes: usize,
+    /// Reject passwords on the embedded common-password list
+    pub reject_common: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: DEFAULT_MIN_LENGTH,
+            max_length: DEFAULT_MAX_LENGTH,
+            min_character_classes: DEFAULT_MIN_CHARACTER_CLASSES,
+            reject_common: true,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// Check `password` against each rule in turn, reporting the first broken
+    pub fn validate_password(&self, password: &str) -> Result<(), PolicyError> {
+        if password.chars().count() < self.min_length {
+            return Err(PolicyError::TooShort(self.min_length));
+        }
+        if password.len() > self.max_length {
+            return Err(PolicyError::TooLong(self.max_length));
+        }
+        if character_classes(password) < self.min_character_classes {
+            return Err(PolicyError::TooFewCharacterClasses(self.min_character_classes));
+        }
+        if self.reject_common && is_common(password) {
+            return Err(PolicyError::Common);
+        }
+        Ok(())
+    }
+}
+
+fn character_classes(password: &str) -> usize {
+    let checks: [fn(char) -> bool; 4] = [
+        char::is_lowercase,
+        char::is_uppercase,
+        |c| c.is_ascii_digit(),
+        |c| !c.is_alphanumeric(),
+    ];
+    checks.iter().filter(|check| password.chars().any(**check)).count()
+}
+
+fn is_common(password: &str) -> bool {
+    static LIST: OnceLock<HashSet<String>> = OnceLock::new();
+    let list = LIST.get_or_init(|| {
+        COMMON_PASSWORDS
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_lowercase)
+            .collect()
+    });
+    list.contains(&password.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_too_short_rejected() {
+        let policy = PasswordPolicy::default();
+        assert_eq!(policy.validate_password("aB3$"), Err(PolicyError::TooShort(8)));
+        // Counted in characters, not bytes
+        assert_eq!(policy.validate_password("ééééééé1"), Ok(()));
+    }
+
+    #[test]
+    fn test_too_long_rejected() {
+        let policy = PasswordPolicy::default();
+        let password = format!("aB3{}", "x".repeat(70));
+        assert_eq!(policy.validate_password(&password), Err(PolicyError::TooLong(72)));
+    }
+
+    #[test]
+    fn test_character_classes_required() {
+        let policy = PasswordPolicy {
+            min_character_classes: 3,
+            ..PasswordPolicy::default()
+        };
+        assert_eq!(
+            policy.validate_password("onlylowercase"),
+            Err(PolicyError::TooFewCharacterClasses(3))
+        );
+        assert_eq!(
+            policy.validate_password("lower-and-symbols"),
+            Err(PolicyError::TooFewCharacterClasses(3))
+        );
+        assert_eq!(policy.validate_password("Lower-and-symbols"), Ok(()));
+    }
+
+    #[test]
+    fn test_common_passwords_rejected() {
+        let policy = PasswordPolicy::default();
+        assert_eq!(policy.validate_password("password1"), Err(PolicyError::Common));
+        assert_eq!(policy.validate_password("PASSWORD1"), Err(PolicyError::Common));
+
+        let permissive = PasswordPolicy {
+            reject_common: false,
+            ..PasswordPolicy::default()
+        };
+        assert_eq!(permissive.validate_password("password1"), Ok(()));
+    }
+
+    #[test]
+    fn test_examples_from_weak_rule() {
+        // Both passed the old length-only check
+        let policy = PasswordPolicy::default();
+        assert!(policy.validate_password("password").is_err());
+        assert!(policy.validate_password("12345678").is_err());
+        assert_eq!(policy.validate_password("correct horse battery"), Ok(()));
+    }
+
+    #[test]
+    fn test_rule_names_are_stable() {
+        assert_eq!(PolicyError::TooShort(8).rule(), "min_length");
+        assert_eq!(PolicyError::Common.rule(), "common_password");
+    }
+}
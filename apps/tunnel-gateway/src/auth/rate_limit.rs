@@ -0,0 +1,153 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Failed logins allowed per key within the window
+const DEFAULT_MAX_FAILURES: usize = 5;
+
+/// Sliding window failed logins are counted over
+const DEFAULT_WINDOW_SECS: u64 = 15 * 60;
+
+#[derive(Debug, Clone)]
+pub struct LoginLimiterConfig {
+    pub max_failures: usize,
+    pub window: Duration,
+}
+
+impl Default for LoginLimiterConfig {
+    fn default() -> Self {
+        Self {
+            max_failures: DEFAULT_MAX_FAILURES,
+            window: Duration::from_secs(DEFAULT_WINDOW_SECS),
+        }
+    }
+}
+
+/// Sliding-window limiter on failed logins
+///
+/// Callers pass one key per dimension being limited (username, client IP).
+/// Keys are tracked whether or not the account exists, so being throttled
+/// says nothing about which usernames are real. Counts are per process;
+/// `AccountLockout` is the limit shared between gateway instances.
+pub struct LoginRateLimiter {
+    config: LoginLimiterConfig,
+    failures: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl LoginRateLimiter {
+    pub fn new(config: LoginLimiterConfig) -> Self {
+        Self {
+            config,
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// `Err(retry_after)` if any key has used up its failures in the window
+    pub fn check(&self, keys: &[String]) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mut retry_after = None;
+        for key in keys {
+            let Some(attempts) = failures.get_mut(key) else {
+                continue;
+            };
+            self.prune(attempts, now);
+            if attempts.is_empty() {
+                failures.remove(key);
+                continue;
+            }
+            if attempts.len() >= self.config.max_failures {
+                // Allowed again once the oldest failure leaves the window
+                let wait = self.config.window.saturating_sub(now - attempts[0]);
+                retry_after = retry_after.max(Some(wait));
+            }
+        }
+
+        match retry_after {
+            Some(wait) => Err(wait),
+            None => Ok(()),
+        }
+    }
+
+    pub fn record_failure(&self, keys: &[String]) {
+        let now = Instant::now();
+        let mut failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+
+        for key in keys {
+            let attempts = failures.entry(key.clone()).or_default();
+            self.prune(attempts, now);
+            attempts.push_back(now);
+        }
+    }
+
+    /// Forget failures for `key`, e.g. after a successful login
+    pub fn reset(&self, key: &str) {
+        self.failures.lock().unwrap_or_else(|e| e.into_inner()).remove(key);
+    }
+
+    fn prune(&self, attempts: &mut VecDeque<Instant>, now: Instant) {
+        while attempts.front().is_some_and(|t| now - *t >= self.config.window) {
+            attempts.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(max_failures: usize, window: Duration) -> LoginRateLimiter {
+        LoginRateLimiter::new(LoginLimiterConfig { max_failures, window })
+    }
+
+    fn keys(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_blocks_after_max_failures() {
+        let limiter = limiter(3, Duration::from_secs(60));
+        let user = keys(&["user:alice"]);
+
+        for _ in 0..3 {
+            assert!(limiter.check(&user).is_ok());
+            limiter.record_failure(&user);
+        }
+
+        let retry_after = limiter.check(&user).unwrap_err();
+        assert!(retry_after <= Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_any_blocked_key_blocks() {
+        let limiter = limiter(2, Duration::from_secs(60));
+        limiter.record_failure(&keys(&["user:alice", "ip:1.2.3.4"]));
+        limiter.record_failure(&keys(&["user:bob", "ip:1.2.3.4"]));
+
+        // Fresh username, same address
+        assert!(limiter.check(&keys(&["user:carol", "ip:1.2.3.4"])).is_err());
+        assert!(limiter.check(&keys(&["user:carol", "ip:5.6.7.8"])).is_ok());
+    }
+
+    #[test]
+    fn test_reset_clears_failures() {
+        let limiter = limiter(1, Duration::from_secs(60));
+        let user = keys(&["user:alice"]);
+        limiter.record_failure(&user);
+        assert!(limiter.check(&user).is_err());
+
+        limiter.reset("user:alice");
+        assert!(limiter.check(&user).is_ok());
+    }
+
+    #[test]
+    fn test_failures_expire_with_window() {
+        let limiter = limiter(1, Duration::ZERO);
+        let user = keys(&["user:alice"]);
+        limiter.record_failure(&user);
+
+        assert!(limiter.check(&user).is_ok());
+        assert!(limiter.failures.lock().unwrap().is_empty());
+    }
+}
@@ -1,65 +1,242 @@
 use tonic::{Request, Response, Status};
 use identra_proto::auth::auth_service_server::AuthService;
 use identra_proto::auth::{
-    LoginRequest, LoginResponse, RefreshTokenRequest, RefreshTokenResponse, 
-    RegisterRequest, RegisterResponse, VerifyTokenRequest, VerifyTokenResponse,
+    ChangePasswordRequest, ChangePasswordResponse, DeleteAccountRequest, DeleteAccountResponse,
+    LoginRequest, LoginResponse, LogoutRequest, LogoutResponse, RefreshTokenRequest,
+    RefreshTokenResponse, RegisterRequest, RegisterResponse, RequestPasswordResetRequest,
+    RequestPasswordResetResponse, ResetPasswordRequest, ResetPasswordResponse, VerifyEmailRequest,
+    VerifyEmailResponse, VerifyTokenRequest, VerifyTokenResponse,
 };
-use crate::auth::supabase_client::SupabaseClient;
+use crate::auth::middleware::extract_bearer_token;
+use crate::auth::lockout::AccountLockout;
+use crate::auth::password_policy::{PasswordPolicy, PolicyError};
+use crate::auth::rate_limit::LoginRateLimiter;
+use crate::auth::backend::{upstream_status, AuthBackend, AuthError, Registration, Session, SignOutScope};
+use crate::auth::middleware::AuthClaims;
+use crate::database::MemoryDatabase;
+use crate::ipc_client::VaultClientPool;
+use crate::services::vault::user_key_prefix;
+use std::collections::HashMap;
 use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
 
-pub struct AuthServiceImpl {
-    supabase: Arc<SupabaseClient>,
+pub struct AuthServiceImpl<S> {
+    backend: Arc<dyn AuthBackend>,
+    /// Memories are removed along with the account
+    db: Arc<MemoryDatabase>,
+    /// And so are the account's vault keys
+    vault: VaultClientPool<S>,
+    login_limiter: LoginRateLimiter,
+    lockout: AccountLockout,
+    password_policy: PasswordPolicy,
 }
 
-impl AuthServiceImpl {
-    pub fn new(supabase: Arc<SupabaseClient>) -> Self {
-        Self { supabase }
+impl<S> AuthServiceImpl<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    pub fn new(
+        backend: Arc<dyn AuthBackend>,
+        db: Arc<MemoryDatabase>,
+        vault: VaultClientPool<S>,
+        login_limiter: LoginRateLimiter,
+        lockout: AccountLockout,
+    ) -> Self {
+        Self {
+            backend,
+            db,
+            vault,
+            login_limiter,
+            lockout,
+            password_policy: PasswordPolicy::default(),
+        }
+    }
+
+    /// Rules new passwords must satisfy on register and change-password
+    pub fn with_password_policy(mut self, password_policy: PasswordPolicy) -> Self {
+        self.password_policy = password_policy;
+        self
+    }
+
+    /// Check `password` against the account behind `user`, throttled like login
+    ///
+    /// Sensitive operations re-authenticate so that a stolen access token
+    /// alone can neither guess the password nor act on it.
+    async fn confirm_password(&self, user: &AuthClaims, password: &str) -> Result<Session, Status> {
+        let limit_keys = [format!("user:{}", user.email.trim().to_lowercase())];
+        if let Err(retry_after) = self.login_limiter.check(&limit_keys) {
+            return Err(Status::resource_exhausted(format!(
+                "Too many failed attempts. Try again in {} seconds",
+                retry_after.as_secs().max(1)
+            )));
+        }
+        match self.backend.login(&user.email, password).await {
+            Ok(session) if session.user_id == user.sub => Ok(session),
+            Err(e) if upstream_status(&e).is_some() => {
+                tracing::error!("Password check unavailable for user {}: {}", user.sub, e);
+                Err(upstream_status(&e).unwrap())
+            }
+            Ok(_) | Err(_) => {
+                tracing::warn!("Wrong password confirmation for user: {}", user.sub);
+                self.login_limiter.record_failure(&limit_keys);
+                Err(Status::permission_denied("Password is incorrect"))
+            }
+        }
+    }
+
+    /// Set `new_password` for `session`'s user, then sign out all their sessions
+    ///
+    /// Returns whether the sign-out worked; the password is changed either way.
+    async fn replace_password(&self, session: &Session, new_password: &str) -> Result<bool, Status> {
+        self.backend.update_password(&session.access_token, new_password).await.map_err(|e| {
+            tracing::error!("Password update failed for user {}: {}", session.user_id, e);
+            match e {
+                // Supabase's own password rules, stricter than ours
+                AuthError::BadRequest(message) => Status::invalid_argument(message),
+                e => upstream_status(&e).unwrap_or_else(|| Status::internal("Failed to update password")),
+            }
+        })?;
+        
+        // Revoke every refresh token so other sessions have to log in again
+        match self.backend.logout(&session.access_token, SignOutScope::Global).await {
+            Ok(()) => Ok(true),
+            Err(e) => {
+                tracing::warn!("Failed to sign out other sessions of {}: {}", session.user_id, e);
+                Ok(false)
+            }
+        }
+    }
+}
+
+const ACCOUNT_LOCKED: &str = "Account temporarily locked after too many failed login attempts. Try again later";
+
+// Reason codes in `field_errors`, besides the password policy's rule names
+const REQUIRED: &str = "required";
+const INVALID: &str = "invalid";
+const TAKEN: &str = "taken";
+
+const EMAIL_NOT_VERIFIED: &str = "Email address not verified. Follow the link in the confirmation email first";
+
+fn weak_password(error: PolicyError) -> Status {
+    let mut status = Status::invalid_argument(error.to_string());
+    status.metadata_mut().insert("x-password-rule", error.rule().parse().unwrap());
+    status
+}
+
+#[allow(clippy::result_large_err)]
+fn bearer_token<T>(request: &Request<T>) -> Result<String, Status> {
+    request.metadata()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(extract_bearer_token)
+        .ok_or_else(|| Status::unauthenticated("Missing authorization token"))
+}
+
+fn failed_login(message: String) -> LoginResponse {
+    LoginResponse {
+        success: false,
+        message,
+        access_token: String::new(),
+        refresh_token: String::new(),
+        expires_in: 0,
+        field_errors: HashMap::new(),
+    }
+}
+
+fn failed_registration(message: String, field_errors: HashMap<String, String>) -> RegisterResponse {
+    RegisterResponse {
+        success: false,
+        message,
+        user_id: String::new(),
+        password_rule: field_errors.get("password").cloned().unwrap_or_default(),
+        verification_required: false,
+        field_errors,
+    }
+}
+
+/// Shape check only; Supabase decides whether the address is deliverable
+fn is_plausible_email(email: &str) -> bool {
+    match email.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && !domain.contains('@')
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !email.chars().any(char::is_whitespace)
+        }
+        None => false,
     }
 }
 
 #[tonic::async_trait]
-impl AuthService for AuthServiceImpl {
+impl<S> AuthService for AuthServiceImpl<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
     async fn register(
         &self,
         request: Request<RegisterRequest>,
     ) -> Result<Response<RegisterResponse>, Status> {
         let req = request.into_inner();
         
-        // Validation
+        // Validation; report every bad field at once so all can be highlighted
+        let mut field_errors = HashMap::new();
+        let mut messages = Vec::new();
         if req.username.trim().is_empty() {
-            return Ok(Response::new(RegisterResponse {
-                success: false,
-                message: "Username cannot be empty".to_string(),
-                user_id: String::new(),
-            }));
+            field_errors.insert("username".to_string(), REQUIRED.to_string());
+            messages.push("Username cannot be empty".to_string());
         }
-        
-        if req.password.len() < 8 {
-            return Ok(Response::new(RegisterResponse {
-                success: false,
-                message: "Password must be at least 8 characters".to_string(),
-                user_id: String::new(),
-            }));
+        let email = req.email.trim();
+        if email.is_empty() {
+            field_errors.insert("email".to_string(), REQUIRED.to_string());
+            messages.push("Email cannot be empty".to_string());
+        } else if !is_plausible_email(email) {
+            field_errors.insert("email".to_string(), INVALID.to_string());
+            messages.push("Email address is not valid".to_string());
+        }
+        if let Err(e) = self.password_policy.validate_password(&req.password) {
+            field_errors.insert("password".to_string(), e.rule().to_string());
+            messages.push(e.to_string());
+        }
+        if !field_errors.is_empty() {
+            return Ok(Response::new(failed_registration(messages.join(". "), field_errors)));
         }
         
-        // Use Supabase Auth for registration
-        match self.supabase.sign_up(&req.email, &req.password, &req.username).await {
-            Ok(auth_response) => {
-                tracing::info!("User registered: {} ({})", req.username, auth_response.user.id);
+        match self.backend.register(email, &req.password, &req.username).await {
+            Ok(registration) => {
+                tracing::info!("User registered: {} ({})", req.username, registration.user_id());
                 
+                let (message, verification_required) = match registration {
+                    Registration::Active(_) => ("User registered successfully", false),
+                    Registration::PendingVerification { .. } => {
+                        ("User registered. Check your email to confirm your address before logging in", true)
+                    }
+                };
                 Ok(Response::new(RegisterResponse {
                     success: true,
-                    message: "User registered successfully".to_string(),
-                    user_id: auth_response.user.id,
+                    message: message.to_string(),
+                    user_id: registration.user_id().to_string(),
+                    password_rule: String::new(),
+                    verification_required,
+                    field_errors: HashMap::new(),
                 }))
             }
+            // Only reported with "Confirm email" off; otherwise Supabase
+            // answers as if it were a new sign-up to hide existing accounts
+            Err(AuthError::AlreadyRegistered) => {
+                tracing::info!("Registration with a taken email: {}", email);
+                Ok(Response::new(failed_registration(
+                    "An account with this email already exists".to_string(),
+                    HashMap::from([("email".to_string(), TAKEN.to_string())]),
+                )))
+            }
             Err(e) => {
                 tracing::error!("Registration failed: {}", e);
-                Ok(Response::new(RegisterResponse {
-                    success: false,
-                    message: e,
-                    user_id: String::new(),
-                }))
+                if let Some(status) = upstream_status(&e) {
+                    return Err(status);
+                }
+                Ok(Response::new(failed_registration(e.to_string(), HashMap::new())))
             }
         }
     }
@@ -68,31 +245,92 @@ impl AuthService for AuthServiceImpl {
         &self,
         request: Request<LoginRequest>,
     ) -> Result<Response<LoginResponse>, Status> {
+        let client_ip = request.remote_addr().map(|addr| addr.ip());
         let req = request.into_inner();
         
+        let mut field_errors = HashMap::new();
+        if req.username.trim().is_empty() {
+            field_errors.insert("username".to_string(), REQUIRED.to_string());
+        }
+        if req.password.is_empty() {
+            field_errors.insert("password".to_string(), REQUIRED.to_string());
+        }
+        if !field_errors.is_empty() {
+            return Ok(Response::new(LoginResponse {
+                field_errors,
+                ..failed_login("Email and password are required".to_string())
+            }));
+        }
+        
+        // Throttle by username and by client address; the same message is
+        // used whether or not the account exists
+        let user_key = format!("user:{}", req.username.trim().to_lowercase());
+        let mut limit_keys = vec![user_key.clone()];
+        if let Some(ip) = client_ip {
+            limit_keys.push(format!("ip:{}", ip));
+        }
+        if let Err(retry_after) = self.login_limiter.check(&limit_keys) {
+            tracing::warn!("Login throttled for user: {}", req.username);
+            return Ok(Response::new(failed_login(format!(
+                "Too many failed login attempts. Try again in {} seconds",
+                retry_after.as_secs().max(1)
+            ))));
+        }
+        
+        let locked = self.lockout.is_locked(&req.username).await.map_err(|e| {
+            tracing::error!("Failed to check account lockout: {}", e);
+            Status::unavailable("Login temporarily unavailable")
+        })?;
+        if locked {
+            tracing::warn!("Login attempt on locked account: {}", req.username);
+            return Ok(Response::new(failed_login(ACCOUNT_LOCKED.to_string())));
+        }
+        
         // Supabase uses email for login
         // We treat username field as email
-        match self.supabase.sign_in(&req.username, &req.password).await {
-            Ok(auth_response) => {
-                tracing::info!("User logged in: {}", auth_response.user.id);
+        match self.backend.login(&req.username, &req.password).await {
+            Ok(session) => {
+                tracing::info!("User logged in: {}", session.user_id);
+                crate::access_log::record_user_id(&session.user_id);
+                self.login_limiter.reset(&user_key);
+                if let Err(e) = self.lockout.reset_failed_logins(&req.username).await {
+                    tracing::error!("Failed to reset failed logins for {}: {}", req.username, e);
+                }
                 
                 Ok(Response::new(LoginResponse {
                     success: true,
                     message: "Login successful".to_string(),
-                    access_token: auth_response.access_token,
-                    refresh_token: auth_response.refresh_token,
-                    expires_in: auth_response.expires_in as i64,
+                    access_token: session.access_token,
+                    refresh_token: session.refresh_token,
+                    expires_in: session.expires_in as i64,
+                    field_errors: HashMap::new(),
                 }))
             }
+            // Supabase checks the password first, so this reveals nothing to a guesser
+            Err(AuthError::EmailNotConfirmed) => {
+                tracing::info!("Login before email confirmation: {}", req.username);
+                Ok(Response::new(failed_login(EMAIL_NOT_VERIFIED.to_string())))
+            }
             Err(e) => {
-                tracing::warn!("Login failed for user: {}", req.username);
-                Ok(Response::new(LoginResponse {
-                    success: false,
-                    message: "Invalid credentials".to_string(),
-                    access_token: String::new(),
-                    refresh_token: String::new(),
-                    expires_in: 0,
-                }))
+                // An outage isn't a wrong password; don't count it against the user
+                if let Some(status) = upstream_status(&e) {
+                    tracing::error!("Login unavailable for user {}: {}", req.username, e);
+                    return Err(status);
+                }
+                tracing::warn!("Login failed for user {}: {}", req.username, e);
+                self.login_limiter.record_failure(&limit_keys);
+                
+                match self.lockout.record_failed_login(&req.username).await {
+                    Ok(true) => {
+                        tracing::warn!("Account locked after repeated failures: {}", req.username);
+                        Ok(Response::new(failed_login(ACCOUNT_LOCKED.to_string())))
+                    }
+                    Ok(false) => Ok(Response::new(failed_login("Invalid credentials".to_string()))),
+                    Err(e) => {
+                        tracing::error!("Failed to record failed login: {}", e);
+                        Ok(Response::new(failed_login("Invalid credentials".to_string())))
+                    }
+                }
             }
         }
     }
@@ -103,12 +341,15 @@ impl AuthService for AuthServiceImpl {
     ) -> Result<Response<RefreshTokenResponse>, Status> {
         let req = request.into_inner();
         
-        match self.supabase.refresh_token(&req.refresh_token).await {
-            Ok(auth_response) => {
+        // Supabase rotates refresh tokens: the one we were sent is now spent,
+        // and replaying it revokes the whole session family
+        match self.backend.refresh(&req.refresh_token).await {
+            Ok(session) => {
                 Ok(Response::new(RefreshTokenResponse {
                     success: true,
-                    access_token: auth_response.access_token,
-                    expires_in: auth_response.expires_in as i64,
+                    access_token: session.access_token,
+                    expires_in: session.expires_in as i64,
+                    refresh_token: session.refresh_token,
                 }))
             }
             Err(e) => {
@@ -117,6 +358,47 @@ impl AuthService for AuthServiceImpl {
                     success: false,
                     access_token: String::new(),
                     expires_in: 0,
+                    refresh_token: String::new(),
+                }))
+            }
+        }
+    }
+    
+    async fn verify_email(
+        &self,
+        request: Request<VerifyEmailRequest>,
+    ) -> Result<Response<VerifyEmailResponse>, Status> {
+        let req = request.into_inner();
+        if req.token.trim().is_empty() {
+            return Err(Status::invalid_argument("Verification token is required"));
+        }
+        
+        match self.backend.verify_email(req.token.trim()).await {
+            Ok(session) => {
+                tracing::info!("Email verified for user: {}", session.user_id);
+                Ok(Response::new(VerifyEmailResponse {
+                    success: true,
+                    message: "Email verified".to_string(),
+                    user_id: session.user_id,
+                    access_token: session.access_token,
+                    refresh_token: session.refresh_token,
+                    expires_in: session.expires_in as i64,
+                }))
+            }
+            Err(e) => {
+                if let Some(status) = upstream_status(&e) {
+                    tracing::error!("Email verification unavailable: {}", e);
+                    return Err(status);
+                }
+                // Used tokens look the same as expired ones to Supabase
+                tracing::warn!("Email verification failed: {}", e);
+                Ok(Response::new(VerifyEmailResponse {
+                    success: false,
+                    message: "Verification link is invalid or has expired".to_string(),
+                    user_id: String::new(),
+                    access_token: String::new(),
+                    refresh_token: String::new(),
+                    expires_in: 0,
                 }))
             }
         }
@@ -128,13 +410,13 @@ impl AuthService for AuthServiceImpl {
     ) -> Result<Response<VerifyTokenResponse>, Status> {
         let req = request.into_inner();
         
-        match self.supabase.verify_token(&req.token).await {
-            Ok(verify_response) => {
+        match self.backend.verify(&req.token).await {
+            Ok(claims) => {
                 Ok(Response::new(VerifyTokenResponse {
                     valid: true,
-                    user_id: verify_response.sub,
-                    username: verify_response.email,
-                    expires_at: verify_response.exp as i64,
+                    user_id: claims.sub,
+                    username: claims.email,
+                    expires_at: claims.exp as i64,
                 }))
             }
             Err(e) => {
@@ -148,4 +430,723 @@ impl AuthService for AuthServiceImpl {
             }
         }
     }
+    
+    async fn logout(
+        &self,
+        request: Request<LogoutRequest>,
+    ) -> Result<Response<LogoutResponse>, Status> {
+        let token = bearer_token(&request)?;
+        
+        let scope = if request.get_ref().all_sessions {
+            SignOutScope::Global
+        } else {
+            SignOutScope::Local
+        };
+        
+        match self.backend.logout(&token, scope).await {
+            Ok(()) => Ok(Response::new(LogoutResponse {
+                success: true,
+                message: "Logged out".to_string(),
+            })),
+            Err(e) => {
+                tracing::warn!("Logout failed: {}", e);
+                Ok(Response::new(LogoutResponse {
+                    success: false,
+                    message: e.to_string(),
+                }))
+            }
+        }
+    }
+    
+    async fn change_password(
+        &self,
+        request: Request<ChangePasswordRequest>,
+    ) -> Result<Response<ChangePasswordResponse>, Status> {
+        let token = bearer_token(&request)?;
+        let req = request.into_inner();
+        
+        let user = self.backend.verify(&token).await.map_err(|e| {
+            tracing::warn!("Change password with invalid token: {}", e);
+            upstream_status(&e).unwrap_or_else(|| Status::unauthenticated("Invalid or expired token"))
+        })?;
+        crate::access_log::record_user_id(&user.sub);
+        
+        self.password_policy.validate_password(&req.new_password).map_err(weak_password)?;
+        if req.new_password == req.old_password {
+            return Err(Status::invalid_argument("New password must differ from the old one"));
+        }
+        
+        let session = self.confirm_password(&user, &req.old_password).await.map_err(|status| {
+            match status.code() {
+                tonic::Code::PermissionDenied => Status::permission_denied("Old password is incorrect"),
+                _ => status,
+            }
+        })?;
+        
+        let signed_out = self.replace_password(&session, &req.new_password).await?;
+        tracing::info!("Password changed for user: {}", user.sub);
+        
+        Ok(Response::new(ChangePasswordResponse {
+            success: true,
+            message: if signed_out {
+                "Password changed".to_string()
+            } else {
+                "Password changed, but other sessions could not be signed out".to_string()
+            },
+        }))
+    }
+    
+    async fn request_password_reset(
+        &self,
+        request: Request<RequestPasswordResetRequest>,
+    ) -> Result<Response<RequestPasswordResetResponse>, Status> {
+        let req = request.into_inner();
+        let email = req.email.trim();
+        if email.is_empty() {
+            return Err(Status::invalid_argument("Email is required"));
+        }
+        
+        // Only an outage may change the answer; everything else, including
+        // Supabase's per-address rate limit, would tell accounts apart
+        match self.backend.request_password_reset(email).await {
+            Ok(()) => tracing::info!("Password reset requested for: {}", email),
+            Err(AuthError::Network(e)) => {
+                tracing::error!("Password reset unavailable for {}: {}", email, e);
+                return Err(Status::unavailable("Authentication service unavailable"));
+            }
+            Err(e) => tracing::warn!("Password reset for {} not sent: {}", email, e),
+        }
+        
+        Ok(Response::new(RequestPasswordResetResponse {
+            success: true,
+            message: "If an account exists for this address, a password reset link has been sent".to_string(),
+        }))
+    }
+    
+    async fn reset_password(
+        &self,
+        request: Request<ResetPasswordRequest>,
+    ) -> Result<Response<ResetPasswordResponse>, Status> {
+        let req = request.into_inner();
+        if req.token.trim().is_empty() {
+            return Err(Status::invalid_argument("Reset token is required"));
+        }
+        // Checked first so a weak password doesn't spend the token
+        self.password_policy.validate_password(&req.new_password).map_err(weak_password)?;
+        
+        let session = match self.backend.verify_reset_token(req.token.trim()).await {
+            Ok(session) => session,
+            Err(e) => {
+                if let Some(status) = upstream_status(&e) {
+                    tracing::error!("Password reset unavailable: {}", e);
+                    return Err(status);
+                }
+                tracing::warn!("Password reset with a bad token: {}", e);
+                return Ok(Response::new(ResetPasswordResponse {
+                    success: false,
+                    message: "Reset link is invalid or has expired".to_string(),
+                }));
+            }
+        };
+        
+        let signed_out = self.replace_password(&session, &req.new_password).await?;
+        tracing::info!("Password reset for user: {}", session.user_id);
+        
+        Ok(Response::new(ResetPasswordResponse {
+            success: true,
+            message: if signed_out {
+                "Password reset".to_string()
+            } else {
+                "Password reset, but existing sessions could not be signed out".to_string()
+            },
+        }))
+    }
+    
+    async fn delete_account(
+        &self,
+        request: Request<DeleteAccountRequest>,
+    ) -> Result<Response<DeleteAccountResponse>, Status> {
+        let token = bearer_token(&request)?;
+        let req = request.into_inner();
+        
+        let user = self.backend.verify(&token).await.map_err(|e| {
+            tracing::warn!("Delete account with invalid token: {}", e);
+            upstream_status(&e).unwrap_or_else(|| Status::unauthenticated("Invalid or expired token"))
+        })?;
+        crate::access_log::record_user_id(&user.sub);
+        
+        self.confirm_password(&user, &req.password).await?;
+        
+        // Memories are deleted in a transaction that only commits once the
+        // vault keys and the account itself are gone; any failure before
+        // then leaves the account and its memories in place to retry
+        let mut tx = self.db.begin().await.map_err(|e| {
+            tracing::error!("Delete account failed to start transaction: {}", e);
+            Status::unavailable("Database unavailable")
+        })?;
+        let memories_deleted = self.db.delete_user_memories(&mut tx, &user.sub).await.map_err(|e| {
+            tracing::error!("Delete account failed to delete memories of {}: {}", user.sub, e);
+            Status::internal("Failed to delete memories")
+        })?;
+        
+        // Keys can't be restored once deleted, but a retry deletes the rest
+        let mut vault = self.vault.acquire().await?;
+        let vault_keys_deleted = vault.delete_keys_with_prefix(user_key_prefix(&user.sub)).await.map_err(|e| {
+            tracing::error!("Delete account failed to delete vault keys of {}: {}", user.sub, e);
+            Status::from(e)
+        })?;
+        
+        self.backend.delete_user(&user.sub).await.map_err(|e| {
+            tracing::error!("Delete account failed to delete user {}: {}", user.sub, e);
+            upstream_status(&e).unwrap_or_else(|| Status::internal("Failed to delete account"))
+        })?;
+        
+        if let Err(e) = tx.commit().await {
+            // The account is gone, so these memories can no longer be reached
+            tracing::error!(
+                "Deleted user {} but failed to delete their {} memories: {}",
+                user.sub, memories_deleted, e
+            );
+            return Err(Status::internal("Account deleted, but its memories could not be removed"));
+        }
+        
+        self.login_limiter.reset(&format!("user:{}", user.email.trim().to_lowercase()));
+        if let Err(e) = self.lockout.reset_failed_logins(&user.email).await {
+            tracing::warn!("Failed to clear login failures of deleted user {}: {}", user.sub, e);
+        }
+        
+        tracing::info!(
+            "Deleted account {} ({} memories, {} vault keys)",
+            user.sub, memories_deleted, vault_keys_deleted
+        );
+        Ok(Response::new(DeleteAccountResponse {
+            success: true,
+            message: "Account deleted".to_string(),
+            memories_deleted: memories_deleted as i64,
+            vault_keys_deleted: vault_keys_deleted as i64,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::lockout::LockoutConfig;
+    use crate::auth::rate_limit::LoginLimiterConfig;
+    use crate::services::vault::tests::FakeVault;
+    use std::sync::Mutex;
+    use tokio::io::DuplexStream;
+
+    const USER_ID: &str = "user-1";
+    const EMAIL: &str = "user@example.com";
+    const ACCESS_TOKEN: &str = "access-token";
+    /// Address the fake backend treats as already registered
+    const TAKEN_EMAIL: &str = "taken@example.com";
+
+    /// Single-user backend that records what the service asked of it
+    #[derive(Default)]
+    struct FakeBackend {
+        password: Mutex<String>,
+        global_sign_outs: Mutex<usize>,
+        local_sign_outs: Mutex<usize>,
+        /// Refresh tokens exchanged so far; each is good for one refresh
+        spent_refresh_tokens: Mutex<Vec<String>>,
+        deleted: Mutex<bool>,
+        /// Confirmation tokens "emailed" on register, and whether each expired
+        outbox: Mutex<HashMap<String, bool>>,
+        email_confirmed: Mutex<bool>,
+        /// Password reset tokens sent, the same way
+        reset_outbox: Mutex<HashMap<String, bool>>,
+    }
+
+    impl FakeBackend {
+        fn session() -> Session {
+            Session {
+                user_id: USER_ID.to_string(),
+                access_token: ACCESS_TOKEN.to_string(),
+                refresh_token: "refresh-token".to_string(),
+                expires_in: 3600,
+            }
+        }
+    }
+
+    #[tonic::async_trait]
+    impl AuthBackend for FakeBackend {
+        async fn register(&self, email: &str, _: &str, _: &str) -> Result<Registration, AuthError> {
+            if email == TAKEN_EMAIL {
+                return Err(AuthError::AlreadyRegistered);
+            }
+            let mut outbox = self.outbox.lock().unwrap();
+            let token = format!("confirm-{}", outbox.len());
+            outbox.insert(token, false);
+            Ok(Registration::PendingVerification { user_id: USER_ID.to_string() })
+        }
+
+        async fn login(&self, email: &str, password: &str) -> Result<Session, AuthError> {
+            if email != EMAIL || password != *self.password.lock().unwrap() {
+                Err(AuthError::BadRequest("Invalid login credentials".to_string()))
+            } else if !*self.email_confirmed.lock().unwrap() {
+                Err(AuthError::EmailNotConfirmed)
+            } else {
+                Ok(Self::session())
+            }
+        }
+
+        async fn verify_email(&self, token: &str) -> Result<Session, AuthError> {
+            // Spent whether or not it was still valid, like Supabase's OTPs
+            match self.outbox.lock().unwrap().remove(token) {
+                Some(false) => {
+                    *self.email_confirmed.lock().unwrap() = true;
+                    Ok(Self::session())
+                }
+                _ => Err(AuthError::BadRequest("Token has expired or is invalid".to_string())),
+            }
+        }
+
+        async fn request_password_reset(&self, email: &str) -> Result<(), AuthError> {
+            if email == EMAIL {
+                let mut outbox = self.reset_outbox.lock().unwrap();
+                let token = format!("reset-{}", outbox.len());
+                outbox.insert(token, false);
+            }
+            Ok(())
+        }
+
+        async fn verify_reset_token(&self, token: &str) -> Result<Session, AuthError> {
+            match self.reset_outbox.lock().unwrap().remove(token) {
+                Some(false) => Ok(Self::session()),
+                _ => Err(AuthError::BadRequest("Token has expired or is invalid".to_string())),
+            }
+        }
+
+        async fn refresh(&self, refresh_token: &str) -> Result<Session, AuthError> {
+            // Rotates like Supabase: a replayed token is refused
+            let mut spent = self.spent_refresh_tokens.lock().unwrap();
+            if spent.iter().any(|t| t == refresh_token) {
+                return Err(AuthError::BadRequest("Invalid Refresh Token: Already Used".to_string()));
+            }
+            spent.push(refresh_token.to_string());
+            Ok(Session {
+                refresh_token: format!("refresh-token-{}", spent.len()),
+                ..Self::session()
+            })
+        }
+
+        async fn verify(&self, access_token: &str) -> Result<AuthClaims, AuthError> {
+            if access_token != ACCESS_TOKEN {
+                return Err(AuthError::Unauthorized("Invalid or expired token".to_string()));
+            }
+            Ok(AuthClaims {
+                sub: USER_ID.to_string(),
+                email: EMAIL.to_string(),
+                role: "authenticated".to_string(),
+                exp: 0,
+            })
+        }
+
+        async fn logout(&self, _: &str, scope: SignOutScope) -> Result<(), AuthError> {
+            match scope {
+                SignOutScope::Global => *self.global_sign_outs.lock().unwrap() += 1,
+                SignOutScope::Local => *self.local_sign_outs.lock().unwrap() += 1,
+            }
+            Ok(())
+        }
+
+        async fn update_password(&self, _: &str, new_password: &str) -> Result<(), AuthError> {
+            *self.password.lock().unwrap() = new_password.to_string();
+            Ok(())
+        }
+
+        async fn delete_user(&self, _: &str) -> Result<(), AuthError> {
+            *self.deleted.lock().unwrap() = true;
+            Ok(())
+        }
+    }
+
+    fn service(password: &str) -> (AuthServiceImpl<DuplexStream>, Arc<FakeBackend>) {
+        let backend = Arc::new(FakeBackend {
+            password: Mutex::new(password.to_string()),
+            email_confirmed: Mutex::new(true),
+            ..Default::default()
+        });
+
+        // These tests stop before any query, so the pool never connects
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/unused")
+            .unwrap();
+        let service = AuthServiceImpl::new(
+            backend.clone(),
+            Arc::new(MemoryDatabase::from_pool(pool.clone())),
+            FakeVault::default().pool(),
+            LoginRateLimiter::new(LoginLimiterConfig::default()),
+            AccountLockout::new(pool, LockoutConfig::default()),
+        );
+        (service, backend)
+    }
+
+    fn change_request(old_password: &str, new_password: &str) -> Request<ChangePasswordRequest> {
+        let mut request = Request::new(ChangePasswordRequest {
+            old_password: old_password.to_string(),
+            new_password: new_password.to_string(),
+        });
+        request.metadata_mut().insert(
+            "authorization",
+            format!("Bearer {}", ACCESS_TOKEN).parse().unwrap(),
+        );
+        request
+    }
+
+    #[tokio::test]
+    async fn test_refresh_returns_rotated_token_and_refuses_reuse() {
+        let (service, _) = service("password");
+        let refresh = |refresh_token: &str| {
+            service.refresh_token(Request::new(RefreshTokenRequest { refresh_token: refresh_token.to_string() }))
+        };
+
+        let first = refresh("refresh-token").await.unwrap().into_inner();
+        assert!(first.success);
+        assert_eq!(first.access_token, ACCESS_TOKEN);
+        assert_eq!(first.expires_in, 3600);
+        assert_ne!(first.refresh_token, "refresh-token");
+
+        let second = refresh(&first.refresh_token).await.unwrap().into_inner();
+        assert!(second.success);
+        assert_ne!(second.refresh_token, first.refresh_token);
+
+        let replayed = refresh("refresh-token").await.unwrap().into_inner();
+        assert!(!replayed.success);
+        assert!(replayed.access_token.is_empty());
+        assert!(replayed.refresh_token.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_logout_all_sessions_signs_out_globally() {
+        let (service, backend) = service("password");
+        let logout = |all_sessions: bool| {
+            let mut request = Request::new(LogoutRequest { all_sessions });
+            request.metadata_mut().insert("authorization", format!("Bearer {}", ACCESS_TOKEN).parse().unwrap());
+            service.logout(request)
+        };
+
+        assert!(logout(false).await.unwrap().into_inner().success);
+        assert_eq!(*backend.local_sign_outs.lock().unwrap(), 1);
+        assert_eq!(*backend.global_sign_outs.lock().unwrap(), 0);
+
+        assert!(logout(true).await.unwrap().into_inner().success);
+        assert_eq!(*backend.global_sign_outs.lock().unwrap(), 1);
+
+        let status = service.logout(Request::new(LogoutRequest { all_sessions: true })).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+    }
+
+    #[tokio::test]
+    async fn test_change_password_updates_and_signs_out_everywhere() {
+        let (service, backend) = service("old-password");
+
+        let response = service
+            .change_password(change_request("old-password", "new-password"))
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert!(response.success);
+        assert_eq!(*backend.password.lock().unwrap(), "new-password");
+        assert_eq!(*backend.global_sign_outs.lock().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_change_password_rejects_wrong_old_password() {
+        let (service, backend) = service("old-password");
+
+        let status = service
+            .change_password(change_request("not-my-password", "new-password"))
+            .await
+            .unwrap_err();
+
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+        assert_eq!(*backend.password.lock().unwrap(), "old-password");
+    }
+
+    #[tokio::test]
+    async fn test_change_password_rejects_weak_new_password() {
+        let (service, backend) = service("old-password");
+
+        let status = service
+            .change_password(change_request("old-password", "short"))
+            .await
+            .unwrap_err();
+
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert_eq!(status.metadata().get("x-password-rule").unwrap(), "min_length");
+        assert_eq!(*backend.password.lock().unwrap(), "old-password");
+    }
+
+    #[tokio::test]
+    async fn test_change_password_requires_token() {
+        let (service, _) = service("old-password");
+        let request = Request::new(ChangePasswordRequest {
+            old_password: "old-password".to_string(),
+            new_password: "new-password".to_string(),
+        });
+
+        let status = service.change_password(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+    }
+
+    fn register_request() -> Request<RegisterRequest> {
+        Request::new(RegisterRequest {
+            username: "user".to_string(),
+            email: EMAIL.to_string(),
+            password: "a-good-password".to_string(),
+        })
+    }
+
+    fn verify_request(token: &str) -> Request<VerifyEmailRequest> {
+        Request::new(VerifyEmailRequest { token: token.to_string() })
+    }
+
+    /// Register an account that still has to confirm its address
+    async fn register_unconfirmed(service: &AuthServiceImpl<DuplexStream>, backend: &FakeBackend) -> String {
+        *backend.email_confirmed.lock().unwrap() = false;
+        let response = service.register(register_request()).await.unwrap().into_inner();
+        assert!(response.success);
+        assert!(response.verification_required);
+        assert_eq!(response.user_id, USER_ID);
+
+        let outbox = backend.outbox.lock().unwrap();
+        assert_eq!(outbox.len(), 1, "one confirmation email per registration");
+        outbox.keys().next().unwrap().clone()
+    }
+
+    async fn register_errors(username: &str, email: &str, password: &str) -> RegisterResponse {
+        let (service, _) = service("a-good-password");
+        let request = Request::new(RegisterRequest {
+            username: username.to_string(),
+            email: email.to_string(),
+            password: password.to_string(),
+        });
+        let response = service.register(request).await.unwrap().into_inner();
+        assert!(!response.success);
+        assert!(!response.message.is_empty());
+        response
+    }
+
+    fn field_errors(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(field, code)| (field.to_string(), code.to_string())).collect()
+    }
+
+    #[tokio::test]
+    async fn test_register_reports_every_bad_field() {
+        let response = register_errors(" ", "not-an-email", "short").await;
+
+        assert_eq!(
+            response.field_errors,
+            field_errors(&[("username", "required"), ("email", "invalid"), ("password", "min_length")])
+        );
+        assert_eq!(response.password_rule, "min_length");
+    }
+
+    #[tokio::test]
+    async fn test_register_field_codes() {
+        let response = register_errors("user", "", "a-good-password").await;
+        assert_eq!(response.field_errors, field_errors(&[("email", "required")]));
+
+        for email in ["user@", "@example.com", "user@localhost", "us er@example.com", "a@b@example.com"] {
+            let response = register_errors("user", email, "a-good-password").await;
+            assert_eq!(response.field_errors, field_errors(&[("email", "invalid")]), "{}", email);
+        }
+
+        let response = register_errors("user", EMAIL, "onlylowercase").await;
+        assert_eq!(response.field_errors, field_errors(&[("password", "character_classes")]));
+
+        let response = register_errors("user", TAKEN_EMAIL, "a-good-password").await;
+        assert_eq!(response.field_errors, field_errors(&[("email", "taken")]));
+        assert!(response.password_rule.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_login_reports_missing_fields() {
+        let (service, _) = service("a-good-password");
+        let request = Request::new(LoginRequest { username: String::new(), password: String::new() });
+
+        // Rejected before the lockout check, so the unconnected pool is never used
+        let response = service.login(request).await.unwrap().into_inner();
+
+        assert!(!response.success);
+        assert_eq!(response.field_errors, field_errors(&[("username", "required"), ("password", "required")]));
+    }
+
+    #[tokio::test]
+    async fn test_verify_email_signs_in_once_per_token() {
+        let (service, backend) = service("a-good-password");
+        let token = register_unconfirmed(&service, &backend).await;
+
+        let response = service.verify_email(verify_request(&token)).await.unwrap().into_inner();
+        assert!(response.success);
+        assert_eq!(response.user_id, USER_ID);
+        assert_eq!(response.access_token, ACCESS_TOKEN);
+        assert!(*backend.email_confirmed.lock().unwrap());
+
+        let again = service.verify_email(verify_request(&token)).await.unwrap().into_inner();
+        assert!(!again.success);
+        assert!(again.access_token.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_verify_email_rejects_expired_token() {
+        let (service, backend) = service("a-good-password");
+        let token = register_unconfirmed(&service, &backend).await;
+        backend.outbox.lock().unwrap().insert(token.clone(), true);
+
+        let response = service.verify_email(verify_request(&token)).await.unwrap().into_inner();
+
+        assert!(!response.success);
+        assert!(response.access_token.is_empty());
+        assert!(!*backend.email_confirmed.lock().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_verify_email_requires_token() {
+        let (service, _) = service("a-good-password");
+
+        let status = service.verify_email(verify_request("  ")).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    fn reset_request(token: &str, new_password: &str) -> Request<ResetPasswordRequest> {
+        Request::new(ResetPasswordRequest {
+            token: token.to_string(),
+            new_password: new_password.to_string(),
+        })
+    }
+
+    /// Request a reset for `email`, returning the token sent, if any
+    async fn request_reset(service: &AuthServiceImpl<DuplexStream>, backend: &FakeBackend, email: &str) -> Option<String> {
+        let request = Request::new(RequestPasswordResetRequest { email: email.to_string() });
+        let response = service.request_password_reset(request).await.unwrap().into_inner();
+        assert!(response.success);
+        assert_eq!(
+            response.message,
+            "If an account exists for this address, a password reset link has been sent"
+        );
+        backend.reset_outbox.lock().unwrap().keys().next().cloned()
+    }
+
+    #[tokio::test]
+    async fn test_password_reset_request_does_not_reveal_accounts() {
+        let (service, backend) = service("old-password");
+
+        assert!(request_reset(&service, &backend, "nobody@example.com").await.is_none());
+        assert!(request_reset(&service, &backend, EMAIL).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_reset_password_consumes_token_and_signs_out_everywhere() {
+        let (service, backend) = service("old-password");
+        let token = request_reset(&service, &backend, EMAIL).await.unwrap();
+
+        let response = service.reset_password(reset_request(&token, "new-password")).await.unwrap().into_inner();
+        assert!(response.success);
+        assert_eq!(*backend.password.lock().unwrap(), "new-password");
+        assert_eq!(*backend.global_sign_outs.lock().unwrap(), 1);
+
+        let reused = service.reset_password(reset_request(&token, "newer-password")).await.unwrap().into_inner();
+        assert!(!reused.success);
+        assert_eq!(*backend.password.lock().unwrap(), "new-password");
+    }
+
+    #[tokio::test]
+    async fn test_reset_password_rejects_expired_token() {
+        let (service, backend) = service("old-password");
+        let token = request_reset(&service, &backend, EMAIL).await.unwrap();
+        backend.reset_outbox.lock().unwrap().insert(token.clone(), true);
+
+        let response = service.reset_password(reset_request(&token, "new-password")).await.unwrap().into_inner();
+
+        assert!(!response.success);
+        assert_eq!(*backend.password.lock().unwrap(), "old-password");
+        assert_eq!(*backend.global_sign_outs.lock().unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_reset_password_rejects_weak_password_without_spending_token() {
+        let (service, backend) = service("old-password");
+        let token = request_reset(&service, &backend, EMAIL).await.unwrap();
+
+        let status = service.reset_password(reset_request(&token, "short")).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert_eq!(status.metadata().get("x-password-rule").unwrap(), "min_length");
+
+        let response = service.reset_password(reset_request(&token, "new-password")).await.unwrap().into_inner();
+        assert!(response.success);
+    }
+
+    #[tokio::test]
+    async fn test_delete_account_rejects_wrong_password() {
+        let (service, backend) = service("password");
+        let mut request = Request::new(DeleteAccountRequest { password: "not-my-password".to_string() });
+        request.metadata_mut().insert(
+            "authorization",
+            format!("Bearer {}", ACCESS_TOKEN).parse().unwrap(),
+        );
+
+        let status = service.delete_account(request).await.unwrap_err();
+
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+        assert!(!*backend.deleted.lock().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_delete_account_requires_token() {
+        let (service, backend) = service("password");
+        let request = Request::new(DeleteAccountRequest { password: "password".to_string() });
+
+        let status = service.delete_account(request).await.unwrap_err();
+
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+        assert!(!*backend.deleted.lock().unwrap());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_delete_account_deletes_keys_stored_through_vault_service() {
+        use crate::auth::middleware::AuthInterceptor;
+        use crate::services::memory::tests::request_as;
+        use crate::services::vault::VaultServiceImpl;
+        use identra_proto::vault::vault_service_server::VaultService;
+        use identra_proto::vault::{RetrieveKeyRequest, StoreKeyRequest};
+
+        let backend = Arc::new(FakeBackend {
+            password: Mutex::new("password".to_string()),
+            email_confirmed: Mutex::new(true),
+            ..Default::default()
+        });
+        let db = crate::database::tests::test_db().await;
+        let lockout = AccountLockout::new(db.pool(), LockoutConfig::default());
+        lockout.migrate().await.unwrap();
+        let fake_vault = FakeVault::default();
+        fake_vault.keys.lock().unwrap().insert("someone-else/signing".to_string(), vec![9]);
+        let vault = VaultServiceImpl::new(fake_vault.pool(), AuthInterceptor::new(backend.clone()));
+        let service = AuthServiceImpl::new(
+            backend.clone(),
+            Arc::new(db),
+            fake_vault.pool(),
+            LoginRateLimiter::new(LoginLimiterConfig::default()),
+            lockout,
+        );
+
+        let key = StoreKeyRequest { key_id: "signing".to_string(), key_data: vec![1, 2, 3], ..Default::default() };
+        vault.store_key(request_as(ACCESS_TOKEN, key)).await.unwrap();
+        let response = service
+            .delete_account(request_as(ACCESS_TOKEN, DeleteAccountRequest { password: "password".to_string() }))
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert_eq!(response.vault_keys_deleted, 1);
+        assert!(*backend.deleted.lock().unwrap());
+        let retrieved = vault.retrieve_key(request_as(ACCESS_TOKEN, RetrieveKeyRequest { key_id: "signing".to_string() })).await;
+        assert_eq!(retrieved.unwrap_err().code(), tonic::Code::NotFound);
+        assert_eq!(fake_vault.keys.lock().unwrap().keys().collect::<Vec<_>>(), ["someone-else/signing"]);
+    }
 }
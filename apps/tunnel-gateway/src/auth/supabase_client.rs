@@ -1,13 +1,138 @@
-use reqwest::Client;
+use crate::auth::backend::SignOutScope;
+use crate::auth::jwt::{unverified_expiry, JwtVerifier, VerifyError};
+use crate::auth::middleware::ADMIN_ROLE;
+use crate::config::{JwtSettings, SupabaseSettings};
+use reqwest::header::RETRY_AFTER;
+use crate::http::{EgressDenied, HttpClient};
+use reqwest::{RequestBuilder, Response, StatusCode};
+use secrecy::{ExposeSecret, SecretString};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
 
-#[derive(Debug, Clone)]
+/// Shortest API key we accept; real Supabase keys are JWTs well past this
+const MIN_KEY_LENGTH: usize = 32;
+
+/// Retries of a failed call unless `SUPABASE_MAX_RETRIES` says otherwise
+const DEFAULT_MAX_RETRIES: u32 = 2;
+
+/// Wait before the first retry unless `SUPABASE_RETRY_BASE_MS` says otherwise
+const DEFAULT_RETRY_BASE_DELAY: Duration = Duration::from_millis(200);
+
+/// Longest wait between attempts; a longer `Retry-After` ends the retries
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Why the Supabase settings in the environment were rejected
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ConfigError {
+    #[error("{0} not set")]
+    Missing(&'static str),
+
+    #[error("{0} still holds the .env.example placeholder")]
+    Placeholder(&'static str),
+
+    #[error("{0} is shorter than {MIN_KEY_LENGTH} bytes")]
+    TooShort(&'static str),
+
+    #[error("SUPABASE_URL must use https (or http on localhost): {0}")]
+    InsecureUrl(String),
+
+    #[error("Unsupported JWT_ALGORITHM {0} (expected HS256, RS256 or ES256)")]
+    UnsupportedAlgorithm(String),
+
+    #[error("Invalid JWT verification key: {0}")]
+    InvalidKey(String),
+}
+
+/// Retries of Supabase calls that fail transiently
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryConfig {
+    /// Retries after the first attempt; 0 sends every request once
+    pub max_retries: u32,
+    /// Wait before the first retry, doubling for each one after
+    pub base_delay: Duration,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self { max_retries: DEFAULT_MAX_RETRIES, base_delay: DEFAULT_RETRY_BASE_DELAY }
+    }
+}
+
+impl RetryConfig {
+    /// Config from `SUPABASE_MAX_RETRIES` and `SUPABASE_RETRY_BASE_MS`
+    ///
+    /// Unset or unparsable values keep the defaults of 2 retries starting
+    /// at 200ms; `SUPABASE_MAX_RETRIES=0` turns retries off.
+    pub fn from_env() -> Self {
+        let var = |name: &str| env::var(name).ok();
+        let max_retries = var("SUPABASE_MAX_RETRIES")
+            .and_then(|s| s.trim().parse::<u32>().ok())
+            .unwrap_or(DEFAULT_MAX_RETRIES);
+        let base_delay = var("SUPABASE_RETRY_BASE_MS")
+            .and_then(|s| s.trim().parse::<u64>().ok())
+            .filter(|ms| *ms > 0)
+            .map_or(DEFAULT_RETRY_BASE_DELAY, Duration::from_millis);
+        Self { max_retries, base_delay }
+    }
+
+    /// How long to wait before sending again after `result`, or `None` to give up
+    fn delay(&self, result: &Result<Response, reqwest::Error>, repeat: Repeat, retries: u32) -> Option<Duration> {
+        let retry_after = match result {
+            // A failed connect means nothing reached Supabase
+            Err(e) if e.is_connect() || repeat == Repeat::Safe => None,
+            // 429s are turned away before Supabase acts on them
+            Ok(response) if response.status() == StatusCode::TOO_MANY_REQUESTS
+                || (response.status().is_server_error() && repeat == Repeat::Safe) =>
+            {
+                response.headers()
+                    .get(RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(parse_retry_after)
+            }
+            _ => return None,
+        };
+        match retry_after {
+            Some(wait) => (wait <= MAX_RETRY_DELAY).then_some(wait),
+            None => Some(self.base_delay.saturating_mul(1 << retries.min(16)).min(MAX_RETRY_DELAY)),
+        }
+    }
+}
+
+/// Whether a request may be sent again once Supabase may have acted on it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Repeat {
+    /// Sending it twice has the same effect as sending it once
+    Safe,
+    /// Only resent when Supabase can't have seen it, e.g. a sign-up
+    Unsafe,
+}
+
+#[derive(Clone)]
 pub struct SupabaseClient {
-    client: Client,
+    client: HttpClient,
+    retry: RetryConfig,
     url: String,
-    anon_key: String,
-    service_role_key: String,
+    /// Zeroized on drop; only exposed to set request headers
+    anon_key: SecretString,
+    service_role_key: SecretString,
+    /// Verifies access tokens offline when a signing key is configured
+    verifier: Option<Arc<JwtVerifier>>,
+}
+
+impl std::fmt::Debug for SupabaseClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SupabaseClient")
+            .field("url", &self.url)
+            .field("retry", &self.retry)
+            .field("anon_key", &"[REDACTED]")
+            .field("service_role_key", &"[REDACTED]")
+            .field("verifier", &self.verifier)
+            .finish_non_exhaustive()
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -38,19 +163,83 @@ pub struct AuthResponse {
     pub user: SupabaseUser,
 }
 
+/// What `/auth/v1/signup` returns
+///
+/// With "Confirm email" enabled Supabase answers with the bare user and
+/// no session until the address is confirmed.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum SignUpResponse {
+    Session(AuthResponse),
+    Unconfirmed(SupabaseUser),
+}
+
 #[derive(Debug, Deserialize)]
 pub struct SupabaseUser {
     pub id: String,
     pub email: String,
+    /// `authenticated` for ordinary users
+    #[serde(default)]
+    pub role: String,
     #[serde(default)]
     pub user_metadata: serde_json::Value,
     pub created_at: String,
 }
 
+/// Why a Supabase Auth call failed
+#[derive(Debug, Error)]
+pub enum SupabaseError {
+    /// Supabase couldn't be reached or failed on its side (5xx)
+    #[error("Supabase unavailable: {0}")]
+    Network(String),
+
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+
+    /// Right password, but the email address hasn't been confirmed yet
+    #[error("Email not confirmed")]
+    EmailNotConfirmed,
+
+    /// Sign-up with an email that already has an account
+    #[error("User already registered")]
+    AlreadyRegistered,
+
+    #[error("Rate limited by Supabase")]
+    RateLimited { retry_after: Option<Duration> },
+
+    /// Rejected request, e.g. wrong credentials or a weak password
+    #[error("{0}")]
+    BadRequest(String),
+
+    #[error("Failed to parse Supabase response: {0}")]
+    Parse(String),
+}
+
+impl From<reqwest::Error> for SupabaseError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Network(e.to_string())
+    }
+}
+
+impl From<EgressDenied> for SupabaseError {
+    fn from(e: EgressDenied) -> Self {
+        Self::Network(e.to_string())
+    }
+}
+
+/// Error body; GoTrue has used both `error`/`error_description` and `msg`
 #[derive(Debug, Deserialize)]
-pub struct SupabaseError {
-    pub error: String,
-    pub error_description: Option<String>,
+struct ErrorBody {
+    error: Option<String>,
+    error_description: Option<String>,
+    msg: Option<String>,
+    error_code: Option<String>,
+}
+
+impl ErrorBody {
+    fn message(self) -> Option<String> {
+        self.error_description.or(self.msg).or(self.error)
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -58,11 +247,29 @@ pub struct RefreshRequest {
     pub refresh_token: String,
 }
 
+#[derive(Debug, Serialize)]
+pub struct UpdateUserRequest {
+    pub password: String,
+}
+
 #[derive(Debug, Serialize)]
 pub struct VerifyRequest {
     pub token: String,
 }
 
+#[derive(Debug, Serialize)]
+pub struct RecoverRequest {
+    pub email: String,
+}
+
+/// Body of `/auth/v1/verify` for an emailed link's token hash
+#[derive(Debug, Serialize)]
+pub struct VerifyOtpRequest {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub token_hash: String,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct VerifyResponse {
     pub aud: String,
@@ -72,20 +279,58 @@ pub struct VerifyResponse {
     pub role: String,
 }
 
+/// Byte-wise equality taking the same time wherever `a` and `b` differ
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Value of the `scope` parameter of `/auth/v1/logout`
+fn scope_param(scope: SignOutScope) -> &'static str {
+    match scope {
+        SignOutScope::Local => "local",
+        SignOutScope::Global => "global",
+    }
+}
+
 impl SupabaseClient {
-    pub fn new() -> Result<Self, String> {
-        let url = env::var("SUPABASE_URL")
-            .map_err(|_| "SUPABASE_URL not set in environment")?;
-        let anon_key = env::var("SUPABASE_ANON_KEY")
-            .map_err(|_| "SUPABASE_ANON_KEY not set in environment")?;
-        let service_role_key = env::var("SUPABASE_SERVICE_ROLE_KEY")
-            .map_err(|_| "SUPABASE_SERVICE_ROLE_KEY not set in environment")?;
-
-        Ok(Self {
-            client: Client::new(),
+    /// Build a client from explicit settings without validating them
+    ///
+    /// Meant for tests; deployments should go through `from_config`.
+    pub fn new(url: String, anon_key: String, service_role_key: String, client: HttpClient) -> Self {
+        Self {
+            client,
+            retry: RetryConfig::default(),
             url,
-            anon_key,
-            service_role_key,
+            anon_key: SecretString::new(anon_key),
+            service_role_key: SecretString::new(service_role_key),
+            verifier: None,
+        }
+    }
+
+    pub fn with_retry(mut self, retry: RetryConfig) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Verify access tokens locally with `verifier` instead of asking Supabase
+    pub fn with_verifier(mut self, verifier: JwtVerifier) -> Self {
+        self.verifier = Some(Arc::new(verifier));
+        self
+    }
+
+    /// Build a client from the gateway's Supabase and JWT settings
+    ///
+    /// Refuses the same unusable settings as `SupabaseSettings::credentials`.
+    /// Calls to Supabase, and to its JWKS, go through `http`.
+    pub fn from_config(supabase: &SupabaseSettings, jwt: &JwtSettings, http: &HttpClient) -> Result<Self, ConfigError> {
+        let (url, anon_key, service_role_key) = supabase.credentials()?;
+        let verifier = JwtVerifier::from_settings(jwt, url, anon_key, http)?;
+
+        let client = Self::new(url.to_string(), anon_key.to_string(), service_role_key.to_string(), http.clone())
+            .with_retry(RetryConfig::from_env());
+        Ok(match verifier {
+            Some(verifier) => client.with_verifier(verifier),
+            None => client,
         })
     }
 
@@ -94,7 +339,7 @@ impl SupabaseClient {
         email: &str,
         password: &str,
         username: &str,
-    ) -> Result<AuthResponse, String> {
+    ) -> Result<SignUpResponse, SupabaseError> {
         let signup_url = format!("{}/auth/v1/signup", self.url);
         
         let payload = SignUpRequest {
@@ -105,30 +350,17 @@ impl SupabaseClient {
             }),
         };
 
-        let response = self.client
-            .post(&signup_url)
-            .header("apikey", &self.anon_key)
+        let request = self.client
+            .post(&signup_url)?
+            .header("apikey", self.anon_key.expose_secret())
             .header("Content-Type", "application/json")
-            .json(&payload)
-            .send()
-            .await
-            .map_err(|e| format!("Request failed: {}", e))?;
-
-        if response.status().is_success() {
-            response
-                .json::<AuthResponse>()
-                .await
-                .map_err(|e| format!("Failed to parse response: {}", e))
-        } else {
-            let error = response
-                .json::<SupabaseError>()
-                .await
-                .map_err(|e| format!("Failed to parse error: {}", e))?;
-            Err(error.error_description.unwrap_or(error.error))
-        }
+            .json(&payload);
+        let response = self.send(request, Repeat::Unsafe).await?;
+
+        parse_json(check(response).await?).await
     }
 
-    pub async fn sign_in(&self, email: &str, password: &str) -> Result<AuthResponse, String> {
+    pub async fn sign_in(&self, email: &str, password: &str) -> Result<AuthResponse, SupabaseError> {
         let signin_url = format!("{}/auth/v1/token?grant_type=password", self.url);
         
         let payload = SignInRequest {
@@ -136,109 +368,539 @@ impl SupabaseClient {
             password: password.to_string(),
         };
 
-        let response = self.client
-            .post(&signin_url)
-            .header("apikey", &self.anon_key)
+        let request = self.client
+            .post(&signin_url)?
+            .header("apikey", self.anon_key.expose_secret())
             .header("Content-Type", "application/json")
-            .json(&payload)
-            .send()
-            .await
-            .map_err(|e| format!("Request failed: {}", e))?;
-
-        if response.status().is_success() {
-            response
-                .json::<AuthResponse>()
-                .await
-                .map_err(|e| format!("Failed to parse response: {}", e))
-        } else {
-            let error = response
-                .json::<SupabaseError>()
-                .await
-                .map_err(|e| format!("Failed to parse error: {}", e))?;
-            Err(error.error_description.unwrap_or(error.error))
-        }
+            .json(&payload);
+        let response = self.send(request, Repeat::Unsafe).await?;
+
+        parse_json(check(response).await?).await
+    }
+
+    /// Confirm an email address with the token hash from its confirmation link
+    pub async fn verify_email(&self, token_hash: &str) -> Result<AuthResponse, SupabaseError> {
+        self.verify_otp("email", token_hash).await
+    }
+
+    /// Sign in with the token hash from a password recovery link
+    pub async fn verify_recovery(&self, token_hash: &str) -> Result<AuthResponse, SupabaseError> {
+        self.verify_otp("recovery", token_hash).await
+    }
+
+    /// Exchange an emailed token hash of type `kind` for a session
+    ///
+    /// Tokens are single use and expire after the project's OTP expiry, both
+    /// reported as a 403.
+    async fn verify_otp(&self, kind: &'static str, token_hash: &str) -> Result<AuthResponse, SupabaseError> {
+        let verify_url = format!("{}/auth/v1/verify", self.url);
+
+        let payload = VerifyOtpRequest {
+            kind,
+            token_hash: token_hash.to_string(),
+        };
+
+        let request = self.client
+            .post(&verify_url)?
+            .header("apikey", self.anon_key.expose_secret())
+            .header("Content-Type", "application/json")
+            .json(&payload);
+        let response = self.send(request, Repeat::Unsafe).await?;
+
+        parse_json(check(response).await?).await
     }
 
-    pub async fn refresh_token(&self, refresh_token: &str) -> Result<AuthResponse, String> {
+    /// Have Supabase email a password recovery link to `email`
+    ///
+    /// Succeeds for unknown addresses too, so callers learn nothing about
+    /// which accounts exist.
+    pub async fn recover(&self, email: &str) -> Result<(), SupabaseError> {
+        let recover_url = format!("{}/auth/v1/recover", self.url);
+
+        let payload = RecoverRequest {
+            email: email.to_string(),
+        };
+
+        let request = self.client
+            .post(&recover_url)?
+            .header("apikey", self.anon_key.expose_secret())
+            .header("Content-Type", "application/json")
+            .json(&payload);
+        let response = self.send(request, Repeat::Unsafe).await?;
+
+        check(response).await.map(drop)
+    }
+
+    pub async fn refresh_token(&self, refresh_token: &str) -> Result<AuthResponse, SupabaseError> {
         let refresh_url = format!("{}/auth/v1/token?grant_type=refresh_token", self.url);
         
         let payload = RefreshRequest {
             refresh_token: refresh_token.to_string(),
         };
 
-        let response = self.client
-            .post(&refresh_url)
-            .header("apikey", &self.anon_key)
+        let request = self.client
+            .post(&refresh_url)?
+            .header("apikey", self.anon_key.expose_secret())
             .header("Content-Type", "application/json")
-            .json(&payload)
-            .send()
-            .await
-            .map_err(|e| format!("Request failed: {}", e))?;
-
-        if response.status().is_success() {
-            response
-                .json::<AuthResponse>()
-                .await
-                .map_err(|e| format!("Failed to parse response: {}", e))
-        } else {
-            let error = response
-                .json::<SupabaseError>()
-                .await
-                .map_err(|e| format!("Failed to parse error: {}", e))?;
-            Err(error.error_description.unwrap_or(error.error))
+            .json(&payload);
+        let response = self.send(request, Repeat::Unsafe).await?;
+
+        parse_json(check(response).await?).await
+    }
+
+    /// Who `token` belongs to, checked locally when a verifier is configured
+    ///
+    /// The project's service role key is accepted as `ADMIN_ROLE`. It has
+    /// no `sub` or `aud`, so neither the verifier nor `/auth/v1/user` would
+    /// take it, and it is what Supabase hands trusted backends.
+    pub async fn verify_token(&self, token: &str) -> Result<VerifyResponse, SupabaseError> {
+        if constant_time_eq(token.as_bytes(), self.service_role_key.expose_secret().as_bytes()) {
+            return Ok(VerifyResponse {
+                aud: String::new(),
+                exp: unverified_expiry(token).unwrap_or(0),
+                sub: ADMIN_ROLE.to_string(),
+                email: String::new(),
+                role: ADMIN_ROLE.to_string(),
+            });
+        }
+
+        if let Some(verifier) = &self.verifier {
+            match verifier.verify(token).await {
+                Ok(verified) => return Ok(verified),
+                // Keys couldn't be fetched; let Supabase decide instead
+                Err(VerifyError::KeyUnavailable(e)) => {
+                    tracing::warn!("Verifying token with Supabase, signing keys unavailable: {}", e);
+                }
+                Err(e) => return Err(SupabaseError::Unauthorized(e.to_string())),
+            }
         }
+
+        let user_url = format!("{}/auth/v1/user", self.url);
+
+        let request = self.client
+            .get(&user_url)?
+            .header("apikey", self.anon_key.expose_secret())
+            .header("Authorization", format!("Bearer {}", token));
+        let response = self.send(request, Repeat::Safe).await?;
+
+        let user: SupabaseUser = match check(response).await {
+            Ok(response) => parse_json(response).await?,
+            // Supabase answers 403 (bad_jwt) rather than 401 for some malformed tokens
+            Err(SupabaseError::BadRequest(_)) => {
+                return Err(SupabaseError::Unauthorized("Invalid or expired token".to_string()));
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(VerifyResponse {
+            aud: "authenticated".to_string(),
+            // Supabase accepted the token, so its claims can be trusted
+            exp: unverified_expiry(token).unwrap_or(0),
+            sub: user.id,
+            email: user.email,
+            role: if user.role.is_empty() { "authenticated".to_string() } else { user.role },
+        })
     }
 
-    pub async fn verify_token(&self, token: &str) -> Result<VerifyResponse, String> {
+    /// Set a new password for the user `access_token` belongs to
+    pub async fn update_password(&self, access_token: &str, new_password: &str) -> Result<(), SupabaseError> {
         let user_url = format!("{}/auth/v1/user", self.url);
 
-        let response = self.client
-            .get(&user_url)
-            .header("apikey", &self.anon_key)
-            .header("Authorization", format!("Bearer {}", token))
-            .send()
-            .await
-            .map_err(|e| format!("Request failed: {}", e))?;
-
-        if response.status().is_success() {
-            let user = response
-                .json::<SupabaseUser>()
-                .await
-                .map_err(|e| format!("Failed to parse response: {}", e))?;
-            
-            Ok(VerifyResponse {
-                aud: "authenticated".to_string(),
-                exp: 0, // Supabase handles expiration
-                sub: user.id,
-                email: user.email,
-                role: "authenticated".to_string(),
-            })
-        } else {
-            Err("Invalid or expired token".to_string())
+        let payload = UpdateUserRequest {
+            password: new_password.to_string(),
+        };
+
+        let request = self.client
+            .put(&user_url)?
+            .header("apikey", self.anon_key.expose_secret())
+            .header("Authorization", format!("Bearer {}", access_token))
+            .header("Content-Type", "application/json")
+            .json(&payload);
+        let response = self.send(request, Repeat::Safe).await?;
+
+        check(response).await.map(drop)
+    }
+
+    /// Delete the user through the admin API, using the service role key
+    pub async fn delete_user(&self, user_id: &str) -> Result<(), SupabaseError> {
+        let user_url = format!("{}/auth/v1/admin/users/{}", self.url, user_id);
+
+        let request = self.client
+            .delete(&user_url)?
+            .header("apikey", self.service_role_key.expose_secret())
+            .header("Authorization", format!("Bearer {}", self.service_role_key.expose_secret()));
+        let response = self.send(request, Repeat::Unsafe).await?;
+
+        check(response).await.map(drop)
+    }
+
+    /// Revoke refresh tokens for the session(s) behind `access_token`
+    ///
+    /// The access token itself stays valid until it expires.
+    pub async fn sign_out(&self, access_token: &str, scope: SignOutScope) -> Result<(), SupabaseError> {
+        let signout_url = format!("{}/auth/v1/logout?scope={}", self.url, scope_param(scope));
+
+        let request = self.client
+            .post(&signout_url)?
+            .header("apikey", self.anon_key.expose_secret())
+            .header("Authorization", format!("Bearer {}", access_token));
+        let response = self.send(request, Repeat::Safe).await?;
+
+        check(response).await.map(drop)
+    }
+
+    /// Send `request`, retrying transient failures as `self.retry` allows
+    ///
+    /// Connection failures and 429s are retried for any request, since
+    /// Supabase never acted on them; timeouts, dropped connections and 5xx
+    /// responses only when `repeat` is `Safe`, so a sign-up that may have
+    /// gone through is never sent twice. Waits double from the base delay
+    /// unless `Retry-After` says otherwise. Returns the last response,
+    /// successful or not.
+    async fn send(&self, request: RequestBuilder, repeat: Repeat) -> Result<Response, SupabaseError> {
+        let mut retries = 0;
+        loop {
+            let Some(attempt) = request.try_clone().filter(|_| retries < self.retry.max_retries) else {
+                return Ok(request.send().await?);
+            };
+            let result = attempt.send().await;
+            let Some(delay) = self.retry.delay(&result, repeat, retries) else {
+                return Ok(result?);
+            };
+            tracing::warn!("Supabase request failed, retrying in {:?}", delay);
+            tokio::time::sleep(delay).await;
+            retries += 1;
         }
     }
+}
+
+/// Pass successful responses through, turning the rest into a `SupabaseError`
+async fn check(response: Response) -> Result<Response, SupabaseError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
 
-    pub async fn sign_out(&self, access_token: &str) -> Result<(), String> {
-        let signout_url = format!("{}/auth/v1/logout", self.url);
+    let retry_after = response.headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let body = response.text().await.unwrap_or_default();
+    Err(error_for_status(status, retry_after.as_deref(), &body))
+}
 
-        let response = self.client
-            .post(&signout_url)
-            .header("apikey", &self.anon_key)
-            .header("Authorization", format!("Bearer {}", access_token))
-            .send()
-            .await
-            .map_err(|e| format!("Request failed: {}", e))?;
-
-        if response.status().is_success() {
-            Ok(())
-        } else {
-            Err("Failed to sign out".to_string())
+async fn parse_json<T: DeserializeOwned>(response: Response) -> Result<T, SupabaseError> {
+    response.json::<T>().await.map_err(|e| SupabaseError::Parse(e.to_string()))
+}
+
+/// Classify a failed Supabase response
+fn error_for_status(status: StatusCode, retry_after: Option<&str>, body: &str) -> SupabaseError {
+    let body = serde_json::from_str::<ErrorBody>(body).ok();
+    // Older GoTrue versions send the message without an error code
+    if body.as_ref().is_some_and(|body| {
+        body.error_code.as_deref() == Some("email_not_confirmed")
+            || body.msg.as_deref() == Some("Email not confirmed")
+            || body.error_description.as_deref() == Some("Email not confirmed")
+    }) {
+        return SupabaseError::EmailNotConfirmed;
+    }
+    if body.as_ref().is_some_and(|body| body.error_code.as_deref() == Some("user_already_exists")) {
+        return SupabaseError::AlreadyRegistered;
+    }
+    let message = body
+        .and_then(ErrorBody::message)
+        .unwrap_or_else(|| status.to_string());
+
+    match status {
+        StatusCode::UNAUTHORIZED => SupabaseError::Unauthorized(message),
+        StatusCode::TOO_MANY_REQUESTS => SupabaseError::RateLimited {
+            retry_after: retry_after.and_then(parse_retry_after),
+        },
+        status if status.is_server_error() => SupabaseError::Network(message),
+        _ => SupabaseError::BadRequest(message),
+    }
+}
+
+/// `Retry-After` in seconds; the HTTP-date form isn't used by Supabase
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    value.trim().parse().ok().map(Duration::from_secs)
+}
+
+/// True for `.env.example` placeholders like `[YOUR_ANON_KEY]`
+pub(crate) fn is_placeholder(value: &str) -> bool {
+    value.contains('[') && value.contains(']')
+}
+
+pub(crate) fn validate_config(url: &str, anon_key: &str, service_role_key: &str) -> Result<(), ConfigError> {
+    if is_placeholder(url) {
+        return Err(ConfigError::Placeholder("SUPABASE_URL"));
+    }
+    let is_local = ["http://localhost", "http://127.0.0.1", "http://[::1]"]
+        .iter()
+        .any(|prefix| url.starts_with(prefix));
+    if !url.starts_with("https://") && !is_local {
+        return Err(ConfigError::InsecureUrl(url.to_string()));
+    }
+
+    for (name, key) in [
+        ("SUPABASE_ANON_KEY", anon_key),
+        ("SUPABASE_SERVICE_ROLE_KEY", service_role_key),
+    ] {
+        if is_placeholder(key) {
+            return Err(ConfigError::Placeholder(name));
+        }
+        if key.len() < MIN_KEY_LENGTH {
+            return Err(ConfigError::TooShort(name));
         }
     }
+
+    Ok(())
 }
 
-impl Default for SupabaseClient {
-    fn default() -> Self {
-        Self::new().expect("Failed to initialize Supabase client")
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const KEY: &str = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.test-key";
+
+    #[test]
+    fn test_valid_config_accepted() {
+        assert_eq!(validate_config("https://abc.supabase.co", KEY, KEY), Ok(()));
+        assert_eq!(validate_config("http://localhost:54321", KEY, KEY), Ok(()));
+    }
+
+    #[test]
+    fn test_example_placeholders_rejected() {
+        assert_eq!(
+            validate_config("https://[PROJECT_REF].supabase.co", KEY, KEY),
+            Err(ConfigError::Placeholder("SUPABASE_URL"))
+        );
+        assert_eq!(
+            validate_config("https://abc.supabase.co", "[YOUR_ANON_KEY]", KEY),
+            Err(ConfigError::Placeholder("SUPABASE_ANON_KEY"))
+        );
+    }
+
+    #[test]
+    fn test_short_key_rejected() {
+        assert_eq!(
+            validate_config("https://abc.supabase.co", KEY, "too-short"),
+            Err(ConfigError::TooShort("SUPABASE_SERVICE_ROLE_KEY"))
+        );
+    }
+
+    #[test]
+    fn test_rate_limit_reads_retry_after() {
+        let error = error_for_status(StatusCode::TOO_MANY_REQUESTS, Some("7"), "");
+        assert!(matches!(
+            error,
+            SupabaseError::RateLimited { retry_after: Some(d) } if d == Duration::from_secs(7)
+        ));
+
+        let error = error_for_status(StatusCode::TOO_MANY_REQUESTS, None, "");
+        assert!(matches!(error, SupabaseError::RateLimited { retry_after: None }));
+    }
+
+    #[test]
+    fn test_status_codes_map_to_variants() {
+        let body = r#"{"error":"invalid_grant","error_description":"Invalid login credentials"}"#;
+        assert!(matches!(
+            error_for_status(StatusCode::BAD_REQUEST, None, body),
+            SupabaseError::BadRequest(message) if message == "Invalid login credentials"
+        ));
+        assert!(matches!(
+            error_for_status(StatusCode::UNPROCESSABLE_ENTITY, None, r#"{"code":422,"msg":"Password too weak"}"#),
+            SupabaseError::BadRequest(message) if message == "Password too weak"
+        ));
+        assert!(matches!(
+            error_for_status(StatusCode::UNAUTHORIZED, None, ""),
+            SupabaseError::Unauthorized(_)
+        ));
+        assert!(matches!(
+            error_for_status(StatusCode::BAD_GATEWAY, None, "<html>bad gateway</html>"),
+            SupabaseError::Network(_)
+        ));
+    }
+
+    #[test]
+    fn test_unconfirmed_email_recognized() {
+        let body = r#"{"code":400,"error_code":"email_not_confirmed","msg":"Email not confirmed"}"#;
+        assert!(matches!(
+            error_for_status(StatusCode::BAD_REQUEST, None, body),
+            SupabaseError::EmailNotConfirmed
+        ));
+        let legacy = r#"{"error":"invalid_grant","error_description":"Email not confirmed"}"#;
+        assert!(matches!(
+            error_for_status(StatusCode::BAD_REQUEST, None, legacy),
+            SupabaseError::EmailNotConfirmed
+        ));
+        let taken = r#"{"code":422,"error_code":"user_already_exists","msg":"User already registered"}"#;
+        assert!(matches!(
+            error_for_status(StatusCode::UNPROCESSABLE_ENTITY, None, taken),
+            SupabaseError::AlreadyRegistered
+        ));
+        let expired = r#"{"code":403,"error_code":"otp_expired","msg":"Token has expired or is invalid"}"#;
+        assert!(matches!(
+            error_for_status(StatusCode::FORBIDDEN, None, expired),
+            SupabaseError::BadRequest(message) if message == "Token has expired or is invalid"
+        ));
+    }
+
+    #[test]
+    fn test_sign_up_response_with_and_without_session() {
+        let user = r#"{"id":"u1","email":"a@example.com","created_at":"2024-01-01T00:00:00Z","confirmation_sent_at":"2024-01-01T00:00:00Z"}"#;
+        let unconfirmed: SignUpResponse = serde_json::from_str(user).unwrap();
+        assert!(matches!(unconfirmed, SignUpResponse::Unconfirmed(user) if user.id == "u1"));
+
+        let session = format!(
+            r#"{{"access_token":"a","token_type":"bearer","expires_in":3600,"refresh_token":"r","user":{}}}"#,
+            user
+        );
+        let active: SignUpResponse = serde_json::from_str(&session).unwrap();
+        assert!(matches!(active, SignUpResponse::Session(response) if response.user.id == "u1"));
+    }
+
+    #[test]
+    fn test_remote_http_rejected() {
+        assert!(matches!(
+            validate_config("http://abc.supabase.co", KEY, KEY),
+            Err(ConfigError::InsecureUrl(_))
+        ));
+    }
+
+    /// Serve `/auth/v1/user` and `/auth/v1/signup`, answering the first
+    /// `failures` requests with a 503; returns the base URL
+    async fn flaky_server(failures: usize, requests: Arc<AtomicUsize>) -> String {
+        let handler = move || {
+            let n = requests.fetch_add(1, Ordering::SeqCst);
+            async move {
+                if n < failures {
+                    let body = serde_json::json!({"msg": "Service unavailable"});
+                    return (axum::http::StatusCode::SERVICE_UNAVAILABLE, axum::Json(body));
+                }
+                let user = serde_json::json!({"id": "u1", "email": "a@example.com", "created_at": "2024-01-01T00:00:00Z"});
+                (axum::http::StatusCode::OK, axum::Json(user))
+            }
+        };
+        let app = axum::Router::new()
+            .route("/auth/v1/user", axum::routing::get(handler.clone()))
+            .route("/auth/v1/signup", axum::routing::post(handler));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        url
+    }
+
+    fn client(url: String) -> SupabaseClient {
+        SupabaseClient::new(url, KEY.to_string(), KEY.to_string(), HttpClient::default())
+            .with_retry(RetryConfig { max_retries: 2, base_delay: Duration::from_millis(1) })
+    }
+
+    #[test]
+    fn test_debug_output_hides_keys() {
+        let anon = "eyJhbGciOiJIUzI1NiJ9.anon-key-material";
+        let service = "eyJhbGciOiJIUzI1NiJ9.service-key-material";
+        let client = SupabaseClient::new(
+            "https://abc.supabase.co".to_string(), anon.to_string(), service.to_string(), HttpClient::default(),
+        );
+        let debug = format!("{:?}", client);
+        assert!(debug.contains("https://abc.supabase.co"));
+        assert!(!debug.contains("key-material"));
+        assert!(!debug.contains("eyJ"));
+    }
+
+    #[tokio::test]
+    async fn test_idempotent_call_retried_until_it_succeeds() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let client = client(flaky_server(2, requests.clone()).await);
+
+        let verified = client.verify_token("token").await.unwrap();
+        assert_eq!(verified.sub, "u1");
+        assert_eq!(verified.role, "authenticated");
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_sign_out_scope_sent_to_supabase() {
+        let scopes = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = scopes.clone();
+        let app = axum::Router::new().route(
+            "/auth/v1/logout",
+            axum::routing::post(move |query: axum::extract::RawQuery| {
+                recorded.lock().unwrap().push(query.0.unwrap_or_default());
+                async { axum::http::StatusCode::NO_CONTENT }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = client(url);
+        client.sign_out("token", SignOutScope::Global).await.unwrap();
+        client.sign_out("token", SignOutScope::Local).await.unwrap();
+        assert_eq!(*scopes.lock().unwrap(), ["scope=global", "scope=local"]);
+    }
+
+    #[tokio::test]
+    async fn test_only_service_role_key_is_admin() {
+        use crate::auth::backend::SupabaseAuthBackend;
+        use crate::auth::jwt::JwtConfig;
+        use crate::auth::middleware::{require_admin, AuthInterceptor};
+        use jsonwebtoken::{encode, Algorithm, DecodingKey, EncodingKey, Header};
+
+        const URL: &str = "https://abc.supabase.co";
+        const SECRET: &[u8] = b"super-secret-jwt-token-with-at-least-32-characters";
+        let service_key = "eyJhbGciOiJIUzI1NiJ9.service-role-key-material";
+        let verifier = JwtVerifier::with_config(
+            JwtConfig::supabase(URL, Algorithm::HS256),
+            DecodingKey::from_secret(SECRET),
+        );
+        let client = SupabaseClient::new(URL.to_string(), KEY.to_string(), service_key.to_string(), HttpClient::default())
+            .with_verifier(verifier);
+        let interceptor = AuthInterceptor::new(Arc::new(SupabaseAuthBackend::new(client)));
+        let call_as = |token: &str| {
+            let mut req = tonic::Request::new(());
+            req.metadata_mut().insert("authorization", format!("Bearer {}", token).parse().unwrap());
+            interceptor.intercept(req)
+        };
+
+        let admin = call_as(service_key).await.unwrap();
+        assert!(require_admin(&admin).is_ok());
+
+        let claims = serde_json::json!({
+            "sub": "user-1",
+            "email": "a@example.com",
+            "role": "authenticated",
+            "aud": "authenticated",
+            "iss": format!("{}/auth/v1", URL),
+            "exp": chrono::Utc::now().timestamp() + 3600,
+        });
+        let user_token = encode(&Header::new(Algorithm::HS256), &claims, &EncodingKey::from_secret(SECRET)).unwrap();
+        let user = call_as(&user_token).await.unwrap();
+        assert_eq!(require_admin(&user).unwrap_err().code(), tonic::Code::PermissionDenied);
+
+        let near_miss = call_as(&format!("{}x", service_key)).await.unwrap_err();
+        assert_eq!(near_miss.code(), tonic::Code::Unauthenticated);
+    }
+
+    #[tokio::test]
+    async fn test_retries_are_bounded() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let client = client(flaky_server(usize::MAX, requests.clone()).await);
+
+        assert!(matches!(client.verify_token("token").await, Err(SupabaseError::Network(_))));
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_sign_up_not_retried_after_server_error() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let client = client(flaky_server(2, requests.clone()).await);
+
+        // The first attempt may have created the account
+        let result = client.sign_up("a@example.com", "password", "alice").await;
+        assert!(matches!(result, Err(SupabaseError::Network(_))));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
     }
 }
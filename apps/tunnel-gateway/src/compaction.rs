@@ -0,0 +1,90 @@
+use crate::database::MemoryDatabase;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+
+/// How often the memories table is vacuumed when nothing is configured
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// Wait between looks for a moment with no queries in flight
+const QUIET_POLL: Duration = Duration::from_secs(10);
+
+/// Looks before compacting regardless; plain `VACUUM` doesn't block writers
+const QUIET_ATTEMPTS: u32 = 30;
+
+/// Vacuum and analyze the memories table every `interval`
+///
+/// Each run waits a few minutes for the pool to go idle first, so it tends
+/// to land between bursts of traffic. A run that overlaps the next tick
+/// makes that tick be skipped rather than queued.
+pub fn spawn_compaction_task(db: Arc<MemoryDatabase>, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        // The first tick is immediate; nothing needs compacting at startup
+        ticks.tick().await;
+        loop {
+            ticks.tick().await;
+            for _ in 0..QUIET_ATTEMPTS {
+                if db.busy_connections() == 0 {
+                    break;
+                }
+                tokio::time::sleep(QUIET_POLL).await;
+            }
+            match db.compact().await {
+                Ok(Some(reclaimed)) => tracing::info!("🧹 Compacted memories, reclaiming {} bytes", reclaimed),
+                Ok(None) => tracing::debug!("Skipped compaction; another gateway is running one"),
+                Err(e) => tracing::error!("Failed to compact memories: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::tests::{drop_scratch_schema, scratch_schema, scratch_url};
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_compaction_shrinks_table_after_bulk_delete() {
+        let (pool, schema) = scratch_schema("compaction").await;
+        let db = MemoryDatabase::connect(&scratch_url(&schema)).await.unwrap();
+        let content = "x".repeat(1000);
+        for _ in 0..500 {
+            let id = uuid::Uuid::new_v4().to_string();
+            db.store_memory("alice", &id, &content, &[0.5; 4], &HashMap::new(), &[], 0, 0).await.unwrap();
+        }
+        let size = |pool: sqlx::PgPool| async move {
+            sqlx::query_scalar::<_, i64>("SELECT pg_total_relation_size('memories')")
+                .fetch_one(&pool)
+                .await
+                .unwrap()
+        };
+        let before = size(pool.clone()).await;
+
+        sqlx::query("DELETE FROM memories").execute(&pool).await.unwrap();
+        let reclaimed = db.compact().await.unwrap().expect("no other compaction running");
+        assert!(reclaimed > 0);
+        assert_eq!(size(pool.clone()).await, before - reclaimed);
+
+        drop_scratch_schema(&schema).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_compaction_skipped_while_another_runs() {
+        let (pool, schema) = scratch_schema("compaction").await;
+        let db = MemoryDatabase::connect(&scratch_url(&schema)).await.unwrap();
+        let mut other = pool.acquire().await.unwrap();
+        sqlx::query("SELECT pg_advisory_lock(hashtext('memory_compaction'), hashtext(current_schema()))").execute(&mut *other).await.unwrap();
+
+        assert_eq!(db.compact().await.unwrap(), None);
+        sqlx::query("SELECT pg_advisory_unlock(hashtext('memory_compaction'), hashtext(current_schema()))").execute(&mut *other).await.unwrap();
+        assert!(db.compact().await.unwrap().is_some());
+
+        drop(other);
+        drop_scratch_schema(&schema).await;
+    }
+}
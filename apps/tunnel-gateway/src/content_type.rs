@@ -0,0 +1,109 @@
+/// Content type of memories stored without one that isn't anything else
+pub const PLAIN: &str = "text/plain";
+pub const MARKDOWN: &str = "text/markdown";
+/// A bare link, or several one per line
+pub const URI_LIST: &str = "text/uri-list";
+/// Source code in any language
+pub const CODE: &str = "text/x-code";
+
+/// Longest content type accepted, as for a MIME type
+const MAX_LENGTH: usize = 127;
+
+/// Share of non-empty lines that must look like code for `detect` to say so
+const CODE_LINE_SHARE: f32 = 0.5;
+
+/// `requested`, or what `detect` makes of `content` when it is empty
+pub fn resolve(requested: &str, content: &str) -> String {
+    if requested.is_empty() {
+        detect(content).to_string()
+    } else {
+        requested.to_ascii_lowercase()
+    }
+}
+
+/// Why `content_type` can't be stored, if it can't; empty means detect
+pub fn error(content_type: &str) -> Option<String> {
+    if content_type.is_empty() {
+        return None;
+    }
+    let valid = content_type.len() <= MAX_LENGTH
+        && content_type.split_once('/').is_some_and(|(kind, subtype)| is_token(kind) && is_token(subtype));
+    (!valid).then(|| format!("Invalid content type '{}', expected e.g. text/markdown", content_type))
+}
+
+/// A MIME type or subtype name: letters, digits and `!#$&-^_.+`
+fn is_token(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || "!#$&-^_.+".contains(c))
+}
+
+/// Best guess at the type of `content`: links, markdown, code or plain text
+pub fn detect(content: &str) -> &'static str {
+    let lines: Vec<&str> = content.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+    if lines.is_empty() {
+        return PLAIN;
+    }
+    if lines.iter().all(|l| is_url(l)) {
+        return URI_LIST;
+    }
+    if lines.iter().any(|l| is_markdown(l)) {
+        return MARKDOWN;
+    }
+    let code_lines = lines.iter().filter(|l| is_code(l)).count();
+    if code_lines as f32 >= lines.len() as f32 * CODE_LINE_SHARE {
+        return CODE;
+    }
+    PLAIN
+}
+
+fn is_url(line: &str) -> bool {
+    (line.starts_with("http://") || line.starts_with("https://")) && !line.contains(char::is_whitespace)
+}
+
+fn is_markdown(line: &str) -> bool {
+    line.starts_with("```")
+        || line.starts_with("# ")
+        || line.starts_with("## ")
+        || line.starts_with("### ")
+        || line.starts_with("> ")
+        || line.contains("](")
+}
+
+fn is_code(line: &str) -> bool {
+    const KEYWORDS: &[&str] = &["fn ", "def ", "function ", "class ", "import ", "#include", "let ", "const ", "return "];
+    line.ends_with(';')
+        || line.ends_with('{')
+        || line == "}"
+        || KEYWORDS.iter().any(|k| line.starts_with(k))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect("Remember to buy milk"), PLAIN);
+        assert_eq!(detect("   "), PLAIN);
+        assert_eq!(detect("https://example.com/docs"), URI_LIST);
+        assert_eq!(detect("https://a.example\nhttps://b.example\n"), URI_LIST);
+        assert_eq!(detect("see https://example.com for details"), PLAIN);
+        assert_eq!(detect("# Plan\nShip it friday"), MARKDOWN);
+        assert_eq!(detect("Docs are [here](https://example.com)"), MARKDOWN);
+        assert_eq!(detect("fn main() {\n    println!(\"hi\");\n}"), CODE);
+        assert_eq!(detect("def add(a, b):\n    return a + b"), CODE);
+    }
+
+    #[test]
+    fn test_requested_type_validated_and_kept() {
+        assert_eq!(error(""), None);
+        assert_eq!(error("text/markdown"), None);
+        assert_eq!(error("application/vnd.api+json"), None);
+        assert!(error("markdown").is_some());
+        assert!(error("text/").is_some());
+        assert!(error("text/mark down").is_some());
+        assert!(error(&format!("text/{}", "x".repeat(MAX_LENGTH))).is_some());
+
+        assert_eq!(resolve("Text/Markdown", "plain words"), MARKDOWN);
+        assert_eq!(resolve("", "plain words"), PLAIN);
+    }
+}
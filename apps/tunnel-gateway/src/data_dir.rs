@@ -0,0 +1,92 @@
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Directory under the platform data dir when no data dir is configured
+const APP_DIR: &str = "identra";
+
+#[derive(Debug, Error)]
+pub enum DataDirError {
+    #[error("No platform data directory for this user; set IDENTRA_DATA_DIR")]
+    Unresolved,
+
+    #[error("Failed to set up data directory {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+}
+
+/// Where the gateway keeps its own files, created if missing
+///
+/// `configured` (`data_dir`, or `IDENTRA_DATA_DIR`) if set, otherwise
+/// `identra` under the platform data dir (`~/.local/share`,
+/// `~/Library/Application Support`, `%APPDATA%`). Never the working
+/// directory, so the gateway finds the same files however it was launched.
+pub fn open(configured: Option<PathBuf>) -> Result<PathBuf, DataDirError> {
+    let platform = directories::BaseDirs::new().map(|dirs| dirs.data_dir().to_path_buf());
+    let dir = resolve(configured, platform)?;
+    create(&dir)?;
+    Ok(dir)
+}
+
+/// The override if given, else the app directory under `platform`, made absolute
+fn resolve(override_dir: Option<PathBuf>, platform: Option<PathBuf>) -> Result<PathBuf, DataDirError> {
+    let dir = match override_dir.filter(|dir| !dir.as_os_str().is_empty()) {
+        Some(dir) => dir,
+        None => platform.ok_or(DataDirError::Unresolved)?.join(APP_DIR),
+    };
+    std::path::absolute(&dir).map_err(|e| DataDirError::Io(dir, e))
+}
+
+/// Create `dir` readable by its owner only; an existing one is left as is
+fn create(dir: &Path) -> Result<(), DataDirError> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(dir).map_err(|e| DataDirError::Io(dir.to_path_buf(), e))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(dir).map_err(|e| DataDirError::Io(dir.to_path_buf(), e))?.permissions().mode();
+        if mode & 0o077 != 0 {
+            tracing::warn!("Data directory {} is accessible to other users (mode {:o})", dir.display(), mode & 0o777);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_override_wins_over_platform_dir() {
+        let dir = resolve(Some(PathBuf::from("/srv/identra")), Some(PathBuf::from("/home/a/.local/share"))).unwrap();
+        assert_eq!(dir, PathBuf::from("/srv/identra"));
+
+        let relative = resolve(Some(PathBuf::from("state")), None).unwrap();
+        assert!(relative.is_absolute());
+        assert!(relative.ends_with("state"));
+    }
+
+    #[test]
+    fn test_falls_back_to_platform_dir() {
+        let platform = Some(PathBuf::from("/home/a/.local/share"));
+        assert_eq!(resolve(None, platform.clone()).unwrap(), PathBuf::from("/home/a/.local/share/identra"));
+        assert_eq!(resolve(Some(PathBuf::new()), platform).unwrap(), PathBuf::from("/home/a/.local/share/identra"));
+        assert!(matches!(resolve(None, None), Err(DataDirError::Unresolved)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_created_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let root = std::env::temp_dir().join(format!("identra-data-dir-{}", std::process::id()));
+        let dir = root.join("nested").join("identra");
+
+        create(&dir).unwrap();
+        assert_eq!(std::fs::metadata(&dir).unwrap().permissions().mode() & 0o777, 0o700);
+        create(&dir).unwrap();
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,33 +1,763 @@
-use sqlx::postgres::{PgPoolOptions, PgPool};
+use sqlx::postgres::{PgConnectOptions, PgConnection, PgPoolOptions, PgPool, Postgres};
+use sqlx::{QueryBuilder, Transaction};
 use sqlx::Row; 
 use uuid::Uuid;
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::time::Duration;
 
 // Shared model for Service <-> DB
 use crate::services::memory::MemoryModel;
+use crate::export::ExportedMemory;
+use crate::migrations::{self, Migration};
+use crate::quota::{QuotaExceeded, StorageQuota, StorageUsage};
+use thiserror::Error;
+use tokio_stream::StreamExt;
+
+/// Pool size used when none is configured
+pub const DEFAULT_POOL_SIZE: u32 = 10;
+
+/// Longest a query waits on another's row or table lock when none is configured
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// SQLSTATEs of failures caused by other transactions, which a retry may avoid:
+/// serialization_failure, deadlock_detected and lock_not_available
+const TRANSIENT_SQLSTATES: &[&str] = &["40001", "40P01", "55P03"];
+
+/// Whether `error` came from contention rather than a fault, so retrying
+/// the whole request may succeed
+///
+/// Covers lock waits past the lock timeout, deadlocks, serialization
+/// failures and a pool with no connection free in time.
+pub fn is_transient(error: &sqlx::Error) -> bool {
+    match error {
+        sqlx::Error::PoolTimedOut => true,
+        sqlx::Error::Database(e) => e.code().is_some_and(|code| TRANSIENT_SQLSTATES.contains(&code.as_ref())),
+        _ => false,
+    }
+}
+
+// Keeps `memories.search_vector` in sync with content, tags and language.
+// Tags get the higher weight so a tag hit outranks a passing mention in the
+// text. Words are stemmed for the memory's language, English when unknown.
+// CJK text has no spaces between words, so its runs are also indexed as
+// single characters and pairs of adjacent ones; a query matches them by its
+// own pairs, which finds any run of two or more characters.
+const SEARCH_INDEX_DDL: &[&str] = &[
+    "ALTER TABLE memories ADD COLUMN IF NOT EXISTS search_vector tsvector",
+    r#"
+    CREATE OR REPLACE FUNCTION memories_search_config(lang TEXT) RETURNS regconfig AS $$
+        SELECT (CASE coalesce(lang, 'eng')
+            WHEN 'eng' THEN 'english' WHEN 'dan' THEN 'danish' WHEN 'nld' THEN 'dutch'
+            WHEN 'fin' THEN 'finnish' WHEN 'fra' THEN 'french' WHEN 'deu' THEN 'german'
+            WHEN 'hun' THEN 'hungarian' WHEN 'ita' THEN 'italian' WHEN 'nob' THEN 'norwegian'
+            WHEN 'por' THEN 'portuguese' WHEN 'ron' THEN 'romanian' WHEN 'rus' THEN 'russian'
+            WHEN 'spa' THEN 'spanish' WHEN 'swe' THEN 'swedish' WHEN 'tur' THEN 'turkish'
+            ELSE 'simple'
+        END)::regconfig
+    $$ LANGUAGE sql IMMUTABLE
+    "#,
+    r#"
+    CREATE OR REPLACE FUNCTION memories_cjk_terms(body TEXT, with_single BOOLEAN) RETURNS TEXT AS $$
+    DECLARE
+        terms TEXT[] := '{}';
+        run TEXT;
+    BEGIN
+        FOR run IN
+            SELECT m[1] FROM regexp_matches(coalesce(body, ''), '([\u3040-\u30ff\u3400-\u9fff\uf900-\ufaff\uac00-\ud7af]+)', 'g') AS m
+        LOOP
+            IF with_single OR char_length(run) = 1 THEN
+                FOR i IN 1..char_length(run) LOOP
+                    terms := terms || substr(run, i, 1);
+                END LOOP;
+            END IF;
+            FOR i IN 1..char_length(run) - 1 LOOP
+                terms := terms || substr(run, i, 2);
+            END LOOP;
+        END LOOP;
+        RETURN array_to_string(terms, ' ');
+    END
+    $$ LANGUAGE plpgsql IMMUTABLE
+    "#,
+    r#"
+    CREATE OR REPLACE FUNCTION memories_search_query(lang TEXT, query TEXT) RETURNS tsquery AS $$
+        SELECT websearch_to_tsquery(
+            memories_search_config(lang),
+            regexp_replace(query, '[\u3040-\u30ff\u3400-\u9fff\uf900-\ufaff\uac00-\ud7af]+', ' ', 'g')
+                || ' ' || memories_cjk_terms(query, FALSE)
+        )
+    $$ LANGUAGE sql IMMUTABLE
+    "#,
+    r#"
+    CREATE OR REPLACE FUNCTION memories_search_vector_update() RETURNS trigger AS $$
+    DECLARE
+        config regconfig := memories_search_config(NEW.lang);
+        tags TEXT := coalesce(array_to_string(NEW.tags, ' '), '');
+    BEGIN
+        NEW.search_vector :=
+            setweight(to_tsvector(config, tags) || to_tsvector('simple', memories_cjk_terms(tags, TRUE)), 'A') ||
+            setweight(to_tsvector(config, coalesce(NEW.content, '')) || to_tsvector('simple', memories_cjk_terms(NEW.content, TRUE)), 'B');
+        RETURN NEW;
+    END
+    $$ LANGUAGE plpgsql
+    "#,
+    "DROP TRIGGER IF EXISTS memories_search_vector_trigger ON memories",
+    r#"
+    CREATE TRIGGER memories_search_vector_trigger
+    BEFORE INSERT OR UPDATE OF content, tags, lang ON memories
+    FOR EACH ROW EXECUTE FUNCTION memories_search_vector_update()
+    "#,
+    "CREATE INDEX IF NOT EXISTS memories_search_vector_idx ON memories USING GIN (search_vector)",
+    // Backfill rows written before the trigger existed
+    "UPDATE memories SET content = content WHERE search_vector IS NULL",
+];
+
+/// Bumped when `SEARCH_INDEX_DDL` indexes rows differently, so existing
+/// rows are indexed again the next time the gateway starts
+const SEARCH_INDEX_VERSION: &str = "2";
+
+/// Schema of `memories`, applied in order by `connect`
+///
+/// Append new changes here rather than editing applied ones. Full-text
+/// search isn't listed: it needs DDL privileges some deployments lack, so
+/// `init_search_index` sets it up best effort instead.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create memories",
+        statements: &[
+            "CREATE EXTENSION IF NOT EXISTS vector",
+            r#"
+            CREATE TABLE IF NOT EXISTS memories (
+                id UUID PRIMARY KEY,
+                content TEXT NOT NULL,
+                embedding vector,
+                metadata JSONB,
+                tags TEXT[],
+                created_at BIGINT NOT NULL,
+                updated_at BIGINT NOT NULL
+            )
+            "#,
+        ],
+    },
+    // The column default backfills rows that predate per-user scoping with
+    // the nil UUID as their owner, and is dropped afterwards so new rows
+    // must always name theirs
+    Migration {
+        version: 2,
+        name: "user scope",
+        statements: &[
+            "ALTER TABLE memories ADD COLUMN IF NOT EXISTS user_id TEXT NOT NULL DEFAULT '00000000-0000-0000-0000-000000000000'",
+            "ALTER TABLE memories ALTER COLUMN user_id DROP DEFAULT",
+            "CREATE INDEX IF NOT EXISTS memories_user_id_idx ON memories (user_id, created_at DESC)",
+        ],
+    },
+    // Trashed rows keep `deleted_at` (unix seconds) until `purge_trashed`
+    // removes them; every read filters on `deleted_at IS NULL`
+    Migration {
+        version: 3,
+        name: "soft delete",
+        statements: &[
+            "ALTER TABLE memories ADD COLUMN IF NOT EXISTS deleted_at BIGINT",
+            "CREATE INDEX IF NOT EXISTS memories_deleted_at_idx ON memories (deleted_at) WHERE deleted_at IS NOT NULL",
+        ],
+    },
+    // Serve `MemoryFilter`'s tag and metadata containment checks
+    Migration {
+        version: 4,
+        name: "filter indexes",
+        statements: &[
+            "CREATE INDEX IF NOT EXISTS memories_tags_idx ON memories USING GIN (tags)",
+            "CREATE INDEX IF NOT EXISTS memories_metadata_idx ON memories USING GIN (metadata jsonb_path_ops)",
+        ],
+    },
+    // Facts about the stored memories as a whole, e.g. `embedding_dimension`
+    Migration {
+        version: 5,
+        name: "memory settings",
+        statements: &["CREATE TABLE IF NOT EXISTS memory_settings (key TEXT PRIMARY KEY, value TEXT NOT NULL)"],
+    },
+    // `dedup::content_hash` of memories stored with dedup, NULL for the rest;
+    // the index lets each hash name at most one live memory per user
+    Migration {
+        version: 6,
+        name: "content hash",
+        statements: &[
+            "ALTER TABLE memories ADD COLUMN IF NOT EXISTS content_hash TEXT",
+            "CREATE UNIQUE INDEX IF NOT EXISTS memories_content_hash_idx ON memories (user_id, content_hash) WHERE deleted_at IS NULL",
+        ],
+    },
+    // Set by users through `set_memory_flags`; searches can rank by them,
+    // and pinned memories are never evicted or trimmed to fit a quota
+    Migration {
+        version: 7,
+        name: "memory flags",
+        statements: &[
+            "ALTER TABLE memories ADD COLUMN IF NOT EXISTS pinned BOOLEAN NOT NULL DEFAULT FALSE",
+            "ALTER TABLE memories ADD COLUMN IF NOT EXISTS importance REAL NOT NULL DEFAULT 0",
+        ],
+    },
+    // Reads through `get_memory` and search, written in batches by
+    // `access::AccessTracker`; `last_accessed_at` is NULL until the first
+    Migration {
+        version: 8,
+        name: "access tracking",
+        statements: &[
+            "ALTER TABLE memories ADD COLUMN IF NOT EXISTS access_count BIGINT NOT NULL DEFAULT 0",
+            "ALTER TABLE memories ADD COLUMN IF NOT EXISTS last_accessed_at BIGINT",
+        ],
+    },
+    // Last memory re-embedded by a user's unfinished `Reindex`, so it can
+    // resume after a crash; removed once the run completes
+    Migration {
+        version: 9,
+        name: "reindex progress",
+        statements: &[
+            "CREATE TABLE IF NOT EXISTS reindex_progress (user_id TEXT PRIMARY KEY, cursor UUID NOT NULL, updated_at BIGINT NOT NULL)",
+        ],
+    },
+    // MIME type of the content; memories stored before it are plain text
+    Migration {
+        version: 10,
+        name: "content type",
+        statements: &[
+            "ALTER TABLE memories ADD COLUMN IF NOT EXISTS content_type TEXT NOT NULL DEFAULT 'text/plain'",
+        ],
+    },
+    // ISO 639-3 code from `lang::detect`; NULL when it couldn't tell
+    Migration {
+        version: 11,
+        name: "language",
+        statements: &["ALTER TABLE memories ADD COLUMN IF NOT EXISTS lang TEXT"],
+    },
+];
+
+/// Added to a pinned memory's similarity when searching with `boost_pinned`
+const PINNED_BOOST: f32 = 0.05;
+
+/// Added per unit of importance when searching with `boost_pinned`
+const IMPORTANCE_BOOST: f32 = 0.05;
+
+/// Score `search_by_embedding` ranks `memory` by when boosting pinned and
+/// important memories
+pub fn boosted_score(memory: &MemoryModel, similarity: f32) -> f32 {
+    similarity + if memory.pinned { PINNED_BOOST } else { 0.0 } + IMPORTANCE_BOOST * memory.importance
+}
+
+// Inserts nothing, returning no row, when a live memory of the user
+// already has the content hash
+const INSERT_MEMORY: &str = r#"
+    INSERT INTO memories (id, user_id, content, embedding, metadata, tags, created_at, updated_at, content_hash, content_type, lang)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+    ON CONFLICT (user_id, content_hash) WHERE deleted_at IS NULL DO NOTHING
+    RETURNING id
+"#;
+
+const MEMORY_WITH_CONTENT_HASH: &str = r#"
+    SELECT id FROM memories
+    WHERE user_id = $1 AND content_hash = $2 AND deleted_at IS NULL
+"#;
+
+// Live memories and their size, as counted against the storage quota
+const STORAGE_USAGE: &str = r#"
+    SELECT COUNT(*) AS total,
+           COALESCE(SUM(octet_length(content) + COALESCE(octet_length(metadata::text), 0)), 0)::BIGINT AS bytes
+    FROM memories
+    WHERE user_id = $1 AND deleted_at IS NULL
+"#;
+
+// Bytes new memories would add, counted as STORAGE_USAGE does
+const STORAGE_ADDED: &str = r#"
+    SELECT COALESCE(SUM(octet_length(content) + octet_length(metadata::jsonb::text)), 0)::BIGINT AS bytes
+    FROM UNNEST($1::text[], $2::text[]) AS added(content, metadata)
+"#;
+
+/// Why a write that adds to a user's storage failed
+#[derive(Debug, Error)]
+pub enum StoreError {
+    #[error(transparent)]
+    Quota(#[from] QuotaExceeded),
+
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+/// Narrows `query_memories`, `fts_search` and `search_by_embedding`
+///
+/// Empty fields don't filter. Tags use the array operators (`&&`, `@>`)
+/// and metadata JSONB containment, both served by GIN indexes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryFilter {
+    /// At least one of these tags
+    pub tags_any: Vec<String>,
+    /// Every one of these tags
+    pub tags_all: Vec<String>,
+    /// Each key present in metadata with exactly this value
+    pub metadata: BTreeMap<String, String>,
+    /// `created_at >= created_after`
+    pub created_after: Option<i64>,
+    /// `created_at < created_before`
+    pub created_before: Option<i64>,
+    /// Exactly this content type
+    pub content_type: Option<String>,
+    /// Written in this language, as an ISO 639-3 code; ranked search also
+    /// stems the query for it
+    pub lang: Option<String>,
+}
+
+impl MemoryFilter {
+    /// Append the `WHERE` clause scoping rows to `user_id`'s live memories
+    /// that pass this filter; callers continue with `AND ...`
+    fn push_where<'a>(&'a self, builder: &mut QueryBuilder<'a, Postgres>, user_id: &'a str) {
+        builder.push(" WHERE user_id = ").push_bind(user_id).push(" AND deleted_at IS NULL");
+        if !self.tags_any.is_empty() {
+            builder.push(" AND tags && ").push_bind(&self.tags_any);
+        }
+        if !self.tags_all.is_empty() {
+            builder.push(" AND tags @> ").push_bind(&self.tags_all);
+        }
+        if !self.metadata.is_empty() {
+            let metadata: serde_json::Map<String, Value> = self.metadata.iter()
+                .map(|(key, value)| (key.clone(), Value::String(value.clone())))
+                .collect();
+            builder.push(" AND metadata @> ").push_bind(Value::Object(metadata));
+        }
+        if let Some(created_after) = self.created_after {
+            builder.push(" AND created_at >= ").push_bind(created_after);
+        }
+        if let Some(created_before) = self.created_before {
+            builder.push(" AND created_at < ").push_bind(created_before);
+        }
+        if let Some(content_type) = &self.content_type {
+            builder.push(" AND content_type = ").push_bind(content_type);
+        }
+        if let Some(lang) = &self.lang {
+            builder.push(" AND lang = ").push_bind(lang);
+        }
+    }
+}
+
+/// A memory ready to be inserted, as taken by `store_memories_batch`
+#[derive(Debug, Clone)]
+pub struct NewMemory {
+    pub id: String,
+    pub content: String,
+    pub embedding: Vec<f32>,
+    pub metadata: HashMap<String, String>,
+    pub tags: Vec<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    /// `dedup::content_hash` of `content` to store it at most once; `None`
+    /// always inserts
+    pub content_hash: Option<String>,
+    /// MIME type, e.g. `content_type::MARKDOWN`
+    pub content_type: String,
+    /// `lang::detect` of `content`
+    pub lang: Option<String>,
+}
+
+/// Fields to change in `update_memory`; `None` leaves a column as is
+#[derive(Debug, Clone, Default)]
+pub struct MemoryUpdate {
+    pub content: Option<String>,
+    pub embedding: Option<Vec<f32>>,
+    pub metadata: Option<HashMap<String, String>>,
+    pub tags: Option<Vec<String>>,
+    /// `lang::detect` of `content`; ignored unless `content` is set
+    pub lang: Option<String>,
+    pub content_type: Option<String>,
+}
+
+/// Aggregates over one user's live memories, as returned by `memory_stats`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    pub total_count: i64,
+    /// `(tag, count)`, most used first
+    pub top_tags: Vec<(String, i64)>,
+    /// `None` when there are no memories
+    pub oldest_created_at: Option<i64>,
+    pub newest_created_at: Option<i64>,
+    /// Bytes of content plus serialized metadata
+    pub total_bytes: i64,
+}
 
 #[derive(Clone)]
 pub struct MemoryDatabase {
     pool: PgPool,
+    fts_enabled: bool,
+    quota: StorageQuota,
 }
 
 impl MemoryDatabase {
+    /// Connect with the default pool size and lock timeout
+    #[cfg(test)]
     pub async fn connect(database_url: &str) -> Result<Self, sqlx::Error> {
-        tracing::info!("🔌 Connecting to Supabase...");
+        Self::connect_with_options(database_url, None, None).await
+    }
+
+    /// Connect with up to `pool_size` connections (default `DEFAULT_POOL_SIZE`)
+    ///
+    /// Every query checks a connection out of the pool for its own duration,
+    /// so concurrent RPCs only contend once the pool is exhausted. Each
+    /// connection waits up to `lock_timeout` (default `DEFAULT_LOCK_TIMEOUT`,
+    /// zero for no limit) on locks held by other transactions, after which
+    /// the query fails as `is_transient`. The schema changes made here wait
+    /// on locks without limit.
+    pub async fn connect_with_options(
+        database_url: &str,
+        pool_size: Option<u32>,
+        lock_timeout: Option<Duration>,
+    ) -> Result<Self, sqlx::Error> {
+        let pool_size = pool_size.unwrap_or(DEFAULT_POOL_SIZE).max(1);
+        let lock_timeout = lock_timeout.unwrap_or(DEFAULT_LOCK_TIMEOUT);
+        tracing::info!("🔌 Connecting to Supabase (pool size {})...", pool_size);
         
+        let options = PgConnectOptions::from_str(database_url)?
+            .options([("lock_timeout", format!("{}ms", lock_timeout.as_millis()))]);
         let pool = PgPoolOptions::new()
-            .max_connections(10)
-            .connect(database_url)
+            .max_connections(pool_size)
+            .connect_with(options)
             .await?;
 
         tracing::info!("✅ Connected to Supabase Postgres.");
         
-        Ok(Self { pool })
+        let mut db = Self { pool, fts_enabled: false, quota: StorageQuota::default() };
+        migrations::migrate(&db.pool, "memories", MIGRATIONS).await?;
+        db.init_search_index().await;
+        Ok(db)
+    }
+
+    /// Create the full-text index, disabling ranked search if that fails
+    ///
+    /// Lacking privileges for DDL shouldn't stop the gateway from serving;
+    /// `fts_search` falls back to substring matching instead.
+    async fn init_search_index(&mut self) {
+        let result: Result<(), sqlx::Error> = async {
+            let mut tx = migrations::begin_schema_change(&self.pool).await?;
+            for statement in SEARCH_INDEX_DDL {
+                sqlx::query(statement).execute(&mut *tx).await?;
+            }
+            let version: Option<String> = sqlx::query_scalar("SELECT value FROM memory_settings WHERE key = 'search_index_version'")
+                .fetch_optional(&mut *tx)
+                .await?;
+            if version.as_deref() != Some(SEARCH_INDEX_VERSION) {
+                sqlx::query("UPDATE memories SET content = content").execute(&mut *tx).await?;
+                sqlx::query(
+                    "INSERT INTO memory_settings (key, value) VALUES ('search_index_version', $1) \
+                     ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value",
+                )
+                .bind(SEARCH_INDEX_VERSION)
+                .execute(&mut *tx)
+                .await?;
+            }
+            tx.commit().await
+        }
+        .await;
+
+        self.fts_enabled = match result {
+            Ok(()) => true,
+            Err(e) => {
+                tracing::warn!("Full-text search unavailable, using substring matching: {}", e);
+                false
+            }
+        };
+    }
+
+    /// Fail unless stored embeddings have `dimension` components
+    ///
+    /// The first call records `dimension`, after checking any rows that
+    /// predate the record agree with it. Embeddings of another length can't
+    /// be compared with new ones, so switching to a provider of a different
+    /// dimension needs the memories re-embedded first.
+    pub async fn check_embedding_dimension(&self, dimension: usize) -> Result<(), sqlx::Error> {
+        let mismatch = |found: String| sqlx::Error::Configuration(format!(
+            "Stored embeddings have {} dimensions but the embedding provider produces {}",
+            found, dimension
+        ).into());
+
+        let mut tx = migrations::begin_schema_change(&self.pool).await?;
+        sqlx::query("LOCK TABLE memory_settings IN EXCLUSIVE MODE").execute(&mut *tx).await?;
+        let recorded = sqlx::query("SELECT value FROM memory_settings WHERE key = 'embedding_dimension'")
+            .fetch_optional(&mut *tx)
+            .await?;
+        if let Some(row) = recorded {
+            let recorded: String = row.get("value");
+            return if recorded == dimension.to_string() { Ok(()) } else { Err(mismatch(recorded)) };
+        }
+
+        let rows = sqlx::query(
+            "SELECT DISTINCT array_length(embedding::real[], 1) AS dimension FROM memories WHERE embedding IS NOT NULL"
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        let found: Vec<i32> = rows.iter().map(|row| row.get("dimension")).collect();
+        if found.iter().any(|&d| d as usize != dimension) {
+            let found: Vec<String> = found.iter().map(|d| d.to_string()).collect();
+            return Err(mismatch(found.join(" and ")));
+        }
+
+        sqlx::query("INSERT INTO memory_settings (key, value) VALUES ('embedding_dimension', $1)")
+            .bind(dimension.to_string())
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await
+    }
+
+    /// Record `dimension` as that of the stored embeddings, replacing any earlier record
+    ///
+    /// Only for after every embedding has been rewritten, as `reembed` does.
+    pub async fn record_embedding_dimension(&self, dimension: usize) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO memory_settings (key, value) VALUES ('embedding_dimension', $1)
+            ON CONFLICT (key) DO UPDATE SET value = $1
+            "#
+        )
+        .bind(dimension.to_string())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// `(id, content, live)` of up to `limit` memories with ids after
+    /// `after`, in id order, of `user_id` or else of everyone
+    ///
+    /// Trashed memories are included: they can still be restored.
+    pub async fn memory_contents_after(
+        &self,
+        user_id: Option<&str>,
+        after: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<(String, String, bool)>, sqlx::Error> {
+        let _timer = crate::metrics::time_db_query("memory_contents_after");
+        let rows = sqlx::query(
+            r#"
+            SELECT id, content, deleted_at IS NULL AS live FROM memories
+            WHERE ($1::text IS NULL OR user_id = $1) AND id > $2
+            ORDER BY id LIMIT $3
+            "#
+        )
+        .bind(user_id)
+        .bind(cursor_uuid(after))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| {
+            (row.get::<Uuid, _>("id").to_string(), row.get("content"), row.get("live"))
+        }).collect())
+    }
+
+    /// Memories of `user_id`, trashed ones included, with ids after `after`
+    pub async fn count_memories_after(&self, user_id: &str, after: Option<&str>) -> Result<i64, sqlx::Error> {
+        let _timer = crate::metrics::time_db_query("count_memories_after");
+        let row = sqlx::query("SELECT COUNT(*) AS count FROM memories WHERE user_id = $1 AND id > $2")
+            .bind(user_id)
+            .bind(cursor_uuid(after))
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(row.get("count"))
+    }
+
+    /// Replace the embeddings of the memories named in `embeddings`, all or none
+    pub async fn replace_embeddings(&self, embeddings: &[(String, Vec<f32>)]) -> Result<(), sqlx::Error> {
+        let _timer = crate::metrics::time_db_query("replace_embeddings");
+        let mut tx = self.pool.begin().await?;
+        update_embeddings(&mut tx, embeddings).await?;
+        tx.commit().await
+    }
+
+    /// Where `user_id`'s unfinished reindex stopped, if it did
+    pub async fn reindex_cursor(&self, user_id: &str) -> Result<Option<String>, sqlx::Error> {
+        let row = sqlx::query("SELECT cursor FROM reindex_progress WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|row| row.get::<Uuid, _>("cursor").to_string()))
+    }
+
+    /// Replace embeddings of `user_id`'s memories, recording `cursor` as
+    /// reindexed in the same transaction
+    pub async fn reindex_batch(
+        &self,
+        user_id: &str,
+        embeddings: &[(String, Vec<f32>)],
+        cursor: &str,
+    ) -> Result<(), sqlx::Error> {
+        let _timer = crate::metrics::time_db_query("reindex_batch");
+        let mut tx = self.pool.begin().await?;
+        update_embeddings(&mut tx, embeddings).await?;
+        sqlx::query(
+            r#"
+            INSERT INTO reindex_progress (user_id, cursor, updated_at) VALUES ($1, $2, $3)
+            ON CONFLICT (user_id) DO UPDATE SET cursor = $2, updated_at = $3
+            "#
+        )
+        .bind(user_id)
+        .bind(cursor_uuid(Some(cursor)))
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await
+    }
+
+    /// Forget `user_id`'s reindex progress, once it is complete or to start over
+    pub async fn clear_reindex_cursor(&self, user_id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM reindex_progress WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Start a transaction for changes that must land together
+    pub async fn begin(&self) -> Result<Transaction<'static, Postgres>, sqlx::Error> {
+        self.pool.begin().await
+    }
+
+    /// Delete every memory owned by `user_id` within `tx`, returning how many
+    pub async fn delete_user_memories(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        user_id: &str,
+    ) -> Result<u64, sqlx::Error> {
+        let _timer = crate::metrics::time_db_query("delete_user_memories");
+        let result = sqlx::query("DELETE FROM memories WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut **tx)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// False when ranked search couldn't be set up and text queries match substrings
+    pub fn fts_enabled(&self) -> bool {
+        self.fts_enabled
+    }
+
+    /// Wrap `pool` without migrating, for tests that never reach the database
+    #[cfg(test)]
+    pub fn from_pool(pool: PgPool) -> Self {
+        Self { pool, fts_enabled: false, quota: StorageQuota::default() }
+    }
+
+    /// Limit what each user may store; writes past it fail with `StoreError::Quota`
+    pub fn with_storage_quota(mut self, quota: StorageQuota) -> Self {
+        self.quota = quota;
+        self
+    }
+
+    pub fn storage_quota(&self) -> StorageQuota {
+        self.quota
+    }
+
+    /// Serialize `user_id`'s writes until `tx` ends and return their usage
+    ///
+    /// `None`, taking no lock, when there is no quota to enforce.
+    async fn lock_storage_usage(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        user_id: &str,
+    ) -> Result<Option<StorageUsage>, sqlx::Error> {
+        if self.quota.is_unlimited() {
+            return Ok(None);
+        }
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext('memory_quota'), hashtext($1))")
+            .bind(user_id)
+            .execute(&mut **tx)
+            .await?;
+        Ok(Some(storage_usage(tx, user_id).await?))
+    }
+
+    /// Fail if what `tx` wrote since `lock_storage_usage` returned `before`
+    /// takes `user_id` over the quota
+    ///
+    /// Measuring after the fact counts bytes exactly as `memory_stats` does,
+    /// and the caller dropping `tx` on error rejects the write as a whole.
+    async fn check_storage_quota(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        user_id: &str,
+        before: Option<StorageUsage>,
+    ) -> Result<(), StoreError> {
+        let Some(before) = before else {
+            return Ok(());
+        };
+        let after = storage_usage(tx, user_id).await?;
+        let added = StorageUsage {
+            memories: after.memories - before.memories,
+            bytes: after.bytes - before.bytes,
+        };
+        self.quota.check(before, added)?;
+        Ok(())
+    }
+
+    /// `user_id`'s storage usage, and what storing `memories` (content and
+    /// metadata) would add to it
+    ///
+    /// Reads only, taking no lock, so the answer can be stale by the time
+    /// anything is stored.
+    pub async fn projected_storage(
+        &self,
+        user_id: &str,
+        memories: &[(&str, &HashMap<String, String>)],
+    ) -> Result<(StorageUsage, StorageUsage), sqlx::Error> {
+        let _timer = crate::metrics::time_db_query("projected_storage");
+        let contents: Vec<&str> = memories.iter().map(|(content, _)| *content).collect();
+        let metadata: Vec<String> = memories.iter()
+            .map(|(_, metadata)| serde_json::to_string(metadata).unwrap())
+            .collect();
+
+        let mut tx = self.pool.begin().await?;
+        let usage = storage_usage(&mut tx, user_id).await?;
+        let row = sqlx::query(STORAGE_ADDED)
+            .bind(&contents)
+            .bind(&metadata)
+            .fetch_one(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        let added = StorageUsage { memories: memories.len() as i64, bytes: row.get("bytes") };
+        Ok((usage, added))
+    }
+
+    /// Handle to the underlying pool, for stores sharing the connection
+    pub fn pool(&self) -> PgPool {
+        self.pool.clone()
+    }
+
+    /// Run `f`'s writes as one transaction
+    ///
+    /// `f` gets the store, update and delete methods of `MemoryTransaction`
+    /// and returns a boxed future; its writes are committed if that yields
+    /// `Ok` and rolled back if it yields `Err`. Unlike `begin`, callers
+    /// can't forget to commit.
+    pub async fn transaction<'a, T, E, F>(&'a self, f: F) -> Result<T, E>
+    where
+        F: for<'t> FnOnce(&'t mut MemoryTransaction<'a>) -> TransactionFuture<'t, T, E>,
+        E: From<sqlx::Error>,
+    {
+        let mut tx = MemoryTransaction { db: self, tx: self.pool.begin().await? };
+        match f(&mut tx).await {
+            Ok(value) => {
+                tx.tx.commit().await?;
+                Ok(value)
+            }
+            Err(e) => {
+                tx.tx.rollback().await?;
+                Err(e)
+            }
+        }
     }
 
+    /// Store a plain-text memory; the service goes through
+    /// `store_memory_deduplicated`, which also takes a content type
+    #[cfg(test)]
+    #[allow(clippy::too_many_arguments)]
     pub async fn store_memory(
         &self,
+        user_id: &str,
         id: &str,
         content: &str,
         embedding: &[f32],
@@ -35,67 +765,227 @@ impl MemoryDatabase {
         tags: &[String],
         created_at: i64,
         updated_at: i64,
-    ) -> Result<(), sqlx::Error> {
+    ) -> Result<(), StoreError> {
+        let _timer = crate::metrics::time_db_query("store_memory");
         let uuid = Uuid::parse_str(id).unwrap_or_default();
         let metadata_json = serde_json::to_value(metadata).unwrap();
 
+        let mut tx = self.pool.begin().await?;
+        let before = self.lock_storage_usage(&mut tx, user_id).await?;
         // Use pgvector syntax for insertion
-        sqlx::query(
-            r#"
-            INSERT INTO memories (id, content, embedding, metadata, tags, created_at, updated_at)
-            VALUES ($1, $2, $3, $4, $5, $6, $7)
-            "#
-        )
+        sqlx::query(INSERT_MEMORY)
         .bind(uuid)
+        .bind(user_id)
         .bind(content)
         .bind(embedding) 
         .bind(metadata_json)
         .bind(tags)
         .bind(created_at)
         .bind(updated_at)
-        .execute(&self.pool)
+        .bind(None::<&str>)
+        .bind(crate::content_type::PLAIN)
+        .bind(crate::lang::detect(content))
+        .execute(&mut *tx)
         .await?;
+        self.check_storage_quota(&mut tx, user_id, before).await?;
+        tx.commit().await?;
 
         Ok(())
     }
 
-    pub async fn search_memories(
+    /// Store `memory` unless `user_id` already has a live memory with its
+    /// `content_hash`
+    ///
+    /// Returns the id of the memory holding the content: `memory.id` if it
+    /// was inserted, the existing memory's otherwise.
+    pub async fn store_memory_deduplicated(&self, user_id: &str, memory: &NewMemory) -> Result<String, StoreError> {
+        let _timer = crate::metrics::time_db_query("store_memory");
+        self.transaction(|tx| Box::pin(tx.store_memory(user_id, memory))).await
+    }
+
+    /// Id of the live memory `user_id` stored with `content_hash`, if any
+    pub async fn memory_with_content_hash(&self, user_id: &str, content_hash: &str) -> Result<Option<String>, sqlx::Error> {
+        let _timer = crate::metrics::time_db_query("memory_with_content_hash");
+        let row = sqlx::query(MEMORY_WITH_CONTENT_HASH)
+            .bind(user_id)
+            .bind(content_hash)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|row| row.get::<Uuid, _>("id").to_string()))
+    }
+
+    /// Insert many memories in one transaction
+    ///
+    /// Each row goes through its own savepoint, so a failing row is rolled
+    /// back alone and reported in its slot of the returned vector (same order
+    /// as `memories`), as is the id holding each stored row's content (see
+    /// `store_memory_deduplicated`). Only a failure to begin or commit the
+    /// transaction fails the whole call, or the rows that went in taking the
+    /// user over the storage quota, in which case none are kept.
+    pub async fn store_memories_batch(
         &self,
-        embedding: &[f32],
-        limit: i32,
+        user_id: &str,
+        memories: &[NewMemory],
+    ) -> Result<Vec<Result<String, sqlx::Error>>, StoreError> {
+        let _timer = crate::metrics::time_db_query("store_memories_batch");
+        let mut tx = self.pool.begin().await?;
+        let before = self.lock_storage_usage(&mut tx, user_id).await?;
+        let mut results = Vec::with_capacity(memories.len());
+
+        for memory in memories {
+            let uuid = match Uuid::parse_str(&memory.id) {
+                Ok(uuid) => uuid,
+                Err(e) => {
+                    results.push(Err(sqlx::Error::Decode(Box::new(e))));
+                    continue;
+                }
+            };
+
+            let mut savepoint = sqlx::Acquire::begin(&mut *tx).await?;
+            let inserted = insert_memory(&mut savepoint, user_id, uuid, memory).await;
+
+            match inserted {
+                Ok(id) => {
+                    savepoint.commit().await?;
+                    results.push(Ok(id));
+                }
+                Err(e) => {
+                    savepoint.rollback().await?;
+                    results.push(Err(e));
+                }
+            }
+        }
+
+        self.check_storage_quota(&mut tx, user_id, before).await?;
+        tx.commit().await?;
+        Ok(results)
+    }
+
+    /// Brute-force cosine search scored in a single pass over `memories`
+    ///
+    /// Only rows passing `filter` are scored. Each row's similarity is
+    /// computed once in SQL; `ORDER BY ... LIMIT` lets Postgres keep a
+    /// bounded top-N heap, so neither side ever holds more than `limit` rows.
+    /// The scoring is Postgres's CPU, not the gateway's: a large scan is
+    /// spread over its parallel workers (`max_parallel_workers_per_gather`).
+    /// With `boost_pinned` rows are ranked by `boosted_score`; the threshold
+    /// still applies to plain similarity.
+    pub async fn search_by_embedding(
+        &self,
+        user_id: &str,
+        query: &[f32],
+        filter: &MemoryFilter,
         threshold: f32,
-    ) -> Result<Vec<MemoryModel>, sqlx::Error> {
-        // Native Vector Search: 1 - (embedding <=> query)
+        limit: usize,
+        boost_pinned: bool,
+    ) -> Result<Vec<(MemoryModel, f32)>, sqlx::Error> {
+        let _timer = crate::metrics::time_db_query("search_by_embedding");
+        let mut builder = QueryBuilder::new(
+            "SELECT id, content, metadata, tags, created_at, updated_at, pinned, importance, access_count, last_accessed_at, content_type, lang, similarity FROM ( \
+             SELECT id, content, metadata, tags, created_at, updated_at, pinned, importance, access_count, last_accessed_at, content_type, lang, (1 - (embedding <=> "
+        );
+        builder.push_bind(query).push("::vector))::real AS similarity FROM memories");
+        filter.push_where(&mut builder, user_id);
+        builder.push(") scored WHERE similarity > ").push_bind(threshold);
+        builder.push(" ORDER BY similarity");
+        if boost_pinned {
+            builder.push(" + CASE WHEN pinned THEN ").push_bind(PINNED_BOOST)
+                .push(" ELSE 0 END + importance * ").push_bind(IMPORTANCE_BOOST);
+        }
+        builder.push(" DESC LIMIT ").push_bind(limit as i64);
+
+        let rows = builder.build().fetch_all(&self.pool).await?;
+        self.map_scored_rows(rows)
+    }
+
+    /// Exact similarity of each of `candidates` that passes `filter`, best first
+    ///
+    /// Rescores what the ANN index suggested; ids that are trashed, gone or
+    /// not `user_id`'s are dropped.
+    pub async fn score_candidates(
+        &self,
+        user_id: &str,
+        query: &[f32],
+        filter: &MemoryFilter,
+        candidates: &[String],
+    ) -> Result<Vec<(MemoryModel, f32)>, sqlx::Error> {
+        let _timer = crate::metrics::time_db_query("score_candidates");
+        let ids: Vec<Uuid> = candidates.iter().filter_map(|id| Uuid::parse_str(id).ok()).collect();
+        let mut builder = QueryBuilder::new(
+            "SELECT id, content, metadata, tags, created_at, updated_at, pinned, importance, access_count, last_accessed_at, content_type, lang, (1 - (embedding <=> "
+        );
+        builder.push_bind(query).push("::vector))::real AS similarity FROM memories");
+        filter.push_where(&mut builder, user_id);
+        builder.push(" AND id = ANY(").push_bind(ids).push(") ORDER BY similarity DESC");
+
+        let rows = builder.build().fetch_all(&self.pool).await?;
+        self.map_scored_rows(rows)
+    }
+
+    /// Similarity of each of `user_id`'s pinned memories that passes `filter`
+    pub async fn score_pinned(
+        &self,
+        user_id: &str,
+        query: &[f32],
+        filter: &MemoryFilter,
+    ) -> Result<Vec<(MemoryModel, f32)>, sqlx::Error> {
+        let _timer = crate::metrics::time_db_query("score_pinned");
+        let mut builder = QueryBuilder::new(
+            "SELECT id, content, metadata, tags, created_at, updated_at, pinned, importance, access_count, last_accessed_at, content_type, lang, (1 - (embedding <=> "
+        );
+        builder.push_bind(query).push("::vector))::real AS similarity FROM memories");
+        filter.push_where(&mut builder, user_id);
+        builder.push(" AND pinned ORDER BY similarity DESC");
+
+        let rows = builder.build().fetch_all(&self.pool).await?;
+        self.map_scored_rows(rows)
+    }
+
+    /// `(user_id, id, embedding)` of every live memory, to build the ANN index from
+    pub async fn live_embeddings(&self) -> Result<Vec<(String, String, Vec<f32>)>, sqlx::Error> {
+        let _timer = crate::metrics::time_db_query("live_embeddings");
         let rows = sqlx::query(
-            r#"
-            SELECT id, content, metadata, tags, created_at, updated_at
-            FROM memories
-            WHERE 1 - (embedding <=> $1) > $2
-            ORDER BY embedding <=> $1
-            LIMIT $3
-            "#
+            "SELECT user_id, id, embedding::real[] AS embedding FROM memories WHERE deleted_at IS NULL AND embedding IS NOT NULL"
         )
-        .bind(embedding)
-        .bind(threshold)
-        .bind(limit)
         .fetch_all(&self.pool)
         .await?;
 
-        self.map_rows(rows)
+        Ok(rows.into_iter().map(|row| {
+            let id: Uuid = row.get("id");
+            (row.get("user_id"), id.to_string(), row.get("embedding"))
+        }).collect())
+    }
+
+    /// Stored embedding of one of `user_id`'s live memories
+    pub async fn get_embedding(&self, user_id: &str, id: &str) -> Result<Option<Vec<f32>>, sqlx::Error> {
+        let _timer = crate::metrics::time_db_query("get_embedding");
+        let uuid = Uuid::parse_str(id).unwrap_or_default();
+        let row = sqlx::query(
+            "SELECT embedding::real[] AS embedding FROM memories WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL"
+        )
+        .bind(uuid)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.and_then(|row| row.get("embedding")))
     }
 
     // NEW: Fetch recent memories sorted by time
-    pub async fn get_recent_memories(&self, limit: i32) -> Result<Vec<MemoryModel>, sqlx::Error> {
+    pub async fn get_recent_memories(&self, user_id: &str, limit: i32) -> Result<Vec<MemoryModel>, sqlx::Error> {
+        let _timer = crate::metrics::time_db_query("get_recent_memories");
         let limit = if limit <= 0 { 50 } else { limit };
         
         let rows = sqlx::query(
             r#"
-            SELECT id, content, metadata, tags, created_at, updated_at 
+            SELECT id, content, metadata, tags, created_at, updated_at, pinned, importance, access_count, last_accessed_at, content_type, lang 
             FROM memories 
+            WHERE user_id = $1 AND deleted_at IS NULL
             ORDER BY created_at DESC 
-            LIMIT $1
+            LIMIT $2
             "#
         )
+        .bind(user_id)
         .bind(limit)
         .fetch_all(&self.pool)
         .await?;
@@ -103,10 +993,12 @@ impl MemoryDatabase {
         self.map_rows(rows)
     }
 
-    pub async fn get_memory(&self, id: &str) -> Result<Option<MemoryModel>, sqlx::Error> {
+    pub async fn get_memory(&self, user_id: &str, id: &str) -> Result<Option<MemoryModel>, sqlx::Error> {
+        let _timer = crate::metrics::time_db_query("get_memory");
         let uuid = Uuid::parse_str(id).unwrap_or_default();
-        let row = sqlx::query("SELECT id, content, metadata, tags, created_at, updated_at FROM memories WHERE id = $1")
+        let row = sqlx::query("SELECT id, content, metadata, tags, created_at, updated_at, pinned, importance, access_count, last_accessed_at, content_type, lang FROM memories WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL")
             .bind(uuid)
+            .bind(user_id)
             .fetch_optional(&self.pool)
             .await?;
             
@@ -120,45 +1012,1535 @@ impl MemoryDatabase {
         }
     }
 
-    pub async fn query_memories(&self, query: &str, limit: i32) -> Result<Vec<MemoryModel>, sqlx::Error> {
-        let pattern = format!("%{}%", query);
-        let rows = sqlx::query(
-            "SELECT id, content, metadata, tags, created_at, updated_at FROM memories WHERE content ILIKE $1 LIMIT $2"
-        )
-        .bind(pattern)
-        .bind(limit)
-        .fetch_all(&self.pool)
-        .await?;
-        
+    /// Newest first, or with `boost_pinned` pinned memories first, then
+    /// the more important
+    pub async fn query_memories(
+        &self,
+        user_id: &str,
+        query: &str,
+        filter: &MemoryFilter,
+        limit: i64,
+        offset: i64,
+        boost_pinned: bool,
+    ) -> Result<Vec<MemoryModel>, sqlx::Error> {
+        let _timer = crate::metrics::time_db_query("query_memories");
+        let mut builder = QueryBuilder::new("SELECT id, content, metadata, tags, created_at, updated_at, pinned, importance, access_count, last_accessed_at, content_type, lang FROM memories");
+        filter.push_where(&mut builder, user_id);
+        builder.push(" AND content ILIKE ").push_bind(format!("%{}%", query));
+        builder.push(" ORDER BY ").push(pinned_first(boost_pinned)).push("created_at DESC, id LIMIT ").push_bind(limit);
+        builder.push(" OFFSET ").push_bind(offset);
+
+        let rows = builder.build().fetch_all(&self.pool).await?;
+        self.map_rows(rows)
+    }
+
+    /// Number of memories `search_by_embedding` scores for `filter`
+    pub async fn count_search_candidates(&self, user_id: &str, filter: &MemoryFilter) -> Result<i64, sqlx::Error> {
+        let _timer = crate::metrics::time_db_query("count_search_candidates");
+        let mut builder = QueryBuilder::new("SELECT COUNT(*) AS total FROM memories");
+        filter.push_where(&mut builder, user_id);
+
+        let row = builder.build().fetch_one(&self.pool).await?;
+        Ok(row.get("total"))
+    }
+
+    /// Number of memories matching `query` (same predicate as `query_memories`)
+    pub async fn count_memories(&self, user_id: &str, query: &str, filter: &MemoryFilter) -> Result<i64, sqlx::Error> {
+        let _timer = crate::metrics::time_db_query("count_memories");
+        let mut builder = QueryBuilder::new("SELECT COUNT(*) AS total FROM memories");
+        filter.push_where(&mut builder, user_id);
+        builder.push(" AND content ILIKE ").push_bind(format!("%{}%", query));
+
+        let row = builder.build().fetch_one(&self.pool).await?;
+        Ok(row.get("total"))
+    }
+
+    /// Ranked full-text search over content and tags
+    ///
+    /// Supports web-search syntax (`"exact phrase"`, `-exclude`, `or`).
+    /// Falls back to `query_memories` when the index is unavailable or the
+    /// query is empty. `boost_pinned` works as there.
+    pub async fn fts_search(
+        &self,
+        user_id: &str,
+        query: &str,
+        filter: &MemoryFilter,
+        limit: i64,
+        offset: i64,
+        boost_pinned: bool,
+    ) -> Result<Vec<MemoryModel>, sqlx::Error> {
+        if !self.fts_enabled || query.trim().is_empty() {
+            return self.query_memories(user_id, query, filter, limit, offset, boost_pinned).await;
+        }
+
+        let _timer = crate::metrics::time_db_query("fts_search");
+        let mut builder = QueryBuilder::new(
+            "SELECT id, content, metadata, tags, created_at, updated_at, pinned, importance, access_count, last_accessed_at, content_type, lang FROM memories, memories_search_query("
+        );
+        builder.push_bind(filter.lang.as_deref()).push(", ").push_bind(query).push(") AS q");
+        filter.push_where(&mut builder, user_id);
+        builder.push(" AND search_vector @@ q ORDER BY ").push(pinned_first(boost_pinned));
+        builder.push("ts_rank_cd(search_vector, q) DESC, created_at DESC, id LIMIT ");
+        builder.push_bind(limit).push(" OFFSET ").push_bind(offset);
+
+        let rows = builder.build().fetch_all(&self.pool).await?;
         self.map_rows(rows)
     }
 
-    pub async fn delete_memory(&self, id: &str) -> Result<bool, sqlx::Error> {
+    /// Number of memories matching `query` (same predicate as `fts_search`)
+    pub async fn count_fts_matches(&self, user_id: &str, query: &str, filter: &MemoryFilter) -> Result<i64, sqlx::Error> {
+        if !self.fts_enabled || query.trim().is_empty() {
+            return self.count_memories(user_id, query, filter).await;
+        }
+
+        let _timer = crate::metrics::time_db_query("count_fts_matches");
+        let mut builder = QueryBuilder::new("SELECT COUNT(*) AS total FROM memories");
+        filter.push_where(&mut builder, user_id);
+        builder.push(" AND search_vector @@ memories_search_query(").push_bind(filter.lang.as_deref())
+            .push(", ").push_bind(query).push(")");
+
+        let row = builder.build().fetch_one(&self.pool).await?;
+        Ok(row.get("total"))
+    }
+
+    /// Apply `update` to a memory, keeping its id and `created_at`
+    ///
+    /// Returns the updated memory, or `None` if the caller owns no memory
+    /// with that id. New content drops the memory's `content_hash`, so later
+    /// dedup stores no longer resolve to it.
+    pub async fn update_memory(
+        &self,
+        user_id: &str,
+        id: &str,
+        update: &MemoryUpdate,
+        updated_at: i64,
+    ) -> Result<Option<MemoryModel>, StoreError> {
+        let _timer = crate::metrics::time_db_query("update_memory");
+        self.transaction(|tx| Box::pin(tx.update_memory(user_id, id, update, updated_at))).await
+    }
+
+    /// Pin or unpin a memory, or set its importance; `None` leaves a flag as is
+    ///
+    /// Returns the updated memory, or `None` if the caller owns no memory
+    /// with that id.
+    pub async fn set_memory_flags(
+        &self,
+        user_id: &str,
+        id: &str,
+        pinned: Option<bool>,
+        importance: Option<f32>,
+        updated_at: i64,
+    ) -> Result<Option<MemoryModel>, sqlx::Error> {
+        let _timer = crate::metrics::time_db_query("set_memory_flags");
         let uuid = Uuid::parse_str(id).unwrap_or_default();
-        let result = sqlx::query("DELETE FROM memories WHERE id = $1")
-            .bind(uuid)
-            .execute(&self.pool)
-            .await?;
-        Ok(result.rows_affected() > 0)
+        let row = sqlx::query(
+            r#"
+            UPDATE memories SET
+                pinned = COALESCE($3, pinned),
+                importance = COALESCE($4, importance),
+                updated_at = $5
+            WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL
+            RETURNING id, content, metadata, tags, created_at, updated_at, pinned, importance, access_count, last_accessed_at, content_type, lang
+            "#
+        )
+        .bind(uuid)
+        .bind(user_id)
+        .bind(pinned)
+        .bind(importance)
+        .bind(updated_at)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match row {
+            Some(row) => Ok(self.map_rows(vec![row])?.pop()),
+            None => Ok(None),
+        }
     }
 
-    // Helper to map SQL rows to Rust structs
-    fn map_rows(&self, rows: Vec<sqlx::postgres::PgRow>) -> Result<Vec<MemoryModel>, sqlx::Error> {
-        let results = rows.into_iter().map(|row| {
-            let id: Uuid = row.get("id");
-            let meta_val: Value = row.get("metadata");
-            let metadata: HashMap<String, String> = serde_json::from_value(meta_val).unwrap_or_default();
-
-            MemoryModel {
-                id: id.to_string(),
-                content: row.get("content"),
-                metadata,
-                embedding: vec![], // Optimization: Don't return vector to client
-                tags: row.get::<Option<Vec<String>>, _>("tags").unwrap_or_default(),
-                created_at: row.get("created_at"),
-                updated_at: row.get("updated_at"),
-            }
-        }).collect();
-        Ok(results)
+    /// Add `(user_id, memory_id, reads, last_read_at)` to the memories' access counts
+    ///
+    /// One statement for the whole batch. Memories deleted meanwhile are
+    /// skipped, and `last_accessed_at` never moves backwards.
+    pub async fn record_accesses(&self, accesses: &[(String, String, i64, i64)]) -> Result<(), sqlx::Error> {
+        let _timer = crate::metrics::time_db_query("record_accesses");
+        let mut ids = Vec::with_capacity(accesses.len());
+        let mut user_ids = Vec::with_capacity(accesses.len());
+        let mut counts = Vec::with_capacity(accesses.len());
+        let mut lasts = Vec::with_capacity(accesses.len());
+        for (user_id, id, count, last) in accesses {
+            let Ok(uuid) = Uuid::parse_str(id) else { continue };
+            ids.push(uuid);
+            user_ids.push(user_id.as_str());
+            counts.push(*count);
+            lasts.push(*last);
+        }
+
+        sqlx::query(
+            r#"
+            UPDATE memories SET
+                access_count = memories.access_count + reads.count,
+                last_accessed_at = GREATEST(memories.last_accessed_at, reads.last)
+            FROM UNNEST($1::uuid[], $2::text[], $3::bigint[], $4::bigint[]) AS reads(id, user_id, count, last)
+            WHERE memories.id = reads.id AND memories.user_id = reads.user_id
+            "#
+        )
+        .bind(ids)
+        .bind(user_ids)
+        .bind(counts)
+        .bind(lasts)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
     }
-}
\ No newline at end of file
+
+    /// Every live memory of `user_id`, oldest first, in export form
+    ///
+    /// Rows are streamed from Postgres rather than fetched in one result set.
+    pub async fn export_memories(&self, user_id: &str) -> Result<Vec<ExportedMemory>, sqlx::Error> {
+        let _timer = crate::metrics::time_db_query("export_memories");
+        let mut rows = sqlx::query(
+            r#"
+            SELECT id, content, metadata, tags, created_at, updated_at, pinned, importance, access_count, last_accessed_at, content_type, lang
+            FROM memories
+            WHERE user_id = $1 AND deleted_at IS NULL
+            ORDER BY created_at, id
+            "#
+        )
+        .bind(user_id)
+        .fetch(&self.pool);
+
+        let mut memories = Vec::new();
+        while let Some(row) = rows.next().await {
+            let m = self.map_rows(vec![row?])?.remove(0);
+            memories.push(ExportedMemory {
+                content: m.content,
+                metadata: m.metadata,
+                tags: m.tags,
+                created_at: m.created_at,
+                updated_at: m.updated_at,
+                content_type: m.content_type,
+            });
+        }
+        Ok(memories)
+    }
+
+    /// Insert `memories` all or nothing, unlike `store_memories_batch`
+    pub async fn import_memories(&self, user_id: &str, memories: &[NewMemory]) -> Result<(), StoreError> {
+        let _timer = crate::metrics::time_db_query("import_memories");
+        let mut tx = self.pool.begin().await?;
+        let before = self.lock_storage_usage(&mut tx, user_id).await?;
+        for memory in memories {
+            let uuid = Uuid::parse_str(&memory.id).map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+            insert_memory(&mut tx, user_id, uuid, memory).await?;
+        }
+        self.check_storage_quota(&mut tx, user_id, before).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Totals for `user_id` plus its `top_tags` most used tags
+    pub async fn memory_stats(&self, user_id: &str, top_tags: i64) -> Result<MemoryStats, sqlx::Error> {
+        let _timer = crate::metrics::time_db_query("memory_stats");
+        let row = sqlx::query(
+            r#"
+            SELECT COUNT(*) AS total,
+                   MIN(created_at) AS oldest,
+                   MAX(created_at) AS newest,
+                   COALESCE(SUM(octet_length(content) + COALESCE(octet_length(metadata::text), 0)), 0)::BIGINT AS bytes
+            FROM memories
+            WHERE user_id = $1 AND deleted_at IS NULL
+            "#
+        )
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        let tag_rows = sqlx::query(
+            r#"
+            SELECT tag, COUNT(*) AS total
+            FROM memories, unnest(tags) AS tag
+            WHERE user_id = $1 AND deleted_at IS NULL
+            GROUP BY tag
+            ORDER BY total DESC, tag
+            LIMIT $2
+            "#
+        )
+        .bind(user_id)
+        .bind(top_tags)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(MemoryStats {
+            total_count: row.get("total"),
+            top_tags: tag_rows.iter().map(|r| (r.get("tag"), r.get("total"))).collect(),
+            oldest_created_at: row.get("oldest"),
+            newest_created_at: row.get("newest"),
+            total_bytes: row.get("bytes"),
+        })
+    }
+
+    /// Move a memory to the trash, hiding it from every query
+    ///
+    /// Returns false if the caller owns no live memory with that id.
+    pub async fn delete_memory(&self, user_id: &str, id: &str, deleted_at: i64) -> Result<bool, sqlx::Error> {
+        let _timer = crate::metrics::time_db_query("delete_memory");
+        self.transaction(|tx| Box::pin(tx.delete_memory(user_id, id, deleted_at))).await
+    }
+
+    /// Take a memory back out of the trash if it was deleted at or after
+    /// `deleted_since`
+    ///
+    /// Returns the restored memory, or `None` if there is nothing to restore
+    /// (not trashed, not the caller's, or past the retention window). It
+    /// loses its `content_hash` if a live memory took that over meanwhile.
+    pub async fn restore_memory(
+        &self,
+        user_id: &str,
+        id: &str,
+        deleted_since: i64,
+    ) -> Result<Option<MemoryModel>, StoreError> {
+        let _timer = crate::metrics::time_db_query("restore_memory");
+        let uuid = Uuid::parse_str(id).unwrap_or_default();
+        // Restored memories count against the quota again
+        let mut tx = self.pool.begin().await?;
+        let before = self.lock_storage_usage(&mut tx, user_id).await?;
+        let row = sqlx::query(
+            r#"
+            UPDATE memories SET
+                deleted_at = NULL,
+                content_hash = CASE WHEN EXISTS (
+                    SELECT 1 FROM memories live
+                    WHERE live.user_id = $2 AND live.content_hash = memories.content_hash AND live.deleted_at IS NULL
+                ) THEN NULL ELSE content_hash END
+            WHERE id = $1 AND user_id = $2 AND deleted_at >= $3
+            RETURNING id, content, metadata, tags, created_at, updated_at, pinned, importance, access_count, last_accessed_at, content_type, lang
+            "#
+        )
+        .bind(uuid)
+        .bind(user_id)
+        .bind(deleted_since)
+        .fetch_optional(&mut *tx)
+        .await?;
+        self.check_storage_quota(&mut tx, user_id, before).await?;
+        tx.commit().await?;
+
+        match row {
+            Some(row) => Ok(self.map_rows(vec![row])?.pop()),
+            None => Ok(None),
+        }
+    }
+
+    /// Permanently delete memories trashed before `deleted_before`
+    pub async fn purge_trashed(&self, deleted_before: i64) -> Result<u64, sqlx::Error> {
+        let _timer = crate::metrics::time_db_query("purge_trashed");
+        let result = sqlx::query("DELETE FROM memories WHERE deleted_at < $1")
+            .bind(deleted_before)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Queries currently holding a pooled connection
+    pub fn busy_connections(&self) -> usize {
+        (self.pool.size() as usize).saturating_sub(self.pool.num_idle())
+    }
+
+    /// `VACUUM (ANALYZE)` the memories table, returning the bytes it shrank by
+    ///
+    /// A plain vacuum, not `VACUUM FULL`: writers carry on while it runs, and
+    /// trailing empty pages are only handed back to the OS when their lock is
+    /// free at once. Returns `None` without vacuuming when another gateway on
+    /// the same schema is already compacting.
+    pub async fn compact(&self) -> Result<Option<i64>, sqlx::Error> {
+        let _timer = crate::metrics::time_db_query("compact");
+        let mut conn = self.pool.acquire().await?;
+        let locked: bool = sqlx::query_scalar("SELECT pg_try_advisory_lock(hashtext('memory_compaction'), hashtext(current_schema()))")
+            .fetch_one(&mut *conn)
+            .await?;
+        if !locked {
+            return Ok(None);
+        }
+
+        // VACUUM refuses to run in a transaction, which the extended protocol
+        // implies, so it goes through the simple one
+        let vacuum = async {
+            let before = Self::table_size(&mut conn).await?;
+            sqlx::Executor::execute(&mut *conn, "VACUUM (ANALYZE) memories").await?;
+            let after = Self::table_size(&mut conn).await?;
+            Ok(before - after)
+        }
+        .await;
+        sqlx::query("SELECT pg_advisory_unlock(hashtext('memory_compaction'), hashtext(current_schema()))")
+            .execute(&mut *conn)
+            .await?;
+        vacuum.map(Some)
+    }
+
+    /// On-disk size of the memories table with its indexes and TOAST
+    async fn table_size(conn: &mut PgConnection) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar("SELECT pg_total_relation_size('memories')")
+            .fetch_one(conn)
+            .await
+    }
+
+    // Helper to map SQL rows to Rust structs
+    /// `map_rows` for queries that also select `similarity`
+    fn map_scored_rows(&self, rows: Vec<sqlx::postgres::PgRow>) -> Result<Vec<(MemoryModel, f32)>, sqlx::Error> {
+        let scores: Vec<f32> = rows.iter().map(|row| row.get("similarity")).collect();
+        let memories = self.map_rows(rows)?;
+        Ok(memories.into_iter().zip(scores).collect())
+    }
+
+    fn map_rows(&self, rows: Vec<sqlx::postgres::PgRow>) -> Result<Vec<MemoryModel>, sqlx::Error> {
+        Ok(rows.iter().map(MemoryModel::from).collect())
+    }
+}
+
+impl From<&sqlx::postgres::PgRow> for MemoryModel {
+    /// A row selecting the memory columns, `embedding` aside
+    fn from(row: &sqlx::postgres::PgRow) -> Self {
+        let id: Uuid = row.get("id");
+        let meta_val: Value = row.get("metadata");
+        let metadata: HashMap<String, String> = serde_json::from_value(meta_val).unwrap_or_default();
+
+        MemoryModel {
+            id: id.to_string(),
+            content: row.get("content"),
+            metadata,
+            embedding: vec![], // Optimization: Don't return vector to client
+            tags: row.get::<Option<Vec<String>>, _>("tags").unwrap_or_default(),
+            created_at: row.get("created_at"),
+            updated_at: row.get("updated_at"),
+            pinned: row.get("pinned"),
+            importance: row.get("importance"),
+            access_count: row.get("access_count"),
+            last_accessed_at: row.get("last_accessed_at"),
+            content_type: row.get("content_type"),
+            lang: row.get("lang"),
+        }
+    }
+}
+
+/// What a `MemoryDatabase::transaction` closure returns
+pub type TransactionFuture<'t, T, E> = Pin<Box<dyn Future<Output = Result<T, E>> + Send + 't>>;
+
+/// Writes grouped by `MemoryDatabase::transaction`
+///
+/// Each does what the `MemoryDatabase` method of the same name does, quota
+/// check included, but nothing is committed until the closure returns.
+pub struct MemoryTransaction<'a> {
+    db: &'a MemoryDatabase,
+    tx: Transaction<'static, Postgres>,
+}
+
+impl MemoryTransaction<'_> {
+    /// See `MemoryDatabase::store_memory_deduplicated`
+    pub async fn store_memory(&mut self, user_id: &str, memory: &NewMemory) -> Result<String, StoreError> {
+        let uuid = Uuid::parse_str(&memory.id).unwrap_or_default();
+        let before = self.db.lock_storage_usage(&mut self.tx, user_id).await?;
+        let id = insert_memory(&mut self.tx, user_id, uuid, memory).await?;
+        self.db.check_storage_quota(&mut self.tx, user_id, before).await?;
+        Ok(id)
+    }
+
+    /// See `MemoryDatabase::update_memory`
+    pub async fn update_memory(
+        &mut self,
+        user_id: &str,
+        id: &str,
+        update: &MemoryUpdate,
+        updated_at: i64,
+    ) -> Result<Option<MemoryModel>, StoreError> {
+        let uuid = Uuid::parse_str(id).unwrap_or_default();
+        let metadata_json = update.metadata.as_ref().map(|m| serde_json::to_value(m).unwrap());
+
+        // Longer content or metadata counts against the byte quota
+        let before = self.db.lock_storage_usage(&mut self.tx, user_id).await?;
+
+        let row = sqlx::query(
+            r#"
+            UPDATE memories SET
+                content = COALESCE($3, content),
+                embedding = COALESCE($4::vector, embedding),
+                metadata = COALESCE($5, metadata),
+                tags = COALESCE($6, tags),
+                updated_at = $7,
+                content_hash = CASE WHEN $3 IS NULL THEN content_hash END,
+                lang = CASE WHEN $3 IS NULL THEN lang ELSE $8 END,
+                content_type = COALESCE($9, content_type)
+            WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL
+            RETURNING id, content, metadata, tags, created_at, updated_at, pinned, importance, access_count, last_accessed_at, content_type, lang
+            "#
+        )
+        .bind(uuid)
+        .bind(user_id)
+        .bind(update.content.as_deref())
+        .bind(update.embedding.as_deref())
+        .bind(metadata_json)
+        .bind(update.tags.as_deref())
+        .bind(updated_at)
+        .bind(update.lang.as_deref())
+        .bind(update.content_type.as_deref())
+        .fetch_optional(&mut *self.tx)
+        .await?;
+        self.db.check_storage_quota(&mut self.tx, user_id, before).await?;
+
+        match row {
+            Some(row) => Ok(self.db.map_rows(vec![row])?.pop()),
+            None => Ok(None),
+        }
+    }
+
+    /// See `MemoryDatabase::delete_memory`
+    pub async fn delete_memory(&mut self, user_id: &str, id: &str, deleted_at: i64) -> Result<bool, sqlx::Error> {
+        let uuid = Uuid::parse_str(id).unwrap_or_default();
+        let result = sqlx::query(
+            "UPDATE memories SET deleted_at = $3 WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL"
+        )
+        .bind(uuid)
+        .bind(user_id)
+        .bind(deleted_at)
+        .execute(&mut *self.tx)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+/// Leading `ORDER BY` terms putting pinned, then important, memories first
+fn pinned_first(boost_pinned: bool) -> &'static str {
+    if boost_pinned { "pinned DESC, importance DESC, " } else { "" }
+}
+
+/// Run `INSERT_MEMORY` for `memory`, returning the id now holding its content
+async fn insert_memory(
+    conn: &mut PgConnection,
+    user_id: &str,
+    uuid: Uuid,
+    memory: &NewMemory,
+) -> Result<String, sqlx::Error> {
+    let inserted = sqlx::query(INSERT_MEMORY)
+        .bind(uuid)
+        .bind(user_id)
+        .bind(&memory.content)
+        .bind(&memory.embedding)
+        .bind(serde_json::to_value(&memory.metadata).unwrap())
+        .bind(&memory.tags)
+        .bind(memory.created_at)
+        .bind(memory.updated_at)
+        .bind(&memory.content_hash)
+        .bind(&memory.content_type)
+        .bind(&memory.lang)
+        .fetch_optional(&mut *conn)
+        .await?;
+    let row = match inserted {
+        Some(row) => row,
+        None => {
+            sqlx::query(MEMORY_WITH_CONTENT_HASH)
+                .bind(user_id)
+                .bind(&memory.content_hash)
+                .fetch_one(conn)
+                .await?
+        }
+    };
+    Ok(row.get::<Uuid, _>("id").to_string())
+}
+
+/// Memory id to page after; ids sort after the nil UUID, so `None` starts at the first
+fn cursor_uuid(id: Option<&str>) -> Uuid {
+    id.and_then(|id| Uuid::parse_str(id).ok()).unwrap_or_default()
+}
+
+async fn update_embeddings(tx: &mut Transaction<'_, Postgres>, embeddings: &[(String, Vec<f32>)]) -> Result<(), sqlx::Error> {
+    for (id, embedding) in embeddings {
+        sqlx::query("UPDATE memories SET embedding = $2::vector WHERE id = $1")
+            .bind(Uuid::parse_str(id).unwrap_or_default())
+            .bind(embedding)
+            .execute(&mut **tx)
+            .await?;
+    }
+    Ok(())
+}
+
+async fn storage_usage(tx: &mut Transaction<'_, Postgres>, user_id: &str) -> Result<StorageUsage, sqlx::Error> {
+    let row = sqlx::query(STORAGE_USAGE).bind(user_id).fetch_one(&mut **tx).await?;
+    Ok(StorageUsage { memories: row.get("total"), bytes: row.get("bytes") })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    // Integration tests: need DATABASE_URL pointing at Postgres with pgvector.
+    // Run with `just test-integration`.
+    pub(crate) async fn test_db() -> MemoryDatabase {
+        let db = MemoryDatabase::connect(&database_url()).await.expect("Failed to connect");
+        assert!(db.fts_enabled, "full-text index should be available");
+
+        db
+    }
+
+    fn database_url() -> String {
+        dotenvy::dotenv().ok();
+        std::env::var("DATABASE_URL").expect("DATABASE_URL must be set")
+    }
+
+    /// `DATABASE_URL` with unqualified names resolving in `schema` first
+    pub(crate) fn scratch_url(schema: &str) -> String {
+        let url = database_url();
+        let separator = if url.contains('?') { '&' } else { '?' };
+        format!("{}{}options=-c%20search_path%3D{}%2Cpublic", url, separator, schema)
+    }
+
+    /// A pool on a new, empty schema, for tests that need a fresh database
+    pub(crate) async fn scratch_schema(prefix: &str) -> (PgPool, String) {
+        let schema = format!("{}_{}", prefix, Uuid::new_v4().simple());
+        let admin = PgPool::connect(&database_url()).await.expect("Failed to connect");
+        sqlx::query(&format!("CREATE SCHEMA {}", schema)).execute(&admin).await.unwrap();
+
+        let pool = PgPool::connect(&scratch_url(&schema)).await.expect("Failed to connect");
+        (pool, schema)
+    }
+
+    pub(crate) async fn drop_scratch_schema(schema: &str) {
+        let admin = PgPool::connect(&database_url()).await.expect("Failed to connect");
+        sqlx::query(&format!("DROP SCHEMA {} CASCADE", schema)).execute(&admin).await.unwrap();
+    }
+
+    async fn store(db: &MemoryDatabase, user_id: &str, content: &str, tags: &[String]) -> String {
+        let id = Uuid::new_v4().to_string();
+        db.store_memory(user_id, &id, content, &unit_vector(0, 4), &HashMap::new(), tags, 0, 0)
+            .await
+            .unwrap();
+        id
+    }
+
+    pub(crate) async fn cleanup(db: &MemoryDatabase, tag: &str) {
+        sqlx::query("DELETE FROM memories WHERE $1 = ANY(tags)")
+            .bind(tag)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+    }
+
+    fn unit_vector(seed: usize, dim: usize) -> Vec<f32> {
+        let v: Vec<f32> = (0..dim).map(|i| (((seed * 31 + i * 17) % 97) as f32) - 48.0).collect();
+        let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+        v.into_iter().map(|x| x / norm).collect()
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_search_by_embedding_5000_rows() {
+        let db = test_db().await;
+        let tag = format!("bench-{}", Uuid::new_v4());
+        let tags = vec![tag.clone()];
+        let dim = 16;
+
+        for i in 0..5000 {
+            db.store_memory(
+                &tag,
+                &Uuid::new_v4().to_string(),
+                &format!("memory {}", i),
+                &unit_vector(i, dim),
+                &HashMap::new(),
+                &tags,
+                i as i64,
+                i as i64,
+            )
+            .await
+            .unwrap();
+        }
+
+        let started = Instant::now();
+        let results = db.search_by_embedding(&tag, &unit_vector(1234, dim), &MemoryFilter::default(), 0.0, 10, false).await.unwrap();
+        let elapsed = started.elapsed();
+        // Loose, so that only a real regression trips it on a busy machine
+        assert!(elapsed < Duration::from_secs(2), "search_by_embedding over 5000 rows took {:?}", elapsed);
+
+        assert!(results.len() <= 10);
+        assert!(results.windows(2).all(|w| w[0].1 >= w[1].1), "results must be ranked");
+        assert!((results[0].1 - 1.0).abs() < 1e-4, "exact match should rank first");
+
+        cleanup(&db, &tag).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_query_memories_pages_and_count() {
+        let db = test_db().await;
+        let tag = format!("page-{}", Uuid::new_v4());
+        let tags = vec![tag.clone()];
+
+        for i in 0..25 {
+            db.store_memory(
+                &tag,
+                &Uuid::new_v4().to_string(),
+                &format!("{} item {}", tag, i),
+                &unit_vector(i, 4),
+                &HashMap::new(),
+                &tags,
+                i as i64,
+                i as i64,
+            )
+            .await
+            .unwrap();
+        }
+
+        let first = db.query_memories(&tag, &tag, &MemoryFilter::default(), 10, 0, false).await.unwrap();
+        let second = db.query_memories(&tag, &tag, &MemoryFilter::default(), 10, 10, false).await.unwrap();
+        let last = db.query_memories(&tag, &tag, &MemoryFilter::default(), 10, 20, false).await.unwrap();
+
+        assert_eq!((first.len(), second.len(), last.len()), (10, 10, 5));
+        assert!(first.iter().all(|m| second.iter().all(|n| n.id != m.id)));
+        assert_eq!(db.count_memories(&tag, &tag, &MemoryFilter::default()).await.unwrap(), 25);
+
+        cleanup(&db, &tag).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_fts_search_multi_word_ranks_best_match_first() {
+        let db = test_db().await;
+        let tag = format!("fts-{}", Uuid::new_v4());
+        let tags = vec![tag.clone()];
+        let marker = Uuid::new_v4().simple().to_string();
+
+        let both = store(&db, &tag, &format!("{} rust compiler borrow checker", marker), &tags).await;
+        let one = store(&db, &tag, &format!("{} rust programming", marker), &tags).await;
+        store(&db, &tag, &format!("{} gardening tips", marker), &tags).await;
+
+        let results = db.fts_search(&tag, &format!("{} rust borrow", marker), &MemoryFilter::default(), 10, 0, false).await.unwrap();
+        assert_eq!(results.len(), 1, "all terms must match");
+        assert_eq!(results[0].id, both);
+
+        let results = db.fts_search(&tag, &format!("{} rust or borrow", marker), &MemoryFilter::default(), 10, 0, false).await.unwrap();
+        let ids: Vec<_> = results.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec![both.as_str(), one.as_str()]);
+        assert_eq!(db.count_fts_matches(&tag, &format!("{} rust", marker), &MemoryFilter::default()).await.unwrap(), 2);
+
+        cleanup(&db, &tag).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_fts_search_matches_tags() {
+        let db = test_db().await;
+        let tag = format!("fts-{}", Uuid::new_v4());
+        let marker = format!("topic{}", Uuid::new_v4().simple());
+
+        let tagged = store(&db, &tag, "notes from the meeting", &[tag.clone(), marker.clone()]).await;
+        store(&db, &tag, "unrelated notes", std::slice::from_ref(&tag)).await;
+
+        let results = db.fts_search(&tag, &marker, &MemoryFilter::default(), 10, 0, false).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, tagged);
+
+        // Trashed rows drop out of ranked search too
+        db.delete_memory(&tag, &tagged, 0).await.unwrap();
+        assert!(db.fts_search(&tag, &marker, &MemoryFilter::default(), 10, 0, false).await.unwrap().is_empty());
+
+        cleanup(&db, &tag).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_fts_search_by_language() {
+        let db = test_db().await;
+        let tag = format!("fts-{}", Uuid::new_v4());
+        let tags = std::slice::from_ref(&tag);
+        let japanese = store(&db, &tag, "来週の火曜日に東京タワーへ行く予定です", tags).await;
+        let english = store(&db, &tag, "The meetings with the design team moved to Thursday afternoon", tags).await;
+        let german = store(&db, &tag, "Die Besprechungen mit dem Team wurden auf Donnerstag verschoben", tags).await;
+        assert_eq!(db.get_memory(&tag, &japanese).await.unwrap().unwrap().lang.as_deref(), Some("jpn"));
+
+        let search = |query: &'static str, lang: Option<&str>| {
+            let filter = MemoryFilter { lang: lang.map(str::to_string), ..Default::default() };
+            let (db, tag) = (&db, &tag);
+            async move {
+                let results = db.fts_search(tag, query, &filter, 10, 0, false).await.unwrap();
+                results.into_iter().map(|m| m.id).collect::<Vec<_>>()
+            }
+        };
+        // Words inside an unspaced Japanese sentence, and a single character
+        assert_eq!(search("東京タワー", None).await, std::slice::from_ref(&japanese));
+        assert_eq!(search("火曜日", None).await, std::slice::from_ref(&japanese));
+        assert_eq!(search("京", None).await, std::slice::from_ref(&japanese));
+        assert!(search("塔", None).await.is_empty());
+        assert_eq!(db.count_fts_matches(&tag, "東京タワー", &MemoryFilter::default()).await.unwrap(), 1);
+        // English is stemmed as before
+        assert_eq!(search("meeting", None).await, [english]);
+        // Filtering by language also stems the query for it
+        assert_eq!(search("Besprechung", Some("deu")).await, [german]);
+        assert_eq!(search("東京", Some("jpn")).await, [japanese]);
+        assert!(search("meeting", Some("jpn")).await.is_empty());
+
+        cleanup(&db, &tag).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_filter_tags_any_vs_all() {
+        let db = test_db().await;
+        let tag = format!("filter-{}", Uuid::new_v4());
+        let tags = |extra: &[&str]| {
+            let mut tags = vec![tag.clone()];
+            tags.extend(extra.iter().map(|t| t.to_string()));
+            tags
+        };
+
+        let both = store(&db, &tag, "quarterly report", &tags(&["work", "urgent"])).await;
+        let work = store(&db, &tag, "work only", &tags(&["work"])).await;
+        store(&db, &tag, "neither", &tags(&["home"])).await;
+
+        let ids = |memories: Vec<MemoryModel>| {
+            let mut ids: Vec<String> = memories.into_iter().map(|m| m.id).collect();
+            ids.sort();
+            ids
+        };
+        let mut expected = vec![both.clone(), work];
+        expected.sort();
+
+        let any = MemoryFilter { tags_any: vec!["work".into(), "urgent".into()], ..Default::default() };
+        assert_eq!(ids(db.query_memories(&tag, "", &any, 10, 0, false).await.unwrap()), expected);
+        assert_eq!(db.count_memories(&tag, "", &any).await.unwrap(), 2);
+
+        let all = MemoryFilter { tags_all: vec!["work".into(), "urgent".into()], ..Default::default() };
+        assert_eq!(ids(db.query_memories(&tag, "", &all, 10, 0, false).await.unwrap()), vec![both.clone()]);
+        assert_eq!(db.fts_search(&tag, "report", &all, 10, 0, false).await.unwrap()[0].id, both);
+        // Both rows match "work" through their tags; tags_all keeps only one
+        assert_eq!(db.count_fts_matches(&tag, "work", &all).await.unwrap(), 1);
+
+        cleanup(&db, &tag).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_filter_time_range_and_metadata() {
+        let db = test_db().await;
+        let tag = format!("filter-{}", Uuid::new_v4());
+        let tags = vec![tag.clone()];
+
+        let mut ids = Vec::new();
+        for (i, source) in ["slack", "email", "slack"].iter().enumerate() {
+            let id = Uuid::new_v4().to_string();
+            let metadata = HashMap::from([("source".to_string(), source.to_string())]);
+            let at = 100 * (i as i64 + 1);
+            db.store_memory(&tag, &id, "note", &unit_vector(i, 4), &metadata, &tags, at, at)
+                .await
+                .unwrap();
+            ids.push(id);
+        }
+
+        // created_after is inclusive, created_before exclusive
+        let range = MemoryFilter { created_after: Some(200), created_before: Some(300), ..Default::default() };
+        let results = db.query_memories(&tag, "", &range, 10, 0, false).await.unwrap();
+        assert_eq!(results.iter().map(|m| &m.id).collect::<Vec<_>>(), vec![&ids[1]]);
+
+        let slack = MemoryFilter {
+            metadata: BTreeMap::from([("source".to_string(), "slack".to_string())]),
+            ..Default::default()
+        };
+        assert_eq!(db.count_memories(&tag, "", &slack).await.unwrap(), 2);
+
+        let slack_since = MemoryFilter { created_after: Some(200), ..slack };
+        let results = db.query_memories(&tag, "", &slack_since, 10, 0, false).await.unwrap();
+        assert_eq!(results.iter().map(|m| &m.id).collect::<Vec<_>>(), vec![&ids[2]]);
+
+        cleanup(&db, &tag).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_search_by_embedding_scores_only_filtered_rows() {
+        let db = test_db().await;
+        let tag = format!("prefilter-{}", Uuid::new_v4());
+
+        let mut ids = Vec::new();
+        for (i, (kind, source)) in [("work", "slack"), ("personal", "slack"), ("work", "email")].iter().enumerate() {
+            let id = Uuid::new_v4().to_string();
+            let metadata = HashMap::from([("source".to_string(), source.to_string())]);
+            let tags = vec![tag.clone(), kind.to_string()];
+            let at = 100 * (i as i64 + 1);
+            // Identical embeddings, so only the filters tell the rows apart
+            db.store_memory(&tag, &id, "note", &unit_vector(0, 4), &metadata, &tags, at, at)
+                .await
+                .unwrap();
+            ids.push(id);
+        }
+        let query = unit_vector(0, 4);
+
+        let unfiltered = MemoryFilter::default();
+        assert_eq!(db.count_search_candidates(&tag, &unfiltered).await.unwrap(), 3);
+        assert_eq!(db.search_by_embedding(&tag, &query, &unfiltered, 0.0, 10, false).await.unwrap().len(), 3);
+
+        let work = MemoryFilter { tags_any: vec!["work".into()], ..Default::default() };
+        assert_eq!(db.count_search_candidates(&tag, &work).await.unwrap(), 2);
+        let results = db.search_by_embedding(&tag, &query, &work, 0.0, 10, false).await.unwrap();
+        assert!(results.iter().all(|(m, _)| m.id != ids[1]));
+
+        let recent_slack = MemoryFilter {
+            metadata: BTreeMap::from([("source".to_string(), "slack".to_string())]),
+            created_after: Some(200),
+            ..Default::default()
+        };
+        assert_eq!(db.count_search_candidates(&tag, &recent_slack).await.unwrap(), 1);
+        let results = db.search_by_embedding(&tag, &query, &recent_slack, 0.0, 10, false).await.unwrap();
+        assert_eq!(results.iter().map(|(m, _)| &m.id).collect::<Vec<_>>(), vec![&ids[1]]);
+
+        cleanup(&db, &tag).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_connect_upgrades_old_schema_and_keeps_rows() {
+        let (pool, schema) = scratch_schema("upgrade").await;
+        // `memories` as created before user scoping, trash or versioning
+        sqlx::query(
+            "CREATE TABLE memories (id UUID PRIMARY KEY, content TEXT NOT NULL, embedding vector, \
+             metadata JSONB, tags TEXT[], created_at BIGINT NOT NULL, updated_at BIGINT NOT NULL)"
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let id = Uuid::new_v4();
+        sqlx::query("INSERT INTO memories VALUES ($1, 'from before', $2, '{}', '{old}', 1, 1)")
+            .bind(id)
+            .bind(unit_vector(0, 4))
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let version = |pool: PgPool| async move {
+            let row = sqlx::query("SELECT version FROM schema_version WHERE component = 'memories'")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+            row.get::<i32, _>("version")
+        };
+        for _ in 0..2 {
+            // Opening an up-to-date database again changes nothing
+            let db = MemoryDatabase::connect(&scratch_url(&schema)).await.expect("upgrade failed");
+            assert_eq!(version(pool.clone()).await, MIGRATIONS.last().unwrap().version);
+
+            let legacy_owner = "00000000-0000-0000-0000-000000000000";
+            let memory = db.get_memory(legacy_owner, &id.to_string()).await.unwrap().expect("row lost");
+            assert_eq!(memory.content, "from before");
+            assert_eq!(memory.tags, vec!["old"]);
+        }
+
+        drop_scratch_schema(&schema).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_embedding_dimension_is_recorded_and_enforced() {
+        let (_, schema) = scratch_schema("dimension").await;
+        let db = MemoryDatabase::connect(&scratch_url(&schema)).await.unwrap();
+        store(&db, "alice", "four components", &[]).await;
+
+        // Rows stored before the dimension was recorded are checked
+        assert!(db.check_embedding_dimension(8).await.is_err());
+        db.check_embedding_dimension(4).await.unwrap();
+
+        // From then on the record decides
+        assert!(db.check_embedding_dimension(8).await.is_err());
+        db.check_embedding_dimension(4).await.unwrap();
+
+        drop_scratch_schema(&schema).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_memories_are_scoped_to_their_owner() {
+        let db = test_db().await;
+        let tag = format!("scope-{}", Uuid::new_v4());
+        let tags = vec![tag.clone()];
+        let alice = format!("alice-{}", Uuid::new_v4());
+        let mallory = format!("mallory-{}", Uuid::new_v4());
+
+        let id = store(&db, &alice, &format!("{} secret plans", tag), &tags).await;
+
+        assert!(db.get_memory(&mallory, &id).await.unwrap().is_none());
+        assert!(db.query_memories(&mallory, &tag, &MemoryFilter::default(), 10, 0, false).await.unwrap().is_empty());
+        assert!(db.fts_search(&mallory, "secret plans", &MemoryFilter::default(), 10, 0, false).await.unwrap().is_empty());
+        assert!(db.search_by_embedding(&mallory, &unit_vector(0, 4), &MemoryFilter::default(), -1.0, 10, false).await.unwrap().is_empty());
+        assert!(db.get_recent_memories(&mallory, 10).await.unwrap().is_empty());
+        assert!(!db.delete_memory(&mallory, &id, 0).await.unwrap());
+
+        assert!(db.get_memory(&alice, &id).await.unwrap().is_some());
+        assert_eq!(db.count_memories(&alice, &tag, &MemoryFilter::default()).await.unwrap(), 1);
+        assert!(db.delete_memory(&alice, &id, 0).await.unwrap());
+
+        cleanup(&db, &tag).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_delete_user_memories_is_transactional() {
+        let db = test_db().await;
+        let tag = format!("delete-user-{}", Uuid::new_v4());
+        let tags = vec![tag.clone()];
+        let alice = format!("alice-{}", Uuid::new_v4());
+        let bob = format!("bob-{}", Uuid::new_v4());
+        store(&db, &alice, "first", &tags).await;
+        store(&db, &alice, "second", &tags).await;
+        store(&db, &bob, "untouched", &tags).await;
+
+        // Rolled back: nothing is gone
+        let mut tx = db.begin().await.unwrap();
+        assert_eq!(db.delete_user_memories(&mut tx, &alice).await.unwrap(), 2);
+        tx.rollback().await.unwrap();
+        assert_eq!(db.count_memories(&alice, "", &MemoryFilter::default()).await.unwrap(), 2);
+
+        let mut tx = db.begin().await.unwrap();
+        assert_eq!(db.delete_user_memories(&mut tx, &alice).await.unwrap(), 2);
+        tx.commit().await.unwrap();
+        assert_eq!(db.count_memories(&alice, "", &MemoryFilter::default()).await.unwrap(), 0);
+        assert_eq!(db.count_memories(&bob, "", &MemoryFilter::default()).await.unwrap(), 1);
+
+        cleanup(&db, &tag).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_transaction_rolls_back_on_err() {
+        let db = test_db().await;
+        let tag = format!("transaction-{}", Uuid::new_v4());
+        let kept = store(&db, &tag, "kept as is", std::slice::from_ref(&tag)).await;
+        let new_memory = NewMemory {
+            id: Uuid::new_v4().to_string(),
+            content: "stored with the rest".to_string(),
+            embedding: unit_vector(1, 4),
+            metadata: HashMap::new(),
+            tags: vec![tag.clone()],
+            created_at: 0,
+            updated_at: 0,
+            content_hash: None,
+            content_type: crate::content_type::PLAIN.to_string(),
+            lang: None,
+        };
+        let update = MemoryUpdate { content: Some("rewritten".to_string()), ..Default::default() };
+        let write_all = |fail: bool| {
+            let (tag, kept, new_memory, update) = (&tag, &kept, &new_memory, &update);
+            db.transaction(move |tx| Box::pin(async move {
+                tx.store_memory(tag, new_memory).await?;
+                assert!(tx.update_memory(tag, kept, update, 1).await?.is_some());
+                assert!(tx.delete_memory(tag, kept, 2).await?);
+                if fail {
+                    return Err(StoreError::Database(sqlx::Error::RowNotFound));
+                }
+                Ok(())
+            }))
+        };
+
+        assert!(write_all(true).await.is_err());
+        let memory = db.get_memory(&tag, &kept).await.unwrap().expect("delete rolled back");
+        assert_eq!(memory.content, "kept as is");
+        assert!(db.get_memory(&tag, &new_memory.id).await.unwrap().is_none());
+
+        write_all(false).await.unwrap();
+        assert!(db.get_memory(&tag, &kept).await.unwrap().is_none());
+        assert_eq!(db.get_memory(&tag, &new_memory.id).await.unwrap().unwrap().content, "stored with the rest");
+
+        cleanup(&db, &tag).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_startup_waits_out_locks_past_lock_timeout() {
+        let (holder, schema) = scratch_schema("startup").await;
+        MemoryDatabase::connect(&scratch_url(&schema)).await.unwrap();
+        let lock = |table: &'static str| {
+            let holder = holder.clone();
+            async move {
+                let mut tx = holder.begin().await.unwrap();
+                sqlx::query(&format!("LOCK TABLE {} IN ACCESS EXCLUSIVE MODE", table))
+                    .execute(&mut *tx)
+                    .await
+                    .unwrap();
+                tx
+            }
+        };
+        // Held for longer than the 100ms lock timeout
+        let release_later = |tx: Transaction<'static, Postgres>| tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            tx.commit().await.unwrap();
+        });
+
+        let release = release_later(lock("memories").await);
+        let db = MemoryDatabase::connect_with_options(&scratch_url(&schema), None, Some(Duration::from_millis(100)))
+            .await
+            .unwrap();
+        assert!(db.fts_enabled(), "a busy table must not turn full-text search off");
+        release.await.unwrap();
+
+        let release = release_later(lock("memory_settings").await);
+        db.check_embedding_dimension(4).await.unwrap();
+        release.await.unwrap();
+
+        drop_scratch_schema(&schema).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_lock_waits_are_bounded_and_transient() {
+        let db = MemoryDatabase::connect_with_options(&database_url(), Some(2), Some(Duration::from_millis(300)))
+            .await
+            .unwrap();
+        let tag = format!("lock-{}", Uuid::new_v4());
+        let id = store(&db, &tag, "contended", std::slice::from_ref(&tag)).await;
+        let holder = PgPool::connect(&database_url()).await.unwrap();
+        let lock_row = || async {
+            let mut tx = holder.begin().await.unwrap();
+            sqlx::query("SELECT id FROM memories WHERE id = $1 FOR UPDATE")
+                .bind(Uuid::parse_str(&id).unwrap())
+                .fetch_one(&mut *tx)
+                .await
+                .unwrap();
+            tx
+        };
+
+        // A lock released within the timeout is waited out
+        let tx = lock_row().await;
+        let release = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            tx.commit().await.unwrap();
+        });
+        let update = MemoryUpdate { content: Some("waited".to_string()), ..Default::default() };
+        assert!(db.update_memory(&tag, &id, &update, 1).await.unwrap().is_some());
+        release.await.unwrap();
+
+        // One held past it fails as worth retrying, and the retry succeeds
+        let tx = lock_row().await;
+        let error = db.delete_memory(&tag, &id, 2).await.unwrap_err();
+        assert!(is_transient(&error), "{}", error);
+        tx.rollback().await.unwrap();
+        assert!(db.delete_memory(&tag, &id, 2).await.unwrap());
+
+        cleanup(&db, &tag).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_trashed_memory_is_hidden_until_restored() {
+        let db = test_db().await;
+        let tag = format!("trash-{}", Uuid::new_v4());
+        let tags = vec![tag.clone()];
+        let id = store(&db, &tag, &format!("{} trashable note", tag), &tags).await;
+
+        assert!(db.delete_memory(&tag, &id, 1_000).await.unwrap());
+        assert!(!db.delete_memory(&tag, &id, 1_000).await.unwrap(), "already in the trash");
+        assert!(db.get_memory(&tag, &id).await.unwrap().is_none());
+        assert!(db.query_memories(&tag, &tag, &MemoryFilter::default(), 10, 0, false).await.unwrap().is_empty());
+        assert!(db.fts_search(&tag, "trashable", &MemoryFilter::default(), 10, 0, false).await.unwrap().is_empty());
+        assert!(db.search_by_embedding(&tag, &unit_vector(0, 4), &MemoryFilter::default(), -1.0, 10, false).await.unwrap().is_empty());
+        assert!(db.get_recent_memories(&tag, 10).await.unwrap().is_empty());
+        assert_eq!(db.count_memories(&tag, "", &MemoryFilter::default()).await.unwrap(), 0);
+
+        // Past the window (deleted before the cutoff) nothing comes back
+        assert!(db.restore_memory(&tag, &id, 1_001).await.unwrap().is_none());
+        let restored = db.restore_memory(&tag, &id, 1_000).await.unwrap().unwrap();
+        assert_eq!(restored.id, id);
+        assert!(db.get_memory(&tag, &id).await.unwrap().is_some());
+        assert_eq!(db.search_by_embedding(&tag, &unit_vector(0, 4), &MemoryFilter::default(), -1.0, 10, false).await.unwrap().len(), 1);
+
+        cleanup(&db, &tag).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_purge_trashed_respects_cutoff() {
+        let db = test_db().await;
+        let tag = format!("purge-{}", Uuid::new_v4());
+        let tags = vec![tag.clone()];
+        let old = store(&db, &tag, "deleted long ago", &tags).await;
+        let recent = store(&db, &tag, "deleted just now", &tags).await;
+        store(&db, &tag, "still live", &tags).await;
+        db.delete_memory(&tag, &old, 100).await.unwrap();
+        db.delete_memory(&tag, &recent, 200).await.unwrap();
+
+        // Other tests' trash may be purged too, so only check our own rows
+        db.purge_trashed(150).await.unwrap();
+        assert!(db.restore_memory(&tag, &old, 0).await.unwrap().is_none());
+        assert!(db.restore_memory(&tag, &recent, 0).await.unwrap().is_some());
+        assert_eq!(db.count_memories(&tag, "", &MemoryFilter::default()).await.unwrap(), 2);
+
+        cleanup(&db, &tag).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_export_import_round_trip() {
+        let db = test_db().await;
+        let tag = format!("export-{}", Uuid::new_v4());
+        let source = format!("source-{}", Uuid::new_v4());
+        let target = format!("target-{}", Uuid::new_v4());
+        let metadata = HashMap::from([("source".to_string(), "chat".to_string())]);
+        for (i, content) in ["first note", "second note"].iter().enumerate() {
+            let id = Uuid::new_v4().to_string();
+            let tags = vec![tag.clone(), format!("n{}", i)];
+            db.store_memory(&source, &id, content, &unit_vector(i, 4), &metadata, &tags, i as i64, 10 + i as i64)
+                .await
+                .unwrap();
+        }
+        let trashed = store(&db, &source, "trashed note", std::slice::from_ref(&tag)).await;
+        db.delete_memory(&source, &trashed, 1).await.unwrap();
+
+        let exported = db.export_memories(&source).await.unwrap();
+        assert_eq!(exported.len(), 2, "trashed memories are not exported");
+
+        let new_memories: Vec<NewMemory> = exported.iter().map(|m| NewMemory {
+            id: Uuid::new_v4().to_string(),
+            content: m.content.clone(),
+            embedding: unit_vector(0, 4),
+            metadata: m.metadata.clone(),
+            tags: m.tags.clone(),
+            created_at: m.created_at,
+            updated_at: m.updated_at,
+            content_hash: None,
+            content_type: m.content_type.clone(),
+            lang: None,
+        }).collect();
+        db.import_memories(&target, &new_memories).await.unwrap();
+
+        assert_eq!(db.export_memories(&target).await.unwrap(), exported);
+
+        cleanup(&db, &tag).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_memory_stats_aggregates_seeded_rows() {
+        let db = test_db().await;
+        let tag = format!("stats-{}", Uuid::new_v4());
+        assert_eq!(db.memory_stats(&tag, 10).await.unwrap(), MemoryStats::default());
+
+        let seed = [("abc", &["work", "urgent"][..], 300), ("defg", &["work"][..], 100), ("hi", &["home"][..], 200)];
+        for (i, (content, extra, at)) in seed.iter().enumerate() {
+            let mut tags = vec![tag.clone()];
+            tags.extend(extra.iter().map(|t| t.to_string()));
+            let metadata = HashMap::from([("k".to_string(), "v".to_string())]);
+            db.store_memory(&tag, &Uuid::new_v4().to_string(), content, &unit_vector(i, 4), &metadata, &tags, *at, *at)
+                .await
+                .unwrap();
+        }
+        let trashed = store(&db, &tag, "trashed", &[tag.clone(), "work".to_string()]).await;
+        db.delete_memory(&tag, &trashed, 0).await.unwrap();
+
+        let stats = db.memory_stats(&tag, 2).await.unwrap();
+        assert_eq!(stats.total_count, 3);
+        assert_eq!(stats.top_tags, vec![(tag.clone(), 3), ("work".to_string(), 2)]);
+        assert_eq!((stats.oldest_created_at, stats.newest_created_at), (Some(100), Some(300)));
+        // 9 content bytes plus `{"k": "v"}` three times
+        assert_eq!(stats.total_bytes, 9 + 3 * 10);
+
+        cleanup(&db, &tag).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_concurrent_store_and_query() {
+        let db = Arc::new(test_db().await);
+        let tag = format!("stress-{}", Uuid::new_v4());
+
+        let tasks: Vec<_> = (0..100)
+            .map(|i| {
+                let db = db.clone();
+                let tag = tag.clone();
+                tokio::spawn(async move {
+                    if i % 2 == 0 {
+                        store(&db, &tag, &format!("{} concurrent {}", tag, i), std::slice::from_ref(&tag)).await;
+                    } else {
+                        db.query_memories(&tag, &tag, &MemoryFilter::default(), 100, 0, false).await.unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        let all = async {
+            for task in tasks {
+                task.await.unwrap();
+            }
+        };
+        tokio::time::timeout(std::time::Duration::from_secs(30), all)
+            .await
+            .expect("concurrent operations deadlocked");
+
+        assert_eq!(db.count_memories(&tag, &tag, &MemoryFilter::default()).await.unwrap(), 50);
+        assert_eq!(db.query_memories(&tag, &tag, &MemoryFilter::default(), 100, 0, false).await.unwrap().len(), 50);
+
+        cleanup(&db, &tag).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_store_memories_batch_isolates_failures() {
+        let db = test_db().await;
+        let tag = format!("batch-{}", Uuid::new_v4());
+        let new_memory = |id: String, content: &str| NewMemory {
+            id,
+            content: content.to_string(),
+            embedding: unit_vector(0, 4),
+            metadata: HashMap::new(),
+            tags: vec![tag.clone()],
+            created_at: 0,
+            updated_at: 0,
+            content_hash: None,
+            content_type: crate::content_type::PLAIN.to_string(),
+            lang: None,
+        };
+
+        let duplicate = Uuid::new_v4().to_string();
+        let batch = vec![
+            new_memory(duplicate.clone(), "first"),
+            new_memory(duplicate.clone(), "same id again"),
+            new_memory("not-a-uuid".to_string(), "bad id"),
+            new_memory(Uuid::new_v4().to_string(), "last"),
+        ];
+
+        let results = db.store_memories_batch(&tag, &batch).await.unwrap();
+
+        assert_eq!(results.len(), 4);
+        assert!(results[0].is_ok());
+        assert!(results[1].is_err(), "duplicate primary key must fail");
+        assert!(results[2].is_err());
+        assert!(results[3].is_ok(), "rows after a failure must still be stored");
+        assert_eq!(db.count_memories(&tag, "", &MemoryFilter::default()).await.unwrap(), 2);
+
+        cleanup(&db, &tag).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_deduplicated_store_hit_and_miss() {
+        let db = test_db().await;
+        let tag = format!("dedup-{}", Uuid::new_v4());
+        let new_memory = |content: &str| NewMemory {
+            id: Uuid::new_v4().to_string(),
+            content: content.to_string(),
+            embedding: unit_vector(0, 4),
+            metadata: HashMap::new(),
+            tags: vec![tag.clone()],
+            created_at: 0,
+            updated_at: 0,
+            content_hash: Some(crate::dedup::content_hash(content)),
+            content_type: crate::content_type::PLAIN.to_string(),
+            lang: None,
+        };
+        let filter = MemoryFilter::default();
+
+        let original = new_memory("Buy  milk");
+        assert_eq!(db.store_memory_deduplicated(&tag, &original).await.unwrap(), original.id);
+
+        // Hit: same content up to case and whitespace
+        let again = new_memory(" buy milk\n");
+        assert_eq!(db.store_memory_deduplicated(&tag, &again).await.unwrap(), original.id);
+        let hash = again.content_hash.as_deref().unwrap();
+        assert_eq!(db.memory_with_content_hash(&tag, hash).await.unwrap(), Some(original.id.clone()));
+
+        // Miss: different content, or the same stored without dedup
+        let other = new_memory("Buy oat milk");
+        assert_eq!(db.store_memory_deduplicated(&tag, &other).await.unwrap(), other.id);
+        store(&db, &tag, "Buy milk", std::slice::from_ref(&tag)).await;
+        assert_eq!(db.count_memories(&tag, "", &filter).await.unwrap(), 3);
+
+        // Within one batch the second copy resolves to the first
+        let (first, second) = (new_memory("call mom"), new_memory("Call Mom"));
+        let results = db.store_memories_batch(&tag, &[first.clone(), second]).await.unwrap();
+        assert_eq!(results[0].as_ref().unwrap(), &first.id);
+        assert_eq!(results[1].as_ref().unwrap(), &first.id);
+        assert_eq!(db.count_memories(&tag, "", &filter).await.unwrap(), 4);
+
+        // Trashed memories don't match; restoring one whose content was
+        // stored again keeps both
+        assert!(db.delete_memory(&tag, &original.id, 10).await.unwrap());
+        let replacement = new_memory("buy milk");
+        assert_eq!(db.store_memory_deduplicated(&tag, &replacement).await.unwrap(), replacement.id);
+        assert!(db.restore_memory(&tag, &original.id, 0).await.unwrap().is_some());
+        assert_eq!(db.memory_with_content_hash(&tag, hash).await.unwrap(), Some(replacement.id.clone()));
+
+        // Edited content no longer matches what it was stored with
+        let update = MemoryUpdate { content: Some("buy bread".to_string()), ..Default::default() };
+        db.update_memory(&tag, &replacement.id, &update, 1).await.unwrap();
+        assert_eq!(db.memory_with_content_hash(&tag, hash).await.unwrap(), None);
+
+        cleanup(&db, &tag).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_storage_quota_at_and_over_limit() {
+        let quota = StorageQuota { max_memories: Some(3), max_bytes: Some(100) };
+        let db = test_db().await.with_storage_quota(quota);
+        let tag = format!("quota-{}", Uuid::new_v4());
+        let new_memory = |content: &str| NewMemory {
+            id: Uuid::new_v4().to_string(),
+            content: content.to_string(),
+            embedding: unit_vector(0, 4),
+            metadata: HashMap::new(),
+            tags: vec![tag.clone()],
+            created_at: 0,
+            updated_at: 0,
+            content_hash: None,
+            content_type: crate::content_type::PLAIN.to_string(),
+            lang: None,
+        };
+        let filter = MemoryFilter::default();
+
+        let first = store(&db, &tag, "note", std::slice::from_ref(&tag)).await;
+        store(&db, &tag, "note", std::slice::from_ref(&tag)).await;
+
+        // Two more would make four: neither is kept
+        let over = db.store_memories_batch(&tag, &[new_memory("a"), new_memory("b")]).await.unwrap_err();
+        match over {
+            StoreError::Quota(e) => {
+                assert_eq!(e.usage.memories, 2);
+                assert_eq!(e.added.memories, 2);
+            }
+            e => panic!("expected a quota error, got {}", e),
+        }
+        assert_eq!(db.count_memories(&tag, "", &filter).await.unwrap(), 2);
+
+        // Exactly at the limit is fine, one past it is not
+        db.store_memories_batch(&tag, &[new_memory("c")]).await.unwrap();
+        let tags = std::slice::from_ref(&tag);
+        let id = Uuid::new_v4().to_string();
+        assert!(matches!(
+            db.store_memory(&tag, &id, "d", &unit_vector(0, 4), &HashMap::new(), tags, 0, 0).await,
+            Err(StoreError::Quota(_))
+        ));
+        assert_eq!(db.count_memories(&tag, "", &filter).await.unwrap(), 3);
+
+        // Trashed memories don't count until restored
+        assert!(db.delete_memory(&tag, &first, 10).await.unwrap());
+        store(&db, &tag, "note", tags).await;
+        assert!(matches!(db.restore_memory(&tag, &first, 0).await, Err(StoreError::Quota(_))));
+
+        // Growing a memory counts against the byte limit
+        let update = MemoryUpdate { content: Some("x".repeat(100)), ..Default::default() };
+        assert!(matches!(db.update_memory(&tag, &id, &update, 1).await, Ok(None)), "rejected store kept");
+        let latest = db.get_recent_memories(&tag, 1).await.unwrap().remove(0);
+        assert!(matches!(db.update_memory(&tag, &latest.id, &update, 1).await, Err(StoreError::Quota(_))));
+
+        cleanup(&db, &tag).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_storage_quota_holds_under_concurrent_stores() {
+        let quota = StorageQuota { max_memories: Some(5), max_bytes: None };
+        let db = Arc::new(test_db().await.with_storage_quota(quota));
+        let tag = format!("quota-race-{}", Uuid::new_v4());
+
+        let stores = (0..20).map(|_| {
+            let (db, tag) = (db.clone(), tag.clone());
+            tokio::spawn(async move {
+                let tags = vec![tag.clone()];
+                let id = Uuid::new_v4().to_string();
+                db.store_memory(&tag, &id, "note", &unit_vector(0, 4), &HashMap::new(), &tags, 0, 0).await
+            })
+        }).collect::<Vec<_>>();
+        let mut stored = 0;
+        for store in stores {
+            match store.await.unwrap() {
+                Ok(()) => stored += 1,
+                Err(StoreError::Quota(_)) => {}
+                Err(e) => panic!("store failed: {}", e),
+            }
+        }
+
+        assert_eq!(stored, 5);
+        assert_eq!(db.count_memories(&tag, "", &MemoryFilter::default()).await.unwrap(), 5);
+        cleanup(&db, &tag).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_update_memory_is_partial() {
+        let db = test_db().await;
+        let tag = format!("update-{}", Uuid::new_v4());
+        let id = store(&db, &tag, "original", std::slice::from_ref(&tag)).await;
+        let embedding_of = |id: String| {
+            let db = db.clone();
+            async move {
+                let row = sqlx::query("SELECT embedding::real[] AS e FROM memories WHERE id = $1")
+                    .bind(Uuid::parse_str(&id).unwrap())
+                    .fetch_one(&db.pool)
+                    .await
+                    .unwrap();
+                row.get::<Vec<f32>, _>("e")
+            }
+        };
+        let before = embedding_of(id.clone()).await;
+
+        let tags_only = MemoryUpdate {
+            tags: Some(vec![tag.clone(), "extra".to_string()]),
+            ..Default::default()
+        };
+        let updated = db.update_memory(&tag, &id, &tags_only, 42).await.unwrap().unwrap();
+        assert_eq!(updated.content, "original");
+        assert_eq!(updated.tags.len(), 2);
+        assert_eq!(updated.content_type, crate::content_type::PLAIN);
+        assert_eq!((updated.created_at, updated.updated_at), (0, 42));
+        assert_eq!(embedding_of(id.clone()).await, before);
+
+        let new_content = MemoryUpdate {
+            content: Some("rewritten".to_string()),
+            embedding: Some(unit_vector(7, 4)),
+            content_type: Some(crate::content_type::MARKDOWN.to_string()),
+            ..Default::default()
+        };
+        let updated = db.update_memory(&tag, &id, &new_content, 43).await.unwrap().unwrap();
+        assert_eq!(updated.content, "rewritten");
+        assert_eq!(updated.tags.len(), 2);
+        assert_eq!(updated.content_type, crate::content_type::MARKDOWN);
+        assert_ne!(embedding_of(id.clone()).await, before);
+
+        let missing = db.update_memory(&tag, &Uuid::new_v4().to_string(), &new_content, 44).await.unwrap();
+        assert!(missing.is_none());
+
+        cleanup(&db, &tag).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_pinned_memories_boosted_and_included() {
+        let db = test_db().await;
+        let tag = format!("pinned-{}", Uuid::new_v4());
+        let tags = vec![tag.clone()];
+        // All stored with the same embedding, so equally similar to any query
+        let plain = store(&db, &tag, "plain", &tags).await;
+        let pinned = store(&db, &tag, "pinned", &tags).await;
+        let important = store(&db, &tag, "important", &tags).await;
+        let flagged = db.set_memory_flags(&tag, &pinned, Some(true), None, 1).await.unwrap().unwrap();
+        assert!(flagged.pinned && flagged.importance == 0.0 && flagged.updated_at == 1);
+        db.set_memory_flags(&tag, &important, None, Some(0.5), 1).await.unwrap().unwrap();
+
+        let query = unit_vector(0, 4);
+        let filter = MemoryFilter::default();
+        let ids = |results: Vec<(MemoryModel, f32)>| results.into_iter().map(|(m, _)| m.id).collect::<Vec<_>>();
+
+        let boosted = db.search_by_embedding(&tag, &query, &filter, 0.0, 10, true).await.unwrap();
+        assert!(boosted.iter().all(|(_, score)| (score - 1.0).abs() < 1e-4), "scores stay plain similarity");
+        assert_eq!(ids(boosted), vec![pinned.clone(), important.clone(), plain.clone()]);
+        // The limit applies after boosting
+        assert_eq!(ids(db.search_by_embedding(&tag, &query, &filter, 0.0, 1, true).await.unwrap()), vec![pinned.clone()]);
+
+        let listed: Vec<String> = db.query_memories(&tag, "", &filter, 10, 0, true).await.unwrap()
+            .into_iter().map(|m| m.id).collect();
+        assert_eq!(listed[..2], [pinned.clone(), important.clone()]);
+        // Pinned memories are scored even when nothing else matches
+        let unrelated = unit_vector(7, 4);
+        assert_eq!(ids(db.score_pinned(&tag, &unrelated, &filter).await.unwrap()), vec![pinned.clone()]);
+
+        let unpinned = db.set_memory_flags(&tag, &pinned, Some(false), None, 2).await.unwrap().unwrap();
+        assert!(!unpinned.pinned);
+        assert!(db.score_pinned(&tag, &query, &filter).await.unwrap().is_empty());
+        let missing = db.set_memory_flags(&tag, &Uuid::new_v4().to_string(), Some(true), None, 2).await.unwrap();
+        assert!(missing.is_none());
+
+        cleanup(&db, &tag).await;
+    }
+}
@@ -0,0 +1,34 @@
+use sha2::{Digest, Sha256};
+
+/// Hex SHA-256 of `content` after normalization, as kept in `memories.content_hash`
+///
+/// Contents differing only in case or whitespace hash the same.
+pub fn content_hash(content: &str) -> String {
+    let digest = Sha256::digest(normalize(content).as_bytes());
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Trimmed and lowercased, with each run of whitespace collapsed to one space
+fn normalize(content: &str) -> String {
+    content.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("  Hello\t\tWORLD \n again "), "hello world again");
+        assert_eq!(normalize("\n \t"), "");
+    }
+
+    #[test]
+    fn test_hash_ignores_case_and_whitespace_only() {
+        let hash = content_hash("Meeting notes: ship v2");
+        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, content_hash("  meeting   NOTES:\nship v2\n"));
+        assert_ne!(hash, content_hash("Meeting notes: ship v3"));
+        assert_ne!(hash, content_hash("Meetingnotes: ship v2"));
+    }
+}
@@ -0,0 +1,307 @@
+use crate::config::{EmbeddingSettings, OpenAiSettings};
+use crate::http::HttpClient;
+use fastembed::{EmbeddingModel, InitOptions, TextEmbedding};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tonic::Status;
+
+/// Source of text embeddings for the memory service
+#[tonic::async_trait]
+pub trait EmbeddingProvider: Send + Sync {
+    /// Embed a single piece of text
+    async fn embed(&self, text: &str) -> Result<Vec<f32>, Status>;
+
+    /// Embed several texts, returning vectors in input order
+    ///
+    /// Defaults to one `embed` call per text; providers with a native batch
+    /// API should override this.
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Status> {
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for text in texts {
+            embeddings.push(self.embed(text).await?);
+        }
+        Ok(embeddings)
+    }
+
+    /// Length of the vectors returned by `embed`
+    fn dimension(&self) -> usize;
+
+    /// Names the model, so cached vectors of different models are never mixed
+    fn model(&self) -> String;
+}
+
+/// Local ONNX model via fastembed (AllMiniLM-L6-v2)
+pub struct FastEmbedProvider {
+    model: Arc<Mutex<TextEmbedding>>,
+}
+
+impl FastEmbedProvider {
+    const DIMENSION: usize = 384;
+
+    /// Load the model, downloading it into `cache_dir` the first time
+    pub fn new(cache_dir: PathBuf) -> Result<Self, String> {
+        tracing::info!("🧠 Initializing Neural Engine (model cache {})...", cache_dir.display());
+
+        let options = InitOptions::new(EmbeddingModel::AllMiniLML6V2)
+            .with_cache_dir(cache_dir)
+            .with_show_download_progress(true);
+
+        let model = TextEmbedding::try_new(options)
+            .map_err(|e| format!("Failed to load local embedding model: {}", e))?;
+
+        Ok(Self { model: Arc::new(Mutex::new(model)) })
+    }
+}
+
+#[tonic::async_trait]
+impl EmbeddingProvider for FastEmbedProvider {
+    async fn embed(&self, text: &str) -> Result<Vec<f32>, Status> {
+        self.embed_batch(&[text.to_string()]).await?
+            .into_iter().next()
+            .ok_or_else(|| Status::internal("No embedding generated"))
+    }
+
+    #[allow(clippy::result_large_err)]
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Status> {
+        let model = Arc::clone(&self.model);
+        let documents = texts.to_vec();
+
+        // Inference is CPU-bound, keep it off the async workers
+        tokio::task::spawn_blocking(move || {
+            // fastembed v5 requires mutable access
+            let mut model = model.lock()
+                .map_err(|_| Status::internal("AI Engine lock failure"))?;
+            model.embed(documents, None)
+                .map_err(|e| Status::internal(format!("Embedding failed: {}", e)))
+        })
+        .await
+        .map_err(|e| Status::internal(format!("Embedding task failed: {}", e)))?
+    }
+
+    fn dimension(&self) -> usize {
+        Self::DIMENSION
+    }
+
+    fn model(&self) -> String {
+        "fastembed/all-MiniLM-L6-v2".to_string()
+    }
+}
+
+/// Deterministic bag-of-words hashing embedder
+///
+/// Produces no real semantics, but identical text always maps to the same
+/// unit vector and shared words increase similarity. Intended for tests and
+/// environments without a model.
+pub struct HashEmbeddingProvider {
+    dimension: usize,
+}
+
+impl HashEmbeddingProvider {
+    pub fn new(dimension: usize) -> Self {
+        Self { dimension: dimension.max(1) }
+    }
+}
+
+impl Default for HashEmbeddingProvider {
+    fn default() -> Self {
+        Self::new(384)
+    }
+}
+
+#[tonic::async_trait]
+impl EmbeddingProvider for HashEmbeddingProvider {
+    async fn embed(&self, text: &str) -> Result<Vec<f32>, Status> {
+        let mut vector = vec![0f32; self.dimension];
+
+        for token in text.split_whitespace() {
+            let mut hasher = DefaultHasher::new();
+            token.to_lowercase().hash(&mut hasher);
+            let hash = hasher.finish();
+
+            let bucket = (hash % self.dimension as u64) as usize;
+            let sign = if hash & (1 << 63) == 0 { 1.0 } else { -1.0 };
+            vector[bucket] += sign;
+        }
+
+        let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|v| *v /= norm);
+        }
+
+        Ok(vector)
+    }
+
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    fn model(&self) -> String {
+        "hash".to_string()
+    }
+}
+
+/// OpenAI-compatible `/embeddings` endpoint
+pub struct OpenAiEmbeddingProvider {
+    client: HttpClient,
+    api_url: String,
+    api_key: String,
+    model: String,
+    dimension: usize,
+}
+
+#[derive(serde::Deserialize)]
+struct OpenAiEmbeddingResponse {
+    data: Vec<OpenAiEmbeddingData>,
+}
+
+#[derive(serde::Deserialize)]
+struct OpenAiEmbeddingData {
+    #[serde(default)]
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+impl OpenAiEmbeddingProvider {
+    pub fn from_settings(settings: &OpenAiSettings, client: HttpClient) -> Result<Self, String> {
+        let api_key = settings.api_key.clone()
+            .ok_or("OPENAI_API_KEY not set")?;
+
+        Ok(Self {
+            client,
+            api_url: settings.url.clone(),
+            api_key,
+            model: settings.model.clone(),
+            dimension: settings.dimension,
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl EmbeddingProvider for OpenAiEmbeddingProvider {
+    async fn embed(&self, text: &str) -> Result<Vec<f32>, Status> {
+        self.embed_batch(&[text.to_string()]).await?
+            .into_iter().next()
+            .ok_or_else(|| Status::internal("No embedding generated"))
+    }
+
+    #[allow(clippy::result_large_err)]
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Status> {
+        let body = serde_json::json!({
+            "model": self.model,
+            "input": texts,
+            "dimensions": self.dimension,
+        });
+
+        let response = self.client
+            .post(&self.api_url)
+            .map_err(|e| Status::unavailable(format!("Embedding request failed: {}", e)))?
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| Status::unavailable(format!("Embedding request failed: {}", e)))?;
+
+        if !response.status().is_success() {
+            return Err(Status::unavailable(format!(
+                "Embedding provider returned {}",
+                response.status()
+            )));
+        }
+
+        let mut parsed = response
+            .json::<OpenAiEmbeddingResponse>()
+            .await
+            .map_err(|e| Status::internal(format!("Failed to parse embedding response: {}", e)))?;
+
+        if parsed.data.len() != texts.len() {
+            return Err(Status::internal(format!(
+                "Embedding provider returned {} embeddings for {} inputs",
+                parsed.data.len(),
+                texts.len()
+            )));
+        }
+
+        // The API tags each embedding with its input position
+        parsed.data.sort_by_key(|d| d.index);
+
+        parsed.data.into_iter().map(|d| {
+            if d.embedding.len() != self.dimension {
+                return Err(Status::internal(format!(
+                    "Embedding provider returned {} dimensions, expected {}",
+                    d.embedding.len(),
+                    self.dimension
+                )));
+            }
+            Ok(d.embedding)
+        }).collect()
+    }
+
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    fn model(&self) -> String {
+        format!("openai/{}", self.model)
+    }
+}
+
+/// Build the provider the settings select (fastembed, openai, hash)
+///
+/// fastembed keeps its model under `data_dir` unless `FASTEMBED_CACHE_DIR`
+/// says otherwise; openai calls go through `http`.
+pub fn provider_from_settings(
+    settings: &EmbeddingSettings,
+    data_dir: &Path,
+    http: &HttpClient,
+) -> Result<Arc<dyn EmbeddingProvider>, String> {
+    match settings.provider.as_str() {
+        "fastembed" => {
+            let cache_dir = settings.fastembed_cache_dir.clone()
+                .unwrap_or_else(|| data_dir.join("models"));
+            Ok(Arc::new(FastEmbedProvider::new(cache_dir)?))
+        }
+        "openai" => Ok(Arc::new(OpenAiEmbeddingProvider::from_settings(&settings.openai, http.clone())?)),
+        "hash" => Ok(Arc::new(HashEmbeddingProvider::default())),
+        other => Err(format!("Unknown EMBEDDING_PROVIDER: {}", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_hash_embedding_is_deterministic() {
+        let provider = HashEmbeddingProvider::new(64);
+
+        let a = provider.embed("remember the milk").await.unwrap();
+        let b = provider.embed("remember the milk").await.unwrap();
+
+        assert_eq!(a.len(), 64);
+        assert_eq!(a, b);
+    }
+
+    #[tokio::test]
+    async fn test_hash_embedding_is_normalized() {
+        let provider = HashEmbeddingProvider::default();
+
+        let v = provider.embed("some words to embed").await.unwrap();
+        let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+
+        assert!((norm - 1.0).abs() < 1e-5);
+    }
+
+    #[tokio::test]
+    async fn test_default_embed_batch_preserves_order() {
+        let provider = HashEmbeddingProvider::new(32);
+        let texts = vec!["first".to_string(), "second".to_string()];
+
+        let batch = provider.embed_batch(&texts).await.unwrap();
+
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch[0], provider.embed("first").await.unwrap());
+        assert_eq!(batch[1], provider.embed("second").await.unwrap());
+    }
+}
@@ -0,0 +1,227 @@
+use crate::config::EmbeddingSettings;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Embeddings kept unless `EMBEDDING_CACHE_SIZE` says otherwise
+pub const DEFAULT_CAPACITY: usize = 10_000;
+
+/// Cache file under the data dir when `EMBEDDING_CACHE_PERSIST` is on
+const FILE_NAME: &str = "embedding-cache.bin";
+
+/// Start of a cache file, bumped if the layout changes
+const MAGIC: &[u8; 8] = b"IDEMBC01";
+
+/// SHA-256 of the embedded text
+type Key = [u8; 32];
+
+/// Size of the cache, and whether it outlives the process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbeddingCacheConfig {
+    pub capacity: usize,
+    /// File the cache is loaded from at startup and saved to at shutdown
+    pub file: Option<PathBuf>,
+}
+
+impl EmbeddingCacheConfig {
+    /// Config from the gateway's embedding settings
+    ///
+    /// A cache size of 0 turns the cache off; persisting keeps it in
+    /// `data_dir` across restarts.
+    pub fn from_settings(settings: &EmbeddingSettings, data_dir: &Path) -> Option<Self> {
+        let capacity = settings.cache_size;
+        (capacity > 0).then(|| Self { capacity, file: settings.cache_persist.then(|| data_dir.join(FILE_NAME)) })
+    }
+}
+
+struct Entry {
+    embedding: Vec<f32>,
+    used: u64,
+}
+
+#[derive(Default)]
+struct Lru {
+    entries: HashMap<Key, Entry>,
+    // use counter -> key, oldest first
+    order: BTreeMap<u64, Key>,
+    clock: u64,
+}
+
+impl Lru {
+    fn touch(&mut self, key: &Key) -> Option<&Entry> {
+        self.clock += 1;
+        let entry = self.entries.get_mut(key)?;
+        self.order.remove(&entry.used);
+        entry.used = self.clock;
+        self.order.insert(self.clock, *key);
+        Some(entry)
+    }
+
+    fn insert(&mut self, key: Key, embedding: Vec<f32>, capacity: usize) {
+        if self.touch(&key).is_some() {
+            return;
+        }
+        while self.entries.len() >= capacity {
+            let Some((_, oldest)) = self.order.pop_first() else { break };
+            self.entries.remove(&oldest);
+        }
+        self.entries.insert(key, Entry { embedding, used: self.clock });
+        self.order.insert(self.clock, key);
+    }
+}
+
+/// Embeddings of recently embedded text, so the same content is only sent
+/// to the provider once
+///
+/// Keyed by a hash of the exact text: changed content is simply a
+/// different key, so nothing ever needs invalidating. One cache serves one
+/// model; a saved cache is only loaded back for the same model and
+/// dimension. The least recently used embedding is dropped once `capacity`
+/// is reached.
+pub struct EmbeddingCache {
+    model: String,
+    dimension: usize,
+    capacity: usize,
+    lru: Mutex<Lru>,
+}
+
+impl EmbeddingCache {
+    pub fn new(model: String, dimension: usize, capacity: usize) -> Self {
+        Self { model, dimension, capacity: capacity.max(1), lru: Mutex::default() }
+    }
+
+    pub fn get(&self, text: &str) -> Option<Vec<f32>> {
+        let mut lru = self.lru.lock().unwrap_or_else(|e| e.into_inner());
+        lru.touch(&key(text)).map(|entry| entry.embedding.clone())
+    }
+
+    pub fn insert(&self, text: &str, embedding: Vec<f32>) {
+        if embedding.len() != self.dimension {
+            return;
+        }
+        let mut lru = self.lru.lock().unwrap_or_else(|e| e.into_inner());
+        lru.insert(key(text), embedding, self.capacity);
+    }
+
+    pub fn embedding_count(&self) -> usize {
+        self.lru.lock().unwrap_or_else(|e| e.into_inner()).entries.len()
+    }
+
+    /// Header identifying the model and dimension a file was saved for
+    fn header(&self) -> Vec<u8> {
+        let mut header = MAGIC.to_vec();
+        header.extend_from_slice(&Sha256::digest(self.model.as_bytes()));
+        header.extend_from_slice(&(self.dimension as u32).to_le_bytes());
+        header
+    }
+
+    /// Add the embeddings saved at `path`, returning how many were loaded
+    ///
+    /// A missing file, or one saved for another model, loads nothing.
+    pub fn load(&self, path: &Path) -> io::Result<usize> {
+        let file = match std::fs::File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        let mut reader = BufReader::new(file);
+
+        let expected = self.header();
+        let mut header = vec![0u8; expected.len()];
+        reader.read_exact(&mut header)?;
+        if header != expected {
+            tracing::info!("Ignoring embedding cache {} saved for another model", path.display());
+            return Ok(0);
+        }
+
+        let mut lru = self.lru.lock().unwrap_or_else(|e| e.into_inner());
+        let mut loaded = 0;
+        let mut key = [0u8; 32];
+        let mut vector = vec![0u8; self.dimension * 4];
+        // Saved oldest first, so recency survives the round trip
+        loop {
+            match reader.read_exact(&mut key) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            }
+            reader.read_exact(&mut vector)?;
+            let embedding = vector.chunks_exact(4)
+                .map(|bytes| f32::from_le_bytes(bytes.try_into().expect("chunks of 4")))
+                .collect();
+            lru.insert(key, embedding, self.capacity);
+            loaded += 1;
+        }
+        Ok(loaded)
+    }
+
+    /// Write the cache to `path`, replacing any earlier file only once complete
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let staging = path.with_extension("tmp");
+        {
+            let mut writer = BufWriter::new(std::fs::File::create(&staging)?);
+            writer.write_all(&self.header())?;
+            let lru = self.lru.lock().unwrap_or_else(|e| e.into_inner());
+            for key in lru.order.values() {
+                writer.write_all(key)?;
+                for value in &lru.entries[key].embedding {
+                    writer.write_all(&value.to_le_bytes())?;
+                }
+            }
+            writer.flush()?;
+        }
+        std::fs::rename(&staging, path)
+    }
+}
+
+fn key(text: &str) -> Key {
+    Sha256::digest(text.as_bytes()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_least_recently_used_evicted() {
+        let cache = EmbeddingCache::new("test".to_string(), 2, 2);
+        cache.insert("a", vec![1.0, 0.0]);
+        cache.insert("b", vec![0.0, 1.0]);
+        assert_eq!(cache.get("a"), Some(vec![1.0, 0.0]));
+
+        cache.insert("c", vec![1.0, 1.0]);
+        assert_eq!(cache.embedding_count(), 2);
+        assert!(cache.get("b").is_none(), "b was used least recently");
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("c").is_some());
+
+        cache.insert("d", vec![1.0]);
+        assert!(cache.get("d").is_none(), "wrong dimension is never cached");
+    }
+
+    #[test]
+    fn test_saved_cache_loads_for_same_model_only() {
+        let dir = std::env::temp_dir().join(format!("identra-embedding-cache-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(FILE_NAME);
+
+        let cache = EmbeddingCache::new("model-a".to_string(), 2, 2);
+        assert_eq!(cache.load(&path).unwrap(), 0, "no file yet");
+        cache.insert("old", vec![1.0, 2.0]);
+        cache.insert("new", vec![3.0, 4.0]);
+        cache.save(&path).unwrap();
+
+        let restored = EmbeddingCache::new("model-a".to_string(), 2, 2);
+        assert_eq!(restored.load(&path).unwrap(), 2);
+        assert_eq!(restored.get("new"), Some(vec![3.0, 4.0]));
+        restored.insert("newer", vec![5.0, 6.0]);
+        assert!(restored.get("old").is_none(), "recency survives a restart");
+
+        let other = EmbeddingCache::new("model-b".to_string(), 2, 2);
+        assert_eq!(other.load(&path).unwrap(), 0);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,251 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use crate::content_type;
+use identra_crypto::{derive_key, generate_salt, CryptoError, Envelope, KeyDerivationParams};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// Version written into every export; bumped when the layout changes
+pub const EXPORT_VERSION: u32 = 1;
+
+/// Key derivation label recorded in encrypted exports
+const KDF_ARGON2ID: &str = "argon2id";
+
+/// Costliest Argon2id settings accepted from an import header, so a crafted
+/// file can't make the gateway allocate gigabytes
+const MAX_MEMORY_COST_KIB: u32 = 256 * 1024;
+const MAX_TIME_COST: u32 = 10;
+const MAX_PARALLELISM: u32 = 16;
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error("Export is not valid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Unsupported export version {0}, expected {EXPORT_VERSION}")]
+    UnsupportedVersion(u32),
+
+    #[error("Export is encrypted; a passphrase is required")]
+    PassphraseRequired,
+
+    #[error("Unsupported key derivation {0:?}")]
+    UnsupportedKdf(String),
+
+    #[error("Key derivation parameters exceed the allowed cost")]
+    KdfTooCostly,
+
+    #[error("Export field {0} is not valid base64")]
+    Base64(&'static str),
+
+    #[error("Wrong passphrase or corrupted export")]
+    Decryption,
+
+    #[error("Encryption failed: {0}")]
+    Crypto(#[from] CryptoError),
+
+    #[error("Memory {index} is invalid: {reason}")]
+    InvalidMemory { index: usize, reason: &'static str },
+}
+
+/// One memory as written to an export; ids and embeddings are not carried
+/// over, importing assigns new ids and re-embeds with the current provider
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedMemory {
+    pub content: String,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    /// Absent from exports made before memories had one
+    #[serde(default = "plain_text")]
+    pub content_type: String,
+}
+
+fn plain_text() -> String {
+    content_type::PLAIN.to_string()
+}
+
+/// Plaintext export document
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemoryExport {
+    pub version: u32,
+    pub exported_at: i64,
+    pub memories: Vec<ExportedMemory>,
+}
+
+/// Passphrase-encrypted export: `ciphertext` is a sealed `MemoryExport`
+#[derive(Debug, Serialize, Deserialize)]
+struct EncryptedExport {
+    version: u32,
+    encryption: KdfHeader,
+    ciphertext: String,
+}
+
+/// How the export key was derived from the passphrase
+#[derive(Debug, Serialize, Deserialize)]
+struct KdfHeader {
+    kdf: String,
+    salt: String,
+    memory_cost: u32,
+    time_cost: u32,
+    parallelism: u32,
+}
+
+/// Just enough of either layout to tell them apart
+#[derive(Deserialize)]
+struct Probe {
+    version: u32,
+    #[serde(default)]
+    encryption: Option<serde::de::IgnoredAny>,
+}
+
+impl MemoryExport {
+    pub fn new(memories: Vec<ExportedMemory>, exported_at: i64) -> Self {
+        Self { version: EXPORT_VERSION, exported_at, memories }
+    }
+
+    /// Serialize to JSON, sealed under `passphrase` if one is given
+    ///
+    /// Argon2id is deliberately slow; call this off the async runtime.
+    pub fn to_bytes(&self, passphrase: Option<&str>, params: &KeyDerivationParams) -> Result<Vec<u8>, ExportError> {
+        let plaintext = serde_json::to_vec(self)?;
+        let Some(passphrase) = passphrase else {
+            return Ok(plaintext);
+        };
+
+        let salt = generate_salt();
+        let key = derive_key(passphrase.as_bytes(), &salt, params)?.to_encryption_key();
+        let sealed = EncryptedExport {
+            version: EXPORT_VERSION,
+            encryption: KdfHeader {
+                kdf: KDF_ARGON2ID.to_string(),
+                salt: STANDARD.encode(salt),
+                memory_cost: params.memory_cost,
+                time_cost: params.time_cost,
+                parallelism: params.parallelism,
+            },
+            ciphertext: STANDARD.encode(Envelope::seal(&key, &plaintext)?),
+        };
+        Ok(serde_json::to_vec(&sealed)?)
+    }
+
+    /// Parse and validate an export produced by `to_bytes`
+    pub fn from_bytes(bytes: &[u8], passphrase: Option<&str>) -> Result<Self, ExportError> {
+        let probe: Probe = serde_json::from_slice(bytes)?;
+        if probe.version != EXPORT_VERSION {
+            return Err(ExportError::UnsupportedVersion(probe.version));
+        }
+
+        let export: Self = if probe.encryption.is_some() {
+            let sealed: EncryptedExport = serde_json::from_slice(bytes)?;
+            let passphrase = passphrase.ok_or(ExportError::PassphraseRequired)?;
+            serde_json::from_slice(&open(&sealed, passphrase)?)?
+        } else {
+            serde_json::from_slice(bytes)?
+        };
+
+        if export.version != EXPORT_VERSION {
+            return Err(ExportError::UnsupportedVersion(export.version));
+        }
+        export.validate()?;
+        Ok(export)
+    }
+
+    fn validate(&self) -> Result<(), ExportError> {
+        for (index, memory) in self.memories.iter().enumerate() {
+            if memory.content.trim().is_empty() {
+                return Err(ExportError::InvalidMemory { index, reason: "content is empty" });
+            }
+            if memory.updated_at < memory.created_at {
+                return Err(ExportError::InvalidMemory { index, reason: "updated_at precedes created_at" });
+            }
+        }
+        Ok(())
+    }
+}
+
+fn open(sealed: &EncryptedExport, passphrase: &str) -> Result<Vec<u8>, ExportError> {
+    let header = &sealed.encryption;
+    if header.kdf != KDF_ARGON2ID {
+        return Err(ExportError::UnsupportedKdf(header.kdf.clone()));
+    }
+    let salt = STANDARD.decode(&header.salt).map_err(|_| ExportError::Base64("salt"))?;
+    let ciphertext = STANDARD.decode(&sealed.ciphertext).map_err(|_| ExportError::Base64("ciphertext"))?;
+
+    if header.memory_cost > MAX_MEMORY_COST_KIB
+        || header.time_cost > MAX_TIME_COST
+        || header.parallelism > MAX_PARALLELISM
+    {
+        return Err(ExportError::KdfTooCostly);
+    }
+    let params = KeyDerivationParams {
+        memory_cost: header.memory_cost,
+        time_cost: header.time_cost,
+        parallelism: header.parallelism,
+    };
+    let key = derive_key(passphrase.as_bytes(), &salt, &params)?.to_encryption_key();
+    Envelope::open(&key, &ciphertext).map_err(|_| ExportError::Decryption)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn export() -> MemoryExport {
+        MemoryExport::new(
+            vec![ExportedMemory {
+                content: "remember the milk".to_string(),
+                metadata: HashMap::from([("source".to_string(), "chat".to_string())]),
+                tags: vec!["errands".to_string()],
+                created_at: 100,
+                updated_at: 200,
+                content_type: content_type::PLAIN.to_string(),
+            }],
+            300,
+        )
+    }
+
+    #[test]
+    fn test_plain_round_trip() {
+        let bytes = export().to_bytes(None, &KeyDerivationParams::fast()).unwrap();
+        assert_eq!(MemoryExport::from_bytes(&bytes, None).unwrap(), export());
+    }
+
+    #[test]
+    fn test_encrypted_round_trip() {
+        let bytes = export().to_bytes(Some("correct horse"), &KeyDerivationParams::fast()).unwrap();
+        assert!(!String::from_utf8_lossy(&bytes).contains("milk"));
+
+        assert_eq!(MemoryExport::from_bytes(&bytes, Some("correct horse")).unwrap(), export());
+        assert!(matches!(
+            MemoryExport::from_bytes(&bytes, Some("battery staple")),
+            Err(ExportError::Decryption)
+        ));
+        assert!(matches!(MemoryExport::from_bytes(&bytes, None), Err(ExportError::PassphraseRequired)));
+    }
+
+    #[test]
+    fn test_rejects_invalid_documents() {
+        let result = MemoryExport::from_bytes(br#"{"version": 2, "exported_at": 0, "memories": []}"#, None);
+        assert!(matches!(result, Err(ExportError::UnsupportedVersion(2))));
+
+        let result = MemoryExport::from_bytes(br#"{"version": 1, "memories": []}"#, None);
+        assert!(matches!(result, Err(ExportError::Json(_))));
+
+        let costly = br#"{"version": 1, "ciphertext": "", "encryption": {"kdf": "argon2id", "salt": "",
+            "memory_cost": 4294967295, "time_cost": 1, "parallelism": 1}}"#;
+        let result = MemoryExport::from_bytes(costly, Some("passphrase"));
+        assert!(matches!(result, Err(ExportError::KdfTooCostly)));
+
+        let mut bad = export();
+        bad.memories[0].content = "  ".to_string();
+        let bytes = bad.to_bytes(None, &KeyDerivationParams::fast()).unwrap();
+        assert!(matches!(
+            MemoryExport::from_bytes(&bytes, None),
+            Err(ExportError::InvalidMemory { index: 0, .. })
+        ));
+    }
+}
@@ -0,0 +1,230 @@
+use crate::config::GrpcWebSettings;
+use std::net::SocketAddr;
+use std::time::Duration;
+use thiserror::Error;
+use tonic::codegen::http::{HeaderName, HeaderValue, Method};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+/// How long browsers may cache a preflight answer
+const PREFLIGHT_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Request headers gRPC-Web clients send
+const ALLOWED_HEADERS: [&str; 6] = [
+    "content-type", "x-grpc-web", "x-user-agent", "grpc-timeout", "authorization", "x-request-id",
+];
+
+/// Response headers browser code may read
+const EXPOSED_HEADERS: [&str; 6] = [
+    "grpc-status", "grpc-message", "grpc-status-details-bin", "x-request-id", "x-password-rule", "retry-after",
+];
+
+#[derive(Debug, Error)]
+pub enum GrpcWebConfigError {
+    #[error("Invalid origin {0:?} in GRPC_WEB_ALLOWED_ORIGINS (expected scheme://host[:port])")]
+    InvalidOrigin(String),
+}
+
+/// Browser origins allowed to make cross-origin calls
+#[derive(Debug, Clone, PartialEq)]
+pub enum AllowedOrigins {
+    /// `*`, only when asked for explicitly
+    Any,
+    /// Exactly these; empty refuses every cross-origin call
+    List(Vec<HeaderValue>),
+}
+
+/// Second listener speaking gRPC-Web over HTTP/1.1, for browser clients
+#[derive(Debug, Clone)]
+pub struct GrpcWebConfig {
+    pub addr: SocketAddr,
+    pub allowed_origins: AllowedOrigins,
+}
+
+impl GrpcWebConfig {
+    /// Config from the gateway's gRPC-Web settings
+    ///
+    /// No address disables gRPC-Web. Origins are a comma-separated list,
+    /// or `*` to allow any.
+    pub fn from_settings(settings: &GrpcWebSettings) -> Result<Option<Self>, GrpcWebConfigError> {
+        Self::from_values(settings.addr, settings.allowed_origins.as_deref())
+    }
+
+    fn from_values(addr: Option<SocketAddr>, origins: Option<&str>) -> Result<Option<Self>, GrpcWebConfigError> {
+        let Some(addr) = addr else {
+            return Ok(None);
+        };
+
+        let origins = origins.unwrap_or_default();
+        let allowed_origins = if origins.trim() == "*" {
+            AllowedOrigins::Any
+        } else {
+            let origins = origins.split(',')
+                .map(str::trim)
+                .filter(|origin| !origin.is_empty())
+                .map(parse_origin)
+                .collect::<Result<_, _>>()?;
+            AllowedOrigins::List(origins)
+        };
+
+        Ok(Some(Self { addr, allowed_origins }))
+    }
+
+    /// CORS for gRPC-Web calls, answering preflight `OPTIONS` itself
+    pub fn cors_layer(&self) -> CorsLayer {
+        let allow_origin = match &self.allowed_origins {
+            AllowedOrigins::Any => AllowOrigin::any(),
+            AllowedOrigins::List(origins) => AllowOrigin::list(origins.iter().cloned()),
+        };
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods([Method::POST])
+            .allow_headers(ALLOWED_HEADERS.map(HeaderName::from_static))
+            .expose_headers(EXPOSED_HEADERS.map(HeaderName::from_static))
+            .max_age(PREFLIGHT_MAX_AGE)
+    }
+}
+
+/// An origin is a scheme and host with an optional port; no path, no trailing slash
+fn parse_origin(origin: &str) -> Result<HeaderValue, GrpcWebConfigError> {
+    let invalid = || GrpcWebConfigError::InvalidOrigin(origin.to_string());
+    let (scheme, host) = origin.split_once("://").ok_or_else(invalid)?;
+    if !matches!(scheme, "http" | "https" | "tauri") || host.is_empty() || host.contains('/') {
+        return Err(invalid());
+    }
+    HeaderValue::from_str(origin).map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::health::HealthService;
+    use identra_proto::health::{health_check_response::ServingStatus, HealthCheckRequest, HealthCheckResponse};
+    use prost::Message;
+    use tonic::transport::Server;
+
+    const ORIGIN: &str = "http://localhost:1420";
+
+    fn config(origins: &str) -> GrpcWebConfig {
+        GrpcWebConfig::from_values(Some("127.0.0.1:0".parse().unwrap()), Some(origins))
+            .unwrap()
+            .unwrap()
+    }
+
+    /// Serve the health service over gRPC-Web, returning its base URL
+    async fn serve(config: GrpcWebConfig) -> String {
+        let listener = tokio::net::TcpListener::bind(config.addr).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Server::builder()
+            .accept_http1(true)
+            .layer(config.cors_layer())
+            .layer(tonic_web::GrpcWebLayer::new())
+            .add_service(HealthService::new().into_server())
+            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener));
+        tokio::spawn(server);
+        format!("http://{}", addr)
+    }
+
+    /// One gRPC-Web frame: flag byte, big-endian length, payload
+    fn frame(flag: u8, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![flag];
+        frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    /// Split a gRPC-Web response body into its (flag, payload) frames
+    fn frames(mut body: &[u8]) -> Vec<(u8, Vec<u8>)> {
+        let mut frames = Vec::new();
+        while body.len() >= 5 {
+            let len = u32::from_be_bytes(body[1..5].try_into().unwrap()) as usize;
+            frames.push((body[0], body[5..5 + len].to_vec()));
+            body = &body[5 + len..];
+        }
+        frames
+    }
+
+    #[test]
+    fn test_disabled_without_address() {
+        assert!(GrpcWebConfig::from_values(None, Some("*")).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_origins_default_to_none() {
+        let unset = GrpcWebConfig::from_values(Some("127.0.0.1:8080".parse().unwrap()), None).unwrap().unwrap();
+        assert_eq!(unset.allowed_origins, AllowedOrigins::List(Vec::new()));
+        assert_eq!(config("*").allowed_origins, AllowedOrigins::Any);
+        assert_eq!(
+            config(" http://localhost:1420, https://app.example.com ").allowed_origins,
+            AllowedOrigins::List(vec![
+                HeaderValue::from_static(ORIGIN),
+                HeaderValue::from_static("https://app.example.com"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_invalid_values_rejected() {
+        for origin in ["localhost:1420", "https://app.example.com/", "ftp://example.com", "*, https://a.com"] {
+            assert!(matches!(
+                GrpcWebConfig::from_values(Some("127.0.0.1:8080".parse().unwrap()), Some(origin)),
+                Err(GrpcWebConfigError::InvalidOrigin(_))
+            ), "{}", origin);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_grpc_web_call_over_http1() {
+        let url = serve(config(ORIGIN)).await;
+        let request = HealthCheckRequest { service: String::new() }.encode_to_vec();
+
+        let response = reqwest::Client::builder()
+            .http1_only()
+            .build()
+            .unwrap()
+            .post(format!("{}/identra.health.v1.Health/Check", url))
+            .header("content-type", "application/grpc-web+proto")
+            .header("x-grpc-web", "1")
+            .header("origin", ORIGIN)
+            .body(frame(0, &request))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["access-control-allow-origin"], ORIGIN);
+        assert_eq!(response.headers()["content-type"], "application/grpc-web+proto");
+
+        let body = response.bytes().await.unwrap();
+        let frames = frames(&body);
+        assert_eq!(frames.len(), 2, "one message and the trailers");
+        let (flag, message) = &frames[0];
+        assert_eq!(*flag, 0);
+        let health = HealthCheckResponse::decode(message.as_slice()).unwrap();
+        assert_eq!(health.status(), ServingStatus::Serving);
+        let (flag, trailers) = &frames[1];
+        assert_eq!(*flag, 0x80);
+        assert!(String::from_utf8_lossy(trailers).contains("grpc-status:0"));
+    }
+
+    #[tokio::test]
+    async fn test_preflight_only_for_allowed_origins() {
+        let url = serve(config(ORIGIN)).await;
+        let preflight = |origin: &'static str| {
+            reqwest::Client::new()
+                .request(reqwest::Method::OPTIONS, format!("{}/identra.health.v1.Health/Check", url))
+                .header("origin", origin)
+                .header("access-control-request-method", "POST")
+                .header("access-control-request-headers", "content-type,x-grpc-web,authorization")
+                .send()
+        };
+
+        let allowed = preflight(ORIGIN).await.unwrap();
+        assert!(allowed.status().is_success());
+        assert_eq!(allowed.headers()["access-control-allow-origin"], ORIGIN);
+        let headers = allowed.headers()["access-control-allow-headers"].to_str().unwrap();
+        assert!(headers.contains("x-grpc-web") && headers.contains("authorization"));
+
+        let refused = preflight("https://evil.example.com").await.unwrap();
+        assert!(refused.headers().get("access-control-allow-origin").is_none());
+    }
+}
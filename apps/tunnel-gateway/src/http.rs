@@ -0,0 +1,176 @@
+use reqwest::redirect::Policy;
+use reqwest::{Client, Method, RequestBuilder, Url};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Time allowed for a whole outbound request unless `HTTP_TIMEOUT_SECS` says otherwise
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Time allowed to open a connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Redirects followed before giving up, as reqwest's default policy does
+const MAX_REDIRECTS: usize = 10;
+
+/// Request refused because its host isn't in `HTTP_ALLOWED_HOSTS`
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("Outbound request to {0} is not allowed")]
+pub struct EgressDenied(pub String);
+
+/// Hosts outbound requests may go to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AllowedHosts(Vec<String>);
+
+impl AllowedHosts {
+    /// Hosts from a comma-separated list; `*.example.com` also allows its subdomains
+    pub fn parse(list: &str) -> Self {
+        Self(
+            list.split(',')
+                .map(|host| host.trim().to_ascii_lowercase())
+                .filter(|host| !host.is_empty())
+                .collect(),
+        )
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn allows(&self, host: &str) -> bool {
+        let host = host.to_ascii_lowercase();
+        self.0.iter().any(|allowed| match allowed.strip_prefix("*.") {
+            Some(domain) => host == domain || host.strip_suffix(domain).is_some_and(|sub| sub.ends_with('.')),
+            None => host == *allowed,
+        })
+    }
+
+    fn allows_url(&self, url: &Url) -> bool {
+        url.host_str().is_some_and(|host| self.allows(host))
+    }
+}
+
+/// Settings for the gateway's outbound HTTP calls
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpConfig {
+    pub timeout: Duration,
+    /// `None` allows every host
+    pub allowed_hosts: Option<AllowedHosts>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self { timeout: DEFAULT_TIMEOUT, allowed_hosts: None }
+    }
+}
+
+/// The client every outbound call of the gateway goes through
+///
+/// Proxies come from `HTTPS_PROXY`/`HTTP_PROXY`/`ALL_PROXY` and `NO_PROXY`,
+/// which reqwest reads when the client is built. With an allow-list,
+/// requests to other hosts fail before anything is sent, and so do
+/// redirects to them. Clones share one connection pool.
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    client: Client,
+    allowed_hosts: Option<Arc<AllowedHosts>>,
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        Self::new(HttpConfig::default()).expect("HTTP client with default settings")
+    }
+}
+
+impl HttpClient {
+    pub fn new(config: HttpConfig) -> Result<Self, reqwest::Error> {
+        let allowed_hosts = config.allowed_hosts.map(Arc::new);
+        let redirect_hosts = allowed_hosts.clone();
+        let redirects = Policy::custom(move |attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                return attempt.error("too many redirects");
+            }
+            match &redirect_hosts {
+                Some(hosts) if !hosts.allows_url(attempt.url()) => {
+                    let denied = EgressDenied(attempt.url().host_str().unwrap_or_default().to_string());
+                    attempt.error(denied)
+                }
+                _ => attempt.follow(),
+            }
+        });
+
+        let client = Client::builder()
+            .timeout(config.timeout)
+            .connect_timeout(CONNECT_TIMEOUT.min(config.timeout))
+            .redirect(redirects)
+            .build()?;
+        Ok(Self { client, allowed_hosts })
+    }
+
+    /// Request to `url`, unless its host isn't allowed
+    pub fn request(&self, method: Method, url: &str) -> Result<RequestBuilder, EgressDenied> {
+        self.check(url)?;
+        Ok(self.client.request(method, url))
+    }
+
+    pub fn get(&self, url: &str) -> Result<RequestBuilder, EgressDenied> {
+        self.request(Method::GET, url)
+    }
+
+    pub fn post(&self, url: &str) -> Result<RequestBuilder, EgressDenied> {
+        self.request(Method::POST, url)
+    }
+
+    pub fn put(&self, url: &str) -> Result<RequestBuilder, EgressDenied> {
+        self.request(Method::PUT, url)
+    }
+
+    pub fn delete(&self, url: &str) -> Result<RequestBuilder, EgressDenied> {
+        self.request(Method::DELETE, url)
+    }
+
+    fn check(&self, url: &str) -> Result<(), EgressDenied> {
+        let Some(hosts) = &self.allowed_hosts else {
+            return Ok(());
+        };
+        match Url::parse(url) {
+            Ok(parsed) if hosts.allows_url(&parsed) => Ok(()),
+            Ok(parsed) => Err(EgressDenied(parsed.host_str().unwrap_or(url).to_string())),
+            Err(_) => Err(EgressDenied(url.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allowed_hosts() {
+        let hosts = AllowedHosts::parse(" api.openai.com, *.Supabase.co ,,");
+        assert!(hosts.allows("api.openai.com"));
+        assert!(hosts.allows("API.OpenAI.com"));
+        assert!(hosts.allows("project.supabase.co"));
+        assert!(hosts.allows("supabase.co"));
+        assert!(!hosts.allows("openai.com"));
+        assert!(!hosts.allows("evilsupabase.co"));
+        assert!(!hosts.allows("api.openai.com.evil.net"));
+    }
+
+    #[test]
+    fn test_disallowed_host_rejected_before_sending() {
+        let client = HttpClient::new(HttpConfig {
+            timeout: DEFAULT_TIMEOUT,
+            allowed_hosts: Some(AllowedHosts::parse("api.openai.com")),
+        })
+        .unwrap();
+
+        // Nothing listens on this port; getting EgressDenied back rather than
+        // a connection error shows no request was attempted
+        let denied = client.post("http://127.0.0.1:9/v1/embeddings").unwrap_err();
+        assert_eq!(denied, EgressDenied("127.0.0.1".to_string()));
+        assert!(client.get("not a url").is_err());
+        assert!(client.post("https://api.openai.com/v1/embeddings").is_ok());
+        assert!(HttpClient::default().get("http://127.0.0.1:9/").is_ok());
+    }
+}
@@ -0,0 +1,35 @@
+/// ISO 639-3 code of the language `content` is written in, when clear
+///
+/// Short or mixed content often isn't; it is left without a language and
+/// indexed for full-text search as before, with English stemming.
+pub fn detect(content: &str) -> Option<String> {
+    whatlang::detect(content)
+        .filter(|info| info.is_reliable())
+        .map(|info| info.lang().code().to_string())
+}
+
+/// Why `lang` can't be used as a filter, if it can't; empty means any
+pub fn error(lang: &str) -> Option<String> {
+    let valid = lang.is_empty() || whatlang::Lang::from_code(lang).is_some();
+    (!valid).then(|| format!("Unknown language '{}', expected an ISO 639-3 code such as eng", lang))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect("The meeting with the design team moved to Thursday afternoon").as_deref(), Some("eng"));
+        assert_eq!(detect("来週の火曜日に東京タワーへ行く予定です").as_deref(), Some("jpn"));
+        assert_eq!(detect("Das Treffen mit dem Team wurde auf Donnerstag verschoben").as_deref(), Some("deu"));
+        assert_eq!(detect("ok"), None);
+    }
+
+    #[test]
+    fn test_filter_validated() {
+        assert_eq!(error(""), None);
+        assert_eq!(error("jpn"), None);
+        assert!(error("japanese").is_some());
+    }
+}
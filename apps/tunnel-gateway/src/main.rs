@@ -1,18 +1,61 @@
+
 use tonic::transport::Server;
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::Arc;
 use dotenvy::dotenv;
 use std::env;
 
+mod access;
+mod access_log;
+mod ann;
+mod compaction;
+mod config;
+mod content_type;
+mod data_dir;
 mod database;
+mod dedup;
+mod embedding;
+mod embedding_cache;
+mod export;
+mod grpc_web;
+mod http;
+mod lang;
+mod listen;
+mod metrics;
+mod migrations;
+mod pagination;
+mod quantize;
+mod quota;
+mod reembed;
 mod services;
+mod shutdown;
+mod trash;
+mod watch;
+mod write_limit;
 pub mod ipc_client;
 mod auth;
 
+use access::AccessTracker;
+use access_log::AccessLogLayer;
+use ann::AnnIndex;
+use config::GatewayConfig;
 use database::MemoryDatabase;
+use embedding_cache::{EmbeddingCache, EmbeddingCacheConfig};
+use grpc_web::GrpcWebConfig;
+use http::HttpClient;
+use listen::ListenConfig;
+use services::health::HealthService;
 use services::memory::MemoryServiceImpl;
 use services::vault::VaultServiceImpl;
-use auth::{SupabaseClient, AuthServiceImpl};
+use ipc_client::{PoolConfig, VaultClientPool};
+use auth::AuthServiceImpl;
+use auth::middleware::AuthInterceptor;
+use auth::lockout::AccountLockout;
+use auth::rate_limit::LoginRateLimiter;
 use identra_proto::auth::auth_service_server::AuthServiceServer;
+use identra_proto::health::health_check_response::ServingStatus;
+use shutdown::{InFlight, InFlightLayer};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -22,28 +65,262 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     tracing::info!("Starting Gateway...");
 
+    // Settings from GATEWAY_CONFIG overridden by the environment; refuse to start invalid
+    let config = GatewayConfig::load().map_err(|e| {
+        tracing::error!("{}", e);
+        e
+    })?;
+
+    // Files of the gateway's own go here (data_dir), whatever the working directory
+    let data_dir = data_dir::open(config.data_dir.clone()).map_err(|e| {
+        tracing::error!("{}", e);
+        e
+    })?;
+    tracing::info!("Data directory: {}", data_dir.display());
+
     // Connect to Postgres
-    let db_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set in .env");
-    let db = Arc::new(MemoryDatabase::connect(&db_url).await?);
+    let db_url = config.database.url.as_deref().expect("validated with the rest of the config");
+    let quota = config.quota.storage_quota();
+    let db = MemoryDatabase::connect_with_options(
+        db_url,
+        Some(config.database.pool_size),
+        Some(config.database.lock_timeout()),
+    )
+    .await?;
+    let db = Arc::new(db.with_storage_quota(quota));
+    if !quota.is_unlimited() {
+        tracing::info!("Storage quota per user: {:?} memories, {:?} bytes", quota.max_memories, quota.max_bytes);
+    }
+
+    // One client for all outbound calls (HTTP_TIMEOUT_SECS, HTTP_ALLOWED_HOSTS, HTTPS_PROXY)
+    let http = HttpClient::new(config.http.config())
+        .map_err(|e| format!("Failed to build HTTP client: {}", e))?;
+
+    // Initialize the auth backend (auth.backend=supabase); refuse to start misconfigured
+    let auth_backend = auth::backend::backend_from_config(&config.auth, &http).map_err(|e| {
+        tracing::error!("{}", e);
+        e
+    })?;
+    tracing::info!("Auth backend initialized");
+
+    // Initialize embedding provider (embedding.provider=fastembed|openai|hash)
+    let embedder = embedding::provider_from_settings(&config.embedding, &data_dir, &http)?;
+    tracing::info!("Embedding provider ready ({} dimensions)", embedder.dimension());
+
+    // `tunnel-gateway reembed` rewrites every embedding with this provider, then exits
+    if env::args().nth(1).as_deref() == Some("reembed") {
+        let reembedded = reembed::reembed_all(&db, embedder.as_ref()).await.map_err(|e| {
+            tracing::error!("{}", e);
+            e
+        })?;
+        tracing::info!("Re-embedded {} memories ({} dimensions)", reembedded, embedder.dimension());
+        return Ok(());
+    }
+    db.check_embedding_dimension(embedder.dimension()).await.map_err(|e| {
+        tracing::error!("{}", e);
+        e
+    })?;
+
+    // Text embedded before isn't sent to the provider again (embedding.cache_size, embedding.cache_persist)
+    let embedding_cache = EmbeddingCacheConfig::from_settings(&config.embedding, &data_dir).map(|cache_config| {
+        let cache = Arc::new(EmbeddingCache::new(embedder.model(), embedder.dimension(), cache_config.capacity));
+        if let Some(file) = &cache_config.file {
+            match cache.load(file) {
+                Ok(loaded) => tracing::info!("Loaded {} cached embeddings from {}", loaded, file.display()),
+                Err(e) => tracing::warn!("Failed to load embedding cache {}: {}", file.display(), e),
+            }
+        }
+        (cache, cache_config.file)
+    });
+
+    // Build the ANN search index from stored embeddings (ann.index=hnsw)
+    let ann_index = match config.ann.config() {
+        Some(config) => {
+            let embeddings = db.live_embeddings().await?;
+            let dimension = embedder.dimension();
+            let index = tokio::task::spawn_blocking(move || AnnIndex::build(config, dimension, embeddings)).await?;
+            tracing::info!("ANN index built over {} memories (ef_search {})", index.memory_count(), config.ef_search);
+            Some(Arc::new(index))
+        }
+        None => None,
+    };
 
-    // Initialize Supabase client for authentication
-    let supabase = Arc::new(SupabaseClient::new()?);
-    tracing::info!("Supabase Auth client initialized");
+    // Reported to clients by Health.GetCapabilities
+    let capabilities = services::health::capabilities(
+        db.fts_enabled(),
+        ann_index.is_some(),
+        &config.auth.backend,
+        embedder.as_ref(),
+    );
 
     // Initialize services
-    let memory_service = MemoryServiceImpl::new(db.clone());
-    let auth_service = AuthServiceImpl::new(supabase);
-    let vault_service = VaultServiceImpl::new();
+    let trash_retention = config.trash.retention();
+    let mut memory_service = MemoryServiceImpl::new(db.clone(), embedder, AuthInterceptor::new(auth_backend.clone()))
+        .with_trash_retention(trash_retention)
+        .with_max_content_bytes(config.limits.max_content_bytes);
+    if let Some(ann_index) = ann_index {
+        memory_service = memory_service.with_ann_index(ann_index);
+    }
+    if let Some((cache, _)) = &embedding_cache {
+        memory_service = memory_service.with_embedding_cache(cache.clone());
+    }
+    // Reads are counted in memory and written every few seconds
+    let access_tracker = Arc::new(AccessTracker::default());
+    access::spawn_flush_task(access_tracker.clone(), db.clone());
+    memory_service = memory_service.with_access_tracking(access_tracker.clone());
+    if let Some(write_limit) = config.rate_limit.write_limit() {
+        tracing::info!("Memory writes limited to {}/s per user (burst {})", write_limit.rate, write_limit.burst);
+        memory_service = memory_service.with_write_limit(write_limit);
+    }
+    trash::spawn_purge_task(db.clone(), trash_retention);
+    if let Some(interval) = config.compaction.interval() {
+        compaction::spawn_compaction_task(db.clone(), interval);
+    }
+    let login_limiter = LoginRateLimiter::new(config.rate_limit.login_limiter());
+    let lockout = AccountLockout::new(db.pool(), config.rate_limit.lockout());
+    lockout.migrate().await?;
+    let vault_pool = VaultClientPool::new(PoolConfig::default());
+    let vault_service = VaultServiceImpl::new(vault_pool.clone(), AuthInterceptor::new(auth_backend.clone()));
+    let auth_service = AuthServiceImpl::new(auth_backend, db.clone(), vault_pool.clone(), login_limiter, lockout)
+        .with_password_policy(config.password.policy());
+    // Readiness probes check both; `liveness` probes check neither
+    let health_service = HealthService::new()
+        .with_check(db.pool())
+        .with_check(vault_pool)
+        .with_capabilities(capabilities);
+    let health_status = health_service.status_handle();
+
+    // Prometheus scrape endpoint, only when metrics.addr is set
+    match config.metrics.addr {
+        Some(metrics_addr) => {
+            metrics::install(metrics_addr).map_err(|e| {
+                tracing::error!("{}", e);
+                e
+            })?;
+            tracing::info!("Serving metrics on http://{}/metrics", metrics_addr);
+        }
+        None => tracing::info!("Metrics disabled (METRICS_ADDR not set)"),
+    }
+
+    let listen = ListenConfig::from_settings(&config.listen).map_err(|e| {
+        tracing::error!("Invalid listen configuration: {}", e);
+        e
+    })?;
+    let addr = listen.addr;
+    tracing::info!(
+        "Listening on {} (TLS {})",
+        addr,
+        if listen.tls.is_some() { "enabled" } else { "disabled" }
+    );
+
+    // gRPC-Web for browser clients on a second port (grpc_web.addr)
+    let grpc_web = GrpcWebConfig::from_settings(&config.grpc_web).map_err(|e| {
+        tracing::error!("Invalid gRPC-Web configuration: {}", e);
+        e
+    })?;
+
+    let mut builder = Server::builder();
+    if let Some(tls) = listen.tls.clone() {
+        builder = builder.tls_config(tls)?;
+    }
+
+    let in_flight = InFlight::default();
+    let (stop_tx, stop_rx) = tokio::sync::watch::channel(());
+    let stopped = |mut stop_rx: tokio::sync::watch::Receiver<()>| async move {
+        stop_rx.changed().await.ok();
+    };
+
+    // Compression stays off (no accept_compressed): tonic checks a message's
+    // compressed length against these limits, not what it inflates to
+    let limits = &config.limits;
+    let health_server = health_service.into_server()
+        .max_decoding_message_size(limits.max_request_bytes)
+        .max_encoding_message_size(limits.max_response_bytes);
+    let memory_server = memory_service.into_server()
+        .max_decoding_message_size(limits.max_request_bytes)
+        .max_encoding_message_size(limits.max_response_bytes);
+    let auth_server = AuthServiceServer::new(auth_service)
+        .max_decoding_message_size(limits.max_request_bytes)
+        .max_encoding_message_size(limits.max_response_bytes);
+    let vault_server = vault_service.into_server()
+        .max_decoding_message_size(limits.max_request_bytes)
+        .max_encoding_message_size(limits.max_response_bytes);
+
+    let server = builder
+        .layer(InFlightLayer::new(in_flight.clone()))
+        .layer(AccessLogLayer)
+        .add_service(health_server.clone())
+        .add_service(memory_server.clone())
+        .add_service(auth_server.clone())
+        .add_service(vault_server)
+        .serve_with_shutdown(addr, stopped(stop_rx.clone()));
+    tokio::pin!(server);
+
+    // The vault service stays off gRPC-Web: key material is for local clients only
+    let web_enabled = grpc_web.is_some();
+    let web_server: Pin<Box<dyn Future<Output = Result<(), tonic::transport::Error>> + Send>> = match grpc_web {
+        Some(web) => {
+            tracing::info!("Serving gRPC-Web on {} (origins: {:?})", web.addr, web.allowed_origins);
+            let mut web_builder = Server::builder().accept_http1(true);
+            if let Some(tls) = listen.tls {
+                web_builder = web_builder.tls_config(tls)?;
+            }
+            Box::pin(
+                web_builder
+                    .layer(web.cors_layer())
+                    .layer(tonic_web::GrpcWebLayer::new())
+                    .layer(InFlightLayer::new(in_flight.clone()))
+                    .layer(AccessLogLayer)
+                    .add_service(health_server)
+                    .add_service(memory_server)
+                    .add_service(auth_server)
+                    .serve_with_shutdown(web.addr, stopped(stop_rx)),
+            )
+        }
+        None => Box::pin(std::future::ready(Ok(()))),
+    };
+    let mut web_server = web_server;
+
+    tokio::select! {
+        result = &mut server => result?,
+        result = &mut web_server, if web_enabled => result?,
+        _ = shutdown::shutdown_signal() => {
+            // Fail health checks first so load balancers stop routing here
+            *health_status.write().await = ServingStatus::NotServing;
 
-    let addr = "[::1]:50051".parse()?;
-    tracing::info!("Listening on {}", addr);
+            let grace = config.shutdown.grace_period();
+            let active = in_flight.count();
+            tracing::info!("🛑 Shutdown signal received, draining {} in-flight requests ({:?} grace)", active, grace);
 
-    Server::builder()
-        .add_service(memory_service.into_server())
-        .add_service(AuthServiceServer::new(auth_service))
-        .add_service(vault_service.into_server())
-        .serve(addr)
-        .await?;
+            stop_tx.send(()).ok();
+            match tokio::time::timeout(grace, async { tokio::join!(&mut server, &mut web_server) }).await {
+                Ok((result, web_result)) => {
+                    result?;
+                    web_result?;
+                    tracing::info!("Drained {} requests", active);
+                }
+                Err(_) => {
+                    let remaining = in_flight.count();
+                    tracing::warn!(
+                        "Grace period elapsed: drained {} requests, abandoning {}",
+                        active.saturating_sub(remaining),
+                        remaining
+                    );
+                }
+            }
+        }
+    }
 
+    // Don't lose the reads counted since the last flush
+    if let Err(e) = access_tracker.flush(&db).await {
+        tracing::warn!("Failed to record memory reads: {}", e);
+    }
+    if let Some((cache, Some(file))) = &embedding_cache {
+        match cache.save(file) {
+            Ok(()) => tracing::info!("Saved {} cached embeddings to {}", cache.embedding_count(), file.display()),
+            Err(e) => tracing::warn!("Failed to save embedding cache {}: {}", file.display(), e),
+        }
+    }
+    tracing::info!("Gateway stopped");
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,119 @@
+use metrics_exporter_prometheus::{BuildError, PrometheusBuilder};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tonic::Code;
+
+/// Latency buckets in seconds, shared by every histogram we export
+const LATENCY_BUCKETS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Debug, Error)]
+pub enum MetricsConfigError {
+    #[error("Failed to start metrics exporter: {0}")]
+    Exporter(#[from] BuildError),
+}
+
+/// Serve Prometheus metrics over plain HTTP on `addr`
+///
+/// Must be called from inside the Tokio runtime. Until it is, the
+/// `record_*` functions below are no-ops, so the gateway pays nothing
+/// for metrics when `METRICS_ADDR` is unset.
+pub fn install(addr: SocketAddr) -> Result<(), MetricsConfigError> {
+    builder().with_http_listener(addr).install()?;
+    Ok(())
+}
+
+fn builder() -> PrometheusBuilder {
+    PrometheusBuilder::new()
+        .set_buckets(LATENCY_BUCKETS)
+        .expect("LATENCY_BUCKETS is not empty")
+}
+
+/// Count a finished gRPC call and record how long it took
+pub fn record_rpc(method: &str, code: Code, elapsed: Duration) {
+    // Unknown paths are answered with Unimplemented; don't let callers mint
+    // a new label value per made-up method
+    let method = if code == Code::Unimplemented { "unknown".to_string() } else { method.to_string() };
+
+    metrics::counter!(
+        "gateway_grpc_requests_total",
+        "method" => method.clone(),
+        "code" => format!("{:?}", code),
+    )
+    .increment(1);
+    metrics::histogram!("gateway_grpc_request_duration_seconds", "method" => method)
+        .record(elapsed.as_secs_f64());
+}
+
+/// Records the time until it is dropped into a latency histogram
+pub struct Timer {
+    histogram: metrics::Histogram,
+    start: Instant,
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.histogram.record(self.start.elapsed().as_secs_f64());
+    }
+}
+
+/// Time a database query, labelled with the `MemoryDatabase` method name
+pub fn time_db_query(query: &'static str) -> Timer {
+    Timer {
+        histogram: metrics::histogram!("gateway_db_query_duration_seconds", "query" => query),
+        start: Instant::now(),
+    }
+}
+
+/// Time a call to the embedding provider (`embed` or `embed_batch`)
+pub fn time_embedding(operation: &'static str) -> Timer {
+    Timer {
+        histogram: metrics::histogram!("gateway_embedding_duration_seconds", "operation" => operation),
+        start: Instant::now(),
+    }
+}
+
+pub fn record_memories_stored(count: usize) {
+    metrics::counter!("gateway_memories_stored_total").increment(count as u64);
+}
+
+pub fn record_memories_retrieved(count: usize) {
+    metrics::counter!("gateway_memories_retrieved_total").increment(count as u64);
+}
+
+pub fn record_vault_keys_stored() {
+    metrics::counter!("gateway_vault_keys_stored_total").increment(1);
+}
+
+pub fn record_vault_keys_retrieved() {
+    metrics::counter!("gateway_vault_keys_retrieved_total").increment(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_renders_recorded_metrics() {
+        let recorder = builder().build_recorder();
+        let handle = recorder.handle();
+
+        metrics::with_local_recorder(&recorder, || {
+            record_rpc("/identra.memory.MemoryService/StoreMemory", Code::Ok, Duration::from_millis(3));
+            record_rpc("/made.up/Method", Code::Unimplemented, Duration::ZERO);
+            drop(time_db_query("store_memory"));
+            record_memories_stored(2);
+        });
+
+        let output = handle.render();
+        assert!(output.contains(
+            r#"gateway_grpc_requests_total{method="/identra.memory.MemoryService/StoreMemory",code="Ok"} 1"#
+        ));
+        assert!(output.contains(r#"gateway_grpc_requests_total{method="unknown",code="Unimplemented"} 1"#));
+        assert!(!output.contains("made.up"));
+        assert!(output.contains(r#"gateway_db_query_duration_seconds_count{query="store_memory"} 1"#));
+        assert!(output.contains("gateway_memories_stored_total 2"));
+    }
+}
@@ -0,0 +1,181 @@
+use sqlx::postgres::{PgPool, Postgres};
+use sqlx::{Row, Transaction};
+
+// One row per component (e.g. "memories"), holding the highest migration
+// applied to its tables
+const SCHEMA_VERSION_DDL: &str = r#"
+    CREATE TABLE IF NOT EXISTS schema_version (
+        component TEXT PRIMARY KEY,
+        version INTEGER NOT NULL,
+        applied_at BIGINT NOT NULL
+    )
+"#;
+
+/// One schema change, applied at most once per database
+///
+/// Statements must be idempotent (`IF NOT EXISTS` and the like): databases
+/// set up before versioning start from version 0 and replay every
+/// migration over a schema that may already have it.
+pub struct Migration {
+    pub version: i32,
+    pub name: &'static str,
+    pub statements: &'static [&'static str],
+}
+
+/// Apply `component`'s migrations newer than its recorded version, in order
+///
+/// Each migration commits together with its version bump, so a failing one
+/// is rolled back whole and returned as the error, leaving the database at
+/// the previous version. Concurrent callers are serialized by an advisory
+/// lock. Returns the version the schema is now at.
+pub async fn migrate(pool: &PgPool, component: &str, migrations: &[Migration]) -> Result<i32, sqlx::Error> {
+    debug_assert!(
+        migrations.windows(2).all(|w| w[0].version < w[1].version),
+        "migration versions must increase"
+    );
+    let mut tx = begin_schema_change(pool).await?;
+    sqlx::query(SCHEMA_VERSION_DDL).execute(&mut *tx).await?;
+    tx.commit().await?;
+
+    let latest = migrations.last().map_or(0, |m| m.version);
+    let mut version = 0;
+    for migration in migrations {
+        let mut tx = begin_schema_change(pool).await?;
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext('schema_version'), hashtext($1))")
+            .bind(component)
+            .execute(&mut *tx)
+            .await?;
+
+        version = current_version(&mut tx, component).await?;
+        if version > latest {
+            return Err(sqlx::Error::Configuration(format!(
+                "{} schema is at version {}, newer than the {} this build knows",
+                component, version, latest
+            ).into()));
+        }
+        if migration.version <= version {
+            continue;
+        }
+
+        for statement in migration.statements {
+            sqlx::query(statement).execute(&mut *tx).await.map_err(|e| {
+                tracing::error!("{} migration {} ({}) failed: {}", component, migration.version, migration.name, e);
+                e
+            })?;
+        }
+        sqlx::query(
+            r#"
+            INSERT INTO schema_version (component, version, applied_at) VALUES ($1, $2, $3)
+            ON CONFLICT (component) DO UPDATE SET version = $2, applied_at = $3
+            "#
+        )
+        .bind(component)
+        .bind(migration.version)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        tracing::info!("Migrated {} schema to version {} ({})", component, migration.version, migration.name);
+        version = migration.version;
+    }
+    Ok(version)
+}
+
+/// Start a transaction for the schema changes made at startup
+///
+/// The pool's connections give up on locks after their `lock_timeout`,
+/// which suits requests but not these: a gateway starting while another
+/// migrates must wait for it, however long that takes, instead of failing
+/// to start.
+pub async fn begin_schema_change(pool: &PgPool) -> Result<Transaction<'static, Postgres>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("SET LOCAL lock_timeout = 0").execute(&mut *tx).await?;
+    Ok(tx)
+}
+
+async fn current_version(tx: &mut sqlx::PgConnection, component: &str) -> Result<i32, sqlx::Error> {
+    let row = sqlx::query("SELECT version FROM schema_version WHERE component = $1")
+        .bind(component)
+        .fetch_optional(tx)
+        .await?;
+    Ok(row.map_or(0, |row| row.get("version")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::tests::{drop_scratch_schema, scratch_schema, scratch_url};
+    use std::str::FromStr;
+
+    const GOOD: &[Migration] = &[
+        Migration { version: 1, name: "create", statements: &["CREATE TABLE IF NOT EXISTS t (id INTEGER)"] },
+        Migration { version: 2, name: "seed", statements: &["INSERT INTO t VALUES (1)"] },
+    ];
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_applies_each_migration_once() {
+        let (pool, schema) = scratch_schema("migrate").await;
+
+        assert_eq!(migrate(&pool, "test", GOOD).await.unwrap(), 2);
+        assert_eq!(migrate(&pool, "test", GOOD).await.unwrap(), 2);
+        let rows: i64 = sqlx::query("SELECT COUNT(*) AS n FROM t").fetch_one(&pool).await.unwrap().get("n");
+        assert_eq!(rows, 1, "seed must not run twice");
+
+        // A build that knows fewer migrations refuses the newer schema
+        assert!(migrate(&pool, "test", &GOOD[..1]).await.is_err());
+
+        drop_scratch_schema(&schema).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_failed_migration_is_rolled_back() {
+        let (pool, schema) = scratch_schema("migrate").await;
+        let broken = &[
+            Migration { version: 1, name: "create", statements: GOOD[0].statements },
+            Migration {
+                version: 2,
+                name: "broken",
+                statements: &["INSERT INTO t VALUES (1)", "INSERT INTO missing VALUES (1)"],
+            },
+        ];
+
+        assert!(migrate(&pool, "test", broken).await.is_err());
+        let mut conn = pool.acquire().await.unwrap();
+        assert_eq!(current_version(&mut conn, "test").await.unwrap(), 1);
+        let rows: i64 = sqlx::query("SELECT COUNT(*) AS n FROM t").fetch_one(&pool).await.unwrap().get("n");
+        assert_eq!(rows, 0, "the failed migration's first statement must be undone");
+
+        drop_scratch_schema(&schema).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_waits_for_concurrent_migration_past_lock_timeout() {
+        let (holder, schema) = scratch_schema("migrate").await;
+        let options = sqlx::postgres::PgConnectOptions::from_str(&scratch_url(&schema))
+            .unwrap()
+            .options([("lock_timeout", "100ms")]);
+        let pool = PgPool::connect_with(options).await.unwrap();
+        let component = schema.as_str();
+
+        // Another instance holds the migration lock well past the timeout
+        let mut tx = holder.begin().await.unwrap();
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext('schema_version'), hashtext($1))")
+            .bind(component)
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+        let release = tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+            tx.commit().await.unwrap();
+        });
+
+        assert_eq!(migrate(&pool, component, GOOD).await.unwrap(), 2);
+        release.await.unwrap();
+
+        drop_scratch_schema(&schema).await;
+    }
+}
@@ -0,0 +1,91 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use sha2::{Digest, Sha256};
+use tonic::Status;
+
+/// Opaque cursor for paged queries
+///
+/// Encodes the row offset together with a hash of the query it belongs to,
+/// so a token can't be replayed against a different query string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageToken {
+    pub offset: i64,
+    query_hash: String,
+}
+
+impl PageToken {
+    pub fn new(query: &str, offset: i64) -> Self {
+        Self {
+            offset,
+            query_hash: hash_query(query),
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(format!("{}:{}", self.offset, self.query_hash))
+    }
+
+    /// Decode `token` and check it was issued for `query`
+    ///
+    /// An empty token means "first page" and yields offset 0.
+    #[allow(clippy::result_large_err)]
+    pub fn decode(token: &str, query: &str) -> Result<Self, Status> {
+        if token.is_empty() {
+            return Ok(Self::new(query, 0));
+        }
+
+        let invalid = || Status::invalid_argument("Invalid page token");
+
+        let raw = URL_SAFE_NO_PAD.decode(token).map_err(|_| invalid())?;
+        let raw = String::from_utf8(raw).map_err(|_| invalid())?;
+        let (offset, query_hash) = raw.split_once(':').ok_or_else(invalid)?;
+        let offset: i64 = offset.parse().map_err(|_| invalid())?;
+
+        if offset < 0 {
+            return Err(invalid());
+        }
+        if query_hash != hash_query(query) {
+            return Err(Status::invalid_argument("Page token does not match query"));
+        }
+
+        Ok(Self {
+            offset,
+            query_hash: query_hash.to_string(),
+        })
+    }
+}
+
+fn hash_query(query: &str) -> String {
+    let digest = Sha256::digest(query.as_bytes());
+    digest[..8].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let token = PageToken::new("rust", 50).encode();
+        let decoded = PageToken::decode(&token, "rust").unwrap();
+
+        assert_eq!(decoded.offset, 50);
+    }
+
+    #[test]
+    fn test_empty_token_is_first_page() {
+        assert_eq!(PageToken::decode("", "anything").unwrap().offset, 0);
+    }
+
+    #[test]
+    fn test_token_from_other_query_rejected() {
+        let token = PageToken::new("rust", 50).encode();
+        let err = PageToken::decode(&token, "python").unwrap_err();
+
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[test]
+    fn test_garbage_token_rejected() {
+        assert!(PageToken::decode("not-a-token!!", "rust").is_err());
+    }
+}
@@ -0,0 +1,125 @@
+use hnsw_rs::prelude::Distance;
+
+/// Largest magnitude of a quantized component
+const LEVELS: f32 = 127.0;
+
+/// Size of one quantization step of `embedding`, once normalized
+fn step(embedding: &[f32]) -> f32 {
+    let norm = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let max = embedding.iter().fold(0.0f32, |max, x| max.max(x.abs()));
+    // Spend the full byte range on the largest component
+    max / norm / LEVELS
+}
+
+/// Store `embedding` as one signed byte per dimension
+///
+/// The vector is normalized first, so every component lies in [-1, 1] and
+/// rounding is off by at most half a step, whatever the embedding's length.
+/// The step itself is dropped: cosine distance doesn't depend on it.
+pub fn quantize(embedding: &[f32]) -> Vec<i8> {
+    let norm = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let step = step(embedding);
+    if !step.is_normal() {
+        return vec![0; embedding.len()];
+    }
+    embedding.iter()
+        .map(|x| (x / norm / step).round().clamp(-LEVELS, LEVELS) as i8)
+        .collect()
+}
+
+/// Exact integer dot product; cannot overflow below 133k dimensions
+pub fn dot_i8(a: &[i8], b: &[i8]) -> i32 {
+    a.iter().zip(b).map(|(&x, &y)| x as i32 * y as i32).sum()
+}
+
+/// Cosine distance between quantized vectors, for the HNSW graph
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DistCosineI8;
+
+impl Distance<i8> for DistCosineI8 {
+    fn eval(&self, va: &[i8], vb: &[i8]) -> f32 {
+        let norms = (dot_i8(va, va) as f32 * dot_i8(vb, vb) as f32).sqrt();
+        if norms == 0.0 {
+            return 1.0;
+        }
+        (1.0 - dot_i8(va, vb) as f32 / norms).max(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random_vector(state: &mut u64, dim: usize) -> Vec<f32> {
+        (0..dim)
+            .map(|_| {
+                *state ^= *state << 13;
+                *state ^= *state >> 7;
+                *state ^= *state << 17;
+                (*state % 2001) as f32 / 1000.0 - 1.0
+            })
+            .collect()
+    }
+
+    fn cosine(a: &[f32], b: &[f32]) -> f32 {
+        let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+        let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+        dot / (norm(a) * norm(b))
+    }
+
+    #[test]
+    fn test_reconstruction_error_within_half_a_step() {
+        let mut state = 0x9e37_79b9_7f4a_7c15;
+        for _ in 0..100 {
+            let embedding: Vec<f32> = random_vector(&mut state, 384).iter().map(|x| x * 40.0).collect();
+            let norm = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+            let step = step(&embedding);
+            let restored: Vec<f32> = quantize(&embedding).iter().map(|&v| v as f32 * step).collect();
+
+            for (original, restored) in embedding.iter().zip(&restored) {
+                assert!((original / norm - restored).abs() <= step / 2.0 + 1e-6);
+            }
+            assert!(cosine(&embedding, &restored) > 0.999);
+        }
+        assert_eq!(quantize(&[0.0; 4]), vec![0; 4]);
+    }
+
+    #[test]
+    fn test_ranking_preserved() {
+        let mut state = 0x2545_f491_4f6c_dd1d;
+        let rows: Vec<Vec<f32>> = (0..500).map(|_| random_vector(&mut state, 384)).collect();
+        let quantized: Vec<_> = rows.iter().map(|row| quantize(row)).collect();
+
+        let (mut found, mut expected) = (0, 0);
+        for _ in 0..20 {
+            let query = random_vector(&mut state, 384);
+            let query_q = quantize(&query);
+
+            let top = |score: &dyn Fn(usize) -> f32| {
+                let mut ranked: Vec<usize> = (0..rows.len()).collect();
+                ranked.sort_by(|&a, &b| score(b).total_cmp(&score(a)));
+                ranked.truncate(10);
+                ranked
+            };
+            let exact = top(&|i| cosine(&query, &rows[i]));
+            let approximate = top(&|i| -DistCosineI8.eval(&query_q, &quantized[i]));
+
+            assert_eq!(exact[0], approximate[0], "best match must not change");
+            found += exact.iter().filter(|i| approximate.contains(i)).count();
+            expected += exact.len();
+        }
+        assert!(found as f64 / expected as f64 >= 0.95);
+    }
+
+    #[test]
+    fn test_distance_matches_f32_cosine() {
+        let mut state = 42;
+        let (a, b) = (random_vector(&mut state, 64), random_vector(&mut state, 64));
+        let (qa, qb) = (quantize(&a), quantize(&b));
+
+        let distance = DistCosineI8.eval(&qa, &qb);
+        assert!((distance - (1.0 - cosine(&a, &b))).abs() < 0.01);
+        assert!(DistCosineI8.eval(&qa, &qa) < 1e-6);
+        assert_eq!(DistCosineI8.eval(&[0; 64], &qa), 1.0);
+    }
+}
@@ -0,0 +1,107 @@
+use thiserror::Error;
+
+/// Per-user storage limits; `None` leaves that dimension unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StorageQuota {
+    pub max_memories: Option<i64>,
+    /// Bytes of content plus serialized metadata, as in `MemoryStats`
+    pub max_bytes: Option<i64>,
+}
+
+/// Live memories a user has and the bytes they take up
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StorageUsage {
+    pub memories: i64,
+    pub bytes: i64,
+}
+
+/// A write that would take a user past their quota
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error(
+    "Storage quota exceeded: {} of {} memories and {} of {} bytes used; this request adds {} memories, {} bytes",
+    usage.memories, limit(quota.max_memories), usage.bytes, limit(quota.max_bytes), added.memories, added.bytes
+)]
+pub struct QuotaExceeded {
+    /// Usage before the rejected write
+    pub usage: StorageUsage,
+    pub added: StorageUsage,
+    pub quota: StorageQuota,
+}
+
+fn limit(max: Option<i64>) -> String {
+    max.map_or_else(|| "unlimited".to_string(), |max| max.to_string())
+}
+
+impl StorageQuota {
+    pub fn is_unlimited(&self) -> bool {
+        self.max_memories.is_none() && self.max_bytes.is_none()
+    }
+
+    /// Accept `added` on top of `usage` if it fits within both limits
+    ///
+    /// Reaching a limit exactly is allowed, and so is anything that doesn't
+    /// grow usage (`added` is negative when an update shrinks a memory).
+    pub fn check(&self, usage: StorageUsage, added: StorageUsage) -> Result<(), QuotaExceeded> {
+        let fits = |used: i64, added: i64, max: Option<i64>| {
+            added <= 0 || max.is_none_or(|max| used.saturating_add(added) <= max)
+        };
+        if fits(usage.memories, added.memories, self.max_memories) && fits(usage.bytes, added.bytes, self.max_bytes) {
+            Ok(())
+        } else {
+            Err(QuotaExceeded { usage, added, quota: *self })
+        }
+    }
+
+    /// What's left of each limit at `usage`; `None` where there is no limit
+    pub fn remaining(&self, usage: StorageUsage) -> (Option<i64>, Option<i64>) {
+        let left = |used: i64, max: Option<i64>| max.map(|max| max.saturating_sub(used).max(0));
+        (left(usage.memories, self.max_memories), left(usage.bytes, self.max_bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUOTA: StorageQuota = StorageQuota { max_memories: Some(10), max_bytes: Some(1000) };
+
+    fn usage(memories: i64, bytes: i64) -> StorageUsage {
+        StorageUsage { memories, bytes }
+    }
+
+    #[test]
+    fn test_at_limit_accepted_over_rejected() {
+        assert!(QUOTA.check(usage(9, 900), usage(1, 100)).is_ok());
+        assert!(QUOTA.check(usage(9, 900), usage(2, 10)).is_err());
+        assert!(QUOTA.check(usage(0, 990), usage(1, 11)).is_err());
+
+        let error = QUOTA.check(usage(10, 500), usage(1, 5)).unwrap_err();
+        assert_eq!(error.usage, usage(10, 500));
+        assert_eq!(
+            error.to_string(),
+            "Storage quota exceeded: 10 of 10 memories and 500 of 1000 bytes used; this request adds 1 memories, 5 bytes"
+        );
+    }
+
+    #[test]
+    fn test_unlimited_dimensions() {
+        let count_only = StorageQuota { max_memories: Some(1), max_bytes: None };
+        assert!(count_only.check(usage(0, i64::MAX), usage(1, i64::MAX)).is_ok());
+        assert!(StorageQuota::default().is_unlimited());
+        assert_eq!(count_only.remaining(usage(0, 50)), (Some(1), None));
+    }
+
+    #[test]
+    fn test_shrinking_allowed_over_quota() {
+        // Quota lowered below what the user already stores
+        assert!(QUOTA.check(usage(20, 5000), usage(0, -100)).is_ok());
+        assert!(QUOTA.check(usage(20, 5000), usage(0, 1)).is_err());
+    }
+
+    #[test]
+    fn test_remaining_never_negative() {
+        assert_eq!(QUOTA.remaining(usage(4, 250)), (Some(6), Some(750)));
+        // Usage can exceed a quota lowered after the fact
+        assert_eq!(QUOTA.remaining(usage(12, 2000)), (Some(0), Some(0)));
+    }
+}
@@ -0,0 +1,184 @@
+use crate::ann::AnnIndex;
+use crate::database::MemoryDatabase;
+use crate::embedding::EmbeddingProvider;
+use crate::services::memory::db_status;
+use identra_proto::memory::ReindexProgress;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::Status;
+
+/// Memories embedded per provider call
+const BATCH_SIZE: i64 = 64;
+
+/// Progress messages buffered for a slow `Reindex` client
+const STREAM_BUFFER: usize = 16;
+
+pub type ReindexStream = ReceiverStream<Result<ReindexProgress, Status>>;
+
+/// Embeddings of `contents`, checked to have `embedder`'s dimension
+async fn embed_page(embedder: &dyn EmbeddingProvider, contents: &[String]) -> Result<Vec<Vec<f32>>, Status> {
+    let dimension = embedder.dimension();
+    let embeddings = embedder.embed_batch(contents).await?;
+    if embeddings.len() != contents.len() {
+        return Err(Status::internal("Embedding provider returned the wrong number of vectors"));
+    }
+    if let Some(wrong) = embeddings.iter().find(|e| e.len() != dimension) {
+        return Err(Status::internal(format!(
+            "Embedding provider returned {} dimensions instead of {}", wrong.len(), dimension
+        )));
+    }
+    Ok(embeddings)
+}
+
+/// Embed every stored memory again with `embedder`, returning how many
+///
+/// For switching to a model whose vectors can't be compared with the stored
+/// ones, e.g. of another dimension: run it with the gateway stopped (as
+/// `tunnel-gateway reembed`). Memories are rewritten a batch at a time and
+/// the new dimension is only recorded once all of them are, so an
+/// interrupted run leaves the gateway refusing to start until it is rerun.
+pub async fn reembed_all(db: &MemoryDatabase, embedder: &dyn EmbeddingProvider) -> Result<u64, String> {
+    let dimension = embedder.dimension();
+    let mut after: Option<String> = None;
+    let mut reembedded = 0;
+    loop {
+        let page = db.memory_contents_after(None, after.as_deref(), BATCH_SIZE)
+            .await
+            .map_err(|e| format!("Failed to read memories: {}", e))?;
+        let Some((last, _, _)) = page.last() else { break };
+        after = Some(last.clone());
+
+        let contents: Vec<String> = page.iter().map(|(_, content, _)| content.clone()).collect();
+        let embeddings = embed_page(embedder, &contents)
+            .await
+            .map_err(|e| format!("Failed to embed memories: {}", e.message()))?;
+
+        let rows: Vec<(String, Vec<f32>)> = page.into_iter().map(|(id, _, _)| id).zip(embeddings).collect();
+        db.replace_embeddings(&rows)
+            .await
+            .map_err(|e| format!("Failed to store embeddings: {}", e))?;
+        reembedded += rows.len() as u64;
+        tracing::info!("Re-embedded {} memories", reembedded);
+    }
+
+    db.record_embedding_dimension(dimension)
+        .await
+        .map_err(|e| format!("Failed to record embedding dimension: {}", e))?;
+    Ok(reembedded)
+}
+
+/// Embed `user_id`'s memories again with `embedder`, reporting after each batch
+///
+/// For a model swap that keeps the dimension, which `reembed_all` would
+/// otherwise need the gateway stopped for. Each batch is written together
+/// with the cursor, so a run that dies part way (or whose client goes away)
+/// resumes after the last batch written, unless `restart`. Rows are only
+/// locked while their batch is updated; reads carry on throughout. Live
+/// memories are re-indexed in `ann` as they go.
+pub async fn reindex(
+    db: Arc<MemoryDatabase>,
+    embedder: Arc<dyn EmbeddingProvider>,
+    ann: Option<Arc<AnnIndex>>,
+    user_id: String,
+    restart: bool,
+) -> Result<ReindexStream, Status> {
+    if restart {
+        db.clear_reindex_cursor(&user_id).await.map_err(db_status)?;
+    }
+    let mut cursor = db.reindex_cursor(&user_id).await.map_err(db_status)?;
+    let total = db.count_memories_after(&user_id, cursor.as_deref()).await.map_err(db_status)?;
+    if let Some(cursor) = &cursor {
+        tracing::info!("Resuming reindex of {} after {}", user_id, cursor);
+    }
+
+    let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+    tokio::spawn(async move {
+        let mut reindexed = 0;
+        loop {
+            let batch = async {
+                let page = db.memory_contents_after(Some(&user_id), cursor.as_deref(), BATCH_SIZE)
+                    .await
+                    .map_err(db_status)?;
+                let Some((last, _, _)) = page.last() else {
+                    db.clear_reindex_cursor(&user_id).await.map_err(db_status)?;
+                    return Ok(None);
+                };
+                let last = last.clone();
+
+                let contents: Vec<String> = page.iter().map(|(_, content, _)| content.clone()).collect();
+                let embeddings = embed_page(embedder.as_ref(), &contents).await?;
+                let rows: Vec<(String, Vec<f32>)> = page.iter().map(|(id, _, _)| id.clone()).zip(embeddings).collect();
+                db.reindex_batch(&user_id, &rows, &last).await.map_err(db_status)?;
+                if let Some(ann) = &ann {
+                    for ((id, embedding), (_, _, live)) in rows.iter().zip(&page) {
+                        if *live {
+                            ann.insert(&user_id, id, embedding);
+                        }
+                    }
+                }
+                Ok::<_, Status>(Some((last, rows.len() as i64)))
+            }
+            .await;
+
+            let progress = match batch {
+                Ok(Some((last, count))) => {
+                    reindexed += count;
+                    cursor = Some(last);
+                    ReindexProgress { reindexed, total, cursor: cursor.clone().unwrap_or_default(), done: false }
+                }
+                Ok(None) => {
+                    tracing::info!("Reindexed {} memories of {}", reindexed, user_id);
+                    let done = ReindexProgress { reindexed, total, cursor: cursor.unwrap_or_default(), done: true };
+                    let _ = tx.send(Ok(done)).await;
+                    return;
+                }
+                Err(status) => {
+                    tracing::error!("Reindex of {} stopped after {} memories: {}", user_id, reindexed, status.message());
+                    let _ = tx.send(Err(status)).await;
+                    return;
+                }
+            };
+            if tx.send(Ok(progress)).await.is_err() {
+                tracing::info!("Reindex of {} paused after {} memories; its client went away", user_id, reindexed);
+                return;
+            }
+        }
+    });
+    Ok(ReceiverStream::new(rx))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::tests::{drop_scratch_schema, scratch_schema, scratch_url};
+    use crate::embedding::HashEmbeddingProvider;
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_reembed_moves_store_to_new_dimension() {
+        let (_, schema) = scratch_schema("reembed").await;
+        let db = MemoryDatabase::connect(&scratch_url(&schema)).await.unwrap();
+        let old = HashEmbeddingProvider::new(4);
+        db.check_embedding_dimension(4).await.unwrap();
+        for i in 0..(BATCH_SIZE + 3) {
+            let content = format!("memory {}", i);
+            let embedding = old.embed(&content).await.unwrap();
+            let id = uuid::Uuid::new_v4().to_string();
+            db.store_memory("alice", &id, &content, &embedding, &HashMap::new(), &[], 0, 0).await.unwrap();
+        }
+
+        // A provider of another dimension is refused until the store is re-embedded
+        let new = HashEmbeddingProvider::new(8);
+        assert!(db.check_embedding_dimension(8).await.is_err());
+        assert_eq!(reembed_all(&db, &new).await.unwrap(), BATCH_SIZE as u64 + 3);
+        db.check_embedding_dimension(8).await.unwrap();
+        assert!(db.check_embedding_dimension(4).await.is_err());
+
+        let embeddings = db.live_embeddings().await.unwrap();
+        assert!(embeddings.iter().all(|(_, _, embedding)| embedding.len() == 8));
+
+        drop_scratch_schema(&schema).await;
+    }
+}
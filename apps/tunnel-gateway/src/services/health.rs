@@ -1,16 +1,81 @@
+use crate::embedding::EmbeddingProvider;
+use crate::ipc_client::VaultClientPool;
 use identra_proto::health::{
     health_server::{Health, HealthServer},
     HealthCheckRequest, HealthCheckResponse,
     health_check_response::ServingStatus,
+    Capabilities, GetCapabilitiesRequest,
 };
+use identra_proto::API_VERSION;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 use tonic::{Request, Response, Status};
 
+/// Service name for liveness probes: answered without touching dependencies
+pub const LIVENESS: &str = "liveness";
+
+/// Time a dependency gets to answer before it counts as unavailable
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Something the gateway can't serve without, checked on every readiness probe
+#[tonic::async_trait]
+pub trait DependencyCheck: Send + Sync {
+    /// Named in the status message when the check fails
+    fn name(&self) -> &str;
+
+    async fn check(&self) -> Result<(), String>;
+}
+
+#[tonic::async_trait]
+impl DependencyCheck for sqlx::PgPool {
+    fn name(&self) -> &str {
+        "postgres"
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        sqlx::query("SELECT 1").execute(self).await.map(|_| ()).map_err(|e| e.to_string())
+    }
+}
+
+#[tonic::async_trait]
+impl<S> DependencyCheck for VaultClientPool<S>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+{
+    fn name(&self) -> &str {
+        "vault"
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        let mut client = self.acquire().await.map_err(|e| e.to_string())?;
+        client.ping().await.map_err(|e| e.to_string())
+    }
+}
+
+/// Capabilities of a gateway started with these settings
+pub fn capabilities(full_text_search: bool, ann_index: bool, auth_backend: &str, embedder: &dyn EmbeddingProvider) -> Capabilities {
+    Capabilities {
+        api_version: API_VERSION,
+        full_text_search,
+        ann_index,
+        streaming: true,
+        auth_backend: auth_backend.to_string(),
+        embedding_dimension: embedder.dimension() as u32,
+        embedding_model: embedder.model(),
+    }
+}
+
+/// `Check` with an empty service name, or any name but `liveness`, is a
+/// readiness probe: every dependency is checked and the first one failing
+/// turns the answer to `NOT_SERVING`. `liveness` only reports the gateway's
+/// own status.
 pub struct HealthService {
     start_time: Instant,
     status: Arc<RwLock<ServingStatus>>,
+    checks: Vec<Arc<dyn DependencyCheck>>,
+    check_timeout: Duration,
+    capabilities: Capabilities,
 }
 
 impl HealthService {
@@ -18,12 +83,47 @@ impl HealthService {
         Self {
             start_time: Instant::now(),
             status: Arc::new(RwLock::new(ServingStatus::Serving)),
+            checks: Vec::new(),
+            check_timeout: CHECK_TIMEOUT,
+            capabilities: Capabilities { api_version: API_VERSION, streaming: true, ..Default::default() },
         }
     }
+
+    /// Report `capabilities` to `GetCapabilities`
+    pub fn with_capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Report not serving while `check` fails
+    pub fn with_check(mut self, check: impl DependencyCheck + 'static) -> Self {
+        self.checks.push(Arc::new(check));
+        self
+    }
+
+    
+    /// Shared handle for changing the reported status after the service starts
+    pub fn status_handle(&self) -> Arc<RwLock<ServingStatus>> {
+        self.status.clone()
+    }
     
     pub fn into_server(self) -> HealthServer<Self> {
         HealthServer::new(self)
     }
+
+    /// Message naming the first dependency that fails or doesn't answer in time
+    async fn unavailable_dependency(&self) -> Option<String> {
+        for dependency in &self.checks {
+            let failure = match tokio::time::timeout(self.check_timeout, dependency.check()).await {
+                Ok(Ok(())) => continue,
+                Ok(Err(e)) => e,
+                Err(_) => format!("no answer within {:?}", self.check_timeout),
+            };
+            tracing::warn!("Health check of {} failed: {}", dependency.name(), failure);
+            return Some(format!("{} unavailable: {}", dependency.name(), failure));
+        }
+        None
+    }
 }
 
 #[tonic::async_trait]
@@ -32,18 +132,26 @@ impl Health for HealthService {
     
     async fn check(
         &self,
-        _request: Request<HealthCheckRequest>,
+        request: Request<HealthCheckRequest>,
     ) -> Result<Response<HealthCheckResponse>, Status> {
-        let status = *self.status.read().await;
+        let mut status = *self.status.read().await;
         let uptime = self.start_time.elapsed().as_secs() as i64;
+
+        let mut message = match status {
+            ServingStatus::Serving => "Gateway is healthy".to_string(),
+            ServingStatus::NotServing => "Gateway is not serving".to_string(),
+            _ => "Unknown status".to_string(),
+        };
+        if status == ServingStatus::Serving && request.get_ref().service != LIVENESS {
+            if let Some(unavailable) = self.unavailable_dependency().await {
+                status = ServingStatus::NotServing;
+                message = unavailable;
+            }
+        }
         
         let response = HealthCheckResponse {
             status: status as i32,
-            message: match status {
-                ServingStatus::Serving => "Gateway is healthy".to_string(),
-                ServingStatus::NotServing => "Gateway is not serving".to_string(),
-                _ => "Unknown status".to_string(),
-            },
+            message,
             uptime_seconds: uptime,
         };
         
@@ -57,6 +165,13 @@ impl Health for HealthService {
         // TODO: Implement streaming health updates
         Err(Status::unimplemented("Watch not yet implemented"))
     }
+
+    async fn get_capabilities(
+        &self,
+        _request: Request<GetCapabilitiesRequest>,
+    ) -> Result<Response<Capabilities>, Status> {
+        Ok(Response::new(self.capabilities.clone()))
+    }
 }
 
 impl Default for HealthService {
@@ -64,3 +179,95 @@ impl Default for HealthService {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embedding::HashEmbeddingProvider;
+    use crate::ipc_client::{PoolConfig, VaultClientError};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Dependency that is down, or hangs, counting how often it was asked
+    struct Down {
+        hangs: bool,
+        calls: AtomicUsize,
+    }
+
+    #[tonic::async_trait]
+    impl DependencyCheck for Arc<Down> {
+        fn name(&self) -> &str {
+            "stub"
+        }
+
+        async fn check(&self) -> Result<(), String> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.hangs {
+                std::future::pending::<()>().await;
+            }
+            Err("connection refused".to_string())
+        }
+    }
+
+    async fn probe(health: &HealthService, service: &str) -> HealthCheckResponse {
+        let request = Request::new(HealthCheckRequest { service: service.to_string() });
+        health.check(request).await.unwrap().into_inner()
+    }
+
+    #[tokio::test]
+    async fn test_down_dependency_fails_readiness_only() {
+        let down = Arc::new(Down { hangs: false, calls: AtomicUsize::new(0) });
+        let health = HealthService::new().with_check(down.clone());
+
+        let ready = probe(&health, "").await;
+        assert_eq!(ready.status, ServingStatus::NotServing as i32);
+        assert_eq!(ready.message, "stub unavailable: connection refused");
+
+        let live = probe(&health, LIVENESS).await;
+        assert_eq!(live.status, ServingStatus::Serving as i32);
+        assert_eq!(down.calls.load(Ordering::SeqCst), 1, "liveness never checks dependencies");
+
+        let hung = Arc::new(Down { hangs: true, calls: AtomicUsize::new(0) });
+        let mut health = HealthService::new().with_check(hung);
+        health.check_timeout = Duration::from_millis(50);
+        let ready = probe(&health, "").await;
+        assert_eq!(ready.status, ServingStatus::NotServing as i32);
+        assert!(ready.message.starts_with("stub unavailable: no answer within"), "{}", ready.message);
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_vault_and_postgres_reported() {
+        let config = PoolConfig { connect_attempts: 1, ..PoolConfig::default() };
+        let vault = VaultClientPool::<tokio::io::DuplexStream>::with_connector(config, || {
+            Box::pin(async { Err(VaultClientError::ConnectionFailed("no socket".to_string())) })
+        });
+        let health = HealthService::new().with_check(vault);
+        let ready = probe(&health, "").await;
+        assert_eq!(ready.status, ServingStatus::NotServing as i32);
+        assert!(ready.message.starts_with("vault unavailable:"), "{}", ready.message);
+
+        // Nothing listens on port 9
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy("postgres://127.0.0.1:9/unused")
+            .unwrap();
+        let mut health = HealthService::new().with_check(pool);
+        health.check_timeout = Duration::from_millis(500);
+        let ready = probe(&health, "").await;
+        assert_eq!(ready.status, ServingStatus::NotServing as i32);
+        assert!(ready.message.starts_with("postgres unavailable:"), "{}", ready.message);
+    }
+
+    #[tokio::test]
+    async fn test_capabilities_match_configuration() {
+        let embedder = HashEmbeddingProvider::new(8);
+        let health = HealthService::new().with_capabilities(capabilities(false, true, "supabase", &embedder));
+
+        let reported = health.get_capabilities(Request::new(GetCapabilitiesRequest {})).await.unwrap().into_inner();
+        assert_eq!(reported.api_version, API_VERSION);
+        assert!(!reported.full_text_search);
+        assert!(reported.ann_index);
+        assert!(reported.streaming);
+        assert_eq!(reported.auth_backend, "supabase");
+        assert_eq!(reported.embedding_dimension, 8);
+        assert_eq!(reported.embedding_model, embedder.model());
+    }
+}
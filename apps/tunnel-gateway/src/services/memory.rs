@@ -1,21 +1,70 @@
 use identra_proto::memory::{
     memory_service_server::{MemoryService, MemoryServiceServer},
+    store_memory_response::Verdict,
     Memory, MemoryMatch,
     StoreMemoryRequest, StoreMemoryResponse,
+    StoreMemoriesBatchRequest, StoreMemoriesBatchResponse, BatchStoreResult,
     QueryMemoriesRequest, QueryMemoriesResponse,
     GetMemoryRequest, GetMemoryResponse,
+    UpdateMemoryRequest, UpdateMemoryResponse,
+    SetMemoryFlagsRequest, SetMemoryFlagsResponse,
     DeleteMemoryRequest, DeleteMemoryResponse,
+    RestoreMemoryRequest, RestoreMemoryResponse,
+    ExportMemoriesRequest, ExportMemoriesResponse,
+    ImportMemoriesRequest, ImportMemoriesResponse,
+    GetMemoryStatsRequest, GetMemoryStatsResponse, TagCount,
     SearchMemoriesRequest, SearchMemoriesResponse,
     GetRecentMemoriesRequest, GetRecentMemoriesResponse,
+    WatchMemoriesRequest,
+    ReindexRequest,
 };
-use crate::database::MemoryDatabase;
-use std::sync::{Arc, Mutex};
+use crate::access::{self, AccessTracker};
+use crate::ann::AnnIndex;
+use crate::auth::middleware::{get_user_id_from_request, require_admin, AuthInterceptor};
+use crate::content_type;
+use crate::dedup;
+use crate::database::{self, MemoryDatabase, MemoryFilter, MemoryUpdate, NewMemory, StoreError};
+use crate::embedding::EmbeddingProvider;
+use crate::embedding_cache::EmbeddingCache;
+use crate::export::{ExportError, MemoryExport};
+use crate::lang;
+use crate::metrics;
+use crate::pagination::PageToken;
+use crate::quota::StorageUsage;
+use crate::reembed::{self, ReindexStream};
+use crate::trash;
+use crate::watch::{self, EventStream, MemoryEvents};
+use crate::write_limit::{WriteLimitConfig, WriteLimiter};
+use std::sync::Arc;
+use std::time::Duration;
 use tonic::{Request, Response, Status};
 use uuid::Uuid;
-use fastembed::{TextEmbedding, InitOptions, EmbeddingModel};
 use std::collections::HashMap;
+use identra_crypto::KeyDerivationParams;
+
+/// Largest number of items accepted by `store_memories_batch`
+const MAX_BATCH_SIZE: usize = 1000;
+
+/// Longest memory content stored unless `MEMORY_MAX_CONTENT_BYTES` says otherwise
+pub const DEFAULT_MAX_CONTENT_BYTES: usize = 64 * 1024;
+
+/// Largest number of memories accepted by `import_memories`
+const MAX_IMPORT_SIZE: usize = 10_000;
+
+/// Tags counted by `get_memory_stats` when the request doesn't say, and the most it may ask for
+const DEFAULT_TOP_TAGS: i64 = 10;
+const MAX_TOP_TAGS: i64 = 100;
+
+/// ANN candidates rescored per requested match, leaving room for filters
+const ANN_CANDIDATES_PER_MATCH: usize = 4;
+
+/// Matches fetched per requested one when reranking them by reads
+const ACCESS_RERANK_PER_MATCH: usize = 4;
 
 // Shared model for Database <-> Service communication
+//
+// Rows become one in `database` (`From<PgRow>`), and it becomes the wire
+// `Memory` through `From<MemoryModel>` below; neither mapping lives anywhere else.
 #[derive(Debug, Clone)]
 pub struct MemoryModel {
     pub id: String,
@@ -25,150 +74,1667 @@ pub struct MemoryModel {
     pub tags: Vec<String>,
     pub created_at: i64,
     pub updated_at: i64,
+    pub pinned: bool,
+    pub importance: f32,
+    pub access_count: i64,
+    pub last_accessed_at: Option<i64>,
+    pub content_type: String,
+    pub lang: Option<String>,
+}
+
+/// How `search` orders matches beyond plain similarity
+#[derive(Debug, Clone, Copy, Default)]
+struct Ranking {
+    boost_pinned: bool,
+    /// Weight of `access::access_score`; 0 ignores reads
+    access_boost: f32,
+    now: i64,
+}
+
+impl Ranking {
+    fn score(&self, memory: &MemoryModel, similarity: f32) -> f32 {
+        let mut score = if self.boost_pinned { database::boosted_score(memory, similarity) } else { similarity };
+        if self.access_boost > 0.0 {
+            score += self.access_boost * access::access_score(memory, self.now);
+        }
+        score
+    }
 }
 
 pub struct MemoryServiceImpl {
     db: Arc<MemoryDatabase>,
-    embedder: Arc<Mutex<TextEmbedding>>, 
+    embedder: Arc<dyn EmbeddingProvider>,
+    auth: AuthInterceptor,
+    trash_retention: Duration,
+    events: Arc<MemoryEvents>,
+    ann: Option<Arc<AnnIndex>>,
+    write_limiter: Option<WriteLimiter>,
+    access: Option<Arc<AccessTracker>>,
+    embedding_cache: Option<Arc<EmbeddingCache>>,
+    max_content_bytes: usize,
 }
 
 impl MemoryServiceImpl {
-    pub fn new(db: Arc<MemoryDatabase>) -> Self {
-        tracing::info!("🧠 Initializing Neural Engine...");
-        
-        let options = InitOptions::new(EmbeddingModel::AllMiniLML6V2)
-            .with_show_download_progress(true);
-
-        let embedder = TextEmbedding::try_new(options)
-            .expect("Failed to load local embedding model");
-
-        Self { 
-            db, 
-            embedder: Arc::new(Mutex::new(embedder)) 
-        }
+    pub fn new(
+        db: Arc<MemoryDatabase>,
+        embedder: Arc<dyn EmbeddingProvider>,
+        auth: AuthInterceptor,
+    ) -> Self {
+        Self { db, embedder, auth, trash_retention: trash::DEFAULT_RETENTION, events: Arc::default(), ann: None, write_limiter: None, access: None, embedding_cache: None, max_content_bytes: DEFAULT_MAX_CONTENT_BYTES }
+    }
+    
+    /// How long deleted memories can still be restored
+    pub fn with_trash_retention(mut self, trash_retention: Duration) -> Self {
+        self.trash_retention = trash_retention;
+        self
+    }
+    
+    /// Find search candidates in `ann` instead of scoring every memory
+    pub fn with_ann_index(mut self, ann: Arc<AnnIndex>) -> Self {
+        self.ann = Some(ann);
+        self
+    }
+    
+    /// Cap how fast each user may store, update and import memories
+    pub fn with_write_limit(mut self, config: WriteLimitConfig) -> Self {
+        self.write_limiter = Some(WriteLimiter::new(config));
+        self
+    }
+    
+    /// Count reads of memories through `get_memory` and search in `tracker`
+    pub fn with_access_tracking(mut self, tracker: Arc<AccessTracker>) -> Self {
+        self.access = Some(tracker);
+        self
+    }
+    
+    /// Reuse embeddings of text embedded before instead of asking the provider again
+    pub fn with_embedding_cache(mut self, cache: Arc<EmbeddingCache>) -> Self {
+        self.embedding_cache = Some(cache);
+        self
+    }
+    
+    /// Refuse to store or update memories with longer content, in bytes
+    pub fn with_max_content_bytes(mut self, max_content_bytes: usize) -> Self {
+        self.max_content_bytes = max_content_bytes;
+        self
     }
     
     pub fn into_server(self) -> MemoryServiceServer<Self> {
         MemoryServiceServer::new(self)
     }
     
-    fn generate_embedding(&self, content: &str) -> Result<Vec<f32>, Status> {
-        let documents = vec![content.to_string()];
-        // FIX: Added 'mut' here because fastembed v5 requires mutable access
-        let mut embedder = self.embedder.lock()
-            .map_err(|_| Status::internal("AI Engine lock failure"))?;
+    /// Why `content` can't be stored, if it can't
+    fn content_error(&self, content: &str) -> Option<String> {
+        if content.trim().is_empty() {
+            return Some("Content required".to_string());
+        }
+        self.content_too_long(content)
+    }
+    
+    /// Why `r` can't be stored as sent, if it can't
+    fn store_error(&self, r: &StoreMemoryRequest) -> Option<String> {
+        self.content_error(&r.content).or_else(|| content_type::error(&r.content_type))
+    }
+    
+    fn content_too_long(&self, content: &str) -> Option<String> {
+        (content.len() > self.max_content_bytes)
+            .then(|| format!("Content is {} bytes, the limit is {}", content.len(), self.max_content_bytes))
+    }
+    
+    /// Reject query vectors that can't be compared with stored embeddings
+    #[allow(clippy::result_large_err)]
+    fn check_dimension(&self, embedding: &[f32]) -> Result<(), Status> {
+        let expected = self.embedder.dimension();
+        if embedding.len() != expected {
+            return Err(Status::invalid_argument(format!(
+                "Embedding dimension mismatch: expected {}, got {}",
+                expected,
+                embedding.len()
+            )));
+        }
+        Ok(())
+    }
+    
+    /// Refuse provider output that couldn't be compared with stored embeddings
+    #[allow(clippy::result_large_err)]
+    fn check_embedded(&self, embedding: &[f32]) -> Result<(), Status> {
+        let expected = self.embedder.dimension();
+        if embedding.len() != expected {
+            tracing::error!("Embedding provider returned {} dimensions instead of {}", embedding.len(), expected);
+            return Err(Status::internal("Embedding provider returned a vector of the wrong dimension"));
+        }
+        Ok(())
+    }
+    
+    fn cached_embedding(&self, text: &str) -> Option<Vec<f32>> {
+        self.embedding_cache.as_ref()?.get(text)
+    }
+    
+    async fn embed(&self, text: &str) -> Result<Vec<f32>, Status> {
+        if let Some(embedding) = self.cached_embedding(text) {
+            return Ok(embedding);
+        }
+        let _timer = metrics::time_embedding("embed");
+        let embedding = self.embedder.embed(text).await?;
+        self.check_embedded(&embedding)?;
+        if let Some(cache) = &self.embedding_cache {
+            cache.insert(text, embedding.clone());
+        }
+        Ok(embedding)
+    }
+    
+    /// Embeddings of `texts` in order, asking the provider only for uncached ones
+    #[allow(clippy::result_large_err)]
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Status> {
+        let cached: Vec<Option<Vec<f32>>> = texts.iter().map(|text| self.cached_embedding(text)).collect();
+        let missing: Vec<String> = texts.iter().zip(&cached)
+            .filter(|(_, embedding)| embedding.is_none())
+            .map(|(text, _)| text.clone())
+            .collect();
+        if missing.is_empty() {
+            return Ok(cached.into_iter().flatten().collect());
+        }
+        
+        let _timer = metrics::time_embedding("embed_batch");
+        let embeddings = self.embedder.embed_batch(&missing).await?;
+        if embeddings.len() != missing.len() {
+            return Err(Status::internal("Embedding provider returned the wrong number of vectors"));
+        }
+        embeddings.iter().try_for_each(|embedding| self.check_embedded(embedding))?;
+        if let Some(cache) = &self.embedding_cache {
+            for (text, embedding) in missing.iter().zip(&embeddings) {
+                cache.insert(text, embedding.clone());
+            }
+        }
+        
+        let mut embedded = embeddings.into_iter();
+        Ok(cached.into_iter()
+            .map(|embedding| embedding.unwrap_or_else(|| embedded.next().expect("one embedding per uncached text")))
+            .collect())
+    }
+    
+    /// Best matches above `threshold` and how many memories were scored
+    ///
+    /// Uses the ANN index when it covers the user, falling back to brute
+    /// force when filters leave too few of its candidates to fill `limit`.
+    async fn search(
+        &self,
+        user_id: &str,
+        query: &[f32],
+        filter: &MemoryFilter,
+        threshold: f32,
+        limit: usize,
+        ranking: Ranking,
+    ) -> Result<(Vec<(MemoryModel, f32)>, i64), sqlx::Error> {
+        let candidates = self.ann.as_ref()
+            .and_then(|ann| ann.search(user_id, query, limit.saturating_mul(ANN_CANDIDATES_PER_MATCH)));
+        if let Some(candidates) = candidates {
+            let mut scored = self.db.score_candidates(user_id, query, filter, &candidates.ids).await?;
+            rank(&mut scored, ranking);
+            if scored.len() >= limit || candidates.exhaustive {
+                let scanned = scored.len() as i64;
+                let matches = scored.into_iter().filter(|(_, score)| *score > threshold).take(limit).collect();
+                return Ok((matches, scanned));
+            }
+            tracing::debug!("Filters kept {} of {} ANN candidates, searching exhaustively", scored.len(), candidates.ids.len());
+        }
         
-        let embeddings = embedder.embed(documents, None)
-            .map_err(|e| Status::internal(format!("Embedding failed: {}", e)))?;
+        // Reads are only known here, so rerank from a wider cut
+        let fetch = if ranking.access_boost > 0.0 { limit.saturating_mul(ACCESS_RERANK_PER_MATCH) } else { limit };
+        let mut matches = self.db.search_by_embedding(user_id, query, filter, threshold, fetch, ranking.boost_pinned).await?;
+        rank(&mut matches, ranking);
+        matches.truncate(limit);
+        let scanned = self.db.count_search_candidates(user_id, filter).await?;
+        Ok((matches, scanned))
+    }
+    
+    /// Count a read of each of `memory_ids`, to be written in the background
+    fn record_reads<'a>(&self, user_id: &str, memory_ids: impl IntoIterator<Item = &'a str>) {
+        if let Some(access) = &self.access {
+            access.record(user_id, memory_ids, chrono::Utc::now().timestamp());
+        }
+    }
+    
+    fn index_embedding(&self, user_id: &str, memory_id: &str, embedding: &[f32]) {
+        if let Some(ann) = &self.ann {
+            ann.insert(user_id, memory_id, embedding);
+        }
+    }
+    
+    /// Charge one write to `user_id`, refusing it with a `retry-after`
+    /// (seconds) once they are over the write rate
+    #[allow(clippy::result_large_err)]
+    fn check_write_rate(&self, user_id: &str) -> Result<(), Status> {
+        let Some(limiter) = &self.write_limiter else {
+            return Ok(());
+        };
+        limiter.check(user_id).map_err(|retry_after| {
+            let secs = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+            tracing::warn!("User {} is over the write rate", user_id);
+            let mut status = Status::resource_exhausted(format!("Too many writes. Try again in {} seconds", secs));
+            status.metadata_mut().insert("retry-after", secs.into());
+            status
+        })
+    }
+    
+    /// Results storing `memories` would have, found without writing or
+    /// embedding anything
+    ///
+    /// Mirrors a real store: empty content is invalid, dedup items match
+    /// live memories and earlier items of the same request, and the items
+    /// left are checked against the quota together, since a batch is stored
+    /// or refused as a whole.
+    async fn validate_stores(&self, user_id: &str, memories: &[StoreMemoryRequest]) -> Result<Vec<BatchStoreResult>, Status> {
+        let mut results = Vec::with_capacity(memories.len());
+        // content hash -> first item with it
+        let mut hashes: HashMap<String, usize> = HashMap::new();
+        for (i, m) in memories.iter().enumerate() {
+            if let Some(error) = self.store_error(m) {
+                results.push(store_result(Verdict::Invalid, String::new(), error));
+                continue;
+            }
+            if m.dedup {
+                let hash = dedup::content_hash(&m.content);
+                let existing = self.db.memory_with_content_hash(user_id, &hash).await.map_err(db_status)?;
+                if let Some(existing) = existing {
+                    results.push(store_result(Verdict::Duplicate, existing, "Already stored"));
+                    continue;
+                }
+                if let Some(first) = hashes.get(&hash) {
+                    results.push(store_result(Verdict::Duplicate, String::new(), format!("Same content as item {}", first)));
+                    continue;
+                }
+                hashes.insert(hash, i);
+            }
+            results.push(store_result(Verdict::Stored, String::new(), "Would be saved"));
+        }
         
-        embeddings.into_iter().next()
-            .ok_or_else(|| Status::internal("No embedding generated"))
+        let quota = self.db.storage_quota();
+        let storing: Vec<(&str, &HashMap<String, String>)> = memories.iter()
+            .zip(&results)
+            .filter(|(_, result)| result.verdict() == Verdict::Stored)
+            .map(|(m, _)| (m.content.as_str(), &m.metadata))
+            .collect();
+        if quota.is_unlimited() || storing.is_empty() {
+            return Ok(results);
+        }
+        let (usage, added) = self.db.projected_storage(user_id, &storing).await.map_err(db_status)?;
+        if let Err(e) = quota.check(usage, added) {
+            for result in results.iter_mut().filter(|result| result.verdict() == Verdict::Stored) {
+                *result = store_result(Verdict::QuotaExceeded, String::new(), e.to_string());
+            }
+        }
+        Ok(results)
+    }
+    
+    /// Authenticate the caller, returning their user id and the request body
+    async fn authorize<T>(&self, req: Request<T>) -> Result<(String, T), Status> {
+        let req = self.auth.intercept(req).await?;
+        let user_id = get_user_id_from_request(&req)?;
+        Ok((user_id, req.into_inner()))
+    }
+}
+
+/// Protobuf timestamp at `seconds` since the epoch, as stored
+fn timestamp(seconds: i64) -> prost_types::Timestamp {
+    prost_types::Timestamp { seconds, nanos: 0 }
+}
+
+impl From<MemoryModel> for Memory {
+    /// Embeddings stay on the server
+    fn from(m: MemoryModel) -> Self {
+        Memory {
+            id: m.id, content: m.content, metadata: m.metadata, embedding: vec![],
+            created_at: Some(timestamp(m.created_at)),
+            updated_at: Some(timestamp(m.updated_at)),
+            tags: m.tags,
+            pinned: m.pinned,
+            importance: m.importance,
+            access_count: m.access_count,
+            last_accessed_at: m.last_accessed_at.map(timestamp),
+            content_type: m.content_type,
+            lang: m.lang.unwrap_or_default(),
+        }
+    }
+}
+
+impl From<&NewMemory> for MemoryModel {
+    /// The memory as stored: unpinned, unimportant and never read
+    fn from(m: &NewMemory) -> Self {
+        MemoryModel {
+            id: m.id.clone(),
+            content: m.content.clone(),
+            metadata: m.metadata.clone(),
+            embedding: vec![],
+            tags: m.tags.clone(),
+            created_at: m.created_at,
+            updated_at: m.updated_at,
+            pinned: false,
+            importance: 0.0,
+            access_count: 0,
+            last_accessed_at: None,
+            content_type: m.content_type.clone(),
+            lang: m.lang.clone(),
+        }
+    }
+}
+
+/// Order scored matches best first by `ranking`
+fn rank(matches: &mut [(MemoryModel, f32)], ranking: Ranking) {
+    matches.sort_by(|(a, a_similarity), (b, b_similarity)| {
+        ranking.score(b, *b_similarity).total_cmp(&ranking.score(a, *a_similarity))
+    });
+}
+
+
+/// Outcome of storing one item; only stored and duplicate items succeed
+fn store_result(verdict: Verdict, memory_id: String, message: impl Into<String>) -> BatchStoreResult {
+    BatchStoreResult {
+        memory_id,
+        success: matches!(verdict, Verdict::Stored | Verdict::Duplicate),
+        message: message.into(),
+        duplicate: verdict == Verdict::Duplicate,
+        verdict: verdict as i32,
+    }
+}
+
+/// Status for a failed write; going over quota isn't a server fault
+fn store_status(error: StoreError) -> Status {
+    match error {
+        StoreError::Quota(e) => Status::resource_exhausted(e.to_string()),
+        StoreError::Database(e) => db_status(e),
+    }
+}
+
+/// Status for a failed query: `unavailable` when it lost out to other
+/// transactions and a retry may succeed, `internal` otherwise
+pub(crate) fn db_status(error: sqlx::Error) -> Status {
+    if database::is_transient(&error) {
+        Status::unavailable(format!("Database busy, try again: {}", error))
+    } else {
+        Status::internal(format!("DB Error: {}", error))
     }
 }
 
 #[tonic::async_trait]
 impl MemoryService for MemoryServiceImpl {
+    type WatchMemoriesStream = EventStream;
+    type ReindexStream = ReindexStream;
+    
     async fn store_memory(&self, req: Request<StoreMemoryRequest>) -> Result<Response<StoreMemoryResponse>, Status> {
-        let r = req.into_inner();
-        if r.content.trim().is_empty() { return Err(Status::invalid_argument("Content required")); }
+        let (user_id, r) = self.authorize(req).await?;
+        if r.validate_only {
+            let result = self.validate_stores(&user_id, std::slice::from_ref(&r)).await?
+                .pop()
+                .expect("one result per item");
+            return Ok(Response::new(StoreMemoryResponse {
+                memory_id: result.memory_id,
+                success: result.success,
+                message: result.message,
+                duplicate: result.duplicate,
+                verdict: result.verdict,
+            }));
+        }
+        if let Some(error) = self.store_error(&r) { return Err(Status::invalid_argument(error)); }
+        self.check_write_rate(&user_id)?;
         
         let id = Uuid::new_v4().to_string();
         let now = chrono::Utc::now().timestamp();
+        #[allow(clippy::result_large_err)]
+        let duplicate = |memory_id: String| {
+            tracing::info!("Memory {} already holds this content", memory_id);
+            Ok(Response::new(StoreMemoryResponse {
+                memory_id,
+                success: true,
+                message: "Already stored".into(),
+                duplicate: true,
+                verdict: Verdict::Duplicate as i32,
+            }))
+        };
         
-        let embedding = self.generate_embedding(&r.content)?;
+        // Known content is answered before paying for an embedding
+        let content_hash = r.dedup.then(|| dedup::content_hash(&r.content));
+        if let Some(hash) = &content_hash {
+            let existing = self.db.memory_with_content_hash(&user_id, hash)
+                .await
+                .map_err(db_status)?;
+            if let Some(existing) = existing {
+                return duplicate(existing);
+            }
+        }
         
-        self.db.store_memory(&id, &r.content, &embedding, &r.metadata, &r.tags, now, now)
+        let embedding = self.embed(&r.content).await?;
+        let memory = NewMemory {
+            id: id.clone(),
+            content_type: content_type::resolve(&r.content_type, &r.content),
+            lang: lang::detect(&r.content),
+            content: r.content,
+            embedding,
+            metadata: r.metadata,
+            tags: r.tags,
+            created_at: now,
+            updated_at: now,
+            content_hash,
+        };
+        // A concurrent dedup store of the same content may have won
+        let stored_id = self.db.store_memory_deduplicated(&user_id, &memory)
             .await
-            .map_err(|e| Status::internal(format!("DB Error: {}", e)))?;
+            .map_err(store_status)?;
+        if stored_id != id {
+            return duplicate(stored_id);
+        }
         
+        metrics::record_memories_stored(1);
+        self.index_embedding(&user_id, &id, &memory.embedding);
+        self.events.added(&user_id, MemoryModel::from(&memory).into());
         tracing::info!("Indexed memory {}", id);
-        Ok(Response::new(StoreMemoryResponse { memory_id: id, success: true, message: "Saved to Cloud".into() }))
+        Ok(Response::new(StoreMemoryResponse {
+            memory_id: id,
+            success: true,
+            message: "Saved to Cloud".into(),
+            duplicate: false,
+            verdict: Verdict::Stored as i32,
+        }))
+    }
+    
+    async fn store_memories_batch(&self, req: Request<StoreMemoriesBatchRequest>) -> Result<Response<StoreMemoriesBatchResponse>, Status> {
+        let (user_id, r) = self.authorize(req).await?;
+        if r.memories.len() > MAX_BATCH_SIZE {
+            return Err(Status::invalid_argument(format!(
+                "Batch too large: {} items, max {}",
+                r.memories.len(),
+                MAX_BATCH_SIZE
+            )));
+        }
+        if r.validate_only {
+            let results = self.validate_stores(&user_id, &r.memories).await?;
+            let stored_count = results.iter().filter(|r| r.verdict() == Verdict::Stored).count() as i32;
+            return Ok(Response::new(StoreMemoriesBatchResponse { results, stored_count }));
+        }
+        self.check_write_rate(&user_id)?;
+        
+        // Validate up front; only valid items are embedded and inserted
+        let mut results: Vec<Option<BatchStoreResult>> = r.memories.iter()
+            .map(|m| self.store_error(m).map(|error| store_result(Verdict::Invalid, String::new(), error)))
+            .collect();
+        let valid: Vec<&StoreMemoryRequest> = r.memories.iter()
+            .zip(&results)
+            .filter(|(_, result)| result.is_none())
+            .map(|(m, _)| m)
+            .collect();
+        
+        let contents: Vec<String> = valid.iter().map(|m| m.content.clone()).collect();
+        let embeddings = self.embed_batch(&contents).await?;
+        
+        let now = chrono::Utc::now().timestamp();
+        let new_memories: Vec<NewMemory> = valid.into_iter().zip(embeddings).map(|(m, embedding)| NewMemory {
+            id: Uuid::new_v4().to_string(),
+            content: m.content.clone(),
+            embedding,
+            metadata: m.metadata.clone(),
+            tags: m.tags.clone(),
+            created_at: now,
+            updated_at: now,
+            content_hash: m.dedup.then(|| dedup::content_hash(&m.content)),
+            content_type: content_type::resolve(&m.content_type, &m.content),
+            lang: lang::detect(&m.content),
+        }).collect();
+        
+        let outcomes = self.db.store_memories_batch(&user_id, &new_memories)
+            .await
+            .map_err(store_status)?;
+        
+        let mut stored = new_memories.into_iter().zip(outcomes);
+        for slot in results.iter_mut().filter(|slot| slot.is_none()) {
+            let (memory, outcome) = stored.next().expect("one outcome per valid item");
+            *slot = Some(match outcome {
+                Ok(stored_id) if stored_id != memory.id => store_result(Verdict::Duplicate, stored_id, "Already stored"),
+                Ok(_) => {
+                    self.index_embedding(&user_id, &memory.id, &memory.embedding);
+                    self.events.added(&user_id, MemoryModel::from(&memory).into());
+                    store_result(Verdict::Stored, memory.id, "Saved")
+                }
+                Err(e) => store_result(Verdict::Failed, String::new(), format!("DB Error: {}", e)),
+            });
+        }
+        
+        let results: Vec<BatchStoreResult> = results.into_iter().flatten().collect();
+        let stored_count = results.iter().filter(|r| r.success && !r.duplicate).count() as i32;
+        metrics::record_memories_stored(stored_count as usize);
+        
+        tracing::info!("Indexed {} of {} memories in batch", stored_count, results.len());
+        Ok(Response::new(StoreMemoriesBatchResponse { results, stored_count }))
     }
     
     async fn search_memories(&self, req: Request<SearchMemoriesRequest>) -> Result<Response<SearchMemoriesResponse>, Status> {
-        let r = req.into_inner();
+        let (user_id, r) = self.authorize(req).await?;
         
-        let matches = self.db.search_memories(&r.query_embedding, r.limit, r.similarity_threshold)
-            .await
-            .map_err(|e| Status::internal(format!("Search failed: {}", e)))?;
-        
-        let proto_matches = matches.into_iter().map(|m| MemoryMatch {
-            memory: Some(Memory {
-                id: m.id,
-                content: m.content,
-                metadata: m.metadata,
-                embedding: vec![],
-                created_at: Some(prost_types::Timestamp { seconds: m.created_at, nanos: 0 }),
-                updated_at: Some(prost_types::Timestamp { seconds: m.updated_at, nanos: 0 }),
-                tags: m.tags,
-            }),
-            similarity_score: 0.99,
+        // Clients may send raw text and let the server embed it
+        let query_embedding = if r.query_embedding.is_empty() && !r.query_text.trim().is_empty() {
+            self.embed(&r.query_text).await?
+        } else {
+            r.query_embedding
+        };
+        self.check_dimension(&query_embedding)?;
+        if !(0.0..=1.0).contains(&r.access_boost) {
+            return Err(Status::invalid_argument("access_boost must be between 0 and 1"));
+        }
+        
+        let limit = if r.limit > 0 { r.limit as usize } else { 10 };
+        let filter = MemoryFilter {
+            tags_any: r.tags_any,
+            tags_all: r.tags_all,
+            metadata: r.metadata_filters.into_iter().collect(),
+            created_after: r.created_after.map(|t| t.seconds),
+            created_before: None,
+            content_type: None,
+            lang: None,
+        };
+        let ranking = Ranking {
+            boost_pinned: r.boost_pinned,
+            access_boost: r.access_boost,
+            now: chrono::Utc::now().timestamp(),
+        };
+        let (mut matches, candidates_scanned) = self.search(&user_id, &query_embedding, &filter, r.similarity_threshold, limit, ranking)
+            .await
+            .map_err(db_status)?;
+        if r.include_pinned {
+            let pinned = self.db.score_pinned(&user_id, &query_embedding, &filter).await.map_err(db_status)?;
+            for scored in pinned {
+                if !matches.iter().any(|(m, _)| m.id == scored.0.id) {
+                    matches.push(scored);
+                }
+            }
+            rank(&mut matches, ranking);
+        }
+        metrics::record_memories_retrieved(matches.len());
+        self.record_reads(&user_id, matches.iter().map(|(m, _)| m.id.as_str()));
+        
+        let proto_matches = matches.into_iter().map(|(m, score)| MemoryMatch {
+            memory: Some(m.into()),
+            similarity_score: score,
         }).collect();
         
-        Ok(Response::new(SearchMemoriesResponse { matches: proto_matches }))
+        Ok(Response::new(SearchMemoriesResponse { matches: proto_matches, candidates_scanned }))
     }
 
     async fn query_memories(&self, req: Request<QueryMemoriesRequest>) -> Result<Response<QueryMemoriesResponse>, Status> {
-        let r = req.into_inner();
-        let limit = if r.limit > 0 { r.limit } else { 50 };
+        let (user_id, r) = self.authorize(req).await?;
+        let limit = if r.limit > 0 { r.limit as i64 } else { 50 };
+        let filter = MemoryFilter {
+            tags_any: r.tags_any,
+            tags_all: r.tags_all,
+            metadata: r.metadata_filters.into_iter().collect(),
+            created_after: r.created_after.map(|t| t.seconds),
+            created_before: r.created_before.map(|t| t.seconds),
+            content_type: (!r.content_type.is_empty()).then(|| r.content_type.to_ascii_lowercase()),
+            lang: (!r.lang.is_empty()).then(|| r.lang.clone()),
+        };
+        if let Some(error) = lang::error(&r.lang) {
+            return Err(Status::invalid_argument(error));
+        }
+        if let (Some(after), Some(before)) = (filter.created_after, filter.created_before) {
+            if after >= before {
+                return Err(Status::invalid_argument("created_after must be before created_before"));
+            }
+        }
+        // Ranked and substring results are ordered differently, so tokens
+        // from one mode must not be replayed against the other; nor may a
+        // token outlive a change of filter
+        let mode = match (r.ranked, r.boost_pinned) {
+            (false, false) => "",
+            (true, false) => "ranked:",
+            (false, true) => "pinned:",
+            (true, true) => "ranked:pinned:",
+        };
+        let token_scope = format!("{}{}|{:?}", mode, r.query, filter);
+        let page = PageToken::decode(&r.page_token, &token_scope)?;
         
-        let results = self.db.query_memories(&r.query, limit)
-            .await
-            .map_err(|e| Status::internal(e.to_string()))?;
+        // Fetch one extra row to learn whether another page exists
+        let mut results = if r.ranked {
+            self.db.fts_search(&user_id, &r.query, &filter, limit + 1, page.offset, r.boost_pinned).await
+        } else {
+            self.db.query_memories(&user_id, &r.query, &filter, limit + 1, page.offset, r.boost_pinned).await
+        }
+        .map_err(db_status)?;
+        
+        let next_page_token = if results.len() as i64 > limit {
+            results.truncate(limit as usize);
+            PageToken::new(&token_scope, page.offset + limit).encode()
+        } else {
+            String::new()
+        };
+        
+        let total_count = if r.ranked {
+            self.db.count_fts_matches(&user_id, &r.query, &filter).await
+        } else {
+            self.db.count_memories(&user_id, &r.query, &filter).await
+        }
+        .map_err(db_status)?;
             
-        let memories: Vec<Memory> = results.into_iter().map(|m| Memory {
-            id: m.id, content: m.content, metadata: m.metadata, embedding: vec![],
-            created_at: Some(prost_types::Timestamp { seconds: m.created_at, nanos: 0 }),
-            updated_at: Some(prost_types::Timestamp { seconds: m.updated_at, nanos: 0 }),
-            tags: m.tags,
-        }).collect();
+        let memories: Vec<Memory> = results.into_iter().map(Memory::from).collect();
+        metrics::record_memories_retrieved(memories.len());
         
-        Ok(Response::new(QueryMemoriesResponse { total_count: memories.len() as i32, memories }))
+        Ok(Response::new(QueryMemoriesResponse { memories, total_count: total_count as i32, next_page_token }))
     }
     
     async fn get_memory(&self, req: Request<GetMemoryRequest>) -> Result<Response<GetMemoryResponse>, Status> {
-        let r = req.into_inner();
-        let result = self.db.get_memory(&r.memory_id)
+        let (user_id, r) = self.authorize(req).await?;
+        let result = self.db.get_memory(&user_id, &r.memory_id)
             .await
-            .map_err(|e| Status::internal(e.to_string()))?;
+            .map_err(db_status)?;
         
         match result {
-            Some(m) => Ok(Response::new(GetMemoryResponse { memory: Some(Memory {
-                id: m.id, content: m.content, metadata: m.metadata, embedding: vec![],
-                created_at: Some(prost_types::Timestamp { seconds: m.created_at, nanos: 0 }),
-                updated_at: Some(prost_types::Timestamp { seconds: m.updated_at, nanos: 0 }),
-                tags: m.tags,
-            })})),
+            Some(m) => {
+                metrics::record_memories_retrieved(1);
+                self.record_reads(&user_id, [m.id.as_str()]);
+                Ok(Response::new(GetMemoryResponse { memory: Some(m.into()) }))
+            }
+            // Other users' memories are indistinguishable from missing ones
             None => Err(Status::not_found("Not found")),
         }
     }
 
+    async fn update_memory(&self, req: Request<UpdateMemoryRequest>) -> Result<Response<UpdateMemoryResponse>, Status> {
+        let (user_id, r) = self.authorize(req).await?;
+        if let Some(error) = self.content_too_long(&r.content).or_else(|| content_type::error(&r.content_type)) {
+            return Err(Status::invalid_argument(error));
+        }
+        self.check_write_rate(&user_id)?;
+        
+        let existing = self.db.get_memory(&user_id, &r.memory_id)
+            .await
+            .map_err(db_status)?
+            .ok_or_else(|| Status::not_found("Not found"))?;
+        
+        // Only re-embed when the text actually changed
+        let new_content = (!r.content.trim().is_empty() && r.content != existing.content)
+            .then_some(r.content);
+        let embedding = match &new_content {
+            Some(content) => Some(self.embed(content).await?),
+            None => None,
+        };
+        let reembedded = embedding.is_some();
+        // A type not sent is guessed again for new content, as on store
+        let content_type = match &new_content {
+            None if r.content_type.is_empty() => None,
+            content => Some(content_type::resolve(&r.content_type, content.as_deref().unwrap_or_default())),
+        };
+        
+        let update = MemoryUpdate {
+            lang: new_content.as_deref().and_then(lang::detect),
+            content_type,
+            content: new_content,
+            embedding,
+            metadata: r.replace_metadata.then_some(r.metadata),
+            tags: r.replace_tags.then_some(r.tags),
+        };
+        
+        let now = chrono::Utc::now().timestamp();
+        let m = self.db.update_memory(&user_id, &r.memory_id, &update, now)
+            .await
+            .map_err(store_status)?
+            .ok_or_else(|| Status::not_found("Not found"))?;
+        
+        if let Some(embedding) = &update.embedding {
+            self.index_embedding(&user_id, &m.id, embedding);
+        }
+        let memory = Memory::from(m);
+        self.events.updated(&user_id, memory.clone());
+        Ok(Response::new(UpdateMemoryResponse { memory: Some(memory), reembedded }))
+    }
+
+    async fn set_memory_flags(&self, req: Request<SetMemoryFlagsRequest>) -> Result<Response<SetMemoryFlagsResponse>, Status> {
+        let (user_id, r) = self.authorize(req).await?;
+        if r.importance.is_some_and(|importance| !(0.0..=1.0).contains(&importance)) {
+            return Err(Status::invalid_argument("Importance must be between 0 and 1"));
+        }
+        
+        let now = chrono::Utc::now().timestamp();
+        let m = self.db.set_memory_flags(&user_id, &r.memory_id, r.pinned, r.importance, now)
+            .await
+            .map_err(db_status)?
+            .ok_or_else(|| Status::not_found("Not found"))?;
+        
+        let memory = Memory::from(m);
+        self.events.updated(&user_id, memory.clone());
+        Ok(Response::new(SetMemoryFlagsResponse { memory: Some(memory) }))
+    }
+
     async fn delete_memory(&self, req: Request<DeleteMemoryRequest>) -> Result<Response<DeleteMemoryResponse>, Status> {
-        let r = req.into_inner();
-        let success = self.db.delete_memory(&r.memory_id)
+        let (user_id, r) = self.authorize(req).await?;
+        let now = chrono::Utc::now().timestamp();
+        let success = self.db.delete_memory(&user_id, &r.memory_id, now)
             .await
-            .map_err(|e| Status::internal(e.to_string()))?;
+            .map_err(db_status)?;
+        if success {
+            if let Some(ann) = &self.ann {
+                ann.remove(&user_id, &r.memory_id);
+            }
+            self.events.deleted(&user_id, &r.memory_id);
+        }
             
-        Ok(Response::new(DeleteMemoryResponse { success, message: if success { "Deleted".into() } else { "Not found".into() } }))
+        Ok(Response::new(DeleteMemoryResponse { success, message: if success { "Moved to trash".into() } else { "Not found".into() } }))
     }
 
-    async fn get_recent_memories(&self, req: Request<GetRecentMemoriesRequest>) -> Result<Response<GetRecentMemoriesResponse>, Status> {
+    async fn restore_memory(&self, req: Request<RestoreMemoryRequest>) -> Result<Response<RestoreMemoryResponse>, Status> {
+        let (user_id, r) = self.authorize(req).await?;
+        // The purge task only runs hourly, so enforce the window here too
+        let deleted_since = trash::retention_cutoff(chrono::Utc::now().timestamp(), self.trash_retention);
+        let m = self.db.restore_memory(&user_id, &r.memory_id, deleted_since)
+            .await
+            .map_err(store_status)?
+            .ok_or_else(|| Status::not_found("Not found in trash"))?;
+        
+        if self.ann.is_some() {
+            // The index forgot the embedding when the memory was trashed
+            match self.db.get_embedding(&user_id, &m.id).await {
+                Ok(Some(embedding)) => self.index_embedding(&user_id, &m.id, &embedding),
+                Ok(None) => {}
+                Err(e) => tracing::warn!("Failed to re-index restored memory {}: {}", m.id, e),
+            }
+        }
+        let memory = Memory::from(m);
+        self.events.added(&user_id, memory.clone());
+        Ok(Response::new(RestoreMemoryResponse { memory: Some(memory) }))
+    }
+
+    async fn export_memories(&self, req: Request<ExportMemoriesRequest>) -> Result<Response<ExportMemoriesResponse>, Status> {
+        let (user_id, r) = self.authorize(req).await?;
+        let memories = self.db.export_memories(&user_id)
+            .await
+            .map_err(db_status)?;
+        let memory_count = memories.len() as i32;
+        let export = MemoryExport::new(memories, chrono::Utc::now().timestamp());
+        
+        let passphrase = (!r.passphrase.is_empty()).then_some(r.passphrase);
+        let encrypted = passphrase.is_some();
+        // Argon2id would stall the runtime thread
+        let document = tokio::task::spawn_blocking(move || {
+            export.to_bytes(passphrase.as_deref(), &KeyDerivationParams::default())
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map_err(|e| Status::internal(e.to_string()))?;
+        
+        metrics::record_memories_retrieved(memory_count as usize);
+        tracing::info!("Exported {} memories (encrypted: {})", memory_count, encrypted);
+        Ok(Response::new(ExportMemoriesResponse { document, memory_count, encrypted }))
+    }
+
+    async fn import_memories(&self, req: Request<ImportMemoriesRequest>) -> Result<Response<ImportMemoriesResponse>, Status> {
+        let (user_id, r) = self.authorize(req).await?;
+        self.check_write_rate(&user_id)?;
+        
+        let passphrase = (!r.passphrase.is_empty()).then_some(r.passphrase);
+        let export = tokio::task::spawn_blocking(move || MemoryExport::from_bytes(&r.document, passphrase.as_deref()))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .map_err(|e| match e {
+                ExportError::Decryption => Status::permission_denied(e.to_string()),
+                e => Status::invalid_argument(e.to_string()),
+            })?;
+        if export.memories.len() > MAX_IMPORT_SIZE {
+            return Err(Status::invalid_argument(format!(
+                "Import too large: {} memories, max {}",
+                export.memories.len(),
+                MAX_IMPORT_SIZE
+            )));
+        }
+        
+        // Re-embed with the current provider; exports don't carry vectors
+        let mut new_memories = Vec::with_capacity(export.memories.len());
+        for chunk in export.memories.chunks(MAX_BATCH_SIZE) {
+            let contents: Vec<String> = chunk.iter().map(|m| m.content.clone()).collect();
+            let embeddings = self.embed_batch(&contents).await?;
+            new_memories.extend(chunk.iter().zip(embeddings).map(|(m, embedding)| NewMemory {
+                id: Uuid::new_v4().to_string(),
+                content: m.content.clone(),
+                embedding,
+                metadata: m.metadata.clone(),
+                tags: m.tags.clone(),
+                created_at: m.created_at,
+                updated_at: m.updated_at,
+                content_hash: None,
+                content_type: m.content_type.clone(),
+                lang: lang::detect(&m.content),
+            }));
+        }
+        
+        self.db.import_memories(&user_id, &new_memories)
+            .await
+            .map_err(store_status)?;
+        
+        let imported_count = new_memories.len() as i32;
+        metrics::record_memories_stored(new_memories.len());
+        for memory in &new_memories {
+            self.index_embedding(&user_id, &memory.id, &memory.embedding);
+            self.events.added(&user_id, MemoryModel::from(memory).into());
+        }
+        tracing::info!("Imported {} memories", imported_count);
+        Ok(Response::new(ImportMemoriesResponse {
+            memory_ids: new_memories.into_iter().map(|m| m.id).collect(),
+            imported_count,
+        }))
+    }
+
+    async fn get_memory_stats(&self, req: Request<GetMemoryStatsRequest>) -> Result<Response<GetMemoryStatsResponse>, Status> {
+        let (user_id, r) = self.authorize(req).await?;
+        let top_tags = if r.top_tags > 0 { (r.top_tags as i64).min(MAX_TOP_TAGS) } else { DEFAULT_TOP_TAGS };
+        
+        let stats = self.db.memory_stats(&user_id, top_tags)
+            .await
+            .map_err(db_status)?;
+        let usage = StorageUsage { memories: stats.total_count, bytes: stats.total_bytes };
+        let (remaining_memories, remaining_bytes) = self.db.storage_quota().remaining(usage);
+        
+        Ok(Response::new(GetMemoryStatsResponse {
+            total_count: stats.total_count,
+            top_tags: stats.top_tags.into_iter().map(|(tag, count)| TagCount { tag, count }).collect(),
+            oldest_created_at: stats.oldest_created_at.map(timestamp),
+            newest_created_at: stats.newest_created_at.map(timestamp),
+            total_bytes: stats.total_bytes,
+            remaining_memories,
+            remaining_bytes,
+        }))
+    }
+
+    async fn watch_memories(&self, req: Request<WatchMemoriesRequest>) -> Result<Response<Self::WatchMemoriesStream>, Status> {
+        let (user_id, r) = self.authorize(req).await?;
+        
+        // Subscribe first so changes made while the snapshot is read aren't lost
+        let events = self.events.subscribe(&user_id);
+        let snapshot = self.db.get_recent_memories(&user_id, r.snapshot_limit)
+            .await
+            .map_err(db_status)?;
+        metrics::record_memories_retrieved(snapshot.len());
+        
+        Ok(Response::new(watch::event_stream(snapshot.into_iter().map(Memory::from).collect(), events)))
+    }
+
+    async fn reindex(&self, req: Request<ReindexRequest>) -> Result<Response<Self::ReindexStream>, Status> {
+        let req = self.auth.intercept(req).await?;
+        require_admin(&req)?;
         let r = req.into_inner();
+        if r.user_id.is_empty() {
+            return Err(Status::invalid_argument("user_id required"));
+        }
+        
+        tracing::info!("Reindexing memories of {} with {}", r.user_id, self.embedder.model());
+        let stream = reembed::reindex(self.db.clone(), self.embedder.clone(), self.ann.clone(), r.user_id, r.restart).await?;
+        Ok(Response::new(stream))
+    }
+
+    async fn get_recent_memories(&self, req: Request<GetRecentMemoriesRequest>) -> Result<Response<GetRecentMemoriesResponse>, Status> {
+        let (user_id, r) = self.authorize(req).await?;
         
-        let results = self.db.get_recent_memories(r.limit)
+        let results = self.db.get_recent_memories(&user_id, r.limit)
             .await
-            .map_err(|e| Status::internal(format!("Database error: {}", e)))?;
+            .map_err(db_status)?;
 
-        let memories: Vec<Memory> = results.into_iter().map(|m| Memory {
-            id: m.id,
-            content: m.content,
-            metadata: m.metadata,
-            embedding: vec![], 
-            created_at: Some(prost_types::Timestamp { seconds: m.created_at, nanos: 0 }),
-            updated_at: Some(prost_types::Timestamp { seconds: m.updated_at, nanos: 0 }),
-            tags: m.tags,
-        }).collect();
+        let memories: Vec<Memory> = results.into_iter().map(Memory::from).collect();
+        metrics::record_memories_retrieved(memories.len());
         
         Ok(Response::new(GetRecentMemoriesResponse { memories }))
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::ann::AnnConfig;
+    use crate::auth::backend::{AuthBackend, AuthError, Registration, Session, SignOutScope};
+    use crate::auth::middleware::{AuthClaims, ADMIN_ROLE};
+    use crate::database::tests::{cleanup, test_db};
+    use crate::embedding::HashEmbeddingProvider;
+    use identra_proto::memory::memory_event::Kind;
+    use tokio_stream::StreamExt;
+
+    /// Accepts any token as the user it names; `admin` is also an admin
+    pub(crate) struct TokenIsUser;
+
+    #[tonic::async_trait]
+    impl AuthBackend for TokenIsUser {
+        async fn register(&self, _: &str, _: &str, _: &str) -> Result<Registration, AuthError> {
+            Err(AuthError::BadRequest("unsupported".into()))
+        }
+
+        async fn login(&self, _: &str, _: &str) -> Result<Session, AuthError> {
+            Err(AuthError::BadRequest("unsupported".into()))
+        }
+
+        async fn verify_email(&self, _: &str) -> Result<Session, AuthError> {
+            Err(AuthError::BadRequest("unsupported".into()))
+        }
+
+        async fn request_password_reset(&self, _: &str) -> Result<(), AuthError> {
+            Ok(())
+        }
+
+        async fn verify_reset_token(&self, _: &str) -> Result<Session, AuthError> {
+            Err(AuthError::BadRequest("unsupported".into()))
+        }
+
+        async fn refresh(&self, _: &str) -> Result<Session, AuthError> {
+            Err(AuthError::BadRequest("unsupported".into()))
+        }
+
+        async fn verify(&self, access_token: &str) -> Result<AuthClaims, AuthError> {
+            let role = if access_token == "admin" { ADMIN_ROLE } else { "authenticated" };
+            Ok(AuthClaims {
+                sub: access_token.to_string(),
+                email: String::new(),
+                role: role.to_string(),
+                exp: 0,
+            })
+        }
+
+        async fn logout(&self, _: &str, _: SignOutScope) -> Result<(), AuthError> {
+            Ok(())
+        }
+
+        async fn update_password(&self, _: &str, _: &str) -> Result<(), AuthError> {
+            Err(AuthError::BadRequest("unsupported".into()))
+        }
+
+        async fn delete_user(&self, _: &str) -> Result<(), AuthError> {
+            Ok(())
+        }
+    }
+
+    /// Drops the last component of every vector it returns
+    struct TruncatingEmbedder(HashEmbeddingProvider);
+
+    #[tonic::async_trait]
+    impl EmbeddingProvider for TruncatingEmbedder {
+        async fn embed(&self, text: &str) -> Result<Vec<f32>, Status> {
+            let mut embedding = self.0.embed(text).await?;
+            embedding.pop();
+            Ok(embedding)
+        }
+
+        fn dimension(&self) -> usize {
+            self.0.dimension()
+        }
+
+        fn model(&self) -> String {
+            "truncating".to_string()
+        }
+    }
+
+    /// Another model of the same dimension: the hash vectors reversed
+    struct ReversedEmbedder(HashEmbeddingProvider);
+
+    #[tonic::async_trait]
+    impl EmbeddingProvider for ReversedEmbedder {
+        async fn embed(&self, text: &str) -> Result<Vec<f32>, Status> {
+            let mut embedding = self.0.embed(text).await?;
+            embedding.reverse();
+            Ok(embedding)
+        }
+
+        fn dimension(&self) -> usize {
+            self.0.dimension()
+        }
+
+        fn model(&self) -> String {
+            "reversed".to_string()
+        }
+    }
+
+    /// Counts the texts it is asked to embed
+    #[derive(Default)]
+    struct CountingEmbedder {
+        inner: HashEmbeddingProvider,
+        calls: std::sync::atomic::AtomicUsize,
+    }
+
+    #[tonic::async_trait]
+    impl EmbeddingProvider for CountingEmbedder {
+        async fn embed(&self, text: &str) -> Result<Vec<f32>, Status> {
+            self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            self.inner.embed(text).await
+        }
+
+        fn dimension(&self) -> usize {
+            self.inner.dimension()
+        }
+
+        fn model(&self) -> String {
+            self.inner.model()
+        }
+    }
+
+    pub(crate) fn request_as<T>(user_id: &str, message: T) -> Request<T> {
+        let mut req = Request::new(message);
+        req.metadata_mut().insert("authorization", format!("Bearer {}", user_id).parse().unwrap());
+        req
+    }
+
+    /// Service whose pool never connects, for requests refused before any query
+    fn service(embedder: impl EmbeddingProvider + 'static) -> MemoryServiceImpl {
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/unused")
+            .unwrap();
+        MemoryServiceImpl::new(
+            Arc::new(MemoryDatabase::from_pool(pool)),
+            Arc::new(embedder),
+            AuthInterceptor::new(Arc::new(TokenIsUser)),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_wrong_dimension_embeddings_are_never_stored() {
+        let service = service(TruncatingEmbedder(HashEmbeddingProvider::new(8)));
+        let memory = || StoreMemoryRequest { content: "hello".to_string(), ..Default::default() };
+
+        let err = service.store_memory(request_as("alice", memory())).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Internal);
+        let batch = StoreMemoriesBatchRequest { memories: vec![memory(), memory()], ..Default::default() };
+        let err = service.store_memories_batch(request_as("alice", batch)).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Internal);
+
+        // Query vectors come from the client, so a bad one is their mistake
+        let search = SearchMemoriesRequest { query_embedding: vec![1.0; 7], ..Default::default() };
+        let err = service.search_memories(request_as("alice", search)).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_service_role_refused_by_user_rpcs() {
+        let service = service(HashEmbeddingProvider::default());
+        let memory = StoreMemoryRequest { content: "hello".to_string(), ..Default::default() };
+        let err = service.store_memory(request_as("admin", memory)).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+        let err = service.search_memories(request_as("admin", SearchMemoriesRequest::default())).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn test_validate_only_never_embeds() {
+        // Without dedup or a quota there is nothing to look up either
+        let service = service(TruncatingEmbedder(HashEmbeddingProvider::new(8)));
+        let memory = |content: &str| StoreMemoryRequest {
+            content: content.to_string(),
+            validate_only: true,
+            ..Default::default()
+        };
+
+        let verdict = service.store_memory(request_as("alice", memory("hello"))).await.unwrap().into_inner();
+        assert!(verdict.success);
+        assert_eq!(verdict.verdict(), Verdict::Stored);
+        assert!(verdict.memory_id.is_empty());
+
+        let batch = StoreMemoriesBatchRequest { memories: vec![memory("hello"), memory("  ")], validate_only: true };
+        let batch = service.store_memories_batch(request_as("alice", batch)).await.unwrap().into_inner();
+        assert_eq!(batch.stored_count, 1);
+        assert_eq!(batch.results[1].verdict(), Verdict::Invalid);
+        assert!(!batch.results[1].success);
+    }
+
+    #[tokio::test]
+    async fn test_content_at_limit_accepted_over_rejected() {
+        // Over-long content is refused before any query
+        let service = service(HashEmbeddingProvider::default()).with_max_content_bytes(8);
+        let memory = |content: &str, validate_only: bool| StoreMemoryRequest {
+            content: content.to_string(),
+            validate_only,
+            ..Default::default()
+        };
+
+        let at = service.store_memory(request_as("alice", memory("12345678", true))).await.unwrap().into_inner();
+        assert_eq!(at.verdict(), Verdict::Stored);
+        let over = service.store_memory(request_as("alice", memory("123456789", true))).await.unwrap().into_inner();
+        assert_eq!(over.verdict(), Verdict::Invalid);
+        assert_eq!(over.message, "Content is 9 bytes, the limit is 8");
+
+        let err = service.store_memory(request_as("alice", memory("123456789", false))).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        assert_eq!(err.message(), "Content is 9 bytes, the limit is 8");
+        // Bytes, not characters
+        let err = service.store_memory(request_as("alice", memory("ééééé", false))).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+
+        let update = UpdateMemoryRequest { memory_id: Uuid::new_v4().to_string(), content: "123456789".to_string(), ..Default::default() };
+        let err = service.update_memory(request_as("alice", update)).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+
+        let batch = StoreMemoriesBatchRequest {
+            memories: vec![memory("12345678", false), memory("123456789", false)],
+            validate_only: true,
+        };
+        let batch = service.store_memories_batch(request_as("alice", batch)).await.unwrap().into_inner();
+        assert_eq!(batch.results.iter().map(|r| r.verdict()).collect::<Vec<_>>(), [Verdict::Stored, Verdict::Invalid]);
+    }
+
+    #[tokio::test]
+    async fn test_write_rate_limit_rejects_before_embedding() {
+        // Writes let through fail at the embedder, so the code tells them apart
+        let service = service(TruncatingEmbedder(HashEmbeddingProvider::new(8)))
+            .with_write_limit(WriteLimitConfig { rate: 10.0, burst: 2 });
+        let store = |user: &str| {
+            service.store_memory(request_as(user, StoreMemoryRequest { content: "loop".to_string(), ..Default::default() }))
+        };
+
+        for _ in 0..2 {
+            assert_eq!(store("alice").await.unwrap_err().code(), tonic::Code::Internal);
+        }
+        let limited = store("alice").await.unwrap_err();
+        assert_eq!(limited.code(), tonic::Code::ResourceExhausted);
+        assert_eq!(limited.metadata().get("retry-after").unwrap(), "1");
+        let batch = StoreMemoriesBatchRequest { memories: vec![StoreMemoryRequest { content: "loop".to_string(), ..Default::default() }], ..Default::default() };
+        let err = service.store_memories_batch(request_as("alice", batch)).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::ResourceExhausted);
+        assert_eq!(store("bob").await.unwrap_err().code(), tonic::Code::Internal);
+
+        tokio::time::sleep(Duration::from_millis(110)).await;
+        assert_eq!(store("alice").await.unwrap_err().code(), tonic::Code::Internal, "recovered");
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_reindex_after_provider_swap_makes_search_coherent() {
+        let db = Arc::new(test_db().await);
+        let auth = || AuthInterceptor::new(Arc::new(TokenIsUser));
+        let before = MemoryServiceImpl::new(db.clone(), Arc::new(HashEmbeddingProvider::default()), auth());
+        let user = format!("reindex-{}", Uuid::new_v4());
+        let contents = ["apples and pears", "train timetables", "tax return due in april"];
+        for content in contents {
+            let memory = StoreMemoryRequest { content: content.to_string(), tags: vec![user.clone()], ..Default::default() };
+            before.store_memory(request_as(&user, memory)).await.unwrap();
+        }
+
+        let after = MemoryServiceImpl::new(db.clone(), Arc::new(ReversedEmbedder(HashEmbeddingProvider::default())), auth());
+        async fn best_match(service: &MemoryServiceImpl, user: &str, text: &str) -> (String, f32) {
+            // Vectors of different models may not even point the same way
+            let search = SearchMemoriesRequest { query_text: text.to_string(), limit: 1, similarity_threshold: -1.0, ..Default::default() };
+            let matches = service.search_memories(request_as(user, search)).await.unwrap().into_inner().matches;
+            let best = matches.into_iter().next().unwrap();
+            (best.memory.unwrap().content, best.similarity_score)
+        }
+        let (_, similarity) = best_match(&after, &user, contents[2]).await;
+        assert!(similarity < 0.99, "old vectors don't match the new model's");
+
+        let reindex = || ReindexRequest { user_id: user.clone(), restart: false };
+        let denied = after.reindex(request_as(&user, reindex())).await.unwrap_err();
+        assert_eq!(denied.code(), tonic::Code::PermissionDenied);
+
+        let run = |request: ReindexRequest| async {
+            let progress: Vec<_> = after.reindex(request_as("admin", request))
+                .await
+                .unwrap()
+                .into_inner()
+                .map(|progress| progress.unwrap())
+                .collect()
+                .await;
+            progress.last().cloned().unwrap()
+        };
+        let last = run(reindex()).await;
+        assert!(last.done);
+        assert_eq!((last.reindexed, last.total), (3, 3));
+        assert!(db.reindex_cursor(&user).await.unwrap().is_none(), "a finished run leaves nothing to resume");
+
+        // As if a run had died after its first memory
+        let first = db.memory_contents_after(Some(&user), None, 1).await.unwrap().remove(0).0;
+        db.reindex_batch(&user, &[], &first).await.unwrap();
+        let resumed = run(reindex()).await;
+        assert_eq!((resumed.reindexed, resumed.total), (2, 2));
+        db.reindex_batch(&user, &[], &first).await.unwrap();
+        let restarted = run(ReindexRequest { restart: true, ..reindex() }).await;
+        assert_eq!((restarted.reindexed, restarted.total), (3, 3));
+
+        for content in contents {
+            let (best, similarity) = best_match(&after, &user, content).await;
+            assert_eq!(best, content);
+            assert!(similarity > 0.99);
+        }
+
+        cleanup(&db, &user).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_watcher_sees_memory_stored_by_another_client() {
+        let db = Arc::new(test_db().await);
+        let service = MemoryServiceImpl::new(
+            db.clone(),
+            Arc::new(HashEmbeddingProvider::default()),
+            AuthInterceptor::new(Arc::new(TokenIsUser)),
+        );
+        let user = format!("watch-{}", Uuid::new_v4());
+
+        let mut events = service
+            .watch_memories(request_as(&user, WatchMemoriesRequest { snapshot_limit: 0 }))
+            .await
+            .unwrap()
+            .into_inner();
+        let synced = events.next().await.unwrap().unwrap();
+        assert_eq!(synced.kind(), Kind::Synced);
+
+        let stored = service
+            .store_memory(request_as(&user, StoreMemoryRequest {
+                content: "seen from the other device".to_string(),
+                metadata: HashMap::new(),
+                tags: vec![user.clone()],
+                ..Default::default()
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+
+        let event = tokio::time::timeout(Duration::from_secs(5), events.next())
+            .await
+            .expect("no event for the stored memory")
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.kind(), Kind::Added);
+        assert_eq!(event.memory_id, stored.memory_id);
+        assert_eq!(event.memory.unwrap().content, "seen from the other device");
+
+        cleanup(&db, &user).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_dedup_store_returns_existing_id() {
+        let db = Arc::new(test_db().await);
+        let service = MemoryServiceImpl::new(
+            db.clone(),
+            Arc::new(HashEmbeddingProvider::default()),
+            AuthInterceptor::new(Arc::new(TokenIsUser)),
+        );
+        let user = format!("dedup-{}", Uuid::new_v4());
+        let store = |content: &str, dedup: bool| {
+            service.store_memory(request_as(&user, StoreMemoryRequest {
+                content: content.to_string(),
+                tags: vec![user.clone()],
+                dedup,
+                ..Default::default()
+            }))
+        };
+
+        let first = store("Synced from mail", true).await.unwrap().into_inner();
+        assert!(!first.duplicate);
+        let hit = store("synced FROM  mail", true).await.unwrap().into_inner();
+        assert!(hit.success && hit.duplicate);
+        assert_eq!(hit.memory_id, first.memory_id);
+
+        let miss = store("Synced from calendar", true).await.unwrap().into_inner();
+        assert!(!miss.duplicate);
+        assert_ne!(miss.memory_id, first.memory_id);
+        let plain = store("Synced from mail", false).await.unwrap().into_inner();
+        assert!(!plain.duplicate, "without dedup content is always stored");
+
+        let batch = StoreMemoriesBatchRequest {
+            memories: vec![StoreMemoryRequest {
+                content: "SYNCED from mail".to_string(),
+                dedup: true,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let batch = service.store_memories_batch(request_as(&user, batch)).await.unwrap().into_inner();
+        assert_eq!(batch.stored_count, 0);
+        assert!(batch.results[0].duplicate);
+        assert_eq!(batch.results[0].memory_id, first.memory_id);
+
+        cleanup(&db, &user).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_content_type_stored_and_filtered() {
+        let db = Arc::new(test_db().await);
+        let service = MemoryServiceImpl::new(
+            db.clone(),
+            Arc::new(HashEmbeddingProvider::default()),
+            AuthInterceptor::new(Arc::new(TokenIsUser)),
+        );
+        let user = format!("content-type-{}", Uuid::new_v4());
+        let store = |content: &str, content_type: &str| {
+            service.store_memory(request_as(&user, StoreMemoryRequest {
+                content: content.to_string(),
+                tags: vec![user.clone()],
+                content_type: content_type.to_string(),
+                ..Default::default()
+            }))
+        };
+        let query = |content_type: &str| {
+            service.query_memories(request_as(&user, QueryMemoriesRequest {
+                content_type: content_type.to_string(),
+                ..Default::default()
+            }))
+        };
+
+        store("Call the dentist", "").await.unwrap();
+        store("https://example.com/notes", "").await.unwrap();
+        store("# Sprint plan\n- ship", "Text/Markdown").await.unwrap();
+        store("let x = 1;", "application/x-rust").await.unwrap();
+        let invalid = store("body", "markdown").await.unwrap_err();
+        assert_eq!(invalid.code(), tonic::Code::InvalidArgument);
+        // Rows written without a content type read back as plain text
+        let id = Uuid::new_v4().to_string();
+        db.store_memory(&user, &id, "Older note", &[0.0; 4], &HashMap::new(), std::slice::from_ref(&user), 0, 0).await.unwrap();
+
+        let all = query("").await.unwrap().into_inner().memories;
+        assert_eq!(all.len(), 5);
+        let plain = query("text/plain").await.unwrap().into_inner().memories;
+        let mut contents: Vec<&str> = plain.iter().map(|m| m.content.as_str()).collect();
+        contents.sort();
+        assert_eq!(contents, ["Call the dentist", "Older note"]);
+        assert!(plain.iter().all(|m| m.content_type == content_type::PLAIN));
+
+        let links = query("text/uri-list").await.unwrap().into_inner().memories;
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].content, "https://example.com/notes");
+        let markdown = query("text/markdown").await.unwrap().into_inner().memories;
+        assert_eq!(markdown.len(), 1);
+        assert_eq!(markdown[0].content_type, content_type::MARKDOWN);
+        assert_eq!(query("application/x-rust").await.unwrap().into_inner().memories.len(), 1);
+
+        cleanup(&db, &user).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_update_redetects_content_type_of_new_content() {
+        let db = Arc::new(test_db().await);
+        let service = MemoryServiceImpl::new(
+            db.clone(),
+            Arc::new(HashEmbeddingProvider::default()),
+            AuthInterceptor::new(Arc::new(TokenIsUser)),
+        );
+        let user = format!("update-type-{}", Uuid::new_v4());
+        let stored = service.store_memory(request_as(&user, StoreMemoryRequest {
+            content: "https://example.com/notes".to_string(),
+            tags: vec![user.clone()],
+            ..Default::default()
+        })).await.unwrap().into_inner();
+        let update = |content: &str, content_type: &str| {
+            service.update_memory(request_as(&user, UpdateMemoryRequest {
+                memory_id: stored.memory_id.clone(),
+                content: content.to_string(),
+                content_type: content_type.to_string(),
+                ..Default::default()
+            }))
+        };
+        let type_after = |content: &'static str, content_type: &'static str| {
+            let update = &update;
+            async move { update(content, content_type).await.unwrap().into_inner().memory.unwrap().content_type }
+        };
+
+        assert_eq!(type_after("# Notes\n- call back", "").await, content_type::MARKDOWN);
+        // Unchanged content keeps its type unless one is sent
+        assert_eq!(type_after("", "").await, content_type::MARKDOWN);
+        assert_eq!(type_after("", "Application/X-Rust").await, "application/x-rust");
+        assert_eq!(type_after("Call the dentist", "").await, content_type::PLAIN);
+        assert_eq!(type_after("Call the dentist again", "text/markdown").await, content_type::MARKDOWN);
+        let invalid = update("body", "markdown").await.unwrap_err();
+        assert_eq!(invalid.code(), tonic::Code::InvalidArgument);
+
+        cleanup(&db, &user).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_stored_row_maps_to_same_proto_as_sent_to_watchers() {
+        let db = test_db().await;
+        let user = format!("domain-model-{}", Uuid::new_v4());
+        let new_memory = NewMemory {
+            id: Uuid::new_v4().to_string(),
+            content: "Das Treffen mit dem Team wurde auf Donnerstag verschoben".to_string(),
+            embedding: vec![0.5; 4],
+            metadata: HashMap::from([("source".to_string(), "calendar".to_string())]),
+            tags: vec![user.clone(), "meetings".to_string()],
+            created_at: 1_700_000_000,
+            updated_at: 1_700_000_100,
+            content_hash: None,
+            content_type: content_type::MARKDOWN.to_string(),
+            lang: Some("deu".to_string()),
+        };
+        db.store_memory_deduplicated(&user, &new_memory).await.unwrap();
+
+        let row = db.get_memory(&user, &new_memory.id).await.unwrap().expect("stored");
+        let stored = Memory::from(row);
+        assert_eq!(stored, MemoryModel::from(&new_memory).into());
+        assert_eq!(stored.created_at, Some(prost_types::Timestamp { seconds: 1_700_000_000, nanos: 0 }));
+        assert_eq!(stored.updated_at, Some(prost_types::Timestamp { seconds: 1_700_000_100, nanos: 0 }));
+        assert_eq!(stored.metadata["source"], "calendar");
+        assert_eq!(stored.tags, new_memory.tags);
+        assert_eq!(stored.lang, "deu");
+        assert!(stored.embedding.is_empty());
+        assert_eq!(stored.last_accessed_at, None);
+
+        cleanup(&db, &user).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_repeated_store_embeds_once() {
+        let db = Arc::new(test_db().await);
+        let embedder = Arc::new(CountingEmbedder::default());
+        let cache = EmbeddingCache::new(embedder.model(), embedder.dimension(), 16);
+        let service = MemoryServiceImpl::new(
+            db.clone(),
+            embedder.clone(),
+            AuthInterceptor::new(Arc::new(TokenIsUser)),
+        )
+        .with_embedding_cache(Arc::new(cache));
+        let user = format!("embedcache-{}", Uuid::new_v4());
+        let memory = |content: &str| StoreMemoryRequest {
+            content: content.to_string(),
+            tags: vec![user.clone()],
+            ..Default::default()
+        };
+
+        service.store_memory(request_as(&user, memory("Standup at nine"))).await.unwrap();
+        service.store_memory(request_as(&user, memory("Standup at nine"))).await.unwrap();
+        assert_eq!(embedder.calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        let batch = StoreMemoriesBatchRequest {
+            memories: vec![memory("Standup at nine"), memory("Retro on Friday")],
+            ..Default::default()
+        };
+        service.store_memories_batch(request_as(&user, batch)).await.unwrap();
+        assert_eq!(embedder.calls.load(std::sync::atomic::Ordering::SeqCst), 2, "only the new text is embedded");
+
+        cleanup(&db, &user).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_validate_only_verdicts_match_real_stores() {
+        let quota = crate::quota::StorageQuota { max_memories: Some(3), max_bytes: None };
+        let db = Arc::new(test_db().await.with_storage_quota(quota));
+        let embedder = Arc::new(CountingEmbedder::default());
+        let service = MemoryServiceImpl::new(
+            db.clone(),
+            embedder.clone(),
+            AuthInterceptor::new(Arc::new(TokenIsUser)),
+        );
+        let user = format!("validate-{}", Uuid::new_v4());
+        let memory = |content: &str| StoreMemoryRequest {
+            content: content.to_string(),
+            tags: vec![user.clone()],
+            dedup: true,
+            ..Default::default()
+        };
+        let batch = |validate_only: bool| StoreMemoriesBatchRequest {
+            memories: vec![memory(""), memory("Dentist at 3"), memory("Gym at 6"), memory("gym AT 6")],
+            validate_only,
+        };
+        let count = || async { db.memory_stats(&user, 0).await.unwrap().total_count };
+
+        let existing = service.store_memory(request_as(&user, memory("Dentist at 3"))).await.unwrap().into_inner();
+        let calls = embedder.calls.load(std::sync::atomic::Ordering::SeqCst);
+
+        let preview = service.store_memories_batch(request_as(&user, batch(true))).await.unwrap().into_inner();
+        assert_eq!(count().await, 1, "validation stores nothing");
+        assert_eq!(embedder.calls.load(std::sync::atomic::Ordering::SeqCst), calls, "nor embeds anything");
+        assert_eq!(preview.results[1].memory_id, existing.memory_id);
+
+        let real = service.store_memories_batch(request_as(&user, batch(false))).await.unwrap().into_inner();
+        let verdicts = |results: &[BatchStoreResult]| results.iter().map(|r| r.verdict()).collect::<Vec<_>>();
+        assert_eq!(verdicts(&preview.results), [Verdict::Invalid, Verdict::Duplicate, Verdict::Stored, Verdict::Duplicate]);
+        assert_eq!(verdicts(&real.results), verdicts(&preview.results));
+        assert_eq!(real.results[1].memory_id, existing.memory_id);
+        assert_eq!(preview.stored_count, real.stored_count);
+
+        // One memory left under the quota: a batch of two is refused as a whole
+        let over = StoreMemoriesBatchRequest { memories: vec![memory("Call mum"), memory("Pay rent")], validate_only: true };
+        let preview = service.store_memories_batch(request_as(&user, over.clone())).await.unwrap().into_inner();
+        assert_eq!(verdicts(&preview.results), [Verdict::QuotaExceeded, Verdict::QuotaExceeded]);
+        assert_eq!(preview.stored_count, 0);
+        let over = StoreMemoriesBatchRequest { validate_only: false, ..over };
+        let err = service.store_memories_batch(request_as(&user, over)).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::ResourceExhausted);
+
+        let single = StoreMemoryRequest { validate_only: true, ..memory("Call mum") };
+        let preview = service.store_memory(request_as(&user, single)).await.unwrap().into_inner();
+        assert_eq!(preview.verdict(), Verdict::Stored);
+        let real = service.store_memory(request_as(&user, memory("Call mum"))).await.unwrap().into_inner();
+        assert_eq!(real.verdict(), Verdict::Stored);
+
+        cleanup(&db, &user).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_search_uses_ann_candidates_and_falls_back_for_filters() {
+        let db = Arc::new(test_db().await);
+        let embedder = Arc::new(HashEmbeddingProvider::default());
+        let ann = AnnIndex::build(AnnConfig { ef_search: 64, min_rows: 1 }, embedder.dimension(), Vec::new());
+        let service = MemoryServiceImpl::new(db.clone(), embedder, AuthInterceptor::new(Arc::new(TokenIsUser)))
+            .with_ann_index(Arc::new(ann));
+        let user = format!("ann-{}", Uuid::new_v4());
+
+        let store = |content: String, tags: Vec<String>| {
+            service.store_memory(request_as(&user, StoreMemoryRequest { content, tags, ..Default::default() }))
+        };
+        let mut ids = Vec::new();
+        for i in 0..20 {
+            ids.push(store(format!("note {}", i), vec![user.clone()]).await.unwrap().into_inner().memory_id);
+        }
+        let work = store("quarterly planning".to_string(), vec![user.clone(), "work".to_string()])
+            .await
+            .unwrap()
+            .into_inner()
+            .memory_id;
+
+        let search = |query_text: &str, limit: i32, tags_any: Vec<String>| {
+            service.search_memories(request_as(&user, SearchMemoriesRequest {
+                query_text: query_text.to_string(),
+                limit,
+                similarity_threshold: -1.0,
+                tags_any,
+                ..Default::default()
+            }))
+        };
+
+        // Only the index's candidates are scored
+        let response = search("note 3", 1, Vec::new()).await.unwrap().into_inner();
+        assert_eq!(response.matches[0].memory.as_ref().unwrap().id, ids[3]);
+        assert_eq!(response.candidates_scanned, ANN_CANDIDATES_PER_MATCH as i64);
+
+        // Too few candidates pass the filter to fill the page, so every
+        // matching memory is scored instead
+        let response = search("note 3", 2, vec!["work".to_string()]).await.unwrap().into_inner();
+        assert_eq!(response.matches.len(), 1);
+        assert_eq!(response.matches[0].memory.as_ref().unwrap().id, work);
+        assert_eq!(response.candidates_scanned, 1);
+
+        service.delete_memory(request_as(&user, DeleteMemoryRequest { memory_id: ids[3].clone() })).await.unwrap();
+        let response = search("note 3", 1, Vec::new()).await.unwrap().into_inner();
+        assert_ne!(response.matches[0].memory.as_ref().unwrap().id, ids[3]);
+
+        service.restore_memory(request_as(&user, RestoreMemoryRequest { memory_id: ids[3].clone() })).await.unwrap();
+        let response = search("note 3", 1, Vec::new()).await.unwrap().into_inner();
+        assert_eq!(response.matches[0].memory.as_ref().unwrap().id, ids[3]);
+
+        cleanup(&db, &user).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_repeated_reads_increment_access_count() {
+        let db = Arc::new(test_db().await);
+        let access = Arc::new(AccessTracker::default());
+        let service = MemoryServiceImpl::new(db.clone(), Arc::new(HashEmbeddingProvider::default()), AuthInterceptor::new(Arc::new(TokenIsUser)))
+            .with_access_tracking(access.clone());
+        let user = format!("access-{}", Uuid::new_v4());
+        let stored = StoreMemoryRequest { content: "read me often".to_string(), tags: vec![user.clone()], ..Default::default() };
+        let id = service.store_memory(request_as(&user, stored)).await.unwrap().into_inner().memory_id;
+        let get = || service.get_memory(request_as(&user, GetMemoryRequest { memory_id: id.clone() }));
+
+        let before = chrono::Utc::now().timestamp();
+        for _ in 0..3 {
+            get().await.unwrap();
+        }
+        let search = SearchMemoriesRequest { query_text: "read me".to_string(), similarity_threshold: -1.0, ..Default::default() };
+        let matches = service.search_memories(request_as(&user, search)).await.unwrap().into_inner().matches;
+        assert_eq!(matches.len(), 1);
+        // Nothing is written until the tracker flushes
+        assert_eq!(db.get_memory(&user, &id).await.unwrap().unwrap().access_count, 0);
+
+        assert_eq!(access.flush(&db).await.unwrap(), 1);
+        let memory = get().await.unwrap().into_inner().memory.unwrap();
+        assert_eq!(memory.access_count, 4);
+        assert!(memory.last_accessed_at.unwrap().seconds >= before);
+
+        access.flush(&db).await.unwrap();
+        assert_eq!(db.get_memory(&user, &id).await.unwrap().unwrap().access_count, 5);
+
+        cleanup(&db, &user).await;
+    }
+
+    #[test]
+    fn test_contention_is_unavailable_not_internal() {
+        assert_eq!(db_status(sqlx::Error::PoolTimedOut).code(), tonic::Code::Unavailable);
+        assert_eq!(db_status(sqlx::Error::RowNotFound).code(), tonic::Code::Internal);
+    }
+
+    #[test]
+    fn test_boost_ranks_pinned_ahead_of_equal_similarity() {
+        let memory = |id: &str, pinned: bool, importance: f32| MemoryModel {
+            id: id.to_string(),
+            content: String::new(),
+            metadata: HashMap::new(),
+            embedding: vec![],
+            tags: vec![],
+            created_at: 0,
+            updated_at: 0,
+            pinned,
+            importance,
+            access_count: 0,
+            last_accessed_at: None,
+            content_type: String::new(),
+            lang: None,
+        };
+        let matches = || vec![
+            (memory("plain", false, 0.0), 0.8),
+            (memory("pinned", true, 0.0), 0.8),
+            (memory("important", false, 1.0), 0.8),
+            (memory("closer", false, 0.0), 0.9),
+        ];
+        let ids = |matches: &[(MemoryModel, f32)]| matches.iter().map(|(m, _)| m.id.clone()).collect::<Vec<_>>();
+
+        let mut unboosted = matches();
+        rank(&mut unboosted, Ranking::default());
+        assert_eq!(ids(&unboosted), ["closer", "plain", "pinned", "important"]);
+
+        let mut boosted = matches();
+        rank(&mut boosted, Ranking { boost_pinned: true, ..Default::default() });
+        assert_eq!(ids(&boosted), ["closer", "pinned", "important", "plain"]);
+        assert!(boosted.iter().skip(1).all(|(_, score)| *score == 0.8), "scores stay plain similarity");
+    }
+
+    #[tokio::test]
+    async fn test_set_memory_flags_rejects_importance_out_of_range() {
+        let service = service(HashEmbeddingProvider::new(8));
+
+        for importance in [-0.1, 1.5, f32::NAN] {
+            let request = SetMemoryFlagsRequest { memory_id: "m".to_string(), pinned: None, importance: Some(importance) };
+            let err = service.set_memory_flags(request_as("alice", request)).await.unwrap_err();
+            assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        }
+    }
+}
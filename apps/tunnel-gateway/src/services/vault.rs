@@ -6,46 +6,77 @@ use identra_proto::vault::{
     ListKeysRequest, ListKeysResponse,
     KeyExistsRequest, KeyExistsResponse,
 };
-use crate::ipc_client::VaultClient;
+use crate::auth::middleware::{get_user_id_from_request, AuthInterceptor};
+use crate::ipc_client::VaultClientPool;
+use tokio::io::{AsyncRead, AsyncWrite};
 use tonic::{Request, Response, Status};
 
-pub struct VaultServiceImpl;
+/// Prefix of the vault keys belonging to `user_id` (`<user_id>/<name>`)
+///
+/// Deleting an account deletes every key under it.
+pub fn user_key_prefix(user_id: &str) -> String {
+    format!("{}/", user_id)
+}
+
+/// Key callers name `key_id` is stored under for `user_id`
+fn user_key(user_id: &str, key_id: &str) -> String {
+    format!("{}{}", user_key_prefix(user_id), key_id)
+}
 
-impl VaultServiceImpl {
-    pub fn new() -> Self {
-        Self
+/// Vault keys over gRPC, each caller seeing only their own
+///
+/// Key ids are stored under `user_key_prefix` of the caller, so one user
+/// can't name another's keys, and `DeleteAccount` finds them all.
+pub struct VaultServiceImpl<S> {
+    pool: VaultClientPool<S>,
+    auth: AuthInterceptor,
+}
+
+impl<S> VaultServiceImpl<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    pub fn new(pool: VaultClientPool<S>, auth: AuthInterceptor) -> Self {
+        Self { pool, auth }
     }
     
     pub fn into_server(self) -> VaultServiceServer<Self> {
         VaultServiceServer::new(self)
     }
+    
+    /// Authenticate the caller, returning their user id and the request body
+    async fn authorize<T>(&self, req: Request<T>) -> Result<(String, T), Status> {
+        let req = self.auth.intercept(req).await?;
+        let user_id = get_user_id_from_request(&req)?;
+        Ok((user_id, req.into_inner()))
+    }
 }
 
 #[tonic::async_trait]
-impl VaultService for VaultServiceImpl {
+impl<S> VaultService for VaultServiceImpl<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
     async fn store_key(
         &self,
         request: Request<StoreKeyRequest>,
     ) -> Result<Response<StoreKeyResponse>, Status> {
-        let req = request.into_inner();
+        let (user_id, req) = self.authorize(request).await?;
         
-        let mut client = VaultClient::connect()
-            .await
-            .map_err(|e| Status::unavailable(format!("Vault daemon not available: {}", e)))?;
+        let mut client = self.pool.acquire().await?;
         
         // Convert protobuf expires_at (Timestamp) to Unix timestamp
         let expires_at = req.expires_at.map(|ts| ts.seconds);
         
         client.store_key(
-            req.key_id.clone(), 
+            user_key(&user_id, &req.key_id),
             req.key_data,
             req.metadata,
             expires_at,
-        )
-            .await
-            .map_err(|e| Status::internal(format!("Failed to store key: {}", e)))?;
+        ).await?;
         
-        tracing::info!("Stored key: {}", req.key_id);
+        crate::metrics::record_vault_keys_stored();
+        tracing::info!("Stored key {} of {}", req.key_id, user_id);
         
         Ok(Response::new(StoreKeyResponse {
             success: true,
@@ -57,17 +88,14 @@ impl VaultService for VaultServiceImpl {
         &self,
         request: Request<RetrieveKeyRequest>,
     ) -> Result<Response<RetrieveKeyResponse>, Status> {
-        let req = request.into_inner();
+        let (user_id, req) = self.authorize(request).await?;
         
-        let mut client = VaultClient::connect()
-            .await
-            .map_err(|e| Status::unavailable(format!("Vault daemon not available: {}", e)))?;
+        let mut client = self.pool.acquire().await?;
         
-        let (key_data, metadata, created_at, expires_at) = client.retrieve_key(req.key_id.clone())
-            .await
-            .map_err(|e| Status::not_found(format!("Key not found: {}", e)))?;
+        let (key_data, metadata, created_at, expires_at) = client.retrieve_key(user_key(&user_id, &req.key_id)).await?;
         
-        tracing::info!("Retrieved key: {}", req.key_id);
+        crate::metrics::record_vault_keys_retrieved();
+        tracing::info!("Retrieved key {} of {}", req.key_id, user_id);
         
         // Convert Unix timestamp to protobuf Timestamp
         let created_at_ts = Some(prost_types::Timestamp {
@@ -86,17 +114,13 @@ impl VaultService for VaultServiceImpl {
         &self,
         request: Request<DeleteKeyRequest>,
     ) -> Result<Response<DeleteKeyResponse>, Status> {
-        let req = request.into_inner();
+        let (user_id, req) = self.authorize(request).await?;
         
-        let mut client = VaultClient::connect()
-            .await
-            .map_err(|e| Status::unavailable(format!("Vault daemon not available: {}", e)))?;
+        let mut client = self.pool.acquire().await?;
         
-        client.delete_key(req.key_id.clone())
-            .await
-            .map_err(|e| Status::internal(format!("Failed to delete key: {}", e)))?;
+        client.delete_key(user_key(&user_id, &req.key_id)).await?;
         
-        tracing::info!("Deleted key: {}", req.key_id);
+        tracing::info!("Deleted key {} of {}", req.key_id, user_id);
         
         Ok(Response::new(DeleteKeyResponse {
             success: true,
@@ -106,21 +130,19 @@ impl VaultService for VaultServiceImpl {
     
     async fn list_keys(
         &self,
-        _request: Request<ListKeysRequest>,
+        request: Request<ListKeysRequest>,
     ) -> Result<Response<ListKeysResponse>, Status> {
-        let mut client = VaultClient::connect()
-            .await
-            .map_err(|e| Status::unavailable(format!("Vault daemon not available: {}", e)))?;
+        let (user_id, _) = self.authorize(request).await?;
         
-        let key_ids = client.list_keys()
-            .await
-            .map_err(|e| {
-                tracing::warn!("list_keys not supported: {}", e);
-                // Windows Credential Manager doesn't support listing
-                Status::unimplemented("list_keys not supported by OS keychain")
-            })?;
+        let mut client = self.pool.acquire().await?;
+        
+        let prefix = user_key_prefix(&user_id);
+        let key_ids: Vec<String> = client.list_keys().await?
+            .into_iter()
+            .filter_map(|key_id| key_id.strip_prefix(&prefix).map(str::to_string))
+            .collect();
         
-        tracing::info!("Listed {} keys", key_ids.len());
+        tracing::info!("Listed {} keys of {}", key_ids.len(), user_id);
         
         Ok(Response::new(ListKeysResponse {
             key_ids,
@@ -132,22 +154,150 @@ impl VaultService for VaultServiceImpl {
         &self,
         request: Request<KeyExistsRequest>,
     ) -> Result<Response<KeyExistsResponse>, Status> {
-        let req = request.into_inner();
+        let (user_id, req) = self.authorize(request).await?;
         
-        let mut client = VaultClient::connect()
-            .await
-            .map_err(|e| Status::unavailable(format!("Vault daemon not available: {}", e)))?;
+        let mut client = self.pool.acquire().await?;
         
-        let exists = client.key_exists(req.key_id.clone())
-            .await
-            .map_err(|e| Status::internal(format!("Failed to check key existence: {}", e)))?;
+        let exists = client.key_exists(user_key(&user_id, &req.key_id)).await?;
         
         Ok(Response::new(KeyExistsResponse { exists }))
     }
 }
 
-impl Default for VaultServiceImpl {
-    fn default() -> Self {
-        Self::new()
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::ipc_client::{PoolConfig, VaultClient, VaultRequest, VaultResponse};
+    use crate::services::memory::tests::{request_as, TokenIsUser};
+    use identra_ipc::{read_message, write_message, RequestFrame, ResponseFrame};
+    use std::collections::{BTreeMap, HashMap};
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{BufReader, DuplexStream};
+
+    /// In-memory stand-in for the vault daemon, shared by all its connections
+    #[derive(Clone, Default)]
+    pub(crate) struct FakeVault {
+        pub(crate) keys: Arc<Mutex<BTreeMap<String, Vec<u8>>>>,
+    }
+
+    impl FakeVault {
+        pub(crate) fn pool(&self) -> VaultClientPool<DuplexStream> {
+            let vault = self.clone();
+            VaultClientPool::with_connector(PoolConfig::default(), move || {
+                let vault = vault.clone();
+                Box::pin(async move { Ok(vault.connect()) })
+            })
+        }
+
+        fn connect(&self) -> VaultClient<DuplexStream> {
+            let (client, server) = tokio::io::duplex(4096);
+            let vault = self.clone();
+            tokio::spawn(async move {
+                let mut server = BufReader::new(server);
+                while let Ok(Some(frame)) = read_message::<_, RequestFrame>(&mut server).await {
+                    let response = ResponseFrame { request_id: Some(frame.request_id), response: vault.answer(frame.request) };
+                    if write_message(server.get_mut(), &response).await.is_err() {
+                        break;
+                    }
+                }
+            });
+            VaultClient::from_stream(client)
+        }
+
+        fn answer(&self, request: VaultRequest) -> VaultResponse {
+            let mut keys = self.keys.lock().unwrap();
+            match request {
+                VaultRequest::Ping => VaultResponse::Pong,
+                VaultRequest::StoreKey { key_id, key_data, .. } => {
+                    keys.insert(key_id, key_data);
+                    VaultResponse::Success
+                }
+                VaultRequest::RetrieveKey { key_id } => match keys.get(&key_id) {
+                    Some(key_data) => VaultResponse::KeyData {
+                        key_data: key_data.clone(),
+                        metadata: HashMap::new(),
+                        created_at: 0,
+                        expires_at: None,
+                    },
+                    None => VaultResponse::NotFound { key_id },
+                },
+                VaultRequest::DeleteKey { key_id } => match keys.remove(&key_id) {
+                    Some(_) => VaultResponse::Success,
+                    None => VaultResponse::NotFound { key_id },
+                },
+                VaultRequest::KeyExists { key_id } => VaultResponse::Exists(keys.contains_key(&key_id)),
+                VaultRequest::ListKeys => VaultResponse::KeyList(keys.keys().cloned().collect()),
+                VaultRequest::DeleteKeysWithPrefix { prefix } => {
+                    let before = keys.len();
+                    keys.retain(|key_id, _| !key_id.starts_with(&prefix));
+                    VaultResponse::Purged(before - keys.len())
+                }
+                other => VaultResponse::Error(format!("Unsupported by the fake vault: {:?}", other)),
+            }
+        }
+    }
+
+    fn service(vault: &FakeVault) -> VaultServiceImpl<DuplexStream> {
+        VaultServiceImpl::new(vault.pool(), AuthInterceptor::new(Arc::new(TokenIsUser)))
+    }
+
+    fn store_request(key_id: &str) -> StoreKeyRequest {
+        StoreKeyRequest { key_id: key_id.to_string(), key_data: vec![1, 2, 3], ..Default::default() }
+    }
+
+    #[tokio::test]
+    async fn test_keys_are_namespaced_per_user() {
+        let vault = FakeVault::default();
+        let service = service(&vault);
+
+        service.store_key(request_as("alice", store_request("signing"))).await.unwrap();
+        service.store_key(request_as("bob", store_request("backup"))).await.unwrap();
+        assert_eq!(
+            vault.keys.lock().unwrap().keys().cloned().collect::<Vec<_>>(),
+            ["alice/signing", "bob/backup"]
+        );
+
+        let retrieved = service.retrieve_key(request_as("alice", RetrieveKeyRequest { key_id: "signing".to_string() }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(retrieved.key_data, [1, 2, 3]);
+        let err = service.retrieve_key(request_as("bob", RetrieveKeyRequest { key_id: "signing".to_string() }))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+
+        let exists = |user: &str, key_id: &str| {
+            service.key_exists(request_as(user, KeyExistsRequest { key_id: key_id.to_string() }))
+        };
+        assert!(exists("bob", "backup").await.unwrap().into_inner().exists);
+        assert!(!exists("bob", "../alice/signing").await.unwrap().into_inner().exists);
+
+        let listed = service.list_keys(request_as("bob", ListKeysRequest::default())).await.unwrap().into_inner();
+        assert_eq!(listed.key_ids, ["backup"]);
+
+        let err = service.delete_key(request_as("bob", DeleteKeyRequest { key_id: "signing".to_string() }))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+        assert_eq!(vault.keys.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_vault_requires_token() {
+        let vault = FakeVault::default();
+        let err = service(&vault).store_key(Request::new(store_request("signing"))).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unauthenticated);
+        assert!(vault.keys.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_service_role_has_no_keys() {
+        let vault = FakeVault::default();
+        let err = service(&vault).store_key(request_as("admin", store_request("signing"))).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+        let err = service(&vault).list_keys(request_as("admin", ListKeysRequest::default())).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+        assert!(vault.keys.lock().unwrap().is_empty());
     }
 }
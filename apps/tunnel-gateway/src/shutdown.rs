@@ -0,0 +1,176 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tower::{Layer, Service};
+
+/// Default time given to active requests once shutdown starts
+pub const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(30);
+
+/// Resolves on Ctrl+C, or SIGTERM on Unix (what container runtimes send)
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Failed to listen for Ctrl+C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+/// Shared count of requests currently being handled
+#[derive(Clone, Default)]
+pub struct InFlight(Arc<AtomicUsize>);
+
+impl InFlight {
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    fn enter(&self) -> InFlightGuard {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        InFlightGuard(self.0.clone())
+    }
+}
+
+/// Decrements the in-flight count when the request finishes or is dropped
+struct InFlightGuard(Arc<AtomicUsize>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Tower layer that tracks requests in an `InFlight` counter
+#[derive(Clone)]
+pub struct InFlightLayer {
+    in_flight: InFlight,
+}
+
+impl InFlightLayer {
+    pub fn new(in_flight: InFlight) -> Self {
+        Self { in_flight }
+    }
+}
+
+impl<S> Layer<S> for InFlightLayer {
+    type Service = InFlightService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        InFlightService {
+            inner,
+            in_flight: self.in_flight.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct InFlightService<S> {
+    inner: S,
+    in_flight: InFlight,
+}
+
+type BoxFuture<T, E> = Pin<Box<dyn Future<Output = Result<T, E>> + Send>>;
+
+impl<S, Req> Service<Req> for InFlightService<S>
+where
+    S: Service<Req>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<S::Response, S::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        let guard = self.in_flight.enter();
+        let future = self.inner.call(req);
+        Box::pin(async move {
+            let _guard = guard;
+            future.await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use tokio::sync::Semaphore;
+
+    /// Service whose requests complete only once a permit is released
+    struct Blocking(Arc<Semaphore>);
+
+    impl Service<()> for Blocking {
+        type Response = ();
+        type Error = Infallible;
+        type Future = BoxFuture<(), Infallible>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _req: ()) -> Self::Future {
+            let permits = self.0.clone();
+            Box::pin(async move {
+                permits.acquire().await.unwrap().forget();
+                Ok(())
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_in_flight_counts_active_requests() {
+        let in_flight = InFlight::default();
+        let permits = Arc::new(Semaphore::new(0));
+        let mut service = InFlightLayer::new(in_flight.clone()).layer(Blocking(permits.clone()));
+
+        let first = tokio::spawn(service.call(()));
+        let second = tokio::spawn(service.call(()));
+        assert_eq!(in_flight.count(), 2);
+
+        permits.add_permits(2);
+        first.await.unwrap().unwrap();
+        second.await.unwrap().unwrap();
+        assert_eq!(in_flight.count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_request_is_not_counted() {
+        let in_flight = InFlight::default();
+        let mut service = InFlightLayer::new(in_flight.clone())
+            .layer(Blocking(Arc::new(Semaphore::new(0))));
+
+        let pending = service.call(());
+        assert_eq!(in_flight.count(), 1);
+
+        drop(pending);
+        assert_eq!(in_flight.count(), 0);
+    }
+}
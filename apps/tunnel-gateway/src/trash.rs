@@ -0,0 +1,42 @@
+use crate::database::MemoryDatabase;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long deleted memories stay restorable when nothing is configured
+pub const DEFAULT_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// How often trashed memories past the retention window are purged
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Oldest `deleted_at` still inside the retention window at `now`
+pub fn retention_cutoff(now: i64, retention: Duration) -> i64 {
+    now.saturating_sub(i64::try_from(retention.as_secs()).unwrap_or(i64::MAX))
+}
+
+/// Hard-delete memories whose retention window has passed, every hour
+pub fn spawn_purge_task(db: Arc<MemoryDatabase>, retention: Duration) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            let cutoff = retention_cutoff(chrono::Utc::now().timestamp(), retention);
+            match db.purge_trashed(cutoff).await {
+                Ok(0) => {}
+                Ok(purged) => tracing::info!("🧹 Purged {} trashed memories", purged),
+                Err(e) => tracing::error!("Failed to purge trashed memories: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retention_cutoff() {
+        assert_eq!(retention_cutoff(1_000, Duration::from_secs(100)), 900);
+        assert_eq!(retention_cutoff(1_000, Duration::ZERO), 1_000);
+        assert_eq!(retention_cutoff(0, Duration::MAX), -i64::MAX);
+    }
+}
@@ -0,0 +1,169 @@
+use identra_proto::memory::{memory_event::Kind, Memory, MemoryEvent};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::Status;
+
+/// Events buffered per user before lagging watchers are told to resync
+const CHANNEL_CAPACITY: usize = 256;
+
+/// Events buffered between a watcher's broadcast receiver and its gRPC stream
+const STREAM_BUFFER: usize = 16;
+
+pub type EventStream = ReceiverStream<Result<MemoryEvent, Status>>;
+
+/// Per-user fan-out of memory changes to `WatchMemories` streams
+///
+/// Publishing never waits on watchers: one that falls `CHANNEL_CAPACITY`
+/// events behind loses them and gets a `RESYNC` instead. Events only reach
+/// watchers connected to the same gateway process.
+#[derive(Default)]
+pub struct MemoryEvents {
+    channels: Mutex<HashMap<String, broadcast::Sender<MemoryEvent>>>,
+}
+
+impl MemoryEvents {
+    /// Receive every event published for `user_id` from now on
+    pub fn subscribe(&self, user_id: &str) -> broadcast::Receiver<MemoryEvent> {
+        let mut channels = self.channels.lock().unwrap_or_else(|e| e.into_inner());
+        // Forget users whose watchers have all disconnected
+        channels.retain(|_, sender| sender.receiver_count() > 0);
+        channels
+            .entry(user_id.to_string())
+            .or_insert_with(|| broadcast::channel(CHANNEL_CAPACITY).0)
+            .subscribe()
+    }
+
+    pub fn added(&self, user_id: &str, memory: Memory) {
+        self.publish(user_id, memory_event(Kind::Added, memory));
+    }
+
+    pub fn updated(&self, user_id: &str, memory: Memory) {
+        self.publish(user_id, memory_event(Kind::Updated, memory));
+    }
+
+    pub fn deleted(&self, user_id: &str, memory_id: &str) {
+        self.publish(user_id, MemoryEvent {
+            kind: Kind::Deleted as i32,
+            memory: None,
+            memory_id: memory_id.to_string(),
+        });
+    }
+
+    fn publish(&self, user_id: &str, event: MemoryEvent) {
+        let channels = self.channels.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(sender) = channels.get(user_id) {
+            // Fails only when nobody is watching
+            let _ = sender.send(event);
+        }
+    }
+}
+
+fn memory_event(kind: Kind, memory: Memory) -> MemoryEvent {
+    MemoryEvent { kind: kind as i32, memory_id: memory.id.clone(), memory: Some(memory) }
+}
+
+fn marker(kind: Kind) -> MemoryEvent {
+    MemoryEvent { kind: kind as i32, memory: None, memory_id: String::new() }
+}
+
+/// Stream `snapshot`, a `SYNCED` marker, then everything from `events`
+///
+/// Subscribe before reading the snapshot so nothing stored in between is
+/// missed. The forwarding task ends when the client goes away.
+pub fn event_stream(snapshot: Vec<Memory>, mut events: broadcast::Receiver<MemoryEvent>) -> EventStream {
+    let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+    tokio::spawn(async move {
+        let snapshot = snapshot.into_iter().map(|memory| memory_event(Kind::Snapshot, memory));
+        for event in snapshot.chain([marker(Kind::Synced)]) {
+            if tx.send(Ok(event)).await.is_err() {
+                return;
+            }
+        }
+
+        loop {
+            let event = tokio::select! {
+                _ = tx.closed() => return,
+                received = events.recv() => match received {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::debug!("Memory watcher fell {} events behind", skipped);
+                        marker(Kind::Resync)
+                    }
+                    Err(RecvError::Closed) => return,
+                },
+            };
+            if tx.send(Ok(event)).await.is_err() {
+                return;
+            }
+        }
+    });
+    ReceiverStream::new(rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_stream::StreamExt;
+
+    fn memory(id: &str) -> Memory {
+        Memory { id: id.to_string(), ..Default::default() }
+    }
+
+    async fn next_kind(stream: &mut EventStream) -> (Kind, String) {
+        let event = stream.next().await.unwrap().unwrap();
+        (event.kind(), event.memory_id)
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_then_live_events_for_that_user_only() {
+        let events = MemoryEvents::default();
+        let mut stream = event_stream(vec![memory("old")], events.subscribe("alice"));
+
+        events.added("bob", memory("not-yours"));
+        events.added("alice", memory("new"));
+        events.deleted("alice", "old");
+
+        assert_eq!(next_kind(&mut stream).await, (Kind::Snapshot, "old".to_string()));
+        assert_eq!(next_kind(&mut stream).await, (Kind::Synced, String::new()));
+        assert_eq!(next_kind(&mut stream).await, (Kind::Added, "new".to_string()));
+        assert_eq!(next_kind(&mut stream).await, (Kind::Deleted, "old".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_slow_watcher_gets_resync_without_blocking_writers() {
+        let events = MemoryEvents::default();
+        let mut stream = event_stream(Vec::new(), events.subscribe("alice"));
+        assert_eq!(next_kind(&mut stream).await.0, Kind::Synced);
+
+        // Nobody reads the stream meanwhile, and publishing still returns
+        let total = CHANNEL_CAPACITY + STREAM_BUFFER + 100;
+        for i in 0..total {
+            events.updated("alice", memory(&i.to_string()));
+        }
+
+        let mut kinds = Vec::new();
+        while kinds.last() != Some(&Kind::Resync) {
+            kinds.push(next_kind(&mut stream).await.0);
+        }
+        assert!(kinds.len() < total);
+    }
+
+    #[tokio::test]
+    async fn test_disconnected_watchers_are_forgotten() {
+        let events = MemoryEvents::default();
+        drop(event_stream(Vec::new(), events.subscribe("alice")));
+
+        // The forwarding task notices on its next poll
+        for _ in 0..100 {
+            events.subscribe("bob");
+            if !events.channels.lock().unwrap().contains_key("alice") {
+                return;
+            }
+            tokio::task::yield_now().await;
+        }
+        panic!("channel for a disconnected watcher was kept");
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Writes a user may make back to back before the rate applies
+pub const DEFAULT_BURST: u32 = 20;
+
+/// Most users tracked at once; beyond it the longest idle bucket is dropped
+const MAX_TRACKED_USERS: usize = 10_000;
+
+/// Per-user allowance for memory writes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WriteLimitConfig {
+    /// Sustained writes per second
+    pub rate: f64,
+    pub burst: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token bucket per user on the memory writes that compute embeddings
+///
+/// Separate from login throttling: this guards the embedding provider and
+/// the database from a client stuck in a loop, not credentials. A bucket
+/// left idle long enough to refill is the same as no bucket, so those are
+/// dropped, and at most `MAX_TRACKED_USERS` are kept. Per process, like
+/// `LoginRateLimiter`.
+pub struct WriteLimiter {
+    config: WriteLimitConfig,
+    max_users: usize,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl WriteLimiter {
+    pub fn new(config: WriteLimitConfig) -> Self {
+        Self { config, max_users: MAX_TRACKED_USERS, buckets: Mutex::new(HashMap::new()) }
+    }
+
+    /// Take one write from `user_id`'s bucket, or `Err(retry_after)` if it is empty
+    pub fn check(&self, user_id: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if !buckets.contains_key(user_id) && buckets.len() >= self.max_users {
+            self.make_room(&mut buckets, now);
+        }
+
+        let burst = f64::from(self.config.burst);
+        let bucket = buckets.entry(user_id.to_string()).or_insert(Bucket { tokens: burst, updated: now });
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.config.rate))
+        }
+    }
+
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.config.rate).min(f64::from(self.config.burst))
+    }
+
+    /// Drop refilled buckets, or failing that the one idle the longest
+    fn make_room(&self, buckets: &mut HashMap<String, Bucket>, now: Instant) {
+        let burst = f64::from(self.config.burst);
+        buckets.retain(|_, bucket| self.refilled(bucket, now) < burst);
+        if buckets.len() >= self.max_users {
+            let idlest = buckets.iter().min_by_key(|(_, bucket)| bucket.updated).map(|(user, _)| user.clone());
+            if let Some(user) = idlest {
+                buckets.remove(&user);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(rate: f64, burst: u32) -> WriteLimiter {
+        WriteLimiter::new(WriteLimitConfig { rate, burst })
+    }
+
+    #[test]
+    fn test_burst_then_recovers_at_rate() {
+        let limiter = limiter(10.0, 3);
+        for _ in 0..3 {
+            assert!(limiter.check("alice").is_ok());
+        }
+        let retry_after = limiter.check("alice").unwrap_err();
+        assert!(retry_after <= Duration::from_millis(100), "{:?}", retry_after);
+        assert!(limiter.check("bob").is_ok(), "buckets are per user");
+
+        std::thread::sleep(retry_after + Duration::from_millis(10));
+        assert!(limiter.check("alice").is_ok());
+        assert!(limiter.check("alice").is_err(), "one token back, not the whole burst");
+    }
+
+    #[test]
+    fn test_tracked_users_are_bounded() {
+        let mut limiter = limiter(0.001, 2);
+        limiter.max_users = 3;
+        for user in ["a", "b", "c", "d", "e"] {
+            assert!(limiter.check(user).is_ok());
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.len(), 3);
+        assert!(buckets.contains_key("e") && !buckets.contains_key("a"), "idlest dropped first");
+    }
+
+    #[test]
+    fn test_refilled_buckets_expire() {
+        let mut limiter = limiter(1_000_000.0, 1);
+        limiter.max_users = 2;
+        limiter.check("a").unwrap();
+        limiter.check("b").unwrap();
+        std::thread::sleep(Duration::from_millis(1));
+        limiter.check("c").unwrap();
+        assert_eq!(limiter.buckets.lock().unwrap().len(), 1);
+    }
+}
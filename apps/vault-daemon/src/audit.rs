@@ -0,0 +1,200 @@
+use crate::error::Result;
+use identra_ipc::{VaultRequest, VaultResponse};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Size at which the audit file is rotated when no cap is configured
+pub const DEFAULT_MAX_BYTES: u64 = 10 * 1024 * 1024;
+
+/// One line of the audit log; never carries key material or passphrases
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Unix timestamp in milliseconds
+    pub timestamp: i64,
+    pub operation: String,
+    /// Key id, or the prefix for `delete_keys_with_prefix`
+    pub key_id: Option<String>,
+    /// `"success"` or `"error"`
+    pub outcome: String,
+    /// Error message when the outcome is `"error"`
+    pub error: Option<String>,
+}
+
+impl AuditRecord {
+    /// Operation name and key id to audit for `request`
+    ///
+    /// `None` for `Ping` and `Status`: they touch no keys, and pooled
+    /// clients ping on every checkout, which would drown everything else.
+    /// Streamed secrets are recorded once, when the stream is opened, not
+    /// for every piece.
+    pub fn describe(request: &VaultRequest) -> Option<(&'static str, Option<String>)> {
+        let (operation, key_id) = match request {
+            VaultRequest::StoreKey { key_id, .. } => ("store_key", Some(key_id)),
+            VaultRequest::RetrieveKey { key_id } => ("retrieve_key", Some(key_id)),
+            VaultRequest::DeleteKey { key_id } => ("delete_key", Some(key_id)),
+            VaultRequest::KeyExists { key_id } => ("key_exists", Some(key_id)),
+            VaultRequest::ListKeys => ("list_keys", None),
+            VaultRequest::PurgeExpired => ("purge_expired", None),
+            VaultRequest::DeleteKeysWithPrefix { prefix } => ("delete_keys_with_prefix", Some(prefix)),
+            VaultRequest::StoreSecretStream { key_id, .. } => ("store_secret_stream", Some(key_id)),
+            VaultRequest::RetrieveSecretStream { key_id } => ("retrieve_secret_stream", Some(key_id)),
+            VaultRequest::Unlock { .. } => ("unlock", None),
+            VaultRequest::Lock => ("lock", None),
+            VaultRequest::Shutdown => ("shutdown", None),
+            VaultRequest::Authenticate { .. }
+            | VaultRequest::Ping
+            | VaultRequest::Status
+            | VaultRequest::SecretChunk { .. }
+            | VaultRequest::NextSecretChunk => return None,
+        };
+        Some((operation, key_id.cloned()))
+    }
+
+    /// Record of `operation` on `key_id` answered with `response`
+    pub fn new(operation: &str, key_id: Option<String>, response: &VaultResponse) -> Self {
+        let error = match response {
+            VaultResponse::Error(message) => Some(message.clone()),
+            _ => None,
+        };
+
+        Self {
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            operation: operation.to_string(),
+            key_id,
+            outcome: if error.is_some() { "error" } else { "success" }.to_string(),
+            error,
+        }
+    }
+}
+
+/// Append-only JSON-lines audit trail of vault requests
+///
+/// Every record is also emitted as a `tracing` event with target `audit`;
+/// the file is only written when a path is configured. Once the file
+/// reaches `max_bytes` it is renamed to `<path>.1`, replacing the previous
+/// one, so at most twice the cap is kept on disk.
+pub struct AuditLog {
+    file: Option<Mutex<AuditFile>>,
+}
+
+struct AuditFile {
+    path: PathBuf,
+    max_bytes: u64,
+    file: File,
+    len: u64,
+}
+
+impl AuditLog {
+    /// Log to `tracing` only
+    pub fn disabled() -> Self {
+        Self { file: None }
+    }
+
+    /// Append to `path`, creating it if needed
+    pub fn open(path: impl Into<PathBuf>, max_bytes: u64) -> Result<Self> {
+        let path = path.into();
+        let file = open_append(&path)?;
+        let len = file.metadata()?.len();
+        Ok(Self { file: Some(Mutex::new(AuditFile { path, max_bytes, file, len })) })
+    }
+
+    /// Log from `IDENTRA_VAULT_AUDIT_LOG` (file path) and
+    /// `IDENTRA_VAULT_AUDIT_MAX_BYTES`; no path means `tracing` only
+    pub fn from_env() -> Result<Self> {
+        let Some(path) = std::env::var("IDENTRA_VAULT_AUDIT_LOG").ok().filter(|p| !p.trim().is_empty()) else {
+            return Ok(Self::disabled());
+        };
+        let max_bytes = std::env::var("IDENTRA_VAULT_AUDIT_MAX_BYTES")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEFAULT_MAX_BYTES);
+        Self::open(path, max_bytes)
+    }
+
+    pub fn record(&self, record: &AuditRecord) {
+        tracing::info!(
+            target: "audit",
+            operation = %record.operation,
+            key_id = record.key_id.as_deref().unwrap_or(""),
+            outcome = %record.outcome,
+            error = record.error.as_deref().unwrap_or(""),
+        );
+
+        if let Some(file) = &self.file {
+            let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+            if let Err(e) = file.append(record) {
+                tracing::error!("Failed to write audit record to {}: {}", file.path.display(), e);
+            }
+        }
+    }
+}
+
+impl AuditFile {
+    fn append(&mut self, record: &AuditRecord) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        if self.len > 0 && self.len + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(&line)?;
+        self.len += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(".1");
+        std::fs::rename(&self.path, rotated)?;
+        self.file = open_append(&self.path)?;
+        self.len = 0;
+        Ok(())
+    }
+}
+
+/// Open for appending; a new file is readable by the daemon's user only
+fn open_append(path: &Path) -> Result<File> {
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    Ok(options.open(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotates_at_cap() {
+        let path = std::env::temp_dir().join(format!("identra-audit-rotate-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let log = AuditLog::open(&path, 300).unwrap();
+
+        for _ in 0..5 {
+            log.record(&AuditRecord::new("delete_key", Some("k".to_string()), &VaultResponse::Success));
+        }
+
+        let rotated = PathBuf::from(format!("{}.1", path.display()));
+        assert!(std::fs::metadata(&path).unwrap().len() <= 300);
+        assert!(std::fs::metadata(&rotated).unwrap().len() <= 300);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        std::fs::remove_file(&path).ok();
+        std::fs::remove_file(&rotated).ok();
+    }
+
+    #[test]
+    fn test_describe_skips_health_checks_and_secrets() {
+        assert_eq!(AuditRecord::describe(&VaultRequest::Ping), None);
+
+        let unlock = VaultRequest::Unlock { passphrase: "hunter2".to_string() };
+        assert_eq!(AuditRecord::describe(&unlock), Some(("unlock", None)));
+    }
+}
@@ -0,0 +1,154 @@
+//! Operator tool for a running vault daemon
+//!
+//! Talks to the daemon over the same IPC protocol as the gateway and the
+//! desktop app. Key bytes are never printed: `get` only shows metadata.
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use identra_ipc::VaultClient;
+use serde_json::json;
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use zeroize::Zeroizing;
+
+#[derive(Parser)]
+#[command(name = "vault-cli", about = "Administer the Identra vault daemon")]
+struct Cli {
+    /// Print machine-readable JSON instead of text
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check that the daemon is reachable
+    Ping,
+    /// Show whether the vault is locked
+    Status,
+    /// Unlock the vault with a passphrase read from stdin
+    Unlock,
+    /// Lock the vault
+    Lock,
+    /// List stored key ids
+    List,
+    /// Check whether a key exists
+    Exists { key_id: String },
+    /// Show a key's metadata (never its bytes)
+    Get { key_id: String },
+    /// Delete a key
+    Delete { key_id: String },
+    /// Store the contents of a file as a key
+    Store {
+        key_id: String,
+        #[arg(long)]
+        file: PathBuf,
+        /// Unix timestamp after which the key is deleted
+        #[arg(long)]
+        expires_at: Option<i64>,
+        /// Custom metadata entry, repeatable
+        #[arg(long = "meta", value_name = "KEY=VALUE", value_parser = parse_meta)]
+        metadata: Vec<(String, String)>,
+    },
+}
+
+fn parse_meta(entry: &str) -> Result<(String, String), String> {
+    entry
+        .split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected KEY=VALUE, got '{}'", entry))
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli.command, cli.json).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(command: Command, json: bool) -> Result<()> {
+    let mut client = VaultClient::connect().await.context("Failed to connect to vault daemon")?;
+
+    let (text, value) = match command {
+        Command::Ping => {
+            client.ping().await?;
+            ("pong".to_string(), json!({ "ok": true }))
+        }
+        Command::Status => {
+            let locked = client.is_locked().await?;
+            let text = if locked { "locked" } else { "unlocked" };
+            (text.to_string(), json!({ "locked": locked }))
+        }
+        Command::Unlock => {
+            let mut passphrase = String::new();
+            std::io::stdin().lock().read_line(&mut passphrase).context("Failed to read passphrase")?;
+            let passphrase = Zeroizing::new(passphrase);
+            let passphrase = passphrase.trim_end_matches(['\r', '\n']).to_string();
+            client.unlock(passphrase).await?;
+            ("unlocked".to_string(), json!({ "locked": false }))
+        }
+        Command::Lock => {
+            client.lock().await?;
+            ("locked".to_string(), json!({ "locked": true }))
+        }
+        Command::List => {
+            let mut keys = client.list_keys().await?;
+            keys.sort();
+            (keys.join("\n"), json!(keys))
+        }
+        Command::Exists { key_id } => {
+            let exists = client.key_exists(key_id.clone()).await?;
+            let text = format!("{}: {}", key_id, if exists { "exists" } else { "not found" });
+            (text, json!({ "key_id": key_id, "exists": exists }))
+        }
+        Command::Get { key_id } => {
+            let (key_data, metadata, created_at, expires_at) = client.retrieve_key(key_id.clone()).await?;
+            let key_data = Zeroizing::new(key_data);
+            let mut text = format!("{}\n  size: {} bytes\n  created_at: {}", key_id, key_data.len(), created_at);
+            if let Some(expires_at) = expires_at {
+                text.push_str(&format!("\n  expires_at: {}", expires_at));
+            }
+            let mut entries: Vec<_> = metadata.iter().collect();
+            entries.sort();
+            for (key, value) in entries {
+                text.push_str(&format!("\n  {}: {}", key, value));
+            }
+            let value = json!({
+                "key_id": key_id,
+                "size": key_data.len(),
+                "created_at": created_at,
+                "expires_at": expires_at,
+                "metadata": metadata,
+            });
+            (text, value)
+        }
+        Command::Delete { key_id } => {
+            client.delete_key(key_id.clone()).await?;
+            (format!("deleted {}", key_id), json!({ "key_id": key_id, "deleted": true }))
+        }
+        Command::Store { key_id, file, expires_at, metadata } => {
+            let key_data = std::fs::read(&file).with_context(|| format!("Failed to read {}", file.display()))?;
+            let size = key_data.len();
+            let metadata: HashMap<String, String> = metadata.into_iter().collect();
+            client.store_key(key_id.clone(), key_data, metadata, expires_at).await?;
+            let text = format!("stored {} ({} bytes)", key_id, size);
+            (text, json!({ "key_id": key_id, "stored": true, "size": size }))
+        }
+    };
+
+    if json {
+        println!("{}", value);
+    } else if !text.is_empty() {
+        println!("{}", text);
+    }
+    Ok(())
+}
@@ -0,0 +1,248 @@
+use crate::error::{Result, VaultError};
+use crate::keychain::{KeyMetadata, KeyStorage};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use identra_crypto::{decrypt, derive_subkey, encrypt, generate_salt, CryptoError, EncryptionKey, Nonce};
+
+/// Metadata entry holding the base64 salt the per-key subkey is derived with
+pub const SALT_METADATA_KEY: &str = "identra.salt";
+
+/// Metadata entry holding the base64 nonce the key was sealed with
+pub const NONCE_METADATA_KEY: &str = "identra.nonce";
+
+/// Key storage that encrypts key material before it reaches the keychain
+///
+/// Each key is sealed with a subkey of the unlocked master key, derived
+/// from a fresh salt, so an exported keychain holds only ciphertext. Keys
+/// stored without the salt/nonce metadata are rejected, since anyone who
+/// can write the keychain could otherwise plant an unauthenticated key.
+pub struct EncryptedKeyStorage<'a> {
+    inner: &'a dyn KeyStorage,
+    master: EncryptionKey,
+    accept_plaintext: bool,
+}
+
+impl<'a> EncryptedKeyStorage<'a> {
+    pub fn new(inner: &'a dyn KeyStorage, master: EncryptionKey) -> Self {
+        Self { inner, master, accept_plaintext: false }
+    }
+
+    /// Return keys written before encryption was enabled as-is, sealing
+    /// them in place on that first read
+    ///
+    /// Only for migrating an old keychain: while on, stripping a key's
+    /// salt/nonce metadata turns it into one that is trusted unchecked.
+    pub fn accepting_plaintext(mut self, accept: bool) -> Self {
+        self.accept_plaintext = accept;
+        self
+    }
+}
+
+fn decode_metadata(metadata: &KeyMetadata, name: &str) -> Result<Option<Vec<u8>>> {
+    metadata.custom.get(name)
+        .map(|value| STANDARD.decode(value)
+            .map_err(|e| VaultError::Encryption(format!("Invalid {}: {}", name, e))))
+        .transpose()
+}
+
+impl KeyStorage for EncryptedKeyStorage<'_> {
+    fn store_key(&self, key_id: &str, key: &[u8], mut metadata: KeyMetadata) -> Result<()> {
+        let salt = generate_salt();
+        let nonce = Nonce::generate();
+
+        let ciphertext = encrypt(&derive_subkey(&self.master, &salt), &nonce, key)
+            .map_err(|e| VaultError::Encryption(e.to_string()))?;
+
+        metadata.custom.insert(SALT_METADATA_KEY.to_string(), STANDARD.encode(salt));
+        metadata.custom.insert(NONCE_METADATA_KEY.to_string(), STANDARD.encode(nonce.as_bytes()));
+
+        self.inner.store_key(key_id, &ciphertext, metadata)
+    }
+
+    fn retrieve_key(&self, key_id: &str) -> Result<(Vec<u8>, KeyMetadata)> {
+        let (stored, mut metadata) = self.inner.retrieve_key(key_id)?;
+
+        let salt = decode_metadata(&metadata, SALT_METADATA_KEY)?;
+        let nonce = decode_metadata(&metadata, NONCE_METADATA_KEY)?;
+        let (salt, nonce) = match (salt, nonce) {
+            (Some(salt), Some(nonce)) => (salt, nonce),
+            (None, None) if self.accept_plaintext => {
+                // The caller still gets the key if resealing fails
+                if let Err(e) = self.store_key(key_id, &stored, metadata.clone()) {
+                    tracing::warn!("Failed to seal plaintext key '{}': {}", key_id, e);
+                }
+                return Ok((stored, metadata));
+            }
+            (None, None) => return Err(VaultError::Encryption(
+                format!("Key '{}' is not encrypted", key_id)
+            )),
+            _ => return Err(VaultError::Encryption(
+                format!("Key '{}' has incomplete encryption metadata", key_id)
+            )),
+        };
+
+        let nonce = Nonce::from_bytes(&nonce)
+            .map_err(|e| VaultError::Encryption(e.to_string()))?;
+        let key = decrypt(&derive_subkey(&self.master, &salt), &nonce, &stored).map_err(|e| {
+            // The vault is unlocked, so the master key is right: the stored
+            // key or its metadata was changed behind our back
+            if matches!(e, CryptoError::Authentication) {
+                tracing::warn!("Key '{}' failed authentication; its stored ciphertext may have been tampered with", key_id);
+            }
+            VaultError::Encryption(format!("Failed to decrypt key '{}': {}", key_id, e))
+        })?;
+
+        metadata.custom.remove(SALT_METADATA_KEY);
+        metadata.custom.remove(NONCE_METADATA_KEY);
+
+        Ok((key, metadata))
+    }
+
+    fn delete_key(&self, key_id: &str) -> Result<()> {
+        self.inner.delete_key(key_id)
+    }
+
+    fn key_exists(&self, key_id: &str) -> bool {
+        self.inner.key_exists(key_id)
+    }
+
+    fn list_keys(&self) -> Result<Vec<String>> {
+        self.inner.list_keys()
+    }
+
+    fn purge_expired(&self) -> Result<usize> {
+        // Expiry lives in plaintext metadata, so there's nothing to decrypt
+        self.inner.purge_expired()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keychain::MemoryKeyStorage;
+    use std::collections::HashMap;
+
+    fn master(byte: u8) -> EncryptionKey {
+        EncryptionKey::from_bytes(&[byte; identra_crypto::KEY_SIZE]).unwrap()
+    }
+
+    fn metadata() -> KeyMetadata {
+        KeyMetadata {
+            created_at: 0,
+            expires_at: None,
+            custom: HashMap::from([("purpose".to_string(), "test".to_string())]),
+        }
+    }
+
+    #[test]
+    fn test_round_trip_stores_ciphertext() {
+        let inner = MemoryKeyStorage::new();
+        let storage = EncryptedKeyStorage::new(&inner, master(1));
+
+        storage.store_key("k", b"secret key bytes", metadata()).unwrap();
+
+        let (raw, raw_metadata) = inner.retrieve_key("k").unwrap();
+        assert_ne!(raw.as_slice(), b"secret key bytes");
+        assert!(raw_metadata.custom.contains_key(SALT_METADATA_KEY));
+        assert!(raw_metadata.custom.contains_key(NONCE_METADATA_KEY));
+
+        let (key, metadata) = storage.retrieve_key("k").unwrap();
+        assert_eq!(key, b"secret key bytes");
+        assert_eq!(metadata.custom.len(), 1);
+        assert_eq!(metadata.custom["purpose"], "test");
+    }
+
+    #[test]
+    fn test_wrong_master_key_fails() {
+        let inner = MemoryKeyStorage::new();
+        EncryptedKeyStorage::new(&inner, master(1)).store_key("k", b"secret", metadata()).unwrap();
+
+        let result = EncryptedKeyStorage::new(&inner, master(2)).retrieve_key("k");
+        assert!(matches!(result, Err(VaultError::Encryption(_))));
+    }
+
+    /// Storage that keeps what it already has but refuses new writes
+    struct ReadOnlyStorage(MemoryKeyStorage);
+
+    impl KeyStorage for ReadOnlyStorage {
+        fn store_key(&self, _key_id: &str, _key: &[u8], _metadata: KeyMetadata) -> Result<()> {
+            Err(VaultError::Keychain("read-only".to_string()))
+        }
+
+        fn retrieve_key(&self, key_id: &str) -> Result<(Vec<u8>, KeyMetadata)> {
+            self.0.retrieve_key(key_id)
+        }
+
+        fn delete_key(&self, key_id: &str) -> Result<()> {
+            self.0.delete_key(key_id)
+        }
+
+        fn key_exists(&self, key_id: &str) -> bool {
+            self.0.key_exists(key_id)
+        }
+
+        fn list_keys(&self) -> Result<Vec<String>> {
+            self.0.list_keys()
+        }
+
+        fn purge_expired(&self) -> Result<usize> {
+            self.0.purge_expired()
+        }
+    }
+
+    #[test]
+    fn test_plaintext_keys_rejected_by_default() {
+        let inner = MemoryKeyStorage::new();
+        inner.store_key("legacy", b"plain", metadata()).unwrap();
+
+        let result = EncryptedKeyStorage::new(&inner, master(1)).retrieve_key("legacy");
+        assert!(matches!(result, Err(VaultError::Encryption(_))));
+
+        // Left untouched rather than sealed
+        let (raw, _) = inner.retrieve_key("legacy").unwrap();
+        assert_eq!(raw, b"plain");
+    }
+
+    #[test]
+    fn test_stripped_metadata_is_not_a_downgrade() {
+        let inner = MemoryKeyStorage::new();
+        EncryptedKeyStorage::new(&inner, master(1)).store_key("k", b"secret", metadata()).unwrap();
+        let (raw, _) = inner.retrieve_key("k").unwrap();
+        inner.store_key("k", &raw, metadata()).unwrap();
+
+        let result = EncryptedKeyStorage::new(&inner, master(1)).retrieve_key("k");
+        assert!(matches!(result, Err(VaultError::Encryption(_))));
+    }
+
+    #[test]
+    fn test_plaintext_keys_pass_through() {
+        let inner = MemoryKeyStorage::new();
+        inner.store_key("legacy", b"plain", metadata()).unwrap();
+
+        let storage = EncryptedKeyStorage::new(&inner, master(1)).accepting_plaintext(true);
+        let (key, _) = storage.retrieve_key("legacy").unwrap();
+        assert_eq!(key, b"plain");
+
+        // Sealed on first read; later reads decrypt it
+        let (raw, raw_metadata) = inner.retrieve_key("legacy").unwrap();
+        assert_ne!(raw.as_slice(), b"plain");
+        assert!(raw_metadata.custom.contains_key(NONCE_METADATA_KEY));
+        let (key, metadata) = storage.retrieve_key("legacy").unwrap();
+        assert_eq!(key, b"plain");
+        assert_eq!(metadata.custom.len(), 1);
+    }
+
+    #[test]
+    fn test_plaintext_key_returned_when_sealing_fails() {
+        let inner = ReadOnlyStorage(MemoryKeyStorage::new());
+        inner.0.store_key("legacy", b"plain", metadata()).unwrap();
+
+        let storage = EncryptedKeyStorage::new(&inner, master(1)).accepting_plaintext(true);
+        let (key, _) = storage.retrieve_key("legacy").unwrap();
+        assert_eq!(key, b"plain");
+
+        // Still plaintext, so the next read tries again
+        let (raw, raw_metadata) = inner.retrieve_key("legacy").unwrap();
+        assert_eq!(raw, b"plain");
+        assert!(!raw_metadata.custom.contains_key(NONCE_METADATA_KEY));
+    }
+}
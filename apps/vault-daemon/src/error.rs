@@ -5,9 +5,17 @@ pub enum VaultError {
     #[error("Keychain error: {0}")]
     Keychain(String),
     
+    /// No key is stored under this id
+    #[error("Key '{0}' not found")]
+    KeyNotFound(String),
+    
     #[error("Memory lock error: {0}")]
     MemoryLock(String),
     
+    /// Bytes copied into `SecureMemory` of another length
+    #[error("Length mismatch: expected {expected} bytes, got {actual}")]
+    LengthMismatch { expected: usize, actual: usize },
+    
     #[error("IPC error: {0}")]
     Ipc(String),
     
@@ -1,58 +1,46 @@
+use crate::audit::{AuditLog, AuditRecord};
+use crate::encrypted::EncryptedKeyStorage;
 use crate::error::{Result, VaultError};
-use crate::keychain::{KeyStorage, create_key_storage};
-use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use crate::keychain::{KeyStorage, create_key_storage, default_namespace};
+use crate::lock::{derive_master, VaultLock, DEFAULT_AUTO_LOCK, VAULT_LOCKED, VERIFIER_KEY_ID};
+use crate::secret_stream::{delete_chunks, is_chunk, is_stream, Download, Upload};
+use identra_crypto::KeyDerivationParams;
+use identra_ipc::{local_socket_name, read_message, token_path, write_message, IpcError, RequestFrame, ResponseFrame, socket_name};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use zeroize::Zeroizing;
 use tokio::sync::RwLock;
-use interprocess::local_socket::{
-    tokio::prelude::*,
-    GenericNamespaced, ListenerOptions, ToNsName,
-};
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use interprocess::local_socket::{tokio::prelude::*, ListenerOptions};
+use tokio::io::{AsyncRead, AsyncWrite, BufReader};
 
-/// IPC pipe name
-#[cfg(windows)]
-const PIPE_NAME: &str = "@identra-vault";
+pub use identra_ipc::{VaultRequest, VaultResponse};
 
-#[cfg(unix)]
-const PIPE_NAME: &str = "/tmp/identra-vault.sock";
-
-/// IPC message types
-#[derive(Debug, Serialize, Deserialize)]
-pub enum VaultRequest {
-    StoreKey { 
-        key_id: String, 
-        key_data: Vec<u8>,
-        metadata: std::collections::HashMap<String, String>,
-        expires_at: Option<i64>, // Unix timestamp
-    },
-    RetrieveKey { key_id: String },
-    DeleteKey { key_id: String },
-    KeyExists { key_id: String },
-    ListKeys,
-    Ping,
-    Shutdown,
-}
+/// How often expired keys are purged from the keychain
+const PURGE_INTERVAL: Duration = Duration::from_secs(60);
 
-#[derive(Debug, Serialize, Deserialize)]
-pub enum VaultResponse {
-    Success,
-    KeyData {
-        key_data: Vec<u8>,
-        metadata: std::collections::HashMap<String, String>,
-        created_at: i64,
-        expires_at: Option<i64>,
-    },
-    KeyList(Vec<String>),
-    Exists(bool),
-    Error(String),
-    Pong,
-    ShuttingDown,
-}
+/// How often the vault checks whether it has been idle long enough to lock
+const AUTO_LOCK_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Default cap on concurrently open client connections
+pub const DEFAULT_MAX_CONNECTIONS: usize = 64;
+
+/// How long a connection may sit without sending a complete request
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Random bytes in the token clients must present, hex-encoded in its file
+const TOKEN_BYTES: usize = 32;
+
+/// Answer to a connection that doesn't open with a valid `Authenticate`
+const AUTHENTICATION_FAILED: &str = "Authentication failed";
 
 /// Vault server handling IPC communication
 pub struct VaultServer {
     keychain: Arc<Box<dyn KeyStorage>>,
+    lock: Arc<Mutex<VaultLock>>,
     state: Arc<RwLock<VaultState>>,
+    audit: Arc<AuditLog>,
+    max_connections: usize,
+    accept_plaintext_keys: bool,
 }
 
 struct VaultState {
@@ -60,28 +48,75 @@ struct VaultState {
     active_connections: usize,
 }
 
+/// Streamed secret being moved on a connection; opening another, or the
+/// connection closing, abandons it
+enum Transfer {
+    Upload(Upload),
+    Download(Download),
+}
+
 impl VaultServer {
     pub fn new() -> Self {
-        let keychain = create_key_storage();
-        
+        Self::with_storage(
+            create_key_storage(&default_namespace()),
+            VaultLock::new(DEFAULT_AUTO_LOCK, KeyDerivationParams::secure()),
+        )
+    }
+    
+    /// Serve keys from `keychain`, gated by `lock`
+    pub fn with_storage(keychain: Box<dyn KeyStorage>, lock: VaultLock) -> Self {
         Self {
             keychain: Arc::new(keychain),
+            lock: Arc::new(Mutex::new(lock)),
             state: Arc::new(RwLock::new(VaultState {
                 initialized: false,
                 active_connections: 0,
             })),
+            audit: Arc::new(AuditLog::disabled()),
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            accept_plaintext_keys: false,
         }
     }
     
+    /// Refuse connections beyond `max_connections` open at once
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = max_connections;
+        self
+    }
+    
+    /// Record handled requests to `audit` instead of only to `tracing`
+    pub fn with_audit_log(mut self, audit: AuditLog) -> Self {
+        self.audit = Arc::new(audit);
+        self
+    }
+    
+    /// Hand out keys stored before encryption was enabled, sealing each
+    /// on its first read; otherwise retrieving one fails
+    pub fn with_plaintext_keys(mut self, accept: bool) -> Self {
+        self.accept_plaintext_keys = accept;
+        self
+    }
+    
+    /// Serve on `socket_name()` until the process exits
     pub async fn start(&self) -> Result<()> {
-        println!("🔌 Starting IPC server on: {}", PIPE_NAME);
+        self.start_on(&socket_name()).await
+    }
+    
+    /// Serve on `socket`, which only the daemon's own user can connect to
+    ///
+    /// Clients must first authenticate with the token written to
+    /// `token_path(socket)`, fresh on every start.
+    pub async fn start_on(&self, socket: &str) -> Result<()> {
+        tracing::info!("Starting IPC server on: {}", socket);
         
         // Create listener
-        let name = PIPE_NAME.to_ns_name::<GenericNamespaced>()
+        #[cfg(unix)]
+        prepare_socket_path(std::path::Path::new(socket))?;
+        let token = Arc::new(write_token(&token_path(socket))?);
+        let name = local_socket_name(socket)
             .map_err(|e| VaultError::Ipc(format!("Invalid pipe name: {}", e)))?;
         
-        let listener = ListenerOptions::new()
-            .name(name)
+        let listener = owner_only(ListenerOptions::new().name(name))?
             .create_tokio()
             .map_err(|e| VaultError::Ipc(format!("Failed to create IPC listener: {}", e)))?;
         
@@ -90,32 +125,57 @@ impl VaultServer {
             state.initialized = true;
         }
         
-        println!("✅ IPC server ready, waiting for connections...");
+        Self::spawn_purge_task(Arc::clone(&self.keychain));
+        Self::spawn_auto_lock_task(Arc::clone(&self.lock));
+        
+        tracing::info!("IPC server ready, waiting for connections");
         
         // Accept connections in a loop
         loop {
             match listener.accept().await {
-                Ok(stream) => {
-                    println!("📥 New IPC connection accepted");
-                    
-                    // Increment connection counter
-                    {
+                Ok(mut stream) => {
+                    // Increment connection counter, refusing past the cap
+                    let accepted = {
                         let mut state = self.state.write().await;
-                        state.active_connections += 1;
+                        if state.active_connections < self.max_connections {
+                            state.active_connections += 1;
+                            true
+                        } else {
+                            false
+                        }
+                    };
+                    
+                    if !accepted {
+                        tracing::warn!("Connection limit ({}) reached, refusing client", self.max_connections);
+                        tokio::spawn(async move {
+                            let response = unattributed(VaultResponse::Error("Too many connections".to_string()));
+                            let _ = write_message(&mut stream, &response).await;
+                        });
+                        continue;
                     }
                     
+                    tracing::debug!("New IPC connection accepted");
+                    
                     // Handle connection in a separate task
                     let keychain = Arc::clone(&self.keychain);
+                    let lock = Arc::clone(&self.lock);
+                    let audit = Arc::clone(&self.audit);
                     let state = Arc::clone(&self.state);
+                    let token = Arc::clone(&token);
+                    let accept_plaintext = self.accept_plaintext_keys;
                     
                     tokio::spawn(async move {
-                        if let Err(e) = Self::handle_connection(stream, keychain, state.clone()).await {
-                            eprintln!("❌ Connection error: {}", e);
+                        if let Err(e) = Self::handle_connection(stream, keychain, lock, audit, Some(token), READ_TIMEOUT, accept_plaintext).await {
+                            tracing::warn!("Connection error: {}", e);
                         }
+                        
+                        // Decrement connection counter
+                        let mut state = state.write().await;
+                        state.active_connections = state.active_connections.saturating_sub(1);
                     });
                 }
                 Err(e) => {
-                    eprintln!("❌ Failed to accept connection: {}", e);
+                    tracing::error!("Failed to accept connection: {}", e);
                     break;
                 }
             }
@@ -124,73 +184,145 @@ impl VaultServer {
         Ok(())
     }
     
-    async fn handle_connection(
-        stream: interprocess::local_socket::tokio::Stream,
+    /// Periodically delete expired keys from the keychain
+    fn spawn_purge_task(keychain: Arc<Box<dyn KeyStorage>>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PURGE_INTERVAL);
+            loop {
+                interval.tick().await;
+                
+                // Keychain calls block on the OS secret store
+                let keychain = Arc::clone(&keychain);
+                match tokio::task::spawn_blocking(move || keychain.purge_expired()).await {
+                    Ok(Ok(0)) => {}
+                    Ok(Ok(purged)) => tracing::info!("Purged {} expired keys", purged),
+                    Ok(Err(e)) => tracing::error!("Failed to purge expired keys: {}", e),
+                    Err(e) => tracing::error!("Purge task failed: {}", e),
+                }
+            }
+        });
+    }
+    
+    /// Re-lock the vault once it has been idle for the auto-lock period
+    fn spawn_auto_lock_task(lock: Arc<Mutex<VaultLock>>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(AUTO_LOCK_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                
+                let locked = lock.lock().unwrap_or_else(|e| e.into_inner()).lock_if_idle();
+                if locked {
+                    tracing::info!("Vault auto-locked after inactivity");
+                }
+            }
+        });
+    }
+    
+    /// Serve requests on `stream` until the client hangs up
+    ///
+    /// With a `token`, nothing is processed until the client has sent it in
+    /// an `Authenticate` request; any other first message drops the connection.
+    async fn handle_connection<S>(
+        stream: S,
         keychain: Arc<Box<dyn KeyStorage>>,
-        state: Arc<RwLock<VaultState>>,
-    ) -> Result<()> {
-        let (reader, mut writer) = tokio::io::split(stream);
-        let mut buf_reader = BufReader::new(reader);
-        let mut line = String::new();
+        lock: Arc<Mutex<VaultLock>>,
+        audit: Arc<AuditLog>,
+        token: Option<Arc<Zeroizing<String>>>,
+        read_timeout: Duration,
+        accept_plaintext: bool,
+    ) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut stream = BufReader::new(stream);
+        let mut authenticated = token.is_none();
+        let mut transfer = None;
         
         loop {
-            line.clear();
-            
-            match buf_reader.read_line(&mut line).await {
-                Ok(0) => {
+            let read = tokio::time::timeout(read_timeout, read_message(&mut stream)).await;
+            let frame: RequestFrame<serde_json::Value> = match read {
+                Err(_) => {
+                    // Half-open or idle client; don't hold a task for it
+                    let error_response = unattributed(VaultResponse::Error("Timed out waiting for request".to_string()));
+                    let _ = write_message(stream.get_mut(), &error_response).await;
+                    break;
+                }
+                Ok(Ok(Some(frame))) => frame,
+                Ok(Ok(None)) => {
                     // Connection closed
-                    println!("📤 Client disconnected");
+                    tracing::debug!("Client disconnected");
                     break;
                 }
-                Ok(_) => {
-                    // Parse request
-                    let request: VaultRequest = match serde_json::from_str(&line) {
-                        Ok(req) => req,
-                        Err(e) => {
-                            let error_response = VaultResponse::Error(
-                                format!("Invalid request format: {}", e)
-                            );
-                            let response_json = serde_json::to_string(&error_response).unwrap();
-                            writer.write_all(response_json.as_bytes()).await
-                                .map_err(|e| VaultError::Io(e))?;
-                            writer.write_all(b"\n").await
-                                .map_err(|e| VaultError::Io(e))?;
-                            writer.flush().await
-                                .map_err(|e| VaultError::Io(e))?;
-                            continue;
-                        }
-                    };
-                    
-                    // Handle request
-                    let response = Self::handle_request(request, &keychain).await;
-                    
-                    // Send response
-                    let response_json = serde_json::to_string(&response)
-                        .map_err(|e| VaultError::Serialization(e))?;
-                    
-                    writer.write_all(response_json.as_bytes()).await
-                        .map_err(|e| VaultError::Io(e))?;
-                    writer.write_all(b"\n").await
-                        .map_err(|e| VaultError::Io(e))?;
-                    writer.flush().await
-                        .map_err(|e| VaultError::Io(e))?;
-                    
-                    // Check for shutdown
-                    if matches!(response, VaultResponse::ShuttingDown) {
-                        break;
-                    }
+                Ok(Err(IpcError::Serialization(_))) if !authenticated => {
+                    let error_response = unattributed(VaultResponse::Error(AUTHENTICATION_FAILED.to_string()));
+                    let _ = write_message(stream.get_mut(), &error_response).await;
+                    tracing::warn!("Dropped IPC client that did not authenticate");
+                    break;
                 }
-                Err(e) => {
-                    eprintln!("❌ Read error: {}", e);
+                Ok(Err(IpcError::Serialization(e))) => {
+                    // The bad line was consumed, so the connection is still usable
+                    let error_response = unattributed(VaultResponse::Error(
+                        format!("Invalid request format: {}", e)
+                    ));
+                    write_message(stream.get_mut(), &error_response).await
+                        .map_err(|e| VaultError::Ipc(e.to_string()))?;
+                    continue;
+                }
+                Ok(Err(e @ IpcError::MessageTooLarge { .. })) => {
+                    // The rest of the line is unread, so the stream is out of sync
+                    let error_response = unattributed(VaultResponse::Error(e.to_string()));
+                    let _ = write_message(stream.get_mut(), &error_response).await;
                     break;
                 }
+                Ok(Err(e)) => {
+                    tracing::warn!("Read error: {}", e);
+                    break;
+                }
+            };
+            
+            let request = serde_json::from_value::<VaultRequest>(frame.request);
+            if let Some(expected) = token.as_deref().filter(|_| !authenticated) {
+                authenticated = matches!(
+                    &request,
+                    Ok(VaultRequest::Authenticate { token }) if token_matches(expected, token)
+                );
+                let response = if authenticated {
+                    VaultResponse::Success
+                } else {
+                    VaultResponse::Error(AUTHENTICATION_FAILED.to_string())
+                };
+                let response = ResponseFrame { request_id: Some(frame.request_id), response };
+                let _ = write_message(stream.get_mut(), &response).await;
+                if !authenticated {
+                    tracing::warn!("Dropped IPC client that did not authenticate");
+                    break;
+                }
+                continue;
+            }
+            
+            // Handle request; a well-framed but unknown request still gets its id back
+            let response = match request {
+                Ok(request) => {
+                    let description = AuditRecord::describe(&request);
+                    let response = Self::handle_request(request, &keychain, &lock, &mut transfer, accept_plaintext).await;
+                    if let Some((operation, key_id)) = description {
+                        audit.record(&AuditRecord::new(operation, key_id, &response));
+                    }
+                    response
+                }
+                Err(e) => VaultResponse::Error(format!("Invalid request format: {}", e)),
+            };
+            let shutting_down = matches!(response, VaultResponse::ShuttingDown);
+            
+            // Send response
+            let response = ResponseFrame { request_id: Some(frame.request_id), response };
+            write_message(stream.get_mut(), &response).await
+                .map_err(|e| VaultError::Ipc(e.to_string()))?;
+            
+            // Check for shutdown
+            if shutting_down {
+                break;
             }
-        }
-        
-        // Decrement connection counter
-        {
-            let mut state_guard = state.write().await;
-            state_guard.active_connections = state_guard.active_connections.saturating_sub(1);
         }
         
         Ok(())
@@ -198,15 +330,27 @@ impl VaultServer {
     
     async fn handle_request(
         request: VaultRequest,
-        keychain: &Arc<Box<dyn KeyStorage>>,
+        shared_keychain: &Arc<Box<dyn KeyStorage>>,
+        lock: &Mutex<VaultLock>,
+        transfer: &mut Option<Transfer>,
+        accept_plaintext: bool,
     ) -> VaultResponse {
+        let keychain: &dyn KeyStorage = shared_keychain.as_ref().as_ref();
+        
+        // Key material only moves while the vault is unlocked
+        let master_key = || lock.lock().unwrap_or_else(|e| e.into_inner()).master_key();
+        
         match request {
-            VaultRequest::Ping => {
-                println!("🏓 Ping received");
-                VaultResponse::Pong
-            }
+            VaultRequest::Ping => VaultResponse::Pong,
+            // Only reached once the connection is authenticated
+            VaultRequest::Authenticate { .. } => VaultResponse::Success,
             VaultRequest::StoreKey { key_id, key_data, metadata, expires_at } => {
-                println!("📝 Storing key: {}", key_id);
+                if is_reserved(&key_id) {
+                    return VaultResponse::Error(format!("'{}' is a reserved key id", key_id));
+                }
+                let Ok(master) = master_key() else {
+                    return VaultResponse::Error(VAULT_LOCKED.to_string());
+                };
                 
                 let key_metadata = crate::keychain::KeyMetadata {
                     created_at: chrono::Utc::now().timestamp(),
@@ -214,56 +358,208 @@ impl VaultServer {
                     custom: metadata,
                 };
                 
-                match keychain.store_key(&key_id, &key_data, key_metadata) {
-                    Ok(_) => VaultResponse::Success,
+                let replaces_stream = replaces_stream(keychain, &key_id);
+                match EncryptedKeyStorage::new(keychain, master).store_key(&key_id, &key_data, key_metadata) {
+                    Ok(_) => {
+                        if replaces_stream {
+                            delete_pieces(keychain, &key_id);
+                        }
+                        VaultResponse::Success
+                    }
                     Err(e) => VaultResponse::Error(format!("Failed to store key: {}", e)),
                 }
             }
             VaultRequest::RetrieveKey { key_id } => {
-                println!("🔍 Retrieving key: {}", key_id);
-                match keychain.retrieve_key(&key_id) {
-                    Ok((key_data, metadata)) => {
-                        // Check expiration
-                        if let Some(expires_at) = metadata.expires_at {
-                            let now = chrono::Utc::now().timestamp();
-                            if now > expires_at {
-                                return VaultResponse::Error("Key has expired".to_string());
-                            }
-                        }
-                        
-                        VaultResponse::KeyData {
-                            key_data,
-                            metadata: metadata.custom,
-                            created_at: metadata.created_at,
-                            expires_at: metadata.expires_at,
-                        }
-                    }
-                    Err(e) => VaultResponse::Error(format!("Failed to retrieve key: {}", e)),
+                if is_reserved(&key_id) {
+                    return VaultResponse::Error(format!("'{}' is a reserved key id", key_id));
+                }
+                let Ok(master) = master_key() else {
+                    return VaultResponse::Error(VAULT_LOCKED.to_string());
+                };
+                match EncryptedKeyStorage::new(keychain, master).accepting_plaintext(accept_plaintext).retrieve_key(&key_id) {
+                    Ok((_, metadata)) if is_stream(&metadata) => VaultResponse::Error(
+                        format!("'{}' was stored as a stream; use RetrieveSecretStream", key_id)
+                    ),
+                    // Storage rejects (and deletes) expired keys
+                    Ok((key_data, metadata)) => VaultResponse::KeyData {
+                        key_data,
+                        metadata: metadata.custom,
+                        created_at: metadata.created_at,
+                        expires_at: metadata.expires_at,
+                    },
+                    Err(e) => failure("Failed to retrieve key", e),
                 }
             }
             VaultRequest::DeleteKey { key_id } => {
-                println!("🗑️ Deleting key: {}", key_id);
+                if is_reserved(&key_id) {
+                    return VaultResponse::Error(format!("'{}' is a reserved key id", key_id));
+                }
+                let replaces_stream = replaces_stream(keychain, &key_id);
                 match keychain.delete_key(&key_id) {
-                    Ok(_) => VaultResponse::Success,
-                    Err(e) => VaultResponse::Error(format!("Failed to delete key: {}", e)),
+                    Ok(_) => {
+                        if replaces_stream {
+                            delete_pieces(keychain, &key_id);
+                        }
+                        VaultResponse::Success
+                    }
+                    Err(e) => failure("Failed to delete key", e),
                 }
             }
             VaultRequest::KeyExists { key_id } => {
-                let exists = keychain.key_exists(&key_id);
-                println!("🔎 Key exists: {} = {}", key_id, exists);
-                VaultResponse::Exists(exists)
+                VaultResponse::Exists(keychain.key_exists(&key_id))
             }
             VaultRequest::ListKeys => {
-                println!("📋 Listing keys");
                 match keychain.list_keys() {
-                    Ok(keys) => VaultResponse::KeyList(keys),
+                    Ok(keys) => VaultResponse::KeyList(
+                        keys.into_iter().filter(|k| k != VERIFIER_KEY_ID && !is_chunk(k)).collect()
+                    ),
                     Err(e) => VaultResponse::Error(format!("Failed to list keys: {}", e)),
                 }
             }
-            VaultRequest::Shutdown => {
-                println!("🛑 Shutdown requested");
-                VaultResponse::ShuttingDown
+            VaultRequest::PurgeExpired => {
+                match keychain.purge_expired() {
+                    Ok(purged) => VaultResponse::Purged(purged),
+                    Err(e) => VaultResponse::Error(format!("Failed to purge keys: {}", e)),
+                }
+            }
+            VaultRequest::DeleteKeysWithPrefix { prefix } => {
+                // An empty prefix would wipe the whole vault
+                if prefix.is_empty() {
+                    return VaultResponse::Error("Key prefix must not be empty".to_string());
+                }
+                let keys = match keychain.list_keys() {
+                    Ok(keys) => keys,
+                    Err(e) => return VaultResponse::Error(format!("Failed to list keys: {}", e)),
+                };
+                let mut deleted = 0;
+                for key_id in keys.iter().filter(|k| k.starts_with(&prefix) && *k != VERIFIER_KEY_ID) {
+                    if let Err(e) = keychain.delete_key(key_id) {
+                        return VaultResponse::Error(format!(
+                            "Failed to delete key {} after deleting {}: {}",
+                            key_id, deleted, e
+                        ));
+                    }
+                    // Pieces of streamed secrets go with them but aren't keys of their own
+                    if !is_chunk(key_id) {
+                        deleted += 1;
+                    }
+                }
+                VaultResponse::Purged(deleted)
             }
+            VaultRequest::StoreSecretStream { key_id, total_size, metadata, expires_at } => {
+                *transfer = None;
+                if is_reserved(&key_id) {
+                    return VaultResponse::Error(format!("'{}' is a reserved key id", key_id));
+                }
+                if master_key().is_err() {
+                    return VaultResponse::Error(VAULT_LOCKED.to_string());
+                }
+                let key_metadata = crate::keychain::KeyMetadata {
+                    created_at: chrono::Utc::now().timestamp(),
+                    expires_at,
+                    custom: metadata,
+                };
+                match Upload::begin(Arc::clone(shared_keychain), key_id, total_size, key_metadata) {
+                    Ok(upload) => {
+                        *transfer = Some(Transfer::Upload(upload));
+                        VaultResponse::Progress { done: 0, total: total_size }
+                    }
+                    Err(e) => VaultResponse::Error(format!("Failed to store secret: {}", e)),
+                }
+            }
+            VaultRequest::SecretChunk { data } => {
+                let Some(Transfer::Upload(upload)) = transfer.as_mut() else {
+                    return VaultResponse::Error("No secret is being stored on this connection".to_string());
+                };
+                let Ok(master) = master_key() else {
+                    *transfer = None;
+                    return VaultResponse::Error(VAULT_LOCKED.to_string());
+                };
+                match upload.push(master, &data) {
+                    Ok(done) => {
+                        let total = upload.total();
+                        if upload.is_complete() {
+                            *transfer = None;
+                        }
+                        VaultResponse::Progress { done, total }
+                    }
+                    Err(e) => {
+                        *transfer = None;
+                        VaultResponse::Error(format!("Failed to store secret: {}", e))
+                    }
+                }
+            }
+            VaultRequest::RetrieveSecretStream { key_id } => {
+                *transfer = None;
+                if is_reserved(&key_id) {
+                    return VaultResponse::Error(format!("'{}' is a reserved key id", key_id));
+                }
+                let Ok(master) = master_key() else {
+                    return VaultResponse::Error(VAULT_LOCKED.to_string());
+                };
+                match Download::open(keychain, master, &key_id) {
+                    Ok((download, metadata)) => {
+                        let total_size = download.total();
+                        *transfer = Some(Transfer::Download(download));
+                        VaultResponse::SecretStream {
+                            total_size,
+                            metadata: metadata.custom,
+                            created_at: metadata.created_at,
+                            expires_at: metadata.expires_at,
+                        }
+                    }
+                    Err(e) => failure("Failed to retrieve secret", e),
+                }
+            }
+            VaultRequest::NextSecretChunk => {
+                let Some(Transfer::Download(download)) = transfer.as_mut() else {
+                    return VaultResponse::Error("No secret is being read on this connection".to_string());
+                };
+                if master_key().is_err() {
+                    *transfer = None;
+                    return VaultResponse::Error(VAULT_LOCKED.to_string());
+                }
+                match download.next_chunk(keychain) {
+                    Ok((data, done)) => {
+                        if download.is_complete() {
+                            *transfer = None;
+                        }
+                        VaultResponse::SecretChunk { data, done }
+                    }
+                    Err(e) => {
+                        *transfer = None;
+                        VaultResponse::Error(format!("Failed to retrieve secret: {}", e))
+                    }
+                }
+            }
+            VaultRequest::Unlock { passphrase } => {
+                let passphrase = Zeroizing::new(passphrase);
+                let params = lock.lock().unwrap_or_else(|e| e.into_inner()).params().clone();
+                // Argon2 takes as long as it was calibrated to; run it off
+                // the runtime and without the lock every key operation needs
+                let shared_keychain = Arc::clone(shared_keychain);
+                let derived = tokio::task::spawn_blocking(move || {
+                    derive_master(shared_keychain.as_ref().as_ref(), passphrase.as_bytes(), &params)
+                })
+                .await
+                .unwrap_or_else(|e| Err(VaultError::Encryption(format!("Key derivation failed: {}", e))));
+                let unlocked = derived.and_then(|derived| {
+                    lock.lock().unwrap_or_else(|e| e.into_inner()).finish_unlock(keychain, derived)
+                });
+                match unlocked {
+                    Ok(_) => VaultResponse::Success,
+                    Err(e) => VaultResponse::Error(format!("Failed to unlock vault: {}", e)),
+                }
+            }
+            VaultRequest::Lock => {
+                lock.lock().unwrap_or_else(|e| e.into_inner()).lock();
+                VaultResponse::Success
+            }
+            VaultRequest::Status => {
+                let locked = lock.lock().unwrap_or_else(|e| e.into_inner()).is_locked();
+                VaultResponse::Status { locked }
+            }
+            VaultRequest::Shutdown => VaultResponse::ShuttingDown,
         }
     }
     
@@ -277,3 +573,527 @@ impl Default for VaultServer {
         Self::new()
     }
 }
+
+/// Response for a failed key operation; a missing key is answered as
+/// such, so clients can tell it from the vault failing
+fn failure(action: &str, e: VaultError) -> VaultResponse {
+    match e {
+        VaultError::KeyNotFound(key_id) => VaultResponse::NotFound { key_id },
+        e => VaultResponse::Error(format!("{}: {}", action, e)),
+    }
+}
+
+/// Ids clients may not store, read or delete: the passphrase verifier and
+/// the pieces of streamed secrets
+fn is_reserved(key_id: &str) -> bool {
+    key_id == VERIFIER_KEY_ID || is_chunk(key_id)
+}
+
+/// True if `key_id` holds a streamed secret, whose pieces must go with it
+fn replaces_stream(keychain: &dyn KeyStorage, key_id: &str) -> bool {
+    keychain.key_exists(key_id) && keychain.retrieve_key(key_id).is_ok_and(|(_, metadata)| is_stream(&metadata))
+}
+
+fn delete_pieces(keychain: &dyn KeyStorage, key_id: &str) {
+    if let Err(e) = delete_chunks(keychain, key_id, None) {
+        tracing::warn!("Failed to delete the pieces of '{}': {}", key_id, e);
+    }
+}
+
+/// Frame for a response not answering any particular request
+fn unattributed(response: VaultResponse) -> ResponseFrame {
+    ResponseFrame { request_id: None, response }
+}
+
+/// Write a fresh random token to `path`, readable by the daemon's user only
+///
+/// Written beside the file and renamed into place, so a client never reads
+/// half a token and a token file someone else created is replaced, not reused.
+fn write_token(path: &std::path::Path) -> Result<Zeroizing<String>> {
+    use std::io::Write;
+    
+    let bytes = Zeroizing::new(identra_crypto::generate_random_bytes(TOKEN_BYTES));
+    let token = Zeroizing::new(bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>());
+    
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+    let staging = path.with_extension("token.tmp");
+    let _ = std::fs::remove_file(&staging);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(&staging)?;
+    file.write_all(token.as_bytes())?;
+    file.sync_all()?;
+    std::fs::rename(&staging, path)?;
+    Ok(token)
+}
+
+/// Compare tokens without leaking through timing how much of `presented` matched
+fn token_matches(expected: &str, presented: &str) -> bool {
+    expected.len() == presented.len()
+        && expected.bytes().zip(presented.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Socket file readable and writable by the daemon's user only
+#[cfg(unix)]
+fn owner_only(options: ListenerOptions<'_>) -> Result<ListenerOptions<'_>> {
+    use interprocess::os::unix::local_socket::ListenerOptionsExt;
+    Ok(options.mode(0o600))
+}
+
+/// Named pipe with a protected DACL granting access to its owner and
+/// SYSTEM only; the default one lets every user open it for reading
+#[cfg(windows)]
+fn owner_only(options: ListenerOptions<'_>) -> Result<ListenerOptions<'_>> {
+    use interprocess::os::windows::{local_socket::ListenerOptionsExt, security_descriptor::SecurityDescriptor};
+    let sd = SecurityDescriptor::deserialize(widestring::u16cstr!("D:P(A;;GA;;;OW)(A;;GA;;;SY)"))
+        .map_err(|e| VaultError::Ipc(format!("Invalid pipe security descriptor: {}", e)))?;
+    Ok(options.security_descriptor(sd))
+}
+
+/// Get `path` ready to bind: its directory created 0700 if missing and not
+/// another user's, and a socket left by a daemon that died removed
+#[cfg(unix)]
+fn prepare_socket_path(path: &std::path::Path) -> Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, FileTypeExt, MetadataExt};
+    
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(std::path::Path::new("."));
+    std::fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+    // Root's shared dirs like /tmp are fine (sticky); another user's could swap the socket
+    let owner = std::fs::metadata(dir)?.uid();
+    // SAFETY: getuid has no preconditions and cannot fail
+    if owner != 0 && owner != unsafe { libc::getuid() } {
+        return Err(VaultError::Ipc(format!("Socket directory {} belongs to uid {}", dir.display(), owner)));
+    }
+    
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => {
+            if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                return Err(VaultError::Ipc(format!("Another vault daemon is listening on {}", path.display())));
+            }
+            tracing::info!("Removing stale socket {}", path.display());
+            std::fs::remove_file(path)?;
+        }
+        Ok(_) => return Err(VaultError::Ipc(format!("{} exists and is not a socket", path.display()))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keychain::MemoryKeyStorage;
+    use identra_ipc::{VaultClient, VaultClientError};
+    use std::collections::HashMap;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+    fn spawn_server_with(keychain: Box<dyn KeyStorage>, read_timeout: Duration) -> tokio::io::DuplexStream {
+        let lock = VaultLock::new(DEFAULT_AUTO_LOCK, KeyDerivationParams::fast());
+        spawn_server_full(keychain, lock, AuditLog::disabled(), read_timeout)
+    }
+
+    fn spawn_server_full(
+        keychain: Box<dyn KeyStorage>,
+        lock: VaultLock,
+        audit: AuditLog,
+        read_timeout: Duration,
+    ) -> tokio::io::DuplexStream {
+        let (client, server) = tokio::io::duplex(4096);
+        tokio::spawn(VaultServer::handle_connection(
+            server,
+            Arc::new(keychain),
+            Arc::new(Mutex::new(lock)),
+            Arc::new(audit),
+            None,
+            read_timeout,
+            false,
+        ));
+        client
+    }
+
+    fn spawn_server() -> tokio::io::DuplexStream {
+        spawn_server_with(create_key_storage("identra-tests"), READ_TIMEOUT)
+    }
+
+    /// Server requiring `token` before anything else, as `start_on` runs it
+    fn spawn_server_requiring(token: &str) -> tokio::io::DuplexStream {
+        let (client, server) = tokio::io::duplex(4096);
+        tokio::spawn(VaultServer::handle_connection(
+            server,
+            Arc::new(Box::new(MemoryKeyStorage::new()) as Box<dyn KeyStorage>),
+            Arc::new(Mutex::new(VaultLock::new(DEFAULT_AUTO_LOCK, KeyDerivationParams::fast()))),
+            Arc::new(AuditLog::disabled()),
+            Some(Arc::new(Zeroizing::new(token.to_string()))),
+            READ_TIMEOUT,
+            false,
+        ));
+        client
+    }
+
+    /// Read one response line, then expect the server to hang up
+    async fn expect_error_and_close<R: tokio::io::AsyncBufRead + Unpin>(stream: &mut R) -> String {
+        let mut line = String::new();
+        stream.read_line(&mut line).await.unwrap();
+        let ResponseFrame { request_id: None, response: VaultResponse::Error(message) } =
+            serde_json::from_str(&line).unwrap()
+        else {
+            panic!("expected an unattributed error response, got {}", line);
+        };
+
+        line.clear();
+        assert_eq!(stream.read_line(&mut line).await.unwrap(), 0, "server should close");
+        message
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_socket_is_private_to_owner() {
+        use interprocess::local_socket::tokio::Stream;
+        use std::os::unix::fs::PermissionsExt;
+        
+        let dir = std::env::temp_dir().join(format!("identra-ipc-mode-{}", std::process::id()));
+        let path = dir.join("run").join("vault.sock");
+        let socket = path.to_str().unwrap().to_string();
+        // Left behind by a daemon that was killed
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        
+        let server = VaultServer::with_storage(
+            Box::new(MemoryKeyStorage::new()),
+            VaultLock::new(DEFAULT_AUTO_LOCK, KeyDerivationParams::fast()),
+        );
+        let serving = tokio::spawn(async move { server.start_on(&socket).await });
+        
+        let mut client = loop {
+            if let Ok(stream) = Stream::connect(local_socket_name(path.to_str().unwrap()).unwrap()).await {
+                break VaultClient::from_stream(stream);
+            }
+            assert!(!serving.is_finished(), "server failed to start");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        let token_file = token_path(path.to_str().unwrap());
+        client.authenticate(std::fs::read_to_string(&token_file).unwrap()).await.unwrap();
+        client.ping().await.unwrap();
+        
+        let mode = |path: &std::path::Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&path), 0o600);
+        assert_eq!(mode(&token_file), 0o600);
+        let fresh = dir.join("fresh");
+        prepare_socket_path(&fresh.join("vault.sock")).unwrap();
+        assert_eq!(mode(&fresh), 0o700);
+        
+        // A second daemon must not take over the live socket
+        assert!(prepare_socket_path(&path).is_err());
+        
+        serving.abort();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_unauthenticated_connection_is_refused() {
+        let token = "0123456789abcdef";
+
+        // Any other first request is refused and the connection dropped
+        let mut stream = BufReader::new(spawn_server_requiring(token));
+        stream.get_mut().write_all(b"{\"request_id\":1,\"request\":\"ListKeys\"}\n").await.unwrap();
+        let mut line = String::new();
+        stream.read_line(&mut line).await.unwrap();
+        let frame: ResponseFrame = serde_json::from_str(&line).unwrap();
+        assert_eq!(frame.response, VaultResponse::Error(AUTHENTICATION_FAILED.to_string()));
+        line.clear();
+        assert_eq!(stream.read_line(&mut line).await.unwrap(), 0, "server should close");
+
+        // So is garbage, and a wrong token
+        let mut stream = BufReader::new(spawn_server_requiring(token));
+        stream.get_mut().write_all(b"not json\n").await.unwrap();
+        assert_eq!(expect_error_and_close(&mut stream).await, AUTHENTICATION_FAILED);
+        let mut client = VaultClient::from_stream(spawn_server_requiring(token));
+        assert!(client.authenticate("0123456789abcdee".to_string()).await.is_err());
+        assert!(client.ping().await.is_err());
+
+        let mut client = VaultClient::from_stream(spawn_server_requiring(token));
+        client.authenticate(token.to_string()).await.unwrap();
+        client.ping().await.unwrap();
+        assert!(client.list_keys().await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_token_matches() {
+        assert!(token_matches("abcd", "abcd"));
+        assert!(!token_matches("abcd", "abce"));
+        assert!(!token_matches("abcd", "abc"));
+        assert!(!token_matches("abcd", ""));
+    }
+
+    #[tokio::test]
+    async fn test_shared_client_talks_to_server() {
+        let mut client = VaultClient::from_stream(spawn_server());
+
+        client.ping().await.expect("Ping should get Pong");
+        let response = client.send_request(VaultRequest::Shutdown).await.unwrap();
+        assert_eq!(response, VaultResponse::ShuttingDown);
+    }
+
+    #[tokio::test]
+    async fn test_server_recovers_from_malformed_request() {
+        let mut stream = BufReader::new(spawn_server());
+
+        // Unknown request in a valid frame: answered with its id
+        stream.get_mut().write_all(b"{\"request_id\":9,\"request\":{\"NoSuchRequest\":{}}}\n").await.unwrap();
+        let mut line = String::new();
+        stream.read_line(&mut line).await.unwrap();
+        let frame: ResponseFrame = serde_json::from_str(&line).unwrap();
+        assert_eq!(frame.request_id, Some(9));
+        assert!(matches!(frame.response, VaultResponse::Error(_)));
+
+        // Not a frame at all: answered without one
+        stream.get_mut().write_all(b"{\"NoSuchRequest\":{}}\n").await.unwrap();
+        line.clear();
+        stream.read_line(&mut line).await.unwrap();
+        let frame: ResponseFrame = serde_json::from_str(&line).unwrap();
+        assert_eq!(frame.request_id, None);
+        assert!(matches!(frame.response, VaultResponse::Error(_)));
+
+        // Same connection keeps working
+        let mut client = VaultClient::from_stream(stream.into_inner());
+        client.ping().await.expect("Connection should still be usable");
+    }
+
+    #[tokio::test]
+    async fn test_key_operations_require_unlock() {
+        let storage = MemoryKeyStorage::new();
+        let mut client = VaultClient::from_stream(spawn_server_with(Box::new(storage.clone()), READ_TIMEOUT));
+        assert!(client.is_locked().await.unwrap());
+
+        let err = client.store_key("k".to_string(), b"secret".to_vec(), HashMap::new(), None)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains(VAULT_LOCKED));
+
+        client.unlock("correct horse".to_string()).await.unwrap();
+        assert!(!client.is_locked().await.unwrap());
+        client.store_key("k".to_string(), b"secret".to_vec(), HashMap::new(), None).await.unwrap();
+        assert_ne!(storage.retrieve_key("k").unwrap().0, b"secret");
+        assert_eq!(client.retrieve_key("k".to_string()).await.unwrap().0, b"secret");
+        assert_eq!(client.list_keys().await.unwrap(), vec!["k".to_string()]);
+
+        client.lock().await.unwrap();
+        let err = client.retrieve_key("k".to_string()).await.unwrap_err();
+        assert!(err.to_string().contains(VAULT_LOCKED));
+
+        assert!(client.unlock("battery staple".to_string()).await.is_err());
+        assert!(client.is_locked().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_unlock_does_not_stall_other_connections() {
+        // Slow enough that a stalled runtime would only answer once unlocked
+        let params = KeyDerivationParams { time_cost: 8, ..KeyDerivationParams::fast() };
+        let keychain: Arc<Box<dyn KeyStorage>> = Arc::new(Box::new(MemoryKeyStorage::new()));
+        let lock = Arc::new(Mutex::new(VaultLock::new(DEFAULT_AUTO_LOCK, params)));
+        let connect = || {
+            let (client, server) = tokio::io::duplex(4096);
+            tokio::spawn(VaultServer::handle_connection(
+                server,
+                Arc::clone(&keychain),
+                Arc::clone(&lock),
+                Arc::new(AuditLog::disabled()),
+                None,
+                READ_TIMEOUT,
+                false,
+            ));
+            VaultClient::from_stream(client)
+        };
+        let mut unlocking = connect();
+        let mut other = connect();
+
+        let unlock = tokio::spawn(async move { unlocking.unlock("correct horse".to_string()).await });
+        // Let the unlock reach the key derivation
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(other.is_locked().await.unwrap(), "answered while the key was still being derived");
+        unlock.await.unwrap().unwrap();
+        assert!(!other.is_locked().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_store_key_round_trips_metadata_and_expiry() {
+        let storage = MemoryKeyStorage::new();
+        let mut client = VaultClient::from_stream(spawn_server_with(Box::new(storage.clone()), READ_TIMEOUT));
+        client.unlock("correct horse".to_string()).await.unwrap();
+
+        let before = chrono::Utc::now().timestamp();
+        let expires_at = before + 3600;
+        let metadata = HashMap::from([("purpose".to_string(), "memory-encryption".to_string())]);
+        client.store_key("k".to_string(), b"secret".to_vec(), metadata.clone(), Some(expires_at)).await.unwrap();
+
+        let (key_data, retrieved, created_at, retrieved_expiry) = client.retrieve_key("k".to_string()).await.unwrap();
+        assert_eq!(key_data, b"secret");
+        assert_eq!(retrieved, metadata);
+        assert!(created_at >= before && created_at <= chrono::Utc::now().timestamp());
+        assert_eq!(retrieved_expiry, Some(expires_at));
+
+        // Already expired: rejected and removed on retrieve
+        client.store_key("stale".to_string(), b"secret".to_vec(), HashMap::new(), Some(before - 1)).await.unwrap();
+        let err = client.retrieve_key("stale".to_string()).await.unwrap_err();
+        assert!(err.to_string().contains(crate::keychain::KEY_EXPIRED));
+        assert!(!storage.key_exists("stale"));
+    }
+
+    #[tokio::test]
+    async fn test_delete_keys_with_prefix() {
+        let storage = MemoryKeyStorage::new();
+        let mut client = VaultClient::from_stream(spawn_server_with(Box::new(storage.clone()), READ_TIMEOUT));
+        client.unlock("correct horse".to_string()).await.unwrap();
+        for key_id in ["alice/1", "alice/2", "alicea/1", "bob/1"] {
+            client.store_key(key_id.to_string(), b"secret".to_vec(), HashMap::new(), None).await.unwrap();
+        }
+
+        assert_eq!(client.delete_keys_with_prefix("alice/".to_string()).await.unwrap(), 2);
+        let mut remaining = client.list_keys().await.unwrap();
+        remaining.sort();
+        assert_eq!(remaining, vec!["alicea/1".to_string(), "bob/1".to_string()]);
+
+        assert!(client.delete_keys_with_prefix(String::new()).await.is_err());
+        assert_eq!(client.list_keys().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_missing_key_reported_as_not_found() {
+        let mut client = VaultClient::from_stream(spawn_server_with(Box::new(MemoryKeyStorage::new()), READ_TIMEOUT));
+        client.unlock("correct horse".to_string()).await.unwrap();
+
+        let missing = |e| matches!(e, VaultClientError::NotFound(ref key_id) if key_id == "nope");
+        assert!(missing(client.retrieve_key("nope".to_string()).await.unwrap_err()));
+        assert!(missing(client.delete_key("nope".to_string()).await.unwrap_err()));
+        let mut sink = Vec::new();
+        assert!(missing(client.retrieve_secret_stream("nope".to_string(), &mut sink, |_, _| {}).await.unwrap_err()));
+    }
+
+    #[tokio::test]
+    async fn test_multi_megabyte_secret_streams_in_pieces() {
+        let storage = MemoryKeyStorage::new();
+        let mut client = VaultClient::from_stream(spawn_server_with(Box::new(storage.clone()), READ_TIMEOUT));
+        client.unlock("correct horse".to_string()).await.unwrap();
+
+        let secret: Vec<u8> = (0..3 * 1024 * 1024 + 17).map(|i| (i % 251) as u8).collect();
+        let total = secret.len() as u64;
+        let metadata = HashMap::from([("purpose".to_string(), "ssh-key".to_string())]);
+        let mut stored = Vec::new();
+        client.store_secret_stream("big".to_string(), secret.as_slice(), total, metadata.clone(), None, |done, _| {
+            stored.push(done);
+        })
+        .await
+        .unwrap();
+        assert_eq!(stored.len(), 1 + secret.len().div_ceil(identra_ipc::SECRET_CHUNK_SIZE));
+        assert_eq!(stored.last(), Some(&total));
+
+        // Pieces are their own ciphertext entries, hidden from clients
+        let entries = storage.list_keys().unwrap();
+        assert!(entries.len() > 2);
+        for piece in entries.iter().filter(|k| is_chunk(k)) {
+            assert_ne!(storage.retrieve_key(piece).unwrap().0[..16], secret[..16]);
+        }
+        assert_eq!(client.list_keys().await.unwrap(), vec!["big".to_string()]);
+        assert!(client.retrieve_key("big".to_string()).await.unwrap_err().to_string().contains("RetrieveSecretStream"));
+
+        let mut read = Vec::new();
+        let mut progress = 0;
+        let (retrieved, _, expires_at) = client.retrieve_secret_stream("big".to_string(), &mut read, |done, _| {
+            progress = done;
+        })
+        .await
+        .unwrap();
+        assert!(read == secret, "secret changed in the round trip");
+        assert_eq!(progress, total);
+        assert_eq!(retrieved, metadata);
+        assert_eq!(expires_at, None);
+
+        // Replacing it leaves only the new pieces; deleting it leaves none
+        client.store_secret_stream("big".to_string(), &b"smaller"[..], 7, HashMap::new(), None, |_, _| {}).await.unwrap();
+        assert_eq!(storage.list_keys().unwrap().len(), 2 + 1);
+        client.delete_key("big".to_string()).await.unwrap();
+        assert_eq!(storage.list_keys().unwrap(), vec![VERIFIER_KEY_ID.to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_secret_stream_size_capped_and_abandoned_pieces_removed() {
+        let storage = MemoryKeyStorage::new();
+        let mut client = VaultClient::from_stream(spawn_server_with(Box::new(storage.clone()), READ_TIMEOUT));
+        client.unlock("correct horse".to_string()).await.unwrap();
+
+        let too_big = identra_ipc::MAX_SECRET_SIZE + 1;
+        let err = client.store_secret_stream("big".to_string(), &[][..], too_big, HashMap::new(), None, |_, _| {})
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("must be 1 to"), "{}", err);
+
+        // The secret runs out after one piece; starting another stream abandons it
+        let short = vec![7u8; identra_ipc::SECRET_CHUNK_SIZE];
+        let total = 2 * short.len() as u64;
+        assert!(client.store_secret_stream("big".to_string(), short.as_slice(), total, HashMap::new(), None, |_, _| {})
+            .await
+            .is_err());
+        assert_eq!(storage.list_keys().unwrap().len(), 2, "verifier and the piece sent");
+        client.store_secret_stream("other".to_string(), &b"x"[..], 1, HashMap::new(), None, |_, _| {}).await.unwrap();
+        let mut left = storage.list_keys().unwrap();
+        left.retain(|k| k.starts_with("big"));
+        assert!(left.is_empty(), "{:?}", left);
+    }
+
+    #[tokio::test]
+    async fn test_key_access_is_audited() {
+        let path = std::env::temp_dir().join(format!("identra-audit-ipc-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let storage = MemoryKeyStorage::new();
+        let mut lock = VaultLock::new(DEFAULT_AUTO_LOCK, KeyDerivationParams::fast());
+        lock.unlock(&storage, b"correct horse").unwrap();
+        let audit = AuditLog::open(&path, crate::audit::DEFAULT_MAX_BYTES).unwrap();
+        let mut client = VaultClient::from_stream(spawn_server_full(Box::new(storage), lock, audit, READ_TIMEOUT));
+
+        client.ping().await.unwrap();
+        client.store_key("k".to_string(), b"secret".to_vec(), HashMap::new(), None).await.unwrap();
+        client.retrieve_key("k".to_string()).await.unwrap();
+        client.delete_key("k".to_string()).await.unwrap();
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert!(!log.contains("secret"));
+        let records: Vec<AuditRecord> = log.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        let operations: Vec<_> = records.iter().map(|r| r.operation.as_str()).collect();
+        assert_eq!(operations, vec!["store_key", "retrieve_key", "delete_key"]);
+        for record in &records {
+            assert_eq!(record.key_id.as_deref(), Some("k"));
+            assert_eq!(record.outcome, "success");
+            assert_eq!(record.error, None);
+            assert!(record.timestamp > 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_oversized_request_closes_connection() {
+        // Write from another task: the duplex buffer fills long before 1 MiB
+        let (reader, mut writer) = tokio::io::split(spawn_server());
+        tokio::spawn(async move {
+            let _ = writer.write_all(&vec![b'a'; identra_ipc::MAX_MESSAGE_SIZE + 16]).await;
+        });
+
+        let message = expect_error_and_close(&mut BufReader::new(reader)).await;
+        assert!(message.contains("exceeds"));
+    }
+
+    #[tokio::test]
+    async fn test_silent_client_times_out() {
+        let mut stream = BufReader::new(spawn_server_with(
+            create_key_storage("identra-tests"),
+            Duration::from_millis(50),
+        ));
+
+        let message = expect_error_and_close(&mut stream).await;
+        assert!(message.contains("Timed out"));
+    }
+}
@@ -1,7 +1,9 @@
 use crate::error::{Result, VaultError};
+use crate::lock::VERIFIER_KEY_ID;
 use base64::Engine;
 use serde::{Serialize, Deserialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
 
 /// Metadata stored alongside keys
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -11,6 +13,17 @@ pub struct KeyMetadata {
     pub custom: HashMap<String, String>,
 }
 
+/// Error message for keys retrieved after their `expires_at`
+pub const KEY_EXPIRED: &str = "key expired";
+
+impl KeyMetadata {
+    /// True once `expires_at` has passed
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| chrono::Utc::now().timestamp() > expires_at)
+    }
+}
+
 /// Trait for cross-platform key storage
 pub trait KeyStorage: Send + Sync {
     fn store_key(&self, key_id: &str, key: &[u8], metadata: KeyMetadata) -> Result<()>;
@@ -18,6 +31,100 @@ pub trait KeyStorage: Send + Sync {
     fn delete_key(&self, key_id: &str) -> Result<()>;
     fn key_exists(&self, key_id: &str) -> bool;
     fn list_keys(&self) -> Result<Vec<String>>;
+    
+    /// Remove every expired key, returning how many were deleted
+    ///
+    /// `retrieve_key` deletes expired entries as a side effect, so this
+    /// just touches each known key.
+    fn purge_expired(&self) -> Result<usize> {
+        let mut purged = 0;
+        for key_id in self.list_keys()? {
+            if let Err(VaultError::Keychain(msg)) = self.retrieve_key(&key_id) {
+                if msg == KEY_EXPIRED {
+                    purged += 1;
+                }
+            }
+        }
+        Ok(purged)
+    }
+}
+
+/// Reserved keychain entry holding the JSON array of stored key ids
+///
+/// OS keychains can't enumerate entries, so `list_keys` reads this instead.
+pub const INDEX_KEY: &str = "__identra_index__";
+
+/// Serializes read-modify-write cycles on the index within this process
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+fn index_entry(service_name: &str) -> Result<keyring::Entry> {
+    keyring::Entry::new(service_name, INDEX_KEY)
+        .map_err(|e| VaultError::Keychain(format!("Failed to create index entry: {}", e)))
+}
+
+fn read_index(service_name: &str) -> Result<Vec<String>> {
+    match index_entry(service_name)?.get_password() {
+        Ok(json) => serde_json::from_str(&json)
+            .map_err(|e| VaultError::Keychain(format!("Failed to parse key index: {}", e))),
+        Err(keyring::Error::NoEntry) => Ok(Vec::new()),
+        Err(e) => Err(VaultError::Keychain(format!("Failed to read key index: {}", e))),
+    }
+}
+
+fn write_index(service_name: &str, keys: &[String]) -> Result<()> {
+    let json = serde_json::to_string(keys)?;
+    index_entry(service_name)?
+        .set_password(&json)
+        .map_err(|e| VaultError::Keychain(format!("Failed to write key index: {}", e)))
+}
+
+fn index_insert(service_name: &str, key_id: &str) -> Result<()> {
+    let _guard = INDEX_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut keys = read_index(service_name)?;
+    if keys.iter().any(|k| k == key_id) {
+        return Ok(());
+    }
+    keys.push(key_id.to_string());
+    write_index(service_name, &keys)
+}
+
+fn index_remove(service_name: &str, key_id: &str) -> Result<()> {
+    let _guard = INDEX_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut keys = read_index(service_name)?;
+    let before = keys.len();
+    keys.retain(|k| k != key_id);
+    if keys.len() == before {
+        return Ok(());
+    }
+    write_index(service_name, &keys)
+}
+
+/// Indexed keys that still exist, pruning any that vanished behind our back
+fn list_indexed(storage: &dyn KeyStorage, service_name: &str) -> Result<Vec<String>> {
+    let _guard = INDEX_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let keys = read_index(service_name)?;
+    let total = keys.len();
+    let live: Vec<String> = keys.into_iter().filter(|k| storage.key_exists(k)).collect();
+    if live.len() != total {
+        write_index(service_name, &live)?;
+    }
+    Ok(live)
+}
+
+/// `KeyNotFound` for a missing entry, otherwise what `action` failed with
+#[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
+fn keyring_error(key_id: &str, action: &str, e: keyring::Error) -> VaultError {
+    match e {
+        keyring::Error::NoEntry => VaultError::KeyNotFound(key_id.to_string()),
+        e => VaultError::Keychain(format!("{}: {}", action, e)),
+    }
+}
+
+fn reject_reserved(key_id: &str) -> Result<()> {
+    if key_id == INDEX_KEY {
+        return Err(VaultError::Keychain(format!("'{}' is a reserved key id", INDEX_KEY)));
+    }
+    Ok(())
 }
 
 /// Windows implementation using DPAPI via keyring crate
@@ -34,14 +141,14 @@ impl WindowsKeyStorage {
         }
     }
     
-    fn get_entry(&self, key_id: &str) -> Result<Entry> {
-        Entry::new(&self.service_name, key_id)
+    fn get_entry(&self, key_id: &str) -> Result<keyring::Entry> {
+        keyring::Entry::new(&self.service_name, key_id)
             .map_err(|e| VaultError::Keychain(format!("Failed to create entry: {}", e)))
     }
     
-    fn get_metadata_entry(&self, key_id: &str) -> Result<Entry> {
+    fn get_metadata_entry(&self, key_id: &str) -> Result<keyring::Entry> {
         let metadata_key = format!("{}_metadata", key_id);
-        Entry::new(&self.service_name, &metadata_key)
+        keyring::Entry::new(&self.service_name, &metadata_key)
             .map_err(|e| VaultError::Keychain(format!("Failed to create metadata entry: {}", e)))
     }
 }
@@ -49,9 +156,11 @@ impl WindowsKeyStorage {
 #[cfg(target_os = "windows")]
 impl KeyStorage for WindowsKeyStorage {
     fn store_key(&self, key_id: &str, key: &[u8], metadata: KeyMetadata) -> Result<()> {
+        reject_reserved(key_id)?;
+        
         // Store the key
         let entry = self.get_entry(key_id)?;
-        let key_str = general_purpose::STANDARD.encode(key);
+        let key_str = base64::engine::general_purpose::STANDARD.encode(key);
         entry
             .set_password(&key_str)
             .map_err(|e| VaultError::Keychain(format!("Failed to store key: {}", e)))?;
@@ -64,6 +173,13 @@ impl KeyStorage for WindowsKeyStorage {
             .set_password(&metadata_json)
             .map_err(|e| VaultError::Keychain(format!("Failed to store metadata: {}", e)))?;
         
+        // A key missing from the index would be invisible to list_keys
+        if let Err(e) = index_insert(&self.service_name, key_id) {
+            let _ = entry.delete_password();
+            let _ = metadata_entry.delete_password();
+            return Err(e);
+        }
+        
         Ok(())
     }
     
@@ -72,9 +188,9 @@ impl KeyStorage for WindowsKeyStorage {
         let entry = self.get_entry(key_id)?;
         let key_str = entry
             .get_password()
-            .map_err(|e| VaultError::Keychain(format!("Failed to retrieve key: {}", e)))?;
+            .map_err(|e| keyring_error(key_id, "Failed to retrieve key", e))?;
         
-        let key_data = general_purpose::STANDARD.decode(&key_str)
+        let key_data = base64::engine::general_purpose::STANDARD.decode(&key_str)
             .map_err(|e| VaultError::Keychain(format!("Failed to decode key: {}", e)))?;
         
         // Retrieve metadata
@@ -86,6 +202,12 @@ impl KeyStorage for WindowsKeyStorage {
         let metadata: KeyMetadata = serde_json::from_str(&metadata_json)
             .map_err(|e| VaultError::Keychain(format!("Failed to parse metadata: {}", e)))?;
         
+        // Expired keys are removed on first access
+        if metadata.is_expired() {
+            self.delete_key(key_id)?;
+            return Err(VaultError::Keychain(KEY_EXPIRED.to_string()));
+        }
+        
         Ok((key_data, metadata))
     }
     
@@ -94,13 +216,13 @@ impl KeyStorage for WindowsKeyStorage {
         let entry = self.get_entry(key_id)?;
         entry
             .delete_password()
-            .map_err(|e| VaultError::Keychain(format!("Failed to delete key: {}", e)))?;
+            .map_err(|e| keyring_error(key_id, "Failed to delete key", e))?;
         
         // Delete metadata
         let metadata_entry = self.get_metadata_entry(key_id)?;
         let _ = metadata_entry.delete_password(); // Ignore error if metadata doesn't exist
         
-        Ok(())
+        index_remove(&self.service_name, key_id)
     }
     
     fn key_exists(&self, key_id: &str) -> bool {
@@ -115,12 +237,7 @@ impl KeyStorage for WindowsKeyStorage {
     }
     
     fn list_keys(&self) -> Result<Vec<String>> {
-        // Note: keyring crate doesn't support listing all keys
-        // This is a limitation of the OS keychain APIs
-        // For now, return error indicating this limitation
-        Err(VaultError::Keychain(
-            "list_keys not supported by Windows Credential Manager API".to_string()
-        ))
+        list_indexed(self, &self.service_name)
     }
 }
 
@@ -153,6 +270,8 @@ impl LinuxKeyStorage {
 #[cfg(target_os = "linux")]
 impl KeyStorage for LinuxKeyStorage {
     fn store_key(&self, key_id: &str, key: &[u8], metadata: KeyMetadata) -> Result<()> {
+        reject_reserved(key_id)?;
+        
         // Store the key
         let entry = self.get_entry(key_id)?;
         let key_str = base64::engine::general_purpose::STANDARD.encode(key);
@@ -168,6 +287,13 @@ impl KeyStorage for LinuxKeyStorage {
             .set_password(&metadata_json)
             .map_err(|e| VaultError::Keychain(format!("Failed to store metadata: {}", e)))?;
         
+        // A key missing from the index would be invisible to list_keys
+        if let Err(e) = index_insert(&self.service_name, key_id) {
+            let _ = entry.delete_password();
+            let _ = metadata_entry.delete_password();
+            return Err(e);
+        }
+        
         Ok(())
     }
     
@@ -176,7 +302,7 @@ impl KeyStorage for LinuxKeyStorage {
         let entry = self.get_entry(key_id)?;
         let key_str = entry
             .get_password()
-            .map_err(|e| VaultError::Keychain(format!("Failed to retrieve key: {}", e)))?;
+            .map_err(|e| keyring_error(key_id, "Failed to retrieve key", e))?;
         
         let key_data = base64::engine::general_purpose::STANDARD.decode(&key_str)
             .map_err(|e| VaultError::Keychain(format!("Failed to decode key: {}", e)))?;
@@ -190,6 +316,12 @@ impl KeyStorage for LinuxKeyStorage {
         let metadata: KeyMetadata = serde_json::from_str(&metadata_json)
             .map_err(|e| VaultError::Keychain(format!("Failed to parse metadata: {}", e)))?;
         
+        // Expired keys are removed on first access
+        if metadata.is_expired() {
+            self.delete_key(key_id)?;
+            return Err(VaultError::Keychain(KEY_EXPIRED.to_string()));
+        }
+        
         Ok((key_data, metadata))
     }
     
@@ -198,13 +330,13 @@ impl KeyStorage for LinuxKeyStorage {
         let entry = self.get_entry(key_id)?;
         entry
             .delete_password()
-            .map_err(|e| VaultError::Keychain(format!("Failed to delete key: {}", e)))?;
+            .map_err(|e| keyring_error(key_id, "Failed to delete key", e))?;
         
         // Delete metadata
         let metadata_entry = self.get_metadata_entry(key_id)?;
         let _ = metadata_entry.delete_password(); // Ignore error if metadata doesn't exist
         
-        Ok(())
+        index_remove(&self.service_name, key_id)
     }
     
     fn key_exists(&self, key_id: &str) -> bool {
@@ -219,11 +351,7 @@ impl KeyStorage for LinuxKeyStorage {
     }
     
     fn list_keys(&self) -> Result<Vec<String>> {
-        // Note: Linux Secret Service doesn't provide a native list API
-        // We'd need to maintain a separate index or use the secret-service crate directly
-        // For now, return empty list with a note
-        eprintln!("⚠️  list_keys() is not efficiently supported by Linux keyring crate");
-        Ok(vec![])
+        list_indexed(self, &self.service_name)
     }
 }
 
@@ -256,6 +384,8 @@ impl MacOSKeyStorage {
 #[cfg(target_os = "macos")]
 impl KeyStorage for MacOSKeyStorage {
     fn store_key(&self, key_id: &str, key: &[u8], metadata: KeyMetadata) -> Result<()> {
+        reject_reserved(key_id)?;
+        
         // Store the key
         let entry = self.get_entry(key_id)?;
         let key_str = base64::engine::general_purpose::STANDARD.encode(key);
@@ -271,6 +401,13 @@ impl KeyStorage for MacOSKeyStorage {
             .set_password(&metadata_json)
             .map_err(|e| VaultError::Keychain(format!("Failed to store metadata: {}", e)))?;
         
+        // A key missing from the index would be invisible to list_keys
+        if let Err(e) = index_insert(&self.service_name, key_id) {
+            let _ = entry.delete_password();
+            let _ = metadata_entry.delete_password();
+            return Err(e);
+        }
+        
         Ok(())
     }
     
@@ -279,7 +416,7 @@ impl KeyStorage for MacOSKeyStorage {
         let entry = self.get_entry(key_id)?;
         let key_str = entry
             .get_password()
-            .map_err(|e| VaultError::Keychain(format!("Failed to retrieve key: {}", e)))?;
+            .map_err(|e| keyring_error(key_id, "Failed to retrieve key", e))?;
         
         let key_data = base64::engine::general_purpose::STANDARD.decode(&key_str)
             .map_err(|e| VaultError::Keychain(format!("Failed to decode key: {}", e)))?;
@@ -293,6 +430,12 @@ impl KeyStorage for MacOSKeyStorage {
         let metadata: KeyMetadata = serde_json::from_str(&metadata_json)
             .map_err(|e| VaultError::Keychain(format!("Failed to parse metadata: {}", e)))?;
         
+        // Expired keys are removed on first access
+        if metadata.is_expired() {
+            self.delete_key(key_id)?;
+            return Err(VaultError::Keychain(KEY_EXPIRED.to_string()));
+        }
+        
         Ok((key_data, metadata))
     }
     
@@ -301,13 +444,13 @@ impl KeyStorage for MacOSKeyStorage {
         let entry = self.get_entry(key_id)?;
         entry
             .delete_password()
-            .map_err(|e| VaultError::Keychain(format!("Failed to delete key: {}", e)))?;
+            .map_err(|e| keyring_error(key_id, "Failed to delete key", e))?;
         
         // Delete metadata
         let metadata_entry = self.get_metadata_entry(key_id)?;
         let _ = metadata_entry.delete_password(); // Ignore error if metadata doesn't exist
         
-        Ok(())
+        index_remove(&self.service_name, key_id)
     }
     
     fn key_exists(&self, key_id: &str) -> bool {
@@ -322,30 +465,181 @@ impl KeyStorage for MacOSKeyStorage {
     }
     
     fn list_keys(&self) -> Result<Vec<String>> {
-        // Note: macOS Keychain doesn't provide a native list API via keyring crate
-        // We'd need to use the Security framework directly or maintain a separate index
-        // For now, return empty list with a note
-        eprintln!("⚠️  list_keys() is not efficiently supported by macOS keyring crate");
-        Ok(vec![])
+        list_indexed(self, &self.service_name)
+    }
+}
+
+type MemoryEntries = HashMap<String, (Vec<u8>, KeyMetadata)>;
+
+/// Process-local storage for tests and headless or ephemeral daemons
+///
+/// Nothing is persisted, so keys are lost when the process exits. Clones
+/// share the same entries.
+#[derive(Clone, Default)]
+pub struct MemoryKeyStorage {
+    entries: Arc<Mutex<MemoryEntries>>,
+}
+
+/// Memory storages by service name, so that, as with an OS keychain, every
+/// `create_key_storage` call for one namespace sees the same keys
+static MEMORY_KEYCHAINS: Mutex<BTreeMap<String, MemoryKeyStorage>> = Mutex::new(BTreeMap::new());
+
+impl MemoryKeyStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// The process-wide storage for `service_name`
+    fn shared(service_name: &str) -> Self {
+        MEMORY_KEYCHAINS.lock().unwrap_or_else(|e| e.into_inner())
+            .entry(service_name.to_string())
+            .or_default()
+            .clone()
+    }
+    
+    fn entries(&self) -> std::sync::MutexGuard<'_, MemoryEntries> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
     }
 }
 
+impl KeyStorage for MemoryKeyStorage {
+    fn store_key(&self, key_id: &str, key: &[u8], metadata: KeyMetadata) -> Result<()> {
+        reject_reserved(key_id)?;
+        self.entries().insert(key_id.to_string(), (key.to_vec(), metadata));
+        Ok(())
+    }
+    
+    fn retrieve_key(&self, key_id: &str) -> Result<(Vec<u8>, KeyMetadata)> {
+        let mut entries = self.entries();
+        let (key, metadata) = entries.get(key_id)
+            .cloned()
+            .ok_or_else(|| VaultError::KeyNotFound(key_id.to_string()))?;
+        
+        // Expired keys are removed on first access
+        if metadata.is_expired() {
+            entries.remove(key_id);
+            return Err(VaultError::Keychain(KEY_EXPIRED.to_string()));
+        }
+        
+        Ok((key, metadata))
+    }
+    
+    fn delete_key(&self, key_id: &str) -> Result<()> {
+        self.entries()
+            .remove(key_id)
+            .map(|_| ())
+            .ok_or_else(|| VaultError::KeyNotFound(key_id.to_string()))
+    }
+    
+    fn key_exists(&self, key_id: &str) -> bool {
+        self.entries().contains_key(key_id)
+    }
+    
+    fn list_keys(&self) -> Result<Vec<String>> {
+        Ok(self.entries().keys().cloned().collect())
+    }
+}
+
+/// Environment variable selecting the storage backend; `memory` keeps keys
+/// in process instead of the OS keychain
+pub const BACKEND_ENV: &str = "IDENTRA_VAULT_BACKEND";
+
+/// Keychain service name every key lived under before namespaces
+pub const LEGACY_SERVICE_NAME: &str = "identra-vault";
+
+/// Keychain service name for `namespace`
+///
+/// Entries (including the key index) are scoped by service name, so each
+/// namespace gets its own keys and its own `list_keys`.
+pub fn service_name(namespace: &str) -> String {
+    format!("{}.{}", LEGACY_SERVICE_NAME, namespace)
+}
+
+/// Namespace of the current OS user, for daemons not given one explicitly
+pub fn default_namespace() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .ok()
+        .filter(|user| !user.is_empty())
+        .unwrap_or_else(|| "default".to_string())
+}
+
 /// Factory function to create platform-specific key storage
-pub fn create_key_storage() -> Box<dyn KeyStorage> {
+///
+/// `namespace` (an OS user or install id) keeps instances sharing one
+/// keychain from seeing or clobbering each other's keys.
+pub fn create_key_storage(namespace: &str) -> Box<dyn KeyStorage> {
+    platform_key_storage(&service_name(namespace))
+}
+
+/// Storage for keys written under the flat pre-namespace service name
+pub fn legacy_key_storage() -> Box<dyn KeyStorage> {
+    platform_key_storage(LEGACY_SERVICE_NAME)
+}
+
+/// Memory storage in tests, with the `memory-backend` feature, or when
+/// `IDENTRA_VAULT_BACKEND=memory`; otherwise the OS keychain
+fn platform_key_storage(service_name: &str) -> Box<dyn KeyStorage> {
+    if cfg!(any(test, feature = "memory-backend"))
+        || std::env::var(BACKEND_ENV).is_ok_and(|backend| backend == "memory")
+    {
+        return Box::new(MemoryKeyStorage::shared(service_name));
+    }
+    
     #[cfg(target_os = "windows")]
     {
-        Box::new(WindowsKeyStorage::new("identra-vault"))
+        Box::new(WindowsKeyStorage::new(service_name))
     }
     
     #[cfg(target_os = "linux")]
     {
-        Box::new(LinuxKeyStorage::new("identra-vault"))
+        Box::new(LinuxKeyStorage::new(service_name))
     }
     
     #[cfg(target_os = "macos")]
     {
-        Box::new(MacOSKeyStorage::new("identra-vault"))
+        Box::new(MacOSKeyStorage::new(service_name))
+    }
+}
+
+/// Move every key from `legacy` into `storage`, returning how many moved
+///
+/// Entries are copied as stored, so keys sealed by `EncryptedKeyStorage`
+/// stay sealed and the vault's passphrase verifier comes along with them.
+/// A key already present in `storage` is only dropped from `legacy` when
+/// both copies match (an interrupted earlier run); otherwise both are left
+/// alone. Refuses to merge two vaults set up
+/// with different passphrases, as one set of keys would be unreadable.
+pub fn migrate_legacy_keys(storage: &dyn KeyStorage, legacy: &dyn KeyStorage) -> Result<usize> {
+    if storage.key_exists(VERIFIER_KEY_ID) && legacy.key_exists(VERIFIER_KEY_ID) {
+        let (ours, _) = storage.retrieve_key(VERIFIER_KEY_ID)?;
+        let (theirs, _) = legacy.retrieve_key(VERIFIER_KEY_ID)?;
+        if ours != theirs {
+            return Err(VaultError::Keychain(
+                "Legacy keys belong to a different vault; not migrating".to_string()
+            ));
+        }
+    }
+    
+    let mut migrated = 0;
+    for key_id in legacy.list_keys()? {
+        let (key, metadata) = match legacy.retrieve_key(&key_id) {
+            Ok(entry) => entry,
+            // Already deleted by the expiry check
+            Err(VaultError::Keychain(msg)) if msg == KEY_EXPIRED => continue,
+            Err(e) => return Err(e),
+        };
+        if storage.key_exists(&key_id) {
+            if storage.retrieve_key(&key_id)?.0 == key {
+                legacy.delete_key(&key_id)?;
+            }
+            continue;
+        }
+        storage.store_key(&key_id, &key, metadata)?;
+        legacy.delete_key(&key_id)?;
+        migrated += 1;
     }
+    Ok(migrated)
 }
 
 #[cfg(test)]
@@ -1,8 +1,9 @@
-use super::*;
+/// Namespace the keychain tests store under, away from real vault keys
+const TEST_NAMESPACE: &str = "identra-tests";
 
 #[tokio::test]
 async fn test_keychain_store_retrieve_delete() {
-    let storage = create_key_storage();
+    let storage = create_key_storage(TEST_NAMESPACE);
     
     let key_id = "test_key_integration_001";
     let test_key = b"super_secret_encryption_key_12345678";
@@ -42,7 +43,7 @@ async fn test_keychain_store_retrieve_delete() {
 
 #[tokio::test]
 async fn test_keychain_multiple_keys() {
-    let storage = create_key_storage();
+    let storage = create_key_storage(TEST_NAMESPACE);
     
     let keys = vec![
         ("user_001", b"key_for_user_001_abcdefgh"),
@@ -59,7 +60,7 @@ async fn test_keychain_multiple_keys() {
     // Store all keys
     for (key_id, key_data) in &keys {
         storage.store_key(key_id, *key_data, metadata.clone())
-            .expect(&format!("Failed to store key {}", key_id));
+            .unwrap_or_else(|_| panic!("Failed to store key {}", key_id));
     }
     
     // Verify all keys exist
@@ -70,21 +71,21 @@ async fn test_keychain_multiple_keys() {
     // Retrieve and verify all keys
     for (key_id, expected_data) in &keys {
         let (retrieved_data, _) = storage.retrieve_key(key_id)
-            .expect(&format!("Failed to retrieve key {}", key_id));
+            .unwrap_or_else(|_| panic!("Failed to retrieve key {}", key_id));
         assert_eq!(expected_data.as_ref(), retrieved_data.as_slice(), "Data mismatch for key {}", key_id);
     }
     
     // Clean up - delete all keys
     for (key_id, _) in &keys {
         storage.delete_key(key_id)
-            .expect(&format!("Failed to delete key {}", key_id));
+            .unwrap_or_else(|_| panic!("Failed to delete key {}", key_id));
         assert!(!storage.key_exists(key_id), "Key {} should be deleted", key_id);
     }
 }
 
 #[tokio::test]
 async fn test_keychain_retrieve_nonexistent() {
-    let storage = create_key_storage();
+    let storage = create_key_storage(TEST_NAMESPACE);
     
     let result = storage.retrieve_key("nonexistent_key_999");
     
@@ -93,7 +94,7 @@ async fn test_keychain_retrieve_nonexistent() {
 
 #[tokio::test]
 async fn test_keychain_delete_nonexistent() {
-    let storage = create_key_storage();
+    let storage = create_key_storage(TEST_NAMESPACE);
     
     let result = storage.delete_key("nonexistent_key_888");
     
@@ -104,7 +105,7 @@ async fn test_keychain_delete_nonexistent() {
 
 #[tokio::test]
 async fn test_keychain_key_overwrite() {
-    let storage = create_key_storage();
+    let storage = create_key_storage(TEST_NAMESPACE);
     
     let key_id = "test_overwrite_key";
     let original_key = b"original_key_data_12345678";
@@ -137,7 +138,7 @@ async fn test_keychain_key_overwrite() {
 
 #[tokio::test]
 async fn test_keychain_metadata_persistence() {
-    let storage = create_key_storage();
+    let storage = create_key_storage(TEST_NAMESPACE);
     
     let key_id = "test_metadata_key";
     let key_data = b"test_key_with_metadata_123";
@@ -171,3 +172,173 @@ async fn test_keychain_metadata_persistence() {
     storage.delete_key(key_id)
         .expect("Failed to delete key");
 }
+
+#[test]
+fn test_metadata_is_expired() {
+    let now = chrono::Utc::now().timestamp();
+    let metadata = |expires_at| KeyMetadata {
+        created_at: now,
+        expires_at,
+        custom: std::collections::HashMap::new(),
+    };
+    
+    assert!(!metadata(None).is_expired());
+    assert!(!metadata(Some(now + 3600)).is_expired());
+    assert!(metadata(Some(now - 1)).is_expired());
+}
+
+#[tokio::test]
+async fn test_keychain_expired_key_is_deleted() {
+    let storage = create_key_storage(TEST_NAMESPACE);
+    
+    let key_id = "test_expired_key";
+    let metadata = KeyMetadata {
+        created_at: chrono::Utc::now().timestamp() - 120,
+        expires_at: Some(chrono::Utc::now().timestamp() - 60),
+        custom: std::collections::HashMap::new(),
+    };
+    
+    storage.store_key(key_id, b"stale_key_data", metadata)
+        .expect("Failed to store key");
+    
+    // Retrieving an expired key fails and removes it
+    match storage.retrieve_key(key_id) {
+        Err(VaultError::Keychain(msg)) => assert_eq!(msg, KEY_EXPIRED),
+        other => panic!("Expected expired error, got {:?}", other.map(|(_, m)| m)),
+    }
+    assert!(!storage.key_exists(key_id), "Expired key should be deleted");
+}
+
+#[tokio::test]
+async fn test_keychain_list_keys_tracks_store_and_delete() {
+    let storage = create_key_storage(TEST_NAMESPACE);
+    
+    let metadata = KeyMetadata {
+        created_at: chrono::Utc::now().timestamp(),
+        expires_at: None,
+        custom: std::collections::HashMap::new(),
+    };
+    
+    storage.store_key("test_index_a", b"index_key_a", metadata.clone())
+        .expect("Failed to store key a");
+    storage.store_key("test_index_b", b"index_key_b", metadata.clone())
+        .expect("Failed to store key b");
+    // Overwriting must not duplicate the index entry
+    storage.store_key("test_index_a", b"index_key_a2", metadata)
+        .expect("Failed to overwrite key a");
+    
+    let keys = storage.list_keys().expect("Failed to list keys");
+    assert_eq!(keys.iter().filter(|k| *k == "test_index_a").count(), 1);
+    assert!(keys.contains(&"test_index_b".to_string()));
+    
+    storage.delete_key("test_index_a").expect("Failed to delete key a");
+    storage.delete_key("test_index_b").expect("Failed to delete key b");
+    
+    let keys = storage.list_keys().expect("Failed to list keys");
+    assert!(!keys.contains(&"test_index_a".to_string()));
+    assert!(!keys.contains(&"test_index_b".to_string()));
+}
+
+#[test]
+fn test_index_key_is_reserved() {
+    let storage = create_key_storage(TEST_NAMESPACE);
+    let metadata = KeyMetadata {
+        created_at: 0,
+        expires_at: None,
+        custom: std::collections::HashMap::new(),
+    };
+    
+    assert!(storage.store_key(INDEX_KEY, b"nope", metadata).is_err());
+}
+
+
+#[test]
+fn test_namespaces_are_isolated() {
+    let alice = create_key_storage("identra-tests-alice");
+    let bob = create_key_storage("identra-tests-bob");
+    let metadata = KeyMetadata {
+        created_at: chrono::Utc::now().timestamp(),
+        expires_at: None,
+        custom: std::collections::HashMap::new(),
+    };
+    
+    alice.store_key("test_shared_id", b"alice_key", metadata.clone())
+        .expect("Failed to store alice's key");
+    bob.store_key("test_shared_id", b"bob_key", metadata)
+        .expect("Failed to store bob's key");
+    
+    assert_eq!(alice.retrieve_key("test_shared_id").unwrap().0, b"alice_key");
+    assert_eq!(bob.retrieve_key("test_shared_id").unwrap().0, b"bob_key");
+    
+    alice.delete_key("test_shared_id").expect("Failed to delete alice's key");
+    assert!(!alice.list_keys().unwrap().contains(&"test_shared_id".to_string()));
+    assert!(bob.list_keys().unwrap().contains(&"test_shared_id".to_string()));
+    assert!(bob.key_exists("test_shared_id"));
+    
+    bob.delete_key("test_shared_id").expect("Failed to delete bob's key");
+}
+
+#[test]
+fn test_migrate_legacy_keys() {
+    let metadata = KeyMetadata {
+        created_at: 0,
+        expires_at: None,
+        custom: std::collections::HashMap::new(),
+    };
+    let legacy = MemoryKeyStorage::new();
+    legacy.store_key(VERIFIER_KEY_ID, b"verifier", metadata.clone()).unwrap();
+    legacy.store_key("a", b"key_a", metadata.clone()).unwrap();
+    legacy.store_key("clash", b"legacy_clash", metadata.clone()).unwrap();
+    
+    let storage = MemoryKeyStorage::new();
+    storage.store_key("clash", b"new_clash", metadata.clone()).unwrap();
+    
+    assert_eq!(migrate_legacy_keys(&storage, &legacy).unwrap(), 2);
+    assert_eq!(storage.retrieve_key("a").unwrap().0, b"key_a");
+    assert!(storage.key_exists(VERIFIER_KEY_ID));
+    // A clashing key is kept on both sides rather than overwritten or lost
+    assert_eq!(storage.retrieve_key("clash").unwrap().0, b"new_clash");
+    assert_eq!(legacy.list_keys().unwrap(), vec!["clash".to_string()]);
+    
+    // A legacy vault under a different passphrase is left alone
+    let other = MemoryKeyStorage::new();
+    other.store_key(VERIFIER_KEY_ID, b"other_verifier", metadata.clone()).unwrap();
+    other.store_key("b", b"key_b", metadata).unwrap();
+    assert!(migrate_legacy_keys(&storage, &other).is_err());
+    assert!(!storage.key_exists("b"));
+}
+
+#[test]
+fn test_memory_storage_trait_methods() {
+    let storage = MemoryKeyStorage::new();
+    let now = chrono::Utc::now().timestamp();
+    let metadata = |expires_at| KeyMetadata {
+        created_at: now,
+        expires_at,
+        custom: std::collections::HashMap::from([("purpose".to_string(), "test".to_string())]),
+    };
+    
+    storage.store_key("live", b"live_key", metadata(None)).unwrap();
+    storage.store_key("stale", b"stale_key", metadata(Some(now - 1))).unwrap();
+    assert!(storage.store_key(INDEX_KEY, b"nope", metadata(None)).is_err());
+    
+    let mut keys = storage.list_keys().unwrap();
+    keys.sort();
+    assert_eq!(keys, vec!["live".to_string(), "stale".to_string()]);
+    assert!(storage.key_exists("live"));
+    
+    let (key, retrieved) = storage.retrieve_key("live").unwrap();
+    assert_eq!(key, b"live_key");
+    assert_eq!(retrieved.custom["purpose"], "test");
+    
+    assert_eq!(storage.purge_expired().unwrap(), 1);
+    assert_eq!(storage.list_keys().unwrap(), vec!["live".to_string()]);
+    
+    // Clones share entries; separate instances don't
+    storage.clone().delete_key("live").unwrap();
+    assert!(!storage.key_exists("live"));
+    assert!(storage.delete_key("live").is_err());
+    assert!(storage.retrieve_key("live").is_err());
+    storage.store_key("live", b"live_key", metadata(None)).unwrap();
+    assert!(!MemoryKeyStorage::new().key_exists("live"));
+}
@@ -4,13 +4,28 @@ pub mod keychain;
 // Memory security module
 pub mod memory;
 
+// At-rest encryption for stored keys
+pub mod encrypted;
+
+// Passphrase lock state
+pub mod lock;
+
+// Secrets stored and read back in pieces
+pub mod secret_stream;
+
 // IPC communication module
 pub mod ipc;
 
+// Audit trail of key access
+pub mod audit;
+
 // Error types
 mod error;
 
 pub use error::{VaultError, Result};
 pub use keychain::KeyStorage;
-pub use memory::SecureMemory;
+pub use encrypted::EncryptedKeyStorage;
+pub use lock::VaultLock;
+pub use memory::{MemoryGuard, MemoryGuardMut, SecureMemory};
 pub use ipc::VaultServer;
+pub use audit::{AuditLog, AuditRecord};
@@ -0,0 +1,277 @@
+use crate::error::{Result, VaultError};
+use crate::keychain::{KeyMetadata, KeyStorage};
+use crate::memory::SecureMemory;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use identra_crypto::{derive_key, generate_salt, CryptoError, EncryptionKey, Envelope, KeyDerivationParams};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Error message for key operations attempted while the vault is locked
+pub use identra_ipc::VAULT_LOCKED;
+
+/// Reserved key holding the passphrase verifier
+pub const VERIFIER_KEY_ID: &str = "__identra_verifier__";
+
+/// Metadata entry holding the base64 Argon2 salt of the master key
+const VERIFIER_SALT: &str = "salt";
+
+/// Metadata entry holding the Argon2 parameters the master key was derived with
+const VERIFIER_PARAMS: &str = "kdf";
+
+/// Known plaintext sealed under the master key to check passphrases
+const VERIFIER_PLAINTEXT: &[u8] = b"identra-vault";
+
+/// Default inactivity period before the vault re-locks itself
+pub const DEFAULT_AUTO_LOCK: Duration = Duration::from_secs(15 * 60);
+
+/// Locked/unlocked state of the vault
+///
+/// While unlocked the Argon2-derived master key lives in `SecureMemory`;
+/// locking drops (and so zeroes) it. The first unlock against an empty
+/// keychain sets the passphrase by storing a verifier alongside the keys.
+/// The verifier records the Argon2 parameters used then, so changing
+/// `params` later only applies to a passphrase set afterwards.
+pub struct VaultLock {
+    master: Option<SecureMemory>,
+    last_activity: Instant,
+    auto_lock_after: Duration,
+    params: KeyDerivationParams,
+}
+
+impl VaultLock {
+    pub fn new(auto_lock_after: Duration, params: KeyDerivationParams) -> Self {
+        Self {
+            master: None,
+            last_activity: Instant::now(),
+            auto_lock_after,
+            params,
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.master.is_none()
+    }
+
+    /// Argon2 parameters a first unlock sets the passphrase with
+    pub fn params(&self) -> &KeyDerivationParams {
+        &self.params
+    }
+
+    /// Derive the master key from `passphrase` and check it against the verifier
+    pub fn unlock(&mut self, storage: &dyn KeyStorage, passphrase: &[u8]) -> Result<()> {
+        let derived = derive_master(storage, passphrase, &self.params)?;
+        self.finish_unlock(storage, derived)
+    }
+
+    /// Unlock with a key from `derive_master`, storing its verifier if it
+    /// sets the passphrase
+    ///
+    /// Fails if another unlock set a passphrase since `derived` was made;
+    /// replacing that verifier would leave keys stored under a master key
+    /// no passphrase opens any more.
+    pub fn finish_unlock(&mut self, storage: &dyn KeyStorage, derived: DerivedMaster) -> Result<()> {
+        if let Some((sealed, metadata)) = derived.verifier {
+            if storage.key_exists(VERIFIER_KEY_ID) {
+                return Err(VaultError::Encryption("Passphrase was set by another unlock; unlock again".to_string()));
+            }
+            storage.store_key(VERIFIER_KEY_ID, &sealed, metadata)?;
+        }
+
+        self.master = Some(SecureMemory::from_vec(derived.master.as_bytes().to_vec())?);
+        self.last_activity = Instant::now();
+        Ok(())
+    }
+
+    pub fn lock(&mut self) {
+        self.master = None;
+    }
+
+    /// Master key for a key operation, counting as activity
+    pub fn master_key(&mut self) -> Result<EncryptionKey> {
+        let master = self.master.as_ref()
+            .ok_or_else(|| VaultError::Encryption(VAULT_LOCKED.to_string()))?;
+        let key = EncryptionKey::from_bytes(&master.borrow()?)
+            .map_err(|e| VaultError::Encryption(e.to_string()))?;
+
+        self.last_activity = Instant::now();
+        Ok(key)
+    }
+
+    /// Lock if unlocked and idle for longer than the auto-lock period
+    ///
+    /// Returns true if this call locked the vault.
+    pub fn lock_if_idle(&mut self) -> bool {
+        if self.is_locked() || self.last_activity.elapsed() < self.auto_lock_after {
+            return false;
+        }
+        self.lock();
+        true
+    }
+}
+
+/// Master key derived by `derive_master`, not yet in use
+pub struct DerivedMaster {
+    master: EncryptionKey,
+    /// Sealed verifier and its metadata when this sets the passphrase
+    verifier: Option<(Vec<u8>, KeyMetadata)>,
+}
+
+/// Slow half of unlocking: derive the master key from `passphrase` and
+/// check it against the stored verifier, or seal a new one with `params`
+///
+/// Runs Argon2 at whatever cost the verifier records, so it takes no
+/// `VaultLock` and can run off the async runtime; nothing is stored until
+/// `VaultLock::finish_unlock`.
+pub fn derive_master(storage: &dyn KeyStorage, passphrase: &[u8], params: &KeyDerivationParams) -> Result<DerivedMaster> {
+    if storage.key_exists(VERIFIER_KEY_ID) {
+        let (sealed, metadata) = storage.retrieve_key(VERIFIER_KEY_ID)?;
+        let salt = metadata.custom.get(VERIFIER_SALT)
+            .and_then(|salt| STANDARD.decode(salt).ok())
+            .ok_or_else(|| VaultError::Encryption("Corrupt passphrase verifier".to_string()))?;
+
+        // Verifiers from before parameters were recorded used the daemon's
+        let recorded = match metadata.custom.get(VERIFIER_PARAMS) {
+            Some(params) => params.parse()
+                .map_err(|e| VaultError::Encryption(format!("Corrupt passphrase verifier: {}", e)))?,
+            None => params.clone(),
+        };
+
+        let master = derive(passphrase, &salt, &recorded)?;
+        match Envelope::open(&master, &sealed) {
+            Ok(plaintext) if plaintext == VERIFIER_PLAINTEXT => Ok(DerivedMaster { master, verifier: None }),
+            Err(CryptoError::MalformedCiphertext { reason }) => {
+                Err(VaultError::Encryption(format!("Corrupt passphrase verifier: {}", reason)))
+            }
+            _ => Err(VaultError::Encryption("Invalid passphrase".to_string())),
+        }
+    } else {
+        let salt = generate_salt();
+        let master = derive(passphrase, &salt, params)?;
+        let sealed = Envelope::seal(&master, VERIFIER_PLAINTEXT)
+            .map_err(|e| VaultError::Encryption(e.to_string()))?;
+
+        let metadata = KeyMetadata {
+            created_at: chrono::Utc::now().timestamp(),
+            expires_at: None,
+            custom: HashMap::from([
+                (VERIFIER_SALT.to_string(), STANDARD.encode(salt)),
+                (VERIFIER_PARAMS.to_string(), params.to_string()),
+            ]),
+        };
+        Ok(DerivedMaster { master, verifier: Some((sealed, metadata)) })
+    }
+}
+
+fn derive(passphrase: &[u8], salt: &[u8], params: &KeyDerivationParams) -> Result<EncryptionKey> {
+    derive_key(passphrase, salt, params)
+        .map(|key| key.to_encryption_key())
+        .map_err(|e| VaultError::Encryption(format!("Failed to derive master key: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keychain::MemoryKeyStorage;
+
+    fn vault_lock(auto_lock_after: Duration) -> VaultLock {
+        VaultLock::new(auto_lock_after, KeyDerivationParams::fast())
+    }
+
+    #[test]
+    fn test_starts_locked() {
+        let mut lock = vault_lock(DEFAULT_AUTO_LOCK);
+
+        assert!(lock.is_locked());
+        assert!(lock.master_key().is_err());
+    }
+
+    #[test]
+    fn test_first_unlock_sets_passphrase() {
+        let storage = MemoryKeyStorage::new();
+        let mut first = vault_lock(DEFAULT_AUTO_LOCK);
+        first.unlock(&storage, b"correct horse").unwrap();
+        assert!(!first.is_locked());
+        assert!(storage.key_exists(VERIFIER_KEY_ID));
+
+        let mut second = vault_lock(DEFAULT_AUTO_LOCK);
+        assert!(second.unlock(&storage, b"battery staple").is_err());
+        assert!(second.is_locked());
+
+        second.unlock(&storage, b"correct horse").unwrap();
+        assert_eq!(
+            first.master_key().unwrap().as_bytes(),
+            second.master_key().unwrap().as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_truncated_verifier_is_not_a_wrong_passphrase() {
+        let storage = MemoryKeyStorage::new();
+        vault_lock(DEFAULT_AUTO_LOCK).unlock(&storage, b"correct horse").unwrap();
+        let (sealed, metadata) = storage.retrieve_key(VERIFIER_KEY_ID).unwrap();
+        storage.store_key(VERIFIER_KEY_ID, &sealed[..4], metadata).unwrap();
+
+        let error = vault_lock(DEFAULT_AUTO_LOCK).unlock(&storage, b"correct horse").unwrap_err();
+        assert!(error.to_string().contains("Corrupt passphrase verifier"), "{}", error);
+    }
+
+    #[test]
+    fn test_unlock_uses_recorded_params() {
+        let storage = MemoryKeyStorage::new();
+        let mut first = vault_lock(DEFAULT_AUTO_LOCK);
+        first.unlock(&storage, b"correct horse").unwrap();
+        let (_, metadata) = storage.retrieve_key(VERIFIER_KEY_ID).unwrap();
+        assert_eq!(metadata.custom[VERIFIER_PARAMS], KeyDerivationParams::fast().to_string());
+
+        // A daemon since configured differently still derives the same key
+        let changed = KeyDerivationParams { time_cost: 2, ..KeyDerivationParams::fast() };
+        let mut second = VaultLock::new(DEFAULT_AUTO_LOCK, changed);
+        second.unlock(&storage, b"correct horse").unwrap();
+        assert_eq!(
+            first.master_key().unwrap().as_bytes(),
+            second.master_key().unwrap().as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_racing_first_unlocks_keep_one_passphrase() {
+        let storage = MemoryKeyStorage::new();
+        let params = KeyDerivationParams::fast();
+        let first = derive_master(&storage, b"correct horse", &params).unwrap();
+        let second = derive_master(&storage, b"battery staple", &params).unwrap();
+
+        let mut winner = vault_lock(DEFAULT_AUTO_LOCK);
+        winner.finish_unlock(&storage, first).unwrap();
+        let mut loser = vault_lock(DEFAULT_AUTO_LOCK);
+        assert!(loser.finish_unlock(&storage, second).is_err());
+        assert!(loser.is_locked());
+
+        loser.unlock(&storage, b"correct horse").unwrap();
+        assert_eq!(
+            winner.master_key().unwrap().as_bytes(),
+            loser.master_key().unwrap().as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_lock_drops_master_key() {
+        let storage = MemoryKeyStorage::new();
+        let mut lock = vault_lock(DEFAULT_AUTO_LOCK);
+        lock.unlock(&storage, b"correct horse").unwrap();
+
+        lock.lock();
+        assert!(lock.is_locked());
+        assert!(lock.master_key().is_err());
+    }
+
+    #[test]
+    fn test_auto_lock_after_inactivity() {
+        let storage = MemoryKeyStorage::new();
+        let mut lock = vault_lock(Duration::ZERO);
+        lock.unlock(&storage, b"correct horse").unwrap();
+
+        assert!(lock.lock_if_idle());
+        assert!(lock.is_locked());
+        assert!(!lock.lock_if_idle());
+    }
+}
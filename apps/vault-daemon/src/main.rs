@@ -1,28 +1,114 @@
 use anyhow::Result;
-use vault_daemon::VaultServer;
+use identra_crypto::{KeyDerivationParams, Profile};
+use std::time::Duration;
+use vault_daemon::{
+    ipc::DEFAULT_MAX_CONNECTIONS, keychain, lock::DEFAULT_AUTO_LOCK, AuditLog, VaultLock,
+    VaultServer,
+};
+use zeroize::Zeroizing;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    println!("🔐 Identra Vault Daemon starting...");
-    println!("📍 Local secure storage initialized");
-    println!("🔑 OS Keychain integration active");
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
+        )
+        .init();
+    
+    tracing::info!("Identra Vault Daemon starting");
+    
+    let auto_lock = std::env::var("IDENTRA_VAULT_AUTO_LOCK_MINS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .map(|mins: u64| Duration::from_secs(mins * 60))
+        .unwrap_or(DEFAULT_AUTO_LOCK);
+    
+    // One namespace per OS user unless the install picks its own
+    let namespace = std::env::var("IDENTRA_VAULT_NAMESPACE")
+        .ok()
+        .filter(|ns| !ns.is_empty())
+        .unwrap_or_else(keychain::default_namespace);
+    let keychain = keychain::create_key_storage(&namespace);
+    tracing::info!("Using keychain namespace: {}", namespace);
+    
+    // Keys from before namespaces live under the flat service name
+    match keychain::migrate_legacy_keys(keychain.as_ref(), keychain::legacy_key_storage().as_ref()) {
+        Ok(0) => {}
+        Ok(migrated) => tracing::info!("Migrated {} legacy keys into namespace {}", migrated, namespace),
+        Err(e) => tracing::warn!("Failed to migrate legacy keys: {}", e),
+    }
+    let mut lock = VaultLock::new(auto_lock, kdf_params());
+    
+    // Headless setups can unlock at startup; otherwise clients send Unlock
+    if let Ok(passphrase) = std::env::var("IDENTRA_VAULT_PASSPHRASE").map(Zeroizing::new) {
+        if !passphrase.is_empty() {
+            match lock.unlock(keychain.as_ref(), passphrase.as_bytes()) {
+                Ok(_) => tracing::info!("Vault unlocked from IDENTRA_VAULT_PASSPHRASE"),
+                Err(e) => tracing::error!("Failed to unlock vault: {}", e),
+            }
+        }
+    }
+    
+    let max_connections = std::env::var("IDENTRA_VAULT_MAX_CONNECTIONS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_MAX_CONNECTIONS);
+    
+    let audit = AuditLog::from_env()?;
+    
+    // Only while migrating a keychain written before keys were encrypted
+    let accept_plaintext_keys = std::env::var("IDENTRA_VAULT_ACCEPT_PLAINTEXT_KEYS")
+        .is_ok_and(|v| matches!(v.trim(), "1" | "true"));
+    if accept_plaintext_keys {
+        tracing::warn!("Accepting unencrypted keys from the keychain (IDENTRA_VAULT_ACCEPT_PLAINTEXT_KEYS)");
+    }
     
     // Initialize IPC server
-    let server = VaultServer::new();
+    let server = VaultServer::with_storage(keychain, lock)
+        .with_max_connections(max_connections)
+        .with_audit_log(audit)
+        .with_plaintext_keys(accept_plaintext_keys);
     
     // Start listening for IPC connections
     // This will block until shutdown signal
     tokio::select! {
         result = server.start() => {
             if let Err(e) = result {
-                eprintln!("❌ Server error: {}", e);
+                tracing::error!("Server error: {}", e);
             }
         }
         _ = tokio::signal::ctrl_c() => {
-            println!("\n🛑 Shutdown signal received");
+            tracing::info!("Shutdown signal received");
         }
     }
     
-    println!("🛑 Shutting down Vault Daemon...");
+    tracing::info!("Shutting down Vault Daemon");
     Ok(())
 }
+
+/// Argon2 parameters for a passphrase set on this device
+///
+/// `IDENTRA_VAULT_KDF_TARGET_MS` calibrates them to take about that long
+/// here; otherwise `IDENTRA_VAULT_KDF_PROFILE` picks a preset (moderate
+/// unless set).
+fn kdf_params() -> KeyDerivationParams {
+    let target = std::env::var("IDENTRA_VAULT_KDF_TARGET_MS")
+        .ok()
+        .and_then(|s| s.trim().parse::<u64>().ok())
+        .filter(|ms| *ms > 0);
+    if let Some(ms) = target {
+        let params = KeyDerivationParams::calibrate(Duration::from_millis(ms));
+        tracing::info!("Calibrated key derivation to {} for {} ms", params, ms);
+        return params;
+    }
+
+    let profile = match std::env::var("IDENTRA_VAULT_KDF_PROFILE") {
+        Ok(name) => name.parse().unwrap_or_else(|e| {
+            tracing::warn!("{}; using the moderate profile", e);
+            Profile::Moderate
+        }),
+        Err(_) => Profile::Moderate,
+    };
+    KeyDerivationParams::for_profile(profile)
+}
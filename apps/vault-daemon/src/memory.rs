@@ -1,92 +1,236 @@
-use crate::error::Result;
+use crate::error::{Result, VaultError};
+use region::Protection;
 use secrecy::Secret;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
 use zeroize::Zeroize;
 
 /// Secure memory container that locks pages and zeros on drop
+///
+/// The bytes live in their own page-aligned mapping that is locked into RAM
+/// (`mlock`/`VirtualLock`) and kept `NO_ACCESS` except while a
+/// `borrow`/`borrow_mut` guard is alive, so a stray pointer into the region
+/// faults instead of reading plaintext.
 pub struct SecureMemory {
-    data: Vec<u8>,
-    locked: bool,
+    // Declared before `region` so the pages are unlocked before being unmapped
+    _lock: region::LockGuard,
+    region: region::Allocation,
+    len: usize,
+    /// Live read guards; the pages are readable while this is non-zero
+    readers: Mutex<usize>,
+    /// Set once `as_slice`/`as_mut_slice` hand out an unguarded reference
+    exposed: AtomicBool,
 }
 
+// SAFETY: the mapping is owned exclusively by this value, and page
+// protection changes for shared borrows are serialized by `readers`.
+unsafe impl Send for SecureMemory {}
+unsafe impl Sync for SecureMemory {}
+
 impl SecureMemory {
     /// Create new secure memory region
+    ///
+    /// Fails with `VaultError::MemoryLock` if the pages can't be locked,
+    /// e.g. when `RLIMIT_MEMLOCK` is exhausted.
     pub fn new(size: usize) -> Result<Self> {
-        let data = vec![0u8; size];
+        // region can't map zero bytes; an empty buffer still gets a page
+        let region = region::alloc(size.max(1), Protection::READ_WRITE)
+            .map_err(|e| VaultError::MemoryLock(format!("Failed to allocate secure memory: {}", e)))?;
         
         // Lock memory pages to prevent swapping to disk
-        let locked = Self::lock_memory(&data);
+        let lock = region::lock(region.as_ptr::<u8>(), region.len())
+            .map_err(|e| VaultError::MemoryLock(format!("Failed to lock secure memory: {}", e)))?;
+        
+        let memory = Self {
+            _lock: lock,
+            region,
+            len: size,
+            readers: Mutex::new(0),
+            exposed: AtomicBool::new(false),
+        };
+        memory.protect(Protection::NONE)?;
         
-        Ok(Self { data, locked })
+        Ok(memory)
     }
     
     /// Create from existing data (will be zeroized in source)
-    pub fn from_vec(data: Vec<u8>) -> Result<Self> {
-        let locked = Self::lock_memory(&data);
-        Ok(Self { data, locked })
+    pub fn from_vec(mut data: Vec<u8>) -> Result<Self> {
+        let mut memory = Self::new(data.len())?;
+        memory.borrow_mut()?.copy_from_slice(&data);
+        data.zeroize();
+        Ok(memory)
     }
     
-    /// Lock memory pages (platform-specific)
-    fn lock_memory(data: &[u8]) -> bool {
-        #[cfg(windows)]
-        {
-            // Lock the memory pages
-            unsafe {
-                protect(
-                    data.as_ptr(),
-                    data.len(),
-                    Protection::READ_WRITE,
-                ).is_ok()
-            }
+    /// Overwrite the bytes with `src`, which must be exactly as long
+    pub fn copy_from_slice(&mut self, src: &[u8]) -> Result<()> {
+        if src.len() != self.len {
+            return Err(VaultError::LengthMismatch { expected: self.len, actual: src.len() });
         }
-        
-        #[cfg(not(windows))]
+        self.borrow_mut()?.copy_from_slice(src);
+        Ok(())
+    }
+    
+    /// Change the length to `new_len`, keeping the bytes that still fit and
+    /// zero-filling any new ones
+    ///
+    /// A locked mapping can't grow in place: pages past the old end would
+    /// be neither locked nor `NO_ACCESS`, and a `Vec`-style realloc would
+    /// copy the secret into ordinary heap memory. So the bytes move to a
+    /// newly allocated region, locked before they are copied in, and never
+    /// touch swappable memory. The old region is zeroed before it is
+    /// unlocked and unmapped, because unmapped pages go back to the OS with
+    /// whatever they held. Nothing is freed until the copy is done, so on
+    /// failure `self` is left as it was.
+    pub fn resize(&mut self, new_len: usize) -> Result<()> {
+        let mut resized = Self::new(new_len)?;
         {
-            // On Unix, use mlock
-            unsafe {
-                libc::mlock(data.as_ptr() as *const libc::c_void, data.len()) == 0
-            }
+            let old = self.borrow()?;
+            let mut new = resized.borrow_mut()?;
+            let kept = old.len().min(new_len);
+            new[..kept].copy_from_slice(&old[..kept]);
+        }
+        // Dropping the old value zeroes it before its lock guard and mapping go
+        *self = resized;
+        Ok(())
+    }
+    
+    fn protect(&self, protection: Protection) -> Result<()> {
+        // SAFETY: the range is exactly our own mapping
+        unsafe { region::protect(self.region.as_ptr::<u8>(), self.region.len(), protection) }
+            .map_err(|e| VaultError::MemoryLock(format!("Failed to protect secure memory: {}", e)))
+    }
+    
+    /// Read access to the bytes for as long as the guard lives
+    pub fn borrow(&self) -> Result<MemoryGuard<'_>> {
+        let mut readers = self.readers.lock().unwrap_or_else(|e| e.into_inner());
+        if *readers == 0 {
+            self.protect(Protection::READ)?;
         }
+        *readers += 1;
+        Ok(MemoryGuard { memory: self })
+    }
+    
+    /// Write access to the bytes for as long as the guard lives
+    pub fn borrow_mut(&mut self) -> Result<MemoryGuardMut<'_>> {
+        self.protect(Protection::READ_WRITE)?;
+        Ok(MemoryGuardMut { memory: self })
     }
     
     /// Get immutable reference to data
+    ///
+    /// Leaves the pages readable for the rest of this value's life.
+    #[deprecated(note = "use `borrow`, which re-protects the pages when done")]
     pub fn as_slice(&self) -> &[u8] {
-        &self.data
+        self.expose(Protection::READ);
+        // SAFETY: the pages are readable from here on and we own them
+        unsafe { std::slice::from_raw_parts(self.region.as_ptr(), self.len) }
     }
     
     /// Get mutable reference to data
+    ///
+    /// Leaves the pages writable for the rest of this value's life.
+    #[deprecated(note = "use `borrow_mut`, which re-protects the pages when done")]
     pub fn as_mut_slice(&mut self) -> &mut [u8] {
-        &mut self.data
+        self.expose(Protection::READ_WRITE);
+        // SAFETY: the pages are writable from here on and we hold `&mut self`
+        unsafe { std::slice::from_raw_parts_mut(self.region.as_mut_ptr(), self.len) }
+    }
+    
+    fn expose(&self, protection: Protection) {
+        self.exposed.store(true, Ordering::SeqCst);
+        self.protect(protection).expect("Failed to unprotect secure memory");
     }
     
     /// Get length of secure memory
     pub fn len(&self) -> usize {
-        self.data.len()
+        self.len
     }
     
     /// Check if empty
     pub fn is_empty(&self) -> bool {
-        self.data.is_empty()
+        self.len == 0
+    }
+    
+    /// Raw bytes for test assertions
+    #[cfg(test)]
+    pub(crate) fn expose_bytes(&self) -> Vec<u8> {
+        self.borrow().unwrap().to_vec()
+    }
+}
+
+/// Read guard returned by `SecureMemory::borrow`
+pub struct MemoryGuard<'a> {
+    memory: &'a SecureMemory,
+}
+
+impl Deref for MemoryGuard<'_> {
+    type Target = [u8];
+    
+    fn deref(&self) -> &[u8] {
+        // SAFETY: pages stay readable while any read guard is alive
+        unsafe { std::slice::from_raw_parts(self.memory.region.as_ptr(), self.memory.len) }
+    }
+}
+
+impl Drop for MemoryGuard<'_> {
+    fn drop(&mut self) {
+        let mut readers = self.memory.readers.lock().unwrap_or_else(|e| e.into_inner());
+        *readers -= 1;
+        if *readers == 0 && !self.memory.exposed.load(Ordering::SeqCst) {
+            let _ = self.memory.protect(Protection::NONE);
+        }
+    }
+}
+
+/// Write guard returned by `SecureMemory::borrow_mut`
+pub struct MemoryGuardMut<'a> {
+    memory: &'a mut SecureMemory,
+}
+
+impl Deref for MemoryGuardMut<'_> {
+    type Target = [u8];
+    
+    fn deref(&self) -> &[u8] {
+        // SAFETY: pages stay writable while the write guard is alive
+        unsafe { std::slice::from_raw_parts(self.memory.region.as_ptr(), self.memory.len) }
+    }
+}
+
+impl DerefMut for MemoryGuardMut<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        // SAFETY: as above, and the guard holds the only reference
+        unsafe { std::slice::from_raw_parts_mut(self.memory.region.as_mut_ptr(), self.memory.len) }
+    }
+}
+
+impl Drop for MemoryGuardMut<'_> {
+    fn drop(&mut self) {
+        let protection = if self.memory.exposed.load(Ordering::SeqCst) {
+            Protection::READ_WRITE
+        } else {
+            Protection::NONE
+        };
+        let _ = self.memory.protect(protection);
+    }
+}
+
+impl fmt::Debug for SecureMemory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecureMemory")
+            .field("data", &format_args!("[redacted; {}]", self.len))
+            .finish()
     }
 }
 
 impl Drop for SecureMemory {
     fn drop(&mut self) {
         // Zero out memory before dropping
-        self.data.zeroize();
-        
-        // Unlock memory if it was locked
-        if self.locked {
-            #[cfg(windows)]
-            {
-                // Windows automatically unlocks on drop
-            }
-            
-            #[cfg(not(windows))]
-            {
-                unsafe {
-                    libc::munlock(self.data.as_ptr() as *const libc::c_void, self.data.len());
-                }
-            }
+        if self.protect(Protection::READ_WRITE).is_ok() {
+            // SAFETY: the whole mapping is writable and owned by us
+            unsafe { std::slice::from_raw_parts_mut(self.region.as_mut_ptr::<u8>(), self.region.len()) }
+                .zeroize();
         }
     }
 }
@@ -113,10 +257,133 @@ mod tests {
         let mut mem = SecureMemory::from_vec(data).unwrap();
         
         // Modify data
-        mem.as_mut_slice()[0] = 99;
-        assert_eq!(mem.as_slice()[0], 99);
+        mem.borrow_mut().unwrap()[0] = 99;
+        assert_eq!(mem.borrow().unwrap()[0], 99);
         
         // Drop will zeroize
         drop(mem);
     }
+    
+    #[test]
+    fn test_secure_memory_debug_redacts_data() {
+        let mem = SecureMemory::from_vec(vec![0xAB; 8]).unwrap();
+        let debug = format!("{:?}", mem);
+        
+        assert!(debug.contains("[redacted; 8]"));
+        assert!(!debug.contains("171"));
+        assert_eq!(mem.expose_bytes(), [0xAB; 8]);
+    }
+    
+    fn protection_of(mem: &SecureMemory) -> Protection {
+        region::query(mem.region.as_ptr::<u8>()).unwrap().protection()
+    }
+    
+    #[test]
+    fn test_pages_inaccessible_outside_guard() {
+        let mut mem = SecureMemory::from_vec(vec![7; 16]).unwrap();
+        assert_eq!(protection_of(&mem), Protection::NONE);
+        
+        {
+            let first = mem.borrow().unwrap();
+            let second = mem.borrow().unwrap();
+            assert_eq!(protection_of(&mem), Protection::READ);
+            drop(first);
+            
+            // Still readable while another guard is alive
+            assert_eq!(second[0], 7);
+        }
+        assert_eq!(protection_of(&mem), Protection::NONE);
+        
+        mem.borrow_mut().unwrap()[0] = 8;
+        assert_eq!(protection_of(&mem), Protection::NONE);
+    }
+    
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_pages_are_locked() {
+        let mem = SecureMemory::new(32).unwrap();
+        let start = format!("{:x}-", mem.region.as_ptr::<u8>() as usize);
+        
+        // smaps reports how much of each mapping is mlock'ed
+        let smaps = std::fs::read_to_string("/proc/self/smaps").unwrap();
+        let locked_kb: usize = smaps
+            .split_inclusive('\n')
+            .skip_while(|line| !line.starts_with(&start))
+            .find_map(|line| line.strip_prefix("Locked:"))
+            .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+            .expect("mapping should be listed in smaps");
+        
+        assert_eq!(locked_kb * 1024, mem.region.len());
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn test_read_outside_guard_traps() {
+        let mem = SecureMemory::from_vec(vec![7; 16]).unwrap();
+        let ptr = mem.region.as_ptr::<u8>();
+        
+        // The read faults, so do it in a child process and check how it died
+        // SAFETY: the child only performs the read and exits
+        let pid = unsafe { libc::fork() };
+        assert!(pid >= 0, "fork failed");
+        if pid == 0 {
+            unsafe {
+                std::ptr::read_volatile(ptr);
+                libc::_exit(0);
+            }
+        }
+        
+        let mut status = 0;
+        unsafe { libc::waitpid(pid, &mut status, 0) };
+        assert!(libc::WIFSIGNALED(status), "read outside a guard should fault");
+    }
+    
+    #[test]
+    fn test_copy_from_slice_needs_same_length() {
+        let mut mem = SecureMemory::new(4).unwrap();
+        mem.copy_from_slice(&[1, 2, 3, 4]).unwrap();
+        assert_eq!(mem.expose_bytes(), [1, 2, 3, 4]);
+        
+        let err = mem.copy_from_slice(&[9; 5]).unwrap_err();
+        assert!(matches!(err, VaultError::LengthMismatch { expected: 4, actual: 5 }));
+        assert!(mem.copy_from_slice(&[9; 3]).is_err());
+        assert_eq!(mem.expose_bytes(), [1, 2, 3, 4], "a rejected copy writes nothing");
+    }
+    
+    #[test]
+    fn test_resize_shrinks_and_grows() {
+        let mut mem = SecureMemory::from_vec(vec![1, 2, 3, 4, 5, 6]).unwrap();
+        mem.resize(3).unwrap();
+        assert_eq!(mem.len(), 3);
+        assert_eq!(mem.expose_bytes(), [1, 2, 3]);
+        assert_eq!(protection_of(&mem), Protection::NONE);
+        
+        // Past a page, so the new bytes come from fresh pages
+        mem.resize(5000).unwrap();
+        assert_eq!(mem.len(), 5000);
+        let bytes = mem.expose_bytes();
+        assert_eq!(bytes[..3], [1, 2, 3]);
+        assert!(bytes[3..].iter().all(|&b| b == 0));
+        assert_eq!(protection_of(&mem), Protection::NONE);
+    }
+    
+    #[test]
+    fn test_failed_resize_leaves_memory_intact() {
+        let mut mem = SecureMemory::from_vec(vec![7; 16]).unwrap();
+        // More than any address space can map
+        assert!(matches!(mem.resize(usize::MAX / 2), Err(VaultError::MemoryLock(_))));
+        assert_eq!(mem.len(), 16);
+        assert_eq!(mem.expose_bytes(), [7; 16]);
+    }
+    
+    #[test]
+    #[allow(deprecated)]
+    fn test_as_slice_still_works() {
+        let mem = SecureMemory::from_vec(vec![1, 2, 3]).unwrap();
+        assert_eq!(mem.as_slice(), &[1, 2, 3]);
+        
+        // Guards no longer re-protect once the bytes have been exposed
+        drop(mem.borrow().unwrap());
+        assert_eq!(mem.as_slice(), &[1, 2, 3]);
+    }
 }
@@ -0,0 +1,250 @@
+use crate::encrypted::EncryptedKeyStorage;
+use crate::error::{Result, VaultError};
+use crate::keychain::{KeyMetadata, KeyStorage};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use identra_crypto::{generate_random_bytes, EncryptionKey, StreamDecryptor, StreamEncryptor};
+use identra_ipc::{MAX_SECRET_SIZE, SECRET_CHUNK_SIZE};
+use std::sync::Arc;
+
+/// Marks the keychain entries holding a streamed secret's pieces, which
+/// are named `<key_id>#chunk/<generation>/<index>`
+pub const CHUNK_MARKER: &str = "#chunk/";
+
+/// Metadata entries of a streamed secret's own entry
+const STREAM_SIZE: &str = "identra.stream.size";
+const STREAM_CHUNKS: &str = "identra.stream.chunks";
+const STREAM_HEADER: &str = "identra.stream.header";
+const STREAM_GENERATION: &str = "identra.stream.generation";
+
+/// Random bytes naming one upload's pieces, hex-encoded
+const GENERATION_BYTES: usize = 8;
+
+/// True for the entries holding pieces of a streamed secret, which clients
+/// never address directly
+pub fn is_chunk(key_id: &str) -> bool {
+    key_id.contains(CHUNK_MARKER)
+}
+
+/// True for the entry of a secret stored with `Upload`
+pub fn is_stream(metadata: &KeyMetadata) -> bool {
+    metadata.custom.contains_key(STREAM_SIZE)
+}
+
+fn chunk_id(key_id: &str, generation: &str, index: u32) -> String {
+    format!("{}{}{}/{}", key_id, CHUNK_MARKER, generation, index)
+}
+
+/// Delete the pieces stored for `key_id`, except those of `keep`
+pub fn delete_chunks(keychain: &dyn KeyStorage, key_id: &str, keep: Option<&str>) -> Result<usize> {
+    let prefix = format!("{}{}", key_id, CHUNK_MARKER);
+    let kept = keep.map(|generation| format!("{}{}/", prefix, generation));
+    let mut deleted = 0;
+    for chunk in keychain.list_keys()? {
+        if chunk.starts_with(&prefix) && !kept.as_ref().is_some_and(|kept| chunk.starts_with(kept)) {
+            keychain.delete_key(&chunk)?;
+            deleted += 1;
+        }
+    }
+    Ok(deleted)
+}
+
+/// A secret arriving in pieces on one connection
+///
+/// Each piece is sealed with the secret's own random key as it arrives and
+/// stored in an entry of its own, so the whole plaintext is never held.
+/// The secret's entry, holding that key sealed under the master key, is
+/// only written with the last piece; until then an earlier secret under
+/// the same id stays readable. Dropping an unfinished upload deletes the
+/// pieces stored so far.
+pub struct Upload {
+    keychain: Arc<Box<dyn KeyStorage>>,
+    key_id: String,
+    generation: String,
+    metadata: KeyMetadata,
+    data_key: EncryptionKey,
+    // Taken to seal the last piece
+    encryptor: Option<StreamEncryptor>,
+    header: Vec<u8>,
+    total: u64,
+    received: u64,
+    chunks: u32,
+}
+
+impl Upload {
+    pub fn begin(keychain: Arc<Box<dyn KeyStorage>>, key_id: String, total: u64, metadata: KeyMetadata) -> Result<Self> {
+        if total == 0 || total > MAX_SECRET_SIZE {
+            return Err(VaultError::Ipc(format!(
+                "Secret is {} bytes; streamed secrets must be 1 to {} bytes", total, MAX_SECRET_SIZE
+            )));
+        }
+        let generation = generate_random_bytes(GENERATION_BYTES).iter().map(|b| format!("{:02x}", b)).collect();
+        let data_key = EncryptionKey::generate();
+        let encryptor = StreamEncryptor::new(&data_key);
+        let header = encryptor.header().to_vec();
+        Ok(Self {
+            keychain,
+            key_id,
+            generation,
+            metadata,
+            data_key,
+            encryptor: Some(encryptor),
+            header,
+            total,
+            received: 0,
+            chunks: 0,
+        })
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// True once the last piece is in and the secret stored
+    pub fn is_complete(&self) -> bool {
+        self.received == self.total
+    }
+
+    /// Seal and store the next piece, returning the bytes received so far
+    ///
+    /// The last piece also stores the secret's entry, its key sealed under
+    /// `master`, and deletes the pieces of the secret it replaces.
+    pub fn push(&mut self, master: EncryptionKey, data: &[u8]) -> Result<u64> {
+        let remaining = self.total - self.received;
+        if data.is_empty() || data.len() > SECRET_CHUNK_SIZE || data.len() as u64 > remaining {
+            return Err(VaultError::Ipc(format!(
+                "Piece of {} bytes with {} of the secret left; pieces are 1 to {} bytes",
+                data.len(), remaining, SECRET_CHUNK_SIZE
+            )));
+        }
+        let last = data.len() as u64 == remaining;
+        let encryptor = self.encryptor.as_mut().ok_or_else(|| VaultError::Ipc("Secret already stored".to_string()))?;
+        let sealed = if last {
+            self.encryptor.take().expect("checked above").encrypt_last(data)
+        } else {
+            encryptor.encrypt_next(data)
+        }
+        .map_err(|e| VaultError::Encryption(e.to_string()))?;
+
+        let chunk_metadata = KeyMetadata {
+            created_at: self.metadata.created_at,
+            expires_at: self.metadata.expires_at,
+            custom: Default::default(),
+        };
+        self.keychain.store_key(&chunk_id(&self.key_id, &self.generation, self.chunks), &sealed, chunk_metadata)?;
+        self.chunks += 1;
+        // Counted only once stored, so a failed commit still cleans up on drop
+        if last {
+            self.commit(master)?;
+        }
+        self.received += data.len() as u64;
+        Ok(self.received)
+    }
+
+    fn commit(&mut self, master: EncryptionKey) -> Result<()> {
+        let mut metadata = self.metadata.clone();
+        metadata.custom.extend([
+            (STREAM_SIZE.to_string(), self.total.to_string()),
+            (STREAM_CHUNKS.to_string(), self.chunks.to_string()),
+            (STREAM_HEADER.to_string(), STANDARD.encode(&self.header)),
+            (STREAM_GENERATION.to_string(), self.generation.clone()),
+        ]);
+        let keychain: &dyn KeyStorage = self.keychain.as_ref().as_ref();
+        EncryptedKeyStorage::new(keychain, master).store_key(&self.key_id, self.data_key.as_bytes(), metadata)?;
+        if let Err(e) = delete_chunks(keychain, &self.key_id, Some(&self.generation)) {
+            tracing::warn!("Failed to delete replaced pieces of '{}': {}", self.key_id, e);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Upload {
+    fn drop(&mut self) {
+        if self.is_complete() {
+            return;
+        }
+        for index in 0..self.chunks {
+            let _ = self.keychain.delete_key(&chunk_id(&self.key_id, &self.generation, index));
+        }
+    }
+}
+
+/// A streamed secret being read back, one piece at a time
+pub struct Download {
+    key_id: String,
+    generation: String,
+    // Taken to open the last piece
+    decryptor: Option<StreamDecryptor>,
+    total: u64,
+    sent: u64,
+    chunks: u32,
+    next: u32,
+}
+
+impl Download {
+    /// Start reading `key_id`, returning its metadata alongside
+    pub fn open(keychain: &dyn KeyStorage, master: EncryptionKey, key_id: &str) -> Result<(Self, KeyMetadata)> {
+        let (data_key, mut metadata) = EncryptedKeyStorage::new(keychain, master).retrieve_key(key_id)?;
+        if !is_stream(&metadata) {
+            return Err(VaultError::Ipc(format!("'{}' wasn't stored as a stream; use RetrieveKey", key_id)));
+        }
+        let mut field = |name: &str| metadata.custom.remove(name)
+            .ok_or_else(|| VaultError::Encryption(format!("Secret '{}' is missing {}", key_id, name)));
+        let (total, chunks, header, generation) = (
+            field(STREAM_SIZE)?,
+            field(STREAM_CHUNKS)?,
+            field(STREAM_HEADER)?,
+            field(STREAM_GENERATION)?,
+        );
+        let corrupt = |name: &str| VaultError::Encryption(format!("Secret '{}' has an invalid {}", key_id, name));
+        let total = total.parse().map_err(|_| corrupt(STREAM_SIZE))?;
+        let chunks = chunks.parse().map_err(|_| corrupt(STREAM_CHUNKS))?;
+        let header = STANDARD.decode(header).map_err(|_| corrupt(STREAM_HEADER))?;
+
+        let data_key = EncryptionKey::from_bytes(&data_key).map_err(|e| VaultError::Encryption(e.to_string()))?;
+        let decryptor = StreamDecryptor::new(&data_key, &header).map_err(|e| VaultError::Encryption(e.to_string()))?;
+        let download = Self {
+            key_id: key_id.to_string(),
+            generation,
+            decryptor: Some(decryptor),
+            total,
+            sent: 0,
+            chunks,
+            next: 0,
+        };
+        Ok((download, metadata))
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// True once every piece has been read
+    pub fn is_complete(&self) -> bool {
+        self.next == self.chunks
+    }
+
+    /// The next piece, and the bytes read so far including it
+    pub fn next_chunk(&mut self, keychain: &dyn KeyStorage) -> Result<(Vec<u8>, u64)> {
+        if self.is_complete() {
+            return Err(VaultError::Ipc("Secret already read".to_string()));
+        }
+        let (sealed, _) = keychain.retrieve_key(&chunk_id(&self.key_id, &self.generation, self.next))?;
+        let last = self.next + 1 == self.chunks;
+        let decryptor = self.decryptor.as_mut().ok_or_else(|| VaultError::Ipc("Secret already read".to_string()))?;
+        let data = if last {
+            self.decryptor.take().expect("checked above").decrypt_last(&sealed)
+        } else {
+            decryptor.decrypt_next(&sealed)
+        }
+        .map_err(|e| VaultError::Encryption(format!("Failed to decrypt a piece of '{}': {}", self.key_id, e)))?;
+
+        self.next += 1;
+        self.sent += data.len() as u64;
+        if self.sent > self.total || (last && self.sent != self.total) {
+            return Err(VaultError::Encryption(format!(
+                "Secret '{}' is {} bytes, not the {} recorded", self.key_id, self.sent, self.total
+            )));
+        }
+        Ok((data, self.sent))
+    }
+}
@@ -0,0 +1,91 @@
+//! Drives a real daemon process through `vault-cli`
+
+use std::process::{Child, Command, Output, Stdio};
+use std::time::Duration;
+
+/// Daemon on its own socket with in-memory keys, killed on drop
+struct Daemon {
+    child: Child,
+    socket: String,
+}
+
+impl Daemon {
+    fn spawn() -> Self {
+        let socket = format!("/tmp/identra-vault-cli-test-{}.sock", std::process::id());
+        let child = Command::new(env!("CARGO_BIN_EXE_vault-daemon"))
+            .env("IDENTRA_VAULT_SOCKET", &socket)
+            .env("IDENTRA_VAULT_BACKEND", "memory")
+            .env("IDENTRA_VAULT_PASSPHRASE", "correct horse")
+            .env_remove("IDENTRA_VAULT_AUDIT_LOG")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Failed to start vault-daemon");
+        let daemon = Self { child, socket };
+
+        // Startup includes deriving the master key from the passphrase
+        for _ in 0..100 {
+            if daemon.cli(&["ping"]).status.success() {
+                return daemon;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        panic!("vault-daemon did not come up");
+    }
+
+    fn cli(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_vault-cli"))
+            .env("IDENTRA_VAULT_SOCKET", &self.socket)
+            .args(args)
+            .output()
+            .expect("Failed to run vault-cli")
+    }
+
+    fn json(&self, args: &[&str]) -> serde_json::Value {
+        let output = self.cli(&[args, &["--json"]].concat());
+        assert!(output.status.success(), "{:?} failed: {}", args, String::from_utf8_lossy(&output.stderr));
+        serde_json::from_slice(&output.stdout).expect("vault-cli should print JSON")
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_file(identra_ipc::token_path(&self.socket));
+    }
+}
+
+#[test]
+fn test_cli_manages_keys() {
+    let daemon = Daemon::spawn();
+    let file = std::env::temp_dir().join(format!("identra-cli-key-{}", std::process::id()));
+    std::fs::write(&file, b"super secret key bytes").unwrap();
+
+    assert_eq!(daemon.json(&["status"])["locked"], false);
+
+    let stored = daemon.json(&["store", "cli/k", "--file", file.to_str().unwrap(), "--meta", "purpose=test"]);
+    assert_eq!(stored["size"], 22);
+    assert_eq!(daemon.json(&["list"]), serde_json::json!(["cli/k"]));
+    assert_eq!(daemon.json(&["exists", "cli/k"])["exists"], true);
+
+    // `get` shows metadata but never the key itself
+    let output = daemon.cli(&["get", "cli/k"]);
+    assert!(output.status.success());
+    let text = String::from_utf8_lossy(&output.stdout);
+    assert!(text.contains("purpose: test"));
+    assert!(!text.contains("super secret"));
+    let info = daemon.json(&["get", "cli/k"]);
+    assert_eq!(info["metadata"]["purpose"], "test");
+    assert!(!info.to_string().contains("super secret"));
+
+    daemon.json(&["delete", "cli/k"]);
+    assert_eq!(daemon.json(&["exists", "cli/k"])["exists"], false);
+
+    // Failures exit non-zero with the daemon's error on stderr
+    let output = daemon.cli(&["get", "cli/k"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Key 'cli/k' not found"));
+
+    std::fs::remove_file(&file).ok();
+}
@@ -1,12 +1,14 @@
 use crate::state::{NexusState, VaultStatus};
-use identra_crypto::MemoryVault; 
-use tauri::{AppHandle, Manager, State};
+use identra_crypto::{EncryptionKey, Envelope, MemoryVault};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use tauri::{AppHandle, Emitter, Manager, State};
 use std::path::PathBuf;
 use std::fs;
 use aes_gcm::{Aes256Gcm, Key}; // Removed unused KeyInit
 use fastembed::{TextEmbedding, InitOptions, EmbeddingModel};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use std::collections::HashMap;
+use std::time::Duration;
 
 // --- Helper Functions ---
 
@@ -42,8 +44,22 @@ pub struct ChatResponse {
 pub struct SystemStatusResponse {
     pub vault_status: VaultStatus,
     pub active_identity: Option<String>,
+    /// The vault daemon answered a ping
     pub enclave_connection: bool,
+    /// The gateway answered its health check as serving
+    pub gateway_connection: bool,
     pub security_level: String,
+    /// `None` when the gateway is unreachable
+    pub memory_stats: Option<MemoryStatsSummary>,
+}
+
+#[derive(serde::Serialize)]
+pub struct MemoryStatsSummary {
+    pub total_count: i64,
+    pub top_tags: Vec<(String, i64)>,
+    pub oldest_created_at: Option<i64>,
+    pub newest_created_at: Option<i64>,
+    pub total_bytes: i64,
 }
 
 #[derive(serde::Serialize)]
@@ -70,19 +86,116 @@ impl AIState {
 
 // --- System Commands ---
 
+/// How long each status probe may take before its peer counts as down
+const STATUS_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Ping the daemon and read its lock state; `None` if it's unreachable
+async fn probe_daemon() -> Option<bool> {
+    let probe = async {
+        let mut client = crate::ipc_client::VaultClient::connect().await.ok()?;
+        client.ping().await.ok()?;
+        client.is_locked().await.ok()
+    };
+    tokio::time::timeout(STATUS_PROBE_TIMEOUT, probe).await.ok().flatten()
+}
+
+/// Gateway client making memory calls as the signed-in user
+///
+/// Every memory RPC needs a bearer token, so this fails rather than
+/// connecting anonymously when `login_user` hasn't run.
+async fn signed_in_gateway(state: &NexusState) -> Result<crate::grpc_client::GrpcClient, String> {
+    let session = state.session().clone()
+        .ok_or_else(|| "NOT_SIGNED_IN: log in to reach stored memories".to_string())?;
+    let client = crate::grpc_client::GrpcClient::connect()
+        .await
+        .map_err(|e| format!("Failed to connect to gateway: {}", e))?;
+    Ok(client.with_session(session))
+}
+
+/// Whether the gateway is up and serving
+async fn probe_gateway() -> bool {
+    let probe = async {
+        let mut client = crate::grpc_client::GrpcClient::connect().await.ok()?;
+        client.health_check().await.ok()
+    };
+    matches!(tokio::time::timeout(STATUS_PROBE_TIMEOUT, probe).await, Ok(Some(true)))
+}
+
+/// MAXIMUM only while the vault is unlocked and the gateway link uses TLS
+fn security_level(status: &VaultStatus, gateway_encrypted: bool) -> &'static str {
+    match status {
+        VaultStatus::Offline => "OFFLINE",
+        VaultStatus::Locked => "LOCKED",
+        VaultStatus::Unlocked | VaultStatus::Syncing if gateway_encrypted => "MAXIMUM",
+        VaultStatus::Unlocked | VaultStatus::Syncing => "STANDARD",
+    }
+}
+
 #[tauri::command]
 pub async fn get_system_status(state: State<'_, NexusState>) -> Result<SystemStatusResponse, String> {
-    let status = state.status.lock().map_err(|_| "State poisoned")?.clone();
-    let identity = state.active_identity.lock().map_err(|_| "Identity poisoned")?.clone();
+    // Each peer is probed on its own so one being down doesn't hide the other
+    let (daemon_locked, gateway_up) = tokio::join!(probe_daemon(), probe_gateway());
+
+    // The daemon owns the lock state; fall back to Offline if it's unreachable
+    let status = match daemon_locked {
+        Some(true) => VaultStatus::Locked,
+        Some(false) => VaultStatus::Unlocked,
+        None => VaultStatus::Offline,
+    };
+    *state.status() = status.clone();
+    let identity = state.active_identity().clone();
+    let gateway_encrypted = crate::grpc_client::gateway_address().starts_with("https://");
     
     Ok(SystemStatusResponse {
+        security_level: security_level(&status, gateway_encrypted).to_string(),
         vault_status: status,
         active_identity: identity,
-        enclave_connection: true, 
-        security_level: "MAXIMUM".to_string(),
+        enclave_connection: daemon_locked.is_some(),
+        gateway_connection: gateway_up,
+        memory_stats: if gateway_up {
+            tokio::time::timeout(STATUS_PROBE_TIMEOUT, fetch_memory_stats(&state)).await.ok().flatten()
+        } else {
+            None
+        },
+    })
+}
+
+/// Dashboard totals from the gateway; status is still reported without
+/// them, e.g. before signing in
+async fn fetch_memory_stats(state: &NexusState) -> Option<MemoryStatsSummary> {
+    let mut client = signed_in_gateway(state).await.ok()?;
+    let stats = client.get_memory_stats(5).await.ok()?;
+    Some(MemoryStatsSummary {
+        total_count: stats.total_count,
+        top_tags: stats.top_tags.into_iter().map(|t| (t.tag, t.count)).collect(),
+        oldest_created_at: stats.oldest_created_at.map(|t| t.seconds),
+        newest_created_at: stats.newest_created_at.map(|t| t.seconds),
+        total_bytes: stats.total_bytes,
     })
 }
 
+#[tauri::command]
+pub async fn unlock_vault(state: State<'_, NexusState>, passphrase: String) -> Result<String, String> {
+    let mut client = crate::ipc_client::VaultClient::connect()
+        .await
+        .map_err(|e| format!("Vault daemon not available: {}", e))?;
+    client.unlock(passphrase).await.map_err(|e| e.to_string())?;
+    
+    *state.status() = VaultStatus::Unlocked;
+    Ok("Vault Unlocked".to_string())
+}
+
+#[tauri::command]
+pub async fn lock_vault(state: State<'_, NexusState>) -> Result<String, String> {
+    let mut client = crate::ipc_client::VaultClient::connect()
+        .await
+        .map_err(|e| format!("Vault daemon not available: {}", e))?;
+    client.lock().await.map_err(|e| e.to_string())?;
+    
+    *state.status() = VaultStatus::Locked;
+    Ok("Vault Locked".to_string())
+}
+
 #[tauri::command]
 pub async fn toggle_launcher(app: AppHandle) -> Result<(), String> {
     let launcher = app.get_webview_window("launcher").ok_or("Launcher window not found")?;
@@ -109,6 +222,143 @@ pub async fn toggle_main_window(app: AppHandle) -> Result<(), String> {
 
 // --- Security & Vault Commands ---
 
+/// Identity whose key is used while none has been selected
+const DEFAULT_IDENTITY: &str = "default";
+
+/// Suffix of the vault key ids holding identity memory keys
+const MEMORY_KEY_SUFFIX: &str = "/memory-key";
+
+/// Event emitted with the new identity id after `set_active_identity`
+const IDENTITY_CHANGED_EVENT: &str = "identity-changed";
+
+/// Held while a memory key is created; see `memory_key`
+static MEMORY_KEY_CREATION: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Vault daemon key id of an identity's memory encryption key
+fn memory_key_id(identity: &str) -> String {
+    format!("{}{}", identity, MEMORY_KEY_SUFFIX)
+}
+
+fn active_identity(state: &State<'_, NexusState>) -> Result<String, String> {
+    let identity = state.active_identity();
+    Ok(identity.clone().unwrap_or_else(|| DEFAULT_IDENTITY.to_string()))
+}
+
+/// Fetch `identity`'s memory key from the vault daemon, if it has one
+async fn existing_memory_key(
+    client: &mut crate::ipc_client::VaultClient,
+    identity: &str,
+) -> Result<Option<EncryptionKey>, String> {
+    let key_id = memory_key_id(identity);
+    if !client.key_exists(key_id.clone()).await.map_err(|e| e.to_string())? {
+        return Ok(None);
+    }
+    let (key_data, _, _, _) = client.retrieve_key(key_id).await.map_err(|e| e.to_string())?;
+    EncryptionKey::from_bytes(&key_data)
+        .map(Some)
+        .map_err(|e| format!("Crypto Error: {}", e))
+}
+
+/// Fetch `identity`'s memory key from the vault daemon, creating it on first use
+///
+/// `StoreKey` replaces whatever is stored, so two first uses racing to
+/// create the key would each seal under their own and one memory would be
+/// lost with the overwritten key. Creation is serialized instead, checked
+/// again once inside, and the key is read back rather than trusted from
+/// memory, so every caller seals under the key the vault actually holds.
+async fn memory_key(identity: &str) -> Result<EncryptionKey, String> {
+    let mut client = crate::ipc_client::VaultClient::connect()
+        .await
+        .map_err(|e| format!("Vault daemon not available: {}", e))?;
+    if let Some(key) = existing_memory_key(&mut client, identity).await? {
+        return Ok(key);
+    }
+
+    let _creating = MEMORY_KEY_CREATION.lock().await;
+    if let Some(key) = existing_memory_key(&mut client, identity).await? {
+        return Ok(key);
+    }
+    let key_id = memory_key_id(identity);
+    let metadata = HashMap::from([("purpose".to_string(), "memory-encryption".to_string())]);
+    client.store_key(key_id, EncryptionKey::generate().as_bytes().to_vec(), metadata, None)
+        .await
+        .map_err(|e| e.to_string())?;
+    existing_memory_key(&mut client, identity)
+        .await?
+        .ok_or_else(|| format!("Memory key for {} missing right after storing it", identity))
+}
+
+/// `content` sealed in an envelope under `key`, base64-encoded for the gateway
+fn seal_memory(key: &EncryptionKey, content: &str) -> Result<String, String> {
+    Envelope::seal(key, content.as_bytes())
+        .map(|sealed| BASE64.encode(sealed))
+        .map_err(|e| format!("Crypto Error: {}", e))
+}
+
+/// The content `seal_memory` sealed under `key`
+fn open_memory(key: &EncryptionKey, encoded: &str) -> Result<String, String> {
+    let sealed = BASE64.decode(encoded).map_err(|e| format!("Not an envelope: {}", e))?;
+    let plaintext = Envelope::open(key, &sealed).map_err(|e| format!("Crypto Error: {}", e))?;
+    String::from_utf8(plaintext).map_err(|e| format!("Decryption Failed: {}", e))
+}
+
+/// Open a `vault_memory` envelope with `identity`'s key from the daemon
+async fn open_with_memory_key(identity: &str, encoded: &str) -> Result<String, String> {
+    let mut client = crate::ipc_client::VaultClient::connect()
+        .await
+        .map_err(|e| format!("Vault daemon not available: {}", e))?;
+    let key = existing_memory_key(&mut client, identity)
+        .await?
+        .ok_or_else(|| format!("No memory key for identity {}", identity))?;
+    open_memory(&key, encoded)
+}
+
+// --- Identity Commands ---
+
+/// Identities with a memory key in the vault, sorted by id
+#[tauri::command]
+pub async fn list_identities() -> Result<Vec<String>, String> {
+    let mut client = crate::ipc_client::VaultClient::connect()
+        .await
+        .map_err(|e| format!("Vault daemon not available: {}", e))?;
+    let keys = client.list_keys().await.map_err(|e| e.to_string())?;
+
+    let mut identities: Vec<String> = keys.iter()
+        .filter_map(|key| key.strip_suffix(MEMORY_KEY_SUFFIX))
+        .filter(|identity| !identity.is_empty())
+        .map(str::to_string)
+        .collect();
+    identities.sort();
+    Ok(identities)
+}
+
+/// Switch to `id`, which must already have a memory key in the vault
+#[tauri::command]
+pub async fn set_active_identity(
+    app: AppHandle,
+    state: State<'_, NexusState>,
+    id: String,
+) -> Result<String, String> {
+    if id.trim().is_empty() || id.contains('/') {
+        return Err("Invalid identity id".to_string());
+    }
+
+    let mut client = crate::ipc_client::VaultClient::connect()
+        .await
+        .map_err(|e| format!("Vault daemon not available: {}", e))?;
+    let exists = client.key_exists(memory_key_id(&id)).await.map_err(|e| e.to_string())?;
+    if !exists {
+        return Err(format!("IDENTITY_NOT_FOUND: no key for identity {}", id));
+    }
+
+    *state.active_identity() = Some(id.clone());
+    state.persist();
+    app.emit(IDENTITY_CHANGED_EVENT, &id).map_err(|e| e.to_string())?;
+
+    println!("[NEXUS] Active identity: {}", id);
+    Ok(id)
+}
+
 fn get_session_key_path() -> PathBuf {
     let mut path = std::env::temp_dir();
     path.push("identra_session_key.bin");
@@ -137,8 +387,8 @@ pub async fn initialize_session(state: State<'_, NexusState>) -> Result<String,
         new_key
     };
 
-    *state.session_key.lock().map_err(|_| "Key poisoned")? = Some(key);
-    *state.status.lock().map_err(|_| "Status poisoned")? = VaultStatus::Unlocked;
+    *state.session_key() = Some(key);
+    *state.status() = VaultStatus::Unlocked;
 
     println!("[NEXUS] Session Initialized. Vault UNLOCKED.");
     Ok("Vault Unlocked".to_string())
@@ -148,46 +398,48 @@ pub async fn initialize_session(state: State<'_, NexusState>) -> Result<String,
 pub async fn vault_memory(state: State<'_, NexusState>, content: String) -> Result<String, String> {
     if content.trim().is_empty() { return Err("Payload empty.".to_string()); }
 
-    let session_key = {
-        let key_guard = state.session_key.lock().map_err(|_| "Key poisoned")?;
-        match key_guard.as_ref() {
-            Some(k) => k.clone(),
-            None => return Err("VAULT_LOCKED: Please initialize session first.".to_string()),
-        }
-    };
-
-    // Encrypt content
-    let encrypted_blob = MemoryVault::lock(&content, &session_key)
-        .map_err(|e| format!("Crypto Error: {}", e))?;
+    // Seal under the identity's key from the daemon, which must be unlocked
+    let identity = active_identity(&state)?;
+    let key = memory_key(&identity).await?;
+    let sealed = seal_memory(&key, &content)?;
+    let ciphertext_len = Envelope::sealed_len(content.len());
 
     // Store in DB
-    let mut client = crate::grpc_client::GrpcClient::connect()
-        .await
-        .map_err(|e| format!("Failed to connect to gateway: {}", e))?;
+    let mut client = signed_in_gateway(&state).await?;
     
-    let metadata = std::collections::HashMap::from([
+    let metadata = HashMap::from([
         ("encrypted".to_string(), "true".to_string()),
+        ("key_id".to_string(), memory_key_id(&identity)),
         ("timestamp".to_string(), chrono::Utc::now().to_rfc3339()),
     ]);
     
     let memory_id = client
-        .store_memory(encrypted_blob.clone(), metadata, vec![])
+        .store_memory(sealed, metadata, vec![])
         .await
         .map_err(|e| format!("Failed to store memory: {}", e))?;
 
-    // Update metrics
+    // Update metrics; saturating, as a panic here would poison the lock
     {
-        let mut metrics = state.metrics.lock().map_err(|_| "Metrics poisoned")?;
-        metrics.memory_encrypted += content.len();
+        let mut metrics = state.metrics();
+        metrics.memory_encrypted = metrics.memory_encrypted.saturating_add(ciphertext_len);
     }
+    state.persist();
     
     println!("[NEXUS] Vaulted (ID: {})", memory_id);
-    Ok(format!("Stored successfully (ID: {})", memory_id))
+    Ok(memory_id)
 }
 
 #[tauri::command]
 pub async fn decrypt_memory(state: State<'_, NexusState>, encrypted_val: String) -> Result<String, String> {
-    let key_guard = state.session_key.lock().map_err(|_| "Key poisoned")?;
+    // Envelopes from `vault_memory` first; chat history is still sealed
+    // with the session key
+    let identity = active_identity(&state)?;
+    match open_with_memory_key(&identity, &encrypted_val).await {
+        Ok(plaintext) => return Ok(plaintext),
+        Err(e) => eprintln!("[NEXUS] Memory key of {} didn't open it, trying the session key: {}", identity, e),
+    }
+
+    let key_guard = state.session_key();
     let session_key = match &*key_guard {
         Some(k) => k,
         None => return Err("VAULT_LOCKED".to_string()),
@@ -261,7 +513,7 @@ async fn store_chat_interaction(
     model: &str,
 ) -> Result<(), String> {
     let session_key = {
-        let key_guard = state.session_key.lock().map_err(|_| "Key poisoned")?;
+        let key_guard = state.session_key();
         match key_guard.as_ref() {
             Some(k) => k.clone(),
             None => return Ok(()), // Skip storage if vault is locked
@@ -283,9 +535,7 @@ async fn store_chat_interaction(
     let encrypted_blob = MemoryVault::lock(&conversation_str, &session_key)
         .map_err(|e| format!("Encryption error: {}", e))?;
 
-    let mut client = crate::grpc_client::GrpcClient::connect()
-        .await
-        .map_err(|e| format!("Failed to connect: {}", e))?;
+    let mut client = signed_in_gateway(state).await?;
 
     let metadata = HashMap::from([
         ("type".to_string(), "conversation".to_string()),
@@ -455,10 +705,8 @@ async fn call_gemini_api(
 }
 
 #[tauri::command]
-pub async fn query_history(limit: i32) -> Result<Vec<ConversationItem>, String> {
-    let mut client = crate::grpc_client::GrpcClient::connect()
-        .await
-        .map_err(|e| format!("Failed to connect: {}", e))?;
+pub async fn query_history(state: State<'_, NexusState>, limit: i32) -> Result<Vec<ConversationItem>, String> {
+    let mut client = signed_in_gateway(&state).await?;
     
     // Legacy query: empty string matches everything via ILIKE %%
     let memories = client
@@ -476,14 +724,16 @@ pub async fn query_history(limit: i32) -> Result<Vec<ConversationItem>, String>
 // --- Auth Commands ---
 
 #[tauri::command]
-pub async fn login_user(username: String, password: String) -> Result<String, String> {
+pub async fn login_user(state: State<'_, NexusState>, username: String, password: String) -> Result<String, String> {
     let mut client = crate::grpc_client::GrpcClient::connect()
         .await
         .map_err(|e| format!("Connection failed: {}", e))?;
 
-    let token = client.login(username, password)
+    let session = client.login(username, password)
         .await
         .map_err(|e| e.to_string())?;
+    let token = session.access_token.clone();
+    *state.session() = Some(Arc::new(client.authenticated(session)));
 
     println!("[AUTH] Login successful");
     Ok(token)
@@ -507,6 +757,7 @@ pub async fn register_user(username: String, email: String, password: String) ->
 
 #[tauri::command]
 pub async fn semantic_search(
+    state: State<'_, NexusState>,
     ai_state: State<'_, AIState>,
     query: String
 ) -> Result<Vec<ConversationItem>, String> {
@@ -514,15 +765,23 @@ pub async fn semantic_search(
     let embedding = {
         // FIX: model must be mutable for .embed()
         let mut model = ai_state.model.lock().map_err(|_| "AI Busy")?;
-        let documents = vec![query];
+        let documents = vec![query.clone()];
         let embeddings = model.embed(documents, None).map_err(|e| e.to_string())?;
         embeddings[0].clone()
     };
 
     // 2. Send to Backend
-    let mut client = crate::grpc_client::GrpcClient::connect()
-        .await
-        .map_err(|e| format!("Connection failed: {}", e))?;
+    let mut client = signed_in_gateway(&state).await?;
+
+    // A gateway embedding with another model can't compare our vectors; match text instead
+    if !client.accepts_query_embedding(embedding.len()) {
+        let memories = client.query_memories(query, 5)
+            .await
+            .map_err(|e| format!("Search failed: {}", e))?;
+        return Ok(memories.into_iter()
+            .map(|(id, content, timestamp)| ConversationItem { id, content, timestamp })
+            .collect());
+    }
 
     let results = client.search_memories(embedding, 5, 0.5)
         .await
@@ -541,10 +800,8 @@ pub async fn semantic_search(
 }
 
 #[tauri::command]
-pub async fn fetch_history() -> Result<Vec<ConversationItem>, String> {
-    let mut client = crate::grpc_client::GrpcClient::connect()
-        .await
-        .map_err(|e| format!("Connection failed: {}", e))?;
+pub async fn fetch_history(state: State<'_, NexusState>) -> Result<Vec<ConversationItem>, String> {
+    let mut client = signed_in_gateway(&state).await?;
 
     let memories = client.get_recent_memories(50)
         .await
@@ -559,4 +816,25 @@ pub async fn fetch_history() -> Result<Vec<ConversationItem>, String> {
     }).collect();
 
     Ok(items)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sealed_memory_opens_only_with_its_key() {
+        let key = EncryptionKey::generate();
+        let sealed = seal_memory(&key, "met Dana at the station").unwrap();
+        assert_ne!(sealed, seal_memory(&key, "met Dana at the station").unwrap(), "fresh nonce per seal");
+        assert_eq!(open_memory(&key, &sealed).unwrap(), "met Dana at the station");
+        assert_eq!(BASE64.decode(&sealed).unwrap().len(), Envelope::sealed_len("met Dana at the station".len()));
+
+        assert!(open_memory(&EncryptionKey::generate(), &sealed).is_err());
+        // Chat history sealed with the session key isn't an envelope
+        let session_key = MemoryVault::generate_key();
+        let chat = MemoryVault::lock("hello", &session_key).unwrap();
+        assert!(open_memory(&key, &chat).is_err());
+        assert_eq!(MemoryVault::open(&chat, &session_key).unwrap(), "hello");
+    }
+}
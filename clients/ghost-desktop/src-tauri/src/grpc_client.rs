@@ -2,34 +2,111 @@ use identra_proto::memory::{
     memory_service_client::MemoryServiceClient,
     StoreMemoryRequest, QueryMemoriesRequest, 
     SearchMemoriesRequest, GetRecentMemoriesRequest,
+    GetMemoryStatsRequest, GetMemoryStatsResponse,
+};
+use identra_proto::health::{
+    health_client::HealthClient,
+    health_check_response::ServingStatus,
+    Capabilities, GetCapabilitiesRequest, HealthCheckRequest,
 };
 use identra_proto::auth::{
     auth_service_client::AuthServiceClient,
     LoginRequest, RegisterRequest,
 };
+use identra_proto::client::{AuthInterceptor, AuthenticatedClient, Session};
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::service::interceptor::InterceptedService;
 use tonic::transport::Channel;
 
 pub struct GrpcClient {
-    memory_client: MemoryServiceClient<Channel>,
+    channel: Channel,
+    /// Set by `with_session`; memory calls fail until then
+    session: Option<Arc<AuthenticatedClient>>,
     auth_client: AuthServiceClient<Channel>,
+    health_client: HealthClient<Channel>,
+    /// `None` when the gateway predates `GetCapabilities`
+    capabilities: Option<Capabilities>,
+}
+
+/// Gateway URL from `GATEWAY_ADDRESS`
+pub fn gateway_address() -> String {
+    std::env::var("GATEWAY_ADDRESS")
+        .unwrap_or_else(|_| "http://[::1]:50051".to_string())
 }
 
 impl GrpcClient {
     pub async fn connect() -> Result<Self, Box<dyn std::error::Error>> {
         // Connect to the Gateway
-        let gateway_addr = std::env::var("GATEWAY_ADDRESS")
-            .unwrap_or_else(|_| "http://[::1]:50051".to_string());
-        
-        let channel = Channel::from_shared(gateway_addr)?
+        let channel = Channel::from_shared(gateway_address())?
             .connect()
             .await?;
         
+        let mut health_client = HealthClient::new(channel.clone());
+        let capabilities = match health_client.get_capabilities(GetCapabilitiesRequest {}).await {
+            Ok(response) => Some(response.into_inner()),
+            Err(status) if status.code() == tonic::Code::Unimplemented => None,
+            Err(status) => return Err(status.into()),
+        };
+        if let Some(capabilities) = &capabilities {
+            if capabilities.api_version != identra_proto::API_VERSION {
+                println!(
+                    "[GATEWAY] API version {} differs from this client's {}",
+                    capabilities.api_version,
+                    identra_proto::API_VERSION
+                );
+            }
+        }
+        
         Ok(Self { 
-            memory_client: MemoryServiceClient::new(channel.clone()),
-            auth_client: AuthServiceClient::new(channel),
+            auth_client: AuthServiceClient::new(channel.clone()),
+            channel,
+            session: None,
+            health_client,
+            capabilities,
         })
     }
+
+    /// Keep `session` from `login` signed in over this connection, its
+    /// access token refreshed shortly before it expires
+    pub fn authenticated(&self, session: Session) -> AuthenticatedClient {
+        AuthenticatedClient::new(self.channel.clone(), session)
+    }
+
+    /// Make memory calls as the user `session` belongs to
+    pub fn with_session(mut self, session: Arc<AuthenticatedClient>) -> Self {
+        self.session = Some(session);
+        self
+    }
+
+    /// Memory client holding a fresh token for the session
+    async fn memory_client(
+        &self,
+    ) -> Result<MemoryServiceClient<InterceptedService<Channel, AuthInterceptor>>, Box<dyn std::error::Error>> {
+        let session = self.session.as_ref().ok_or("Not signed in")?;
+        Ok(session.memory().await?)
+    }
+
+    /// What the gateway reported on connect, if it could
+    pub fn capabilities(&self) -> Option<&Capabilities> {
+        self.capabilities.as_ref()
+    }
+
+    /// False when the gateway said its query vectors aren't `dimension` long
+    ///
+    /// Gateways that can't say are assumed to match, as before.
+    pub fn accepts_query_embedding(&self, dimension: usize) -> bool {
+        self.capabilities.as_ref().map_or(true, |c| c.embedding_dimension as usize == dimension)
+    }
+
+    /// True when the gateway reports itself as serving
+    pub async fn health_check(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        let request = tonic::Request::new(HealthCheckRequest::default());
+
+        let response = self.health_client.check(request).await?;
+        Ok(response.into_inner().status() == ServingStatus::Serving)
+    }
     
     // --- MEMORY METHODS ---
 
@@ -43,9 +120,11 @@ impl GrpcClient {
             content,
             metadata,
             tags,
+            ..Default::default()
         });
         
-        let response = self.memory_client.store_memory(request).await?;
+        let mut memory_client = self.memory_client().await?;
+        let response = memory_client.store_memory(request).await?;
         let resp = response.into_inner();
         
         if resp.success {
@@ -63,10 +142,11 @@ impl GrpcClient {
         let request = tonic::Request::new(QueryMemoriesRequest {
             query,
             limit,
-            filters: HashMap::new(),
+            ..Default::default()
         });
         
-        let response = self.memory_client.query_memories(request).await?;
+        let mut memory_client = self.memory_client().await?;
+        let response = memory_client.query_memories(request).await?;
         let memories = response.into_inner().memories;
         
         let result = memories.into_iter()
@@ -89,10 +169,11 @@ impl GrpcClient {
             query_embedding,
             limit,
             similarity_threshold,
-            filters: std::collections::HashMap::new(),
+            ..Default::default()
         });
 
-        let response = self.memory_client.search_memories(request).await?;
+        let mut memory_client = self.memory_client().await?;
+        let response = memory_client.search_memories(request).await?;
         let matches = response.into_inner().matches;
 
         let result = matches.into_iter()
@@ -113,7 +194,8 @@ impl GrpcClient {
             limit,
         });
 
-        let response = self.memory_client.get_recent_memories(request).await?;
+        let mut memory_client = self.memory_client().await?;
+        let response = memory_client.get_recent_memories(request).await?;
         let memories = response.into_inner().memories;
 
         let result = memories.into_iter()
@@ -126,9 +208,20 @@ impl GrpcClient {
         Ok(result)
     }
 
+    pub async fn get_memory_stats(
+        &mut self,
+        top_tags: i32,
+    ) -> Result<GetMemoryStatsResponse, Box<dyn std::error::Error>> {
+        let request = tonic::Request::new(GetMemoryStatsRequest { top_tags });
+
+        let mut memory_client = self.memory_client().await?;
+        let response = memory_client.get_memory_stats(request).await?;
+        Ok(response.into_inner())
+    }
+
     // --- AUTH METHODS ---
 
-    pub async fn login(&mut self, username: String, password: String) -> Result<String, Box<dyn std::error::Error>> {
+    pub async fn login(&mut self, username: String, password: String) -> Result<Session, Box<dyn std::error::Error>> {
         let request = tonic::Request::new(LoginRequest {
             username,
             password,
@@ -138,7 +231,11 @@ impl GrpcClient {
         let resp = response.into_inner();
 
         if resp.success {
-            Ok(resp.access_token)
+            Ok(Session {
+                access_token: resp.access_token,
+                refresh_token: resp.refresh_token,
+                expires_in: Duration::from_secs(resp.expires_in.max(0) as u64),
+            })
         } else {
             Err(format!("Login failed: {}", resp.message).into())
         }
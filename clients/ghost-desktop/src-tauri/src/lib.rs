@@ -3,6 +3,8 @@ pub mod grpc_client;
 pub mod ipc_client;
 pub mod state;
 
+use tauri::Manager;
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     // Load environment variables from .env file
@@ -13,7 +15,12 @@ pub fn run() {
         .plugin(tauri_plugin_shell::init())
         // Initialize AI & State Management
         .manage(commands::AIState::new())
-        .manage(state::NexusState::new())
+        .setup(|app| {
+            // Restore the identity and counters saved by the last run
+            let path = app.path().app_data_dir()?.join(state::STATE_FILE);
+            app.manage(state::NexusState::load(&path));
+            Ok(())
+        })
         // Register Commands
         .invoke_handler(tauri::generate_handler![
             // --- System ---
@@ -23,8 +30,12 @@ pub fn run() {
             
             // --- Auth & Session ---
             commands::initialize_session,
+            commands::unlock_vault,
+            commands::lock_vault,
             commands::login_user,
             commands::register_user,
+            commands::list_identities,
+            commands::set_active_identity,
             
             // --- Memory & Intelligence ---
             commands::vault_memory,     // Store
@@ -1,5 +1,10 @@
-use std::sync::Mutex;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
 use aes_gcm::{Key, Aes256Gcm};
+use identra_proto::client::AuthenticatedClient;
+
+/// File in the app data dir holding the persisted part of `NexusState`
+pub const STATE_FILE: &str = "nexus_state.json";
 
 #[derive(Debug, Clone, serde::Serialize, PartialEq)]
 pub enum VaultStatus {
@@ -9,20 +14,39 @@ pub enum VaultStatus {
     Offline,
 }
 
+/// Shared by every command; reach the fields through the accessors, which
+/// survive a command having panicked while holding one of the locks
 pub struct NexusState {
-    pub status: Mutex<VaultStatus>,
-    pub active_identity: Mutex<Option<String>>,
-    pub metrics: Mutex<VaultMetrics>,
+    status: Mutex<VaultStatus>,
+    active_identity: Mutex<Option<String>>,
+    metrics: Mutex<VaultMetrics>,
     // This holds the session key in RAM
-    pub session_key: Mutex<Option<Key<Aes256Gcm>>>, 
+    session_key: Mutex<Option<Key<Aes256Gcm>>>,
+    // Gateway session from the last login, also RAM only
+    session: Mutex<Option<Arc<AuthenticatedClient>>>,
+    // Where `persist` writes; None keeps the state in memory only
+    path: Option<PathBuf>,
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
 pub struct VaultMetrics {
+    #[serde(default)]
     pub memory_encrypted: usize,
+    // Live count, meaningless after a restart
+    #[serde(skip)]
     pub active_tunnels: u32,
 }
 
+/// What survives a restart: identifiers and counters, never secrets.
+/// Vault status is re-read from the daemon instead.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct PersistedState {
+    #[serde(default)]
+    active_identity: Option<String>,
+    #[serde(default)]
+    metrics: VaultMetrics,
+}
+
 impl NexusState {
     pub fn new() -> Self {
         Self {
@@ -30,6 +54,116 @@ impl NexusState {
             active_identity: Mutex::new(None),
             metrics: Mutex::new(VaultMetrics::default()),
             session_key: Mutex::new(None),
+            session: Mutex::new(None),
+            path: None,
         }
     }
-}
\ No newline at end of file
+
+    /// State saved at `path` by an earlier run; defaults if it's missing or corrupt
+    pub fn load(path: &Path) -> Self {
+        let persisted = match std::fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                eprintln!("[NEXUS] Ignoring corrupt state file {}: {}", path.display(), e);
+                PersistedState::default()
+            }),
+            Err(_) => PersistedState::default(),
+        };
+
+        Self {
+            active_identity: Mutex::new(persisted.active_identity),
+            metrics: Mutex::new(persisted.metrics),
+            path: Some(path.to_path_buf()),
+            ..Self::new()
+        }
+    }
+
+    pub fn status(&self) -> MutexGuard<'_, VaultStatus> {
+        lock(&self.status)
+    }
+
+    pub fn active_identity(&self) -> MutexGuard<'_, Option<String>> {
+        lock(&self.active_identity)
+    }
+
+    pub fn metrics(&self) -> MutexGuard<'_, VaultMetrics> {
+        lock(&self.metrics)
+    }
+
+    pub fn session_key(&self) -> MutexGuard<'_, Option<Key<Aes256Gcm>>> {
+        lock(&self.session_key)
+    }
+
+    /// Signs the gateway's memory calls in, refreshing its token as it
+    /// goes; `None` until `login_user`
+    pub fn session(&self) -> MutexGuard<'_, Option<Arc<AuthenticatedClient>>> {
+        lock(&self.session)
+    }
+
+    /// Write the non-secret state to `path`, replacing it atomically
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let persisted = PersistedState {
+            active_identity: self.active_identity().clone(),
+            metrics: self.metrics().clone(),
+        };
+        let json = serde_json::to_vec_pretty(&persisted)?;
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, json)?;
+        std::fs::rename(&tmp, path)
+    }
+
+    /// Save to the path this state was loaded from, logging failures;
+    /// call after changing the identity or metrics
+    pub fn persist(&self) {
+        if let Some(path) = &self.path {
+            if let Err(e) = self.save(path) {
+                eprintln!("[NEXUS] Failed to save state to {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// Lock `mutex` even if a panic poisoned it
+///
+/// Each field is replaced whole or bumped in one step, so a holder that
+/// panicked can't have left it half-written; refusing it forever would
+/// only disable the commands that read it.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| {
+        eprintln!("[NEXUS] Recovering state lock poisoned by an earlier panic");
+        mutex.clear_poison();
+        e.into_inner()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_poisoned_lock_recovers() {
+        let state = NexusState::new();
+        *state.active_identity() = Some("alice".to_string());
+
+        let poisoner = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _guard = state.metrics.lock().unwrap();
+            panic!("command failed while holding the lock");
+        }));
+        assert!(poisoner.is_err());
+        assert!(state.metrics.is_poisoned());
+
+        state.metrics().memory_encrypted += 10;
+        assert!(!state.metrics.is_poisoned());
+        assert_eq!(state.metrics().memory_encrypted, 10);
+        assert_eq!(state.active_identity().as_deref(), Some("alice"));
+
+        let path = std::env::temp_dir().join(format!("nexus-state-poison-{}.json", std::process::id()));
+        state.save(&path).unwrap();
+        let loaded = NexusState::load(&path);
+        std::fs::remove_file(&path).ok();
+        assert_eq!(loaded.metrics().memory_encrypted, 10);
+    }
+}
@@ -49,12 +49,12 @@ export default function Launcher() {
     setIsProcessing(true);
 
     try {
-      const response = await invoke("vault_memory", { content: userMessage.content });
+      const memoryId = await invoke("vault_memory", { content: userMessage.content });
       
       const assistantMessage = {
         id: Date.now() + 1,
         role: "assistant",
-        content: response
+        content: `Stored successfully (ID: ${memoryId})`
       };
 
       setMessages(prev => [...prev, assistantMessage]);
@@ -0,0 +1,38 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use identra_crypto::{encrypt, Cipher, EncryptionKey, Nonce};
+
+/// Messages sealed per iteration, about a batch of stored memories
+const MESSAGES: usize = 1_000;
+
+/// Sealing many small messages under one key, keying a `Cipher` once versus
+/// the one-shot `encrypt` per message
+fn seal_many(c: &mut Criterion) {
+    let key = EncryptionKey::generate();
+    let nonce = Nonce::generate();
+    let mut group = c.benchmark_group("seal_many");
+
+    for size in [64, 256, 1024] {
+        let plaintext = vec![0x5Au8; size];
+        group.throughput(Throughput::Bytes((size * MESSAGES) as u64));
+
+        group.bench_with_input(BenchmarkId::new("one_shot", size), &plaintext, |b, plaintext| {
+            b.iter(|| {
+                for _ in 0..MESSAGES {
+                    encrypt(&key, &nonce, plaintext).unwrap();
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("cipher", size), &plaintext, |b, plaintext| {
+            b.iter(|| {
+                let cipher = Cipher::new(&key);
+                for _ in 0..MESSAGES {
+                    cipher.encrypt(&nonce, plaintext).unwrap();
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, seal_many);
+criterion_main!(benches);
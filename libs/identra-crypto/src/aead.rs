@@ -1,10 +1,11 @@
 use crate::error::{CryptoError, Result};
-use crate::{KEY_SIZE, NONCE_SIZE};
+use crate::{KEY_SIZE, NONCE_SIZE, TAG_SIZE};
 use chacha20poly1305::{
-    aead::{Aead, KeyInit, OsRng},
+    aead::{Aead, KeyInit},
     ChaCha20Poly1305, Key, Nonce as ChaNonce,
 };
-use zeroize::Zeroize;
+use std::fmt;
+use zeroize::{Zeroize, ZeroizeOnDrop};
 
 /// Encryption key wrapper
 #[derive(Clone, Zeroize)]
@@ -37,6 +38,18 @@ impl EncryptionKey {
         getrandom::getrandom(&mut key).expect("Failed to generate random key");
         Self(key)
     }
+    
+    /// Raw key bytes for test assertions
+    #[cfg(test)]
+    pub(crate) fn expose_bytes(&self) -> &[u8; KEY_SIZE] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EncryptionKey([redacted; {}])", KEY_SIZE)
+    }
 }
 
 /// Nonce wrapper
@@ -71,8 +84,50 @@ impl Nonce {
     }
 }
 
+/// ChaCha20-Poly1305 set up once from a key, for sealing many messages
+/// under it
+///
+/// Holds its own copy of the key, wiped when the cipher is dropped.
+pub struct Cipher(ChaCha20Poly1305);
+
+impl Cipher {
+    pub fn new(key: &EncryptionKey) -> Self {
+        Self(ChaCha20Poly1305::new(Key::from_slice(key.as_bytes())))
+    }
+    
+    /// Encrypt `plaintext`, as `encrypt` does
+    pub fn encrypt(&self, nonce: &Nonce, plaintext: &[u8]) -> Result<Vec<u8>> {
+        self.0
+            .encrypt(ChaNonce::from_slice(nonce.as_bytes()), plaintext)
+            .map_err(|e| CryptoError::Encryption(e.to_string()))
+    }
+    
+    /// Decrypt `ciphertext`, as `decrypt` does
+    pub fn decrypt(&self, nonce: &Nonce, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        if ciphertext.len() < TAG_SIZE {
+            return Err(CryptoError::MalformedCiphertext {
+                reason: format!("{} bytes is shorter than the authentication tag", ciphertext.len()),
+            });
+        }
+        
+        self.0
+            .decrypt(ChaNonce::from_slice(nonce.as_bytes()), ciphertext)
+            .map_err(|_| CryptoError::Authentication)
+    }
+}
+
+impl ZeroizeOnDrop for Cipher {}
+
+impl fmt::Debug for Cipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Cipher([redacted])")
+    }
+}
+
 /// Encrypt data using ChaCha20-Poly1305
 ///
+/// A one-shot `Cipher::encrypt`; use a `Cipher` for many messages under one key.
+///
 /// # Arguments
 /// * `key` - Encryption key (32 bytes)
 /// * `nonce` - Nonce for encryption (12 bytes, must be unique per message)
@@ -81,32 +136,24 @@ impl Nonce {
 /// # Returns
 /// Encrypted ciphertext with authentication tag
 pub fn encrypt(key: &EncryptionKey, nonce: &Nonce, plaintext: &[u8]) -> Result<Vec<u8>> {
-    let cipher_key = Key::from_slice(key.as_bytes());
-    let cipher = ChaCha20Poly1305::new(cipher_key);
-    let cipher_nonce = ChaNonce::from_slice(nonce.as_bytes());
-    
-    cipher
-        .encrypt(cipher_nonce, plaintext)
-        .map_err(|e| CryptoError::Encryption(e.to_string()))
+    Cipher::new(key).encrypt(nonce, plaintext)
 }
 
 /// Decrypt data using ChaCha20-Poly1305
 ///
+/// A one-shot `Cipher::decrypt`.
+///
 /// # Arguments
 /// * `key` - Decryption key (32 bytes)
 /// * `nonce` - Nonce used during encryption (12 bytes)
-/// * `ciphertext` - Encrypted data with authentication tag
+/// * `ciphertext` - Encrypted data with authentication tag; at least
+///   `TAG_SIZE` bytes, exactly that for an empty plaintext
 ///
 /// # Returns
-/// Decrypted plaintext if authentication succeeds
+/// Decrypted plaintext if authentication succeeds; `MalformedCiphertext`
+/// if `ciphertext` can't hold a tag, `Authentication` if it doesn't verify
 pub fn decrypt(key: &EncryptionKey, nonce: &Nonce, ciphertext: &[u8]) -> Result<Vec<u8>> {
-    let cipher_key = Key::from_slice(key.as_bytes());
-    let cipher = ChaCha20Poly1305::new(cipher_key);
-    let cipher_nonce = ChaNonce::from_slice(nonce.as_bytes());
-    
-    cipher
-        .decrypt(cipher_nonce, ciphertext)
-        .map_err(|e| CryptoError::Decryption(e.to_string()))
+    Cipher::new(key).decrypt(nonce, ciphertext)
 }
 
 #[cfg(test)]
@@ -137,7 +184,7 @@ mod tests {
         
         // Decrypting with wrong key should fail
         let result = decrypt(&key2, &nonce, &ciphertext);
-        assert!(result.is_err());
+        assert!(matches!(result, Err(CryptoError::Authentication)));
     }
     
     #[test]
@@ -151,6 +198,62 @@ mod tests {
         
         // Decrypting with wrong nonce should fail
         let result = decrypt(&key, &nonce2, &ciphertext);
-        assert!(result.is_err());
+        assert!(matches!(result, Err(CryptoError::Authentication)));
+    }
+    
+    #[test]
+    fn test_tampered_ciphertext_fails_authentication() {
+        let key = EncryptionKey::generate();
+        let nonce = Nonce::generate();
+        let mut ciphertext = encrypt(&key, &nonce, b"Secret message").unwrap();
+        ciphertext[0] ^= 1;
+        
+        assert!(matches!(decrypt(&key, &nonce, &ciphertext), Err(CryptoError::Authentication)));
+    }
+    
+    #[test]
+    fn test_ciphertext_shorter_than_tag_is_malformed() {
+        let key = EncryptionKey::generate();
+        let nonce = Nonce::generate();
+        
+        for len in [0, TAG_SIZE - 1] {
+            let result = decrypt(&key, &nonce, &vec![0u8; len]);
+            assert!(matches!(result, Err(CryptoError::MalformedCiphertext { .. })), "{} bytes", len);
+        }
+        // A bare tag is a valid encryption of nothing, so it gets to authenticate
+        assert!(matches!(decrypt(&key, &nonce, &[0u8; TAG_SIZE]), Err(CryptoError::Authentication)));
+        
+        let empty = encrypt(&key, &nonce, b"").unwrap();
+        assert_eq!(empty.len(), TAG_SIZE);
+        assert!(decrypt(&key, &nonce, &empty).unwrap().is_empty());
+    }
+    
+    #[test]
+    fn test_cipher_matches_one_shot_functions() {
+        let key = EncryptionKey::generate();
+        let cipher = Cipher::new(&key);
+        
+        for plaintext in [&b""[..], b"short", b"a slightly longer memory"] {
+            let nonce = Nonce::generate();
+            let sealed = cipher.encrypt(&nonce, plaintext).unwrap();
+            assert_eq!(sealed, encrypt(&key, &nonce, plaintext).unwrap());
+            assert_eq!(decrypt(&key, &nonce, &sealed).unwrap(), plaintext);
+            assert_eq!(cipher.decrypt(&nonce, &sealed).unwrap(), plaintext);
+        }
+        
+        let nonce = Nonce::generate();
+        let sealed = cipher.encrypt(&nonce, b"secret").unwrap();
+        let other = Cipher::new(&EncryptionKey::generate());
+        assert!(matches!(other.decrypt(&nonce, &sealed), Err(CryptoError::Authentication)));
+        assert_eq!(format!("{:?}", cipher), "Cipher([redacted])");
+    }
+    
+    #[test]
+    fn test_debug_redacts_key() {
+        let key = EncryptionKey::from_bytes(&[0xAB; KEY_SIZE]).unwrap();
+        let debug = format!("{:?}", key);
+        
+        assert_eq!(debug, "EncryptionKey([redacted; 32])");
+        assert_eq!(key.expose_bytes(), &[0xAB; KEY_SIZE]);
     }
 }
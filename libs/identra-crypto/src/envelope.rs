@@ -0,0 +1,148 @@
+use crate::aead::{decrypt, encrypt, EncryptionKey, Nonce};
+use crate::error::{CryptoError, Result};
+use crate::nonce::NonceSequence;
+use crate::{NONCE_SIZE, TAG_SIZE};
+
+/// Current envelope format version
+const ENVELOPE_VERSION: u8 = 1;
+
+/// Size of the envelope header (version byte + nonce)
+pub const HEADER_SIZE: usize = 1 + NONCE_SIZE;
+
+/// Self-describing ciphertext container
+///
+/// Layout: `[version: 1][nonce: 12][ciphertext + tag]`. The nonce is
+/// generated fresh on every `seal`, so callers only need to keep the key.
+pub struct Envelope;
+
+impl Envelope {
+    /// Encrypt `plaintext` under `key` into a versioned envelope
+    pub fn seal(key: &EncryptionKey, plaintext: &[u8]) -> Result<Vec<u8>> {
+        Self::seal_with_nonce(key, &Nonce::generate(), plaintext)
+    }
+
+    /// Like `seal`, taking the nonce from `sequence` instead of at random
+    ///
+    /// For keys that seal so many messages that random nonces could
+    /// collide. `open` reads either kind.
+    pub fn seal_with(key: &EncryptionKey, sequence: &mut NonceSequence, plaintext: &[u8]) -> Result<Vec<u8>> {
+        Self::seal_with_nonce(key, &sequence.next_nonce()?, plaintext)
+    }
+
+    fn seal_with_nonce(key: &EncryptionKey, nonce: &Nonce, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let ciphertext = encrypt(key, nonce, plaintext)?;
+
+        let mut envelope = Vec::with_capacity(HEADER_SIZE + ciphertext.len());
+        envelope.push(ENVELOPE_VERSION);
+        envelope.extend_from_slice(nonce.as_bytes());
+        envelope.extend_from_slice(&ciphertext);
+        Ok(envelope)
+    }
+
+    /// Decrypt an envelope produced by `seal`
+    ///
+    /// An envelope shorter than `HEADER_SIZE + TAG_SIZE`, the seal of an
+    /// empty plaintext, or of an unknown version is `MalformedCiphertext`
+    /// without any decryption attempted; one that doesn't verify under `key`
+    /// is `Authentication`.
+    pub fn open(key: &EncryptionKey, envelope: &[u8]) -> Result<Vec<u8>> {
+        if envelope.len() < HEADER_SIZE + TAG_SIZE {
+            return Err(CryptoError::MalformedCiphertext {
+                reason: format!("Envelope too short: {} bytes, need at least {}", envelope.len(), HEADER_SIZE + TAG_SIZE),
+            });
+        }
+
+        let (header, ciphertext) = envelope.split_at(HEADER_SIZE);
+        if header[0] != ENVELOPE_VERSION {
+            return Err(CryptoError::MalformedCiphertext {
+                reason: format!("Unsupported envelope version: {}", header[0]),
+            });
+        }
+
+        let nonce = Nonce::from_bytes(&header[1..])?;
+        decrypt(key, &nonce, ciphertext)
+    }
+
+    /// Length of the envelope produced for a plaintext of `plaintext_len` bytes
+    pub fn sealed_len(plaintext_len: usize) -> usize {
+        HEADER_SIZE + plaintext_len + TAG_SIZE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_open() {
+        let key = EncryptionKey::generate();
+        let plaintext = b"Hello, Envelope!";
+
+        let envelope = Envelope::seal(&key, plaintext).unwrap();
+        assert_eq!(envelope.len(), Envelope::sealed_len(plaintext.len()));
+
+        let opened = Envelope::open(&key, &envelope).unwrap();
+        assert_eq!(opened.as_slice(), plaintext);
+    }
+
+    #[test]
+    fn test_fresh_nonce_per_seal() {
+        let key = EncryptionKey::generate();
+        let plaintext = b"same message";
+
+        let envelope1 = Envelope::seal(&key, plaintext).unwrap();
+        let envelope2 = Envelope::seal(&key, plaintext).unwrap();
+
+        assert_ne!(envelope1, envelope2);
+    }
+
+    #[test]
+    fn test_seal_with_sequence() {
+        let key = EncryptionKey::generate();
+        let mut sequence = NonceSequence::new();
+
+        let envelope1 = Envelope::seal_with(&key, &mut sequence, b"first").unwrap();
+        let envelope2 = Envelope::seal_with(&key, &mut sequence, b"second").unwrap();
+        assert_ne!(envelope1[1..HEADER_SIZE], envelope2[1..HEADER_SIZE]);
+
+        assert_eq!(Envelope::open(&key, &envelope1).unwrap(), b"first");
+        assert_eq!(Envelope::open(&key, &envelope2).unwrap(), b"second");
+    }
+
+    #[test]
+    fn test_open_rejects_unknown_version() {
+        let key = EncryptionKey::generate();
+        let mut envelope = Envelope::seal(&key, b"data").unwrap();
+        envelope[0] = 0xFF;
+
+        let result = Envelope::open(&key, &envelope);
+        assert!(matches!(result, Err(CryptoError::MalformedCiphertext { .. })));
+    }
+
+    #[test]
+    fn test_open_rejects_short_envelope() {
+        let key = EncryptionKey::generate();
+        let envelope = Envelope::seal(&key, b"").unwrap();
+        assert_eq!(envelope.len(), HEADER_SIZE + TAG_SIZE);
+
+        // Nothing, part of the header, the header alone, and one byte short of a tag
+        for len in [0, HEADER_SIZE - 1, HEADER_SIZE, envelope.len() - 1] {
+            let result = Envelope::open(&key, &envelope[..len]);
+            assert!(matches!(result, Err(CryptoError::MalformedCiphertext { .. })), "{} bytes", len);
+        }
+        assert!(Envelope::open(&key, &envelope).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_open_with_wrong_key_or_tampering_fails_authentication() {
+        let key = EncryptionKey::generate();
+        let mut envelope = Envelope::seal(&key, b"data").unwrap();
+
+        let result = Envelope::open(&EncryptionKey::generate(), &envelope);
+        assert!(matches!(result, Err(CryptoError::Authentication)));
+
+        // The nonce is in the header; flipping it must not pass either
+        envelope[1] ^= 1;
+        assert!(matches!(Envelope::open(&key, &envelope), Err(CryptoError::Authentication)));
+    }
+}
@@ -5,8 +5,13 @@ pub enum CryptoError {
     #[error("Encryption error: {0}")]
     Encryption(String),
     
-    #[error("Decryption error: {0}")]
-    Decryption(String),
+    /// The ciphertext didn't authenticate: wrong key, wrong nonce, or tampered data
+    #[error("Authentication failed: wrong key or tampered ciphertext")]
+    Authentication,
+    
+    /// Input that can't be a ciphertext at all, rejected before decrypting
+    #[error("Malformed ciphertext: {reason}")]
+    MalformedCiphertext { reason: String },
     
     #[error("Invalid key length: expected {expected}, got {actual}")]
     InvalidKeyLength { expected: usize, actual: usize },
@@ -14,6 +19,9 @@ pub enum CryptoError {
     #[error("Invalid nonce length: expected {expected}, got {actual}")]
     InvalidNonceLength { expected: usize, actual: usize },
     
+    #[error("Nonce sequence exhausted; rotate the key")]
+    NonceExhausted,
+    
     #[error("Key derivation error: {0}")]
     KeyDerivation(String),
     
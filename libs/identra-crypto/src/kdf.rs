@@ -1,11 +1,24 @@
 use crate::error::{CryptoError, Result};
 use crate::KEY_SIZE;
+use crate::aead::EncryptionKey;
 use argon2::{
     password_hash::{PasswordHasher, SaltString},
     Argon2, Params, Version,
 };
+use hkdf::Hkdf;
+use sha2::Sha256;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 use zeroize::Zeroize;
 
+/// Memory cost calibration won't go below, in KiB (19 MiB, OWASP's minimum
+/// for Argon2id)
+const MIN_CALIBRATED_MEMORY: u32 = 19 * 1024;
+
+/// Time cost calibration won't go above
+const MAX_CALIBRATED_TIME: u32 = 64;
+
 /// Derived key wrapper
 #[derive(Clone, Zeroize)]
 #[zeroize(drop)]
@@ -21,10 +34,25 @@ impl DerivedKey {
     pub fn to_encryption_key(&self) -> crate::EncryptionKey {
         crate::EncryptionKey::from_bytes(&self.0).expect("DerivedKey always has correct size")
     }
+    
+    /// Raw key bytes for test assertions
+    #[cfg(test)]
+    pub(crate) fn expose_bytes(&self) -> &[u8; KEY_SIZE] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for DerivedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DerivedKey([redacted; {}])", KEY_SIZE)
+    }
 }
 
 /// Key derivation parameters
-#[derive(Clone)]
+///
+/// `Display` writes them as `m=65536,t=3,p=4`, the form `FromStr` reads, so
+/// they can be stored next to a salt and the same key derived again later.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct KeyDerivationParams {
     /// Memory cost in KiB (default: 64 MiB = 65536 KiB)
     pub memory_cost: u32,
@@ -58,6 +86,104 @@ impl KeyDerivationParams {
     pub fn secure() -> Self {
         Self::default()
     }
+
+    /// Preset for `profile`, trading unlock time for resistance to guessing
+    pub fn for_profile(profile: Profile) -> Self {
+        match profile {
+            Profile::Interactive => Self { memory_cost: MIN_CALIBRATED_MEMORY, time_cost: 2, parallelism: 1 },
+            Profile::Moderate => Self::default(),
+            Profile::Sensitive => Self { memory_cost: 262144, time_cost: 4, parallelism: 4 },
+        }
+    }
+
+    /// `Moderate` parameters tuned so one derivation on this machine takes
+    /// about `target`
+    ///
+    /// If a single pass over 64 MiB already takes longer, memory is halved
+    /// until it fits, down to 19 MiB; then `time_cost` is scaled to the target.
+    pub fn calibrate(target: Duration) -> Self {
+        Self::for_profile(Profile::Moderate).tuned(target, time_derivation)
+    }
+
+    /// Tune against `time`, which says how long one derivation with the
+    /// given parameters takes
+    fn tuned(mut self, target: Duration, mut time: impl FnMut(&Self) -> Duration) -> Self {
+        self.time_cost = 1;
+        let mut pass = time(&self);
+        while pass > target && self.memory_cost / 2 >= MIN_CALIBRATED_MEMORY {
+            self.memory_cost /= 2;
+            pass = time(&self);
+        }
+        let passes = target.as_secs_f64() / pass.as_secs_f64().max(f64::EPSILON);
+        self.time_cost = (passes.round() as u32).clamp(1, MAX_CALIBRATED_TIME);
+        self
+    }
+}
+
+/// Wall-clock time of one derivation with `params`
+fn time_derivation(params: &KeyDerivationParams) -> Duration {
+    let started = Instant::now();
+    derive_key(b"calibration", &[0u8; crate::SALT_SIZE], params)
+        .expect("calibration parameters are valid");
+    started.elapsed()
+}
+
+impl fmt::Display for KeyDerivationParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "m={},t={},p={}", self.memory_cost, self.time_cost, self.parallelism)
+    }
+}
+
+impl FromStr for KeyDerivationParams {
+    type Err = CryptoError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || CryptoError::KeyDerivation(format!("Invalid parameters: {:?}", s));
+        let mut params = [None; 3];
+        for part in s.split(',') {
+            let (name, value) = part.split_once('=').ok_or_else(invalid)?;
+            let slot = match name.trim() {
+                "m" => 0,
+                "t" => 1,
+                "p" => 2,
+                _ => return Err(invalid()),
+            };
+            params[slot] = Some(value.trim().parse::<u32>().map_err(|_| invalid())?);
+        }
+        match params {
+            [Some(memory_cost), Some(time_cost), Some(parallelism)] => {
+                Ok(Self { memory_cost, time_cost, parallelism })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// How much unlocking may cost in exchange for resistance to guessing
+///
+/// Roughly a tenth of a second, half a second and a few seconds on a
+/// current laptop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    /// 19 MiB, 2 passes: for devices that unlock often or have little memory
+    Interactive,
+    /// 64 MiB, 3 passes, the default
+    Moderate,
+    /// 256 MiB, 4 passes: for keys that are rarely unlocked
+    Sensitive,
+}
+
+impl FromStr for Profile {
+    type Err = CryptoError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "interactive" => Ok(Self::Interactive),
+            "moderate" => Ok(Self::Moderate),
+            "sensitive" => Ok(Self::Sensitive),
+            _ => Err(CryptoError::KeyDerivation(format!("Unknown profile: {:?}", s))),
+        }
+    }
 }
 
 /// Derive an encryption key from a password using Argon2id
@@ -115,6 +241,26 @@ pub fn derive_key(
     Ok(DerivedKey(key))
 }
 
+/// Derive a purpose-specific subkey from an unlocked master key using HKDF-SHA256
+///
+/// # Arguments
+/// * `master` - Master key (already derived from the passphrase)
+/// * `info` - Domain separation label, e.g. `b"identra/memory-content"`
+///
+/// # Returns
+/// A 32-byte key that is deterministic for a given master+info pair
+pub fn derive_subkey(master: &EncryptionKey, info: &[u8]) -> EncryptionKey {
+    let hkdf = Hkdf::<Sha256>::new(None, master.as_bytes());
+    
+    let mut okm = [0u8; KEY_SIZE];
+    hkdf.expand(info, &mut okm)
+        .expect("KEY_SIZE is a valid HKDF-SHA256 output length");
+    
+    let subkey = EncryptionKey::from_bytes(&okm).expect("okm always has correct size");
+    okm.zeroize();
+    subkey
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -167,4 +313,109 @@ mod tests {
         
         assert_ne!(key1.as_bytes(), key2.as_bytes());
     }
+    
+    #[test]
+    fn test_params_round_trip_as_text() {
+        for profile in [Profile::Interactive, Profile::Moderate, Profile::Sensitive] {
+            let params = KeyDerivationParams::for_profile(profile);
+            assert_eq!(params.to_string().parse::<KeyDerivationParams>().unwrap(), params);
+        }
+        assert_eq!(KeyDerivationParams::default().to_string(), "m=65536,t=3,p=4");
+        assert!("m=65536,t=3".parse::<KeyDerivationParams>().is_err());
+        assert!("m=65536,t=3,p=4,x=1".parse::<KeyDerivationParams>().is_err());
+        assert_eq!(" Sensitive ".parse::<Profile>().unwrap(), Profile::Sensitive);
+    }
+
+    /// A machine where one pass over 64 MiB takes `per_64_mib`, scaling with memory
+    fn machine(per_64_mib: Duration) -> impl FnMut(&KeyDerivationParams) -> Duration {
+        move |params| {
+            assert_eq!(params.time_cost, 1, "calibration times single passes");
+            per_64_mib.mul_f64(params.memory_cost as f64 / 65536.0)
+        }
+    }
+
+    #[test]
+    fn test_calibrate_scales_passes_to_target() {
+        let moderate = KeyDerivationParams::for_profile(Profile::Moderate);
+
+        let tuned = moderate.clone().tuned(Duration::from_millis(300), machine(Duration::from_millis(100)));
+        assert_eq!(tuned, KeyDerivationParams { time_cost: 3, ..moderate.clone() });
+
+        // Rounded to the nearest whole pass
+        let tuned = moderate.clone().tuned(Duration::from_millis(340), machine(Duration::from_millis(100)));
+        assert_eq!(tuned.time_cost, 3);
+        let tuned = moderate.clone().tuned(Duration::from_millis(360), machine(Duration::from_millis(100)));
+        assert_eq!(tuned.time_cost, 4);
+    }
+
+    #[test]
+    fn test_calibrate_halves_memory_on_slow_machines() {
+        let moderate = KeyDerivationParams::for_profile(Profile::Moderate);
+
+        // 64 MiB takes 800ms, 32 MiB 400ms: fits a 500ms target once halved
+        let tuned = moderate.clone().tuned(Duration::from_millis(500), machine(Duration::from_millis(800)));
+        assert_eq!((tuned.memory_cost, tuned.time_cost), (32768, 1));
+
+        // Never below 19 MiB or one pass, however slow
+        let tuned = moderate.clone().tuned(Duration::from_millis(1), machine(Duration::from_secs(60)));
+        assert!(tuned.memory_cost >= MIN_CALIBRATED_MEMORY, "{}", tuned);
+        assert_eq!(tuned.time_cost, 1);
+        assert_eq!(tuned.parallelism, moderate.parallelism);
+    }
+
+    #[test]
+    fn test_calibrate_clamps_and_grows_with_target() {
+        let moderate = KeyDerivationParams::for_profile(Profile::Moderate);
+
+        let tuned = moderate.clone().tuned(Duration::from_secs(10), machine(Duration::from_micros(1)));
+        assert_eq!(tuned.time_cost, MAX_CALIBRATED_TIME);
+        // A pass too quick to measure still clamps instead of dividing by zero
+        let tuned = moderate.clone().tuned(Duration::from_secs(1), |_| Duration::ZERO);
+        assert_eq!(tuned.time_cost, MAX_CALIBRATED_TIME);
+
+        let mut previous = 0;
+        for target_ms in (0..=2000).step_by(50) {
+            let tuned = moderate.clone().tuned(Duration::from_millis(target_ms), machine(Duration::from_millis(70)));
+            assert!(tuned.time_cost >= previous, "{} for {}ms", tuned, target_ms);
+            previous = tuned.time_cost;
+        }
+    }
+
+    #[test]
+    fn test_calibrate_times_real_derivations() {
+        let base = KeyDerivationParams { memory_cost: MIN_CALIBRATED_MEMORY, time_cost: 1, parallelism: 1 };
+        let tiny = base.tuned(Duration::from_nanos(1), time_derivation);
+        assert_eq!(tiny.time_cost, 1, "never below one pass");
+    }
+
+    #[test]
+    fn test_derive_subkey_deterministic() {
+        let master = EncryptionKey::generate();
+        
+        let key1 = derive_subkey(&master, b"identra/memory-content");
+        let key2 = derive_subkey(&master, b"identra/memory-content");
+        
+        assert_eq!(key1.as_bytes(), key2.as_bytes());
+    }
+    
+    #[test]
+    fn test_derive_subkey_different_info_different_key() {
+        let master = EncryptionKey::generate();
+        
+        let content_key = derive_subkey(&master, b"identra/memory-content");
+        let metadata_key = derive_subkey(&master, b"identra/metadata-aad");
+        
+        assert_ne!(content_key.as_bytes(), metadata_key.as_bytes());
+        assert_ne!(content_key.as_bytes(), master.as_bytes());
+    }
+    
+    #[test]
+    fn test_debug_redacts_key() {
+        let salt = generate_salt();
+        let key = derive_key(b"debug_password", &salt, &KeyDerivationParams::fast()).unwrap();
+        let debug = format!("{:?}", key);
+        
+        assert_eq!(debug, "DerivedKey([redacted; 32])");
+        assert_eq!(key.expose_bytes().as_slice(), key.as_bytes());
+    }
 }
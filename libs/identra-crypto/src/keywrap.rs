@@ -0,0 +1,79 @@
+use crate::aead::EncryptionKey;
+use crate::envelope::Envelope;
+use crate::error::Result;
+use crate::KEY_SIZE;
+use zeroize::Zeroize;
+
+/// Size of a wrapped data-encryption key in bytes
+pub const WRAPPED_KEY_SIZE: usize = crate::envelope::HEADER_SIZE + KEY_SIZE + crate::TAG_SIZE;
+
+/// Wrap a data-encryption key (DEK) under a key-encryption key (KEK)
+///
+/// The wrapped blob is an `Envelope`, so it carries its own nonce. Rotating
+/// a password only requires unwrapping the DEK with the old KEK and wrapping
+/// it again with the new one; data encrypted under the DEK is untouched.
+pub fn wrap_key(kek: &EncryptionKey, dek: &EncryptionKey) -> Result<Vec<u8>> {
+    Envelope::seal(kek, dek.as_bytes())
+}
+
+/// Unwrap a data-encryption key previously wrapped with `wrap_key`
+///
+/// Fails if `kek` is not the key used for wrapping or the blob was tampered with.
+pub fn unwrap_key(kek: &EncryptionKey, wrapped: &[u8]) -> Result<EncryptionKey> {
+    let mut dek_bytes = Envelope::open(kek, wrapped)?;
+    let dek = EncryptionKey::from_bytes(&dek_bytes);
+    dek_bytes.zeroize();
+    dek
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_unwrap() {
+        let kek = EncryptionKey::generate();
+        let dek = EncryptionKey::generate();
+
+        let wrapped = wrap_key(&kek, &dek).unwrap();
+        let unwrapped = unwrap_key(&kek, &wrapped).unwrap();
+
+        assert_eq!(unwrapped.as_bytes(), dek.as_bytes());
+    }
+
+    #[test]
+    fn test_wrapped_length_is_fixed() {
+        let kek = EncryptionKey::generate();
+
+        for _ in 0..4 {
+            let dek = EncryptionKey::generate();
+            let wrapped = wrap_key(&kek, &dek).unwrap();
+            assert_eq!(wrapped.len(), WRAPPED_KEY_SIZE);
+        }
+    }
+
+    #[test]
+    fn test_unwrap_with_wrong_kek_fails() {
+        let kek1 = EncryptionKey::generate();
+        let kek2 = EncryptionKey::generate();
+        let dek = EncryptionKey::generate();
+
+        let wrapped = wrap_key(&kek1, &dek).unwrap();
+
+        // Unwrapping with a different KEK should fail
+        assert!(unwrap_key(&kek2, &wrapped).is_err());
+    }
+
+    #[test]
+    fn test_rewrap_for_rotation() {
+        let old_kek = EncryptionKey::generate();
+        let new_kek = EncryptionKey::generate();
+        let dek = EncryptionKey::generate();
+
+        let wrapped = wrap_key(&old_kek, &dek).unwrap();
+        let rewrapped = wrap_key(&new_kek, &unwrap_key(&old_kek, &wrapped).unwrap()).unwrap();
+
+        let recovered = unwrap_key(&new_kek, &rewrapped).unwrap();
+        assert_eq!(recovered.as_bytes(), dek.as_bytes());
+    }
+}
@@ -1,7 +1,37 @@
+pub mod aead;
+pub mod envelope;
+pub mod error;
+pub mod kdf;
+pub mod keywrap;
+pub mod nonce;
+pub mod random;
+pub mod stream;
+
+pub use aead::{decrypt, encrypt, Cipher, EncryptionKey, Nonce};
+pub use envelope::Envelope;
+pub use error::CryptoError;
+pub use kdf::{derive_key, derive_subkey, DerivedKey, KeyDerivationParams, Profile};
+pub use keywrap::{unwrap_key, wrap_key, WRAPPED_KEY_SIZE};
+pub use nonce::NonceSequence;
+pub use random::{generate_key, generate_nonce, generate_random_bytes, generate_salt};
+pub use stream::{StreamDecryptor, StreamEncryptor, STREAM_HEADER_SIZE};
+
+/// Symmetric key size in bytes (256-bit)
+pub const KEY_SIZE: usize = 32;
+
+/// ChaCha20-Poly1305 nonce size in bytes (96-bit)
+pub const NONCE_SIZE: usize = 12;
+
+/// Salt size in bytes for key derivation
+pub const SALT_SIZE: usize = 16;
+
+/// Poly1305 authentication tag size in bytes
+pub const TAG_SIZE: usize = 16;
+
 use aes_gcm::{
     Aes256Gcm,
     Key,
-    Nonce,
+    Nonce as GcmNonce,
     // ADDED: AeadCore (This fixes the generate_nonce error)
     aead::{Aead, AeadCore, KeyInit, OsRng}, 
 };
@@ -47,7 +77,7 @@ impl MemoryVault {
             return Result::Err("Packet too short".to_string());
         }
         let (nonce_bytes, ciphertext) = packet_bytes.split_at(12);
-        let nonce = Nonce::from_slice(nonce_bytes);
+        let nonce = GcmNonce::from_slice(nonce_bytes);
 
         // 3. Init Cipher
         let cipher = Aes256Gcm::new(key);
@@ -0,0 +1,108 @@
+use crate::aead::Nonce;
+use crate::error::{CryptoError, Result};
+use crate::NONCE_SIZE;
+
+/// Size of the random per-key prefix in bytes
+const PREFIX_SIZE: usize = 4;
+
+/// Deterministic nonce source for a long-lived key
+///
+/// Each nonce is a random 32-bit prefix followed by a big-endian 64-bit
+/// counter, so a key never sees the same nonce twice as long as the state
+/// is persisted alongside the key. The prefix keeps two sequences that
+/// were accidentally started for one key (e.g. a restored backup) apart.
+/// Save `to_bytes` after every `next_nonce` and before the nonce is used,
+/// otherwise a crash could hand out the same counter again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NonceSequence {
+    prefix: [u8; PREFIX_SIZE],
+    counter: u64,
+}
+
+impl NonceSequence {
+    /// Start a sequence with a fresh random prefix
+    pub fn new() -> Self {
+        let mut prefix = [0u8; PREFIX_SIZE];
+        getrandom::getrandom(&mut prefix).expect("Failed to generate nonce prefix");
+        Self { prefix, counter: 0 }
+    }
+
+    /// Restore a sequence saved with `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != NONCE_SIZE {
+            return Err(CryptoError::InvalidNonceLength {
+                expected: NONCE_SIZE,
+                actual: bytes.len(),
+            });
+        }
+
+        let (prefix, counter) = bytes.split_at(PREFIX_SIZE);
+        Ok(Self {
+            prefix: prefix.try_into().expect("prefix length checked above"),
+            counter: u64::from_be_bytes(counter.try_into().expect("counter length checked above")),
+        })
+    }
+
+    /// State to persist: the prefix and the counter of the next nonce
+    pub fn to_bytes(&self) -> [u8; NONCE_SIZE] {
+        let mut bytes = [0u8; NONCE_SIZE];
+        bytes[..PREFIX_SIZE].copy_from_slice(&self.prefix);
+        bytes[PREFIX_SIZE..].copy_from_slice(&self.counter.to_be_bytes());
+        bytes
+    }
+
+    /// Next unused nonce; fails rather than wrap, after which the key
+    /// must be rotated
+    pub fn next_nonce(&mut self) -> Result<Nonce> {
+        let next = self.counter.checked_add(1).ok_or(CryptoError::NonceExhausted)?;
+        let nonce = Nonce::from_bytes(&self.to_bytes())?;
+        self.counter = next;
+        Ok(nonce)
+    }
+}
+
+impl Default for NonceSequence {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counter_strictly_increases() {
+        let mut sequence = NonceSequence::new();
+        let nonces: Vec<_> = (0..3).map(|_| sequence.next_nonce().unwrap()).collect();
+
+        for pair in nonces.windows(2) {
+            assert_eq!(pair[0].as_bytes()[..PREFIX_SIZE], pair[1].as_bytes()[..PREFIX_SIZE]);
+            assert!(pair[0].as_bytes()[PREFIX_SIZE..] < pair[1].as_bytes()[PREFIX_SIZE..]);
+        }
+        assert_eq!(nonces[2].as_bytes()[PREFIX_SIZE..], 2u64.to_be_bytes());
+    }
+
+    #[test]
+    fn test_restored_sequence_continues() {
+        let mut sequence = NonceSequence::new();
+        let first = sequence.next_nonce().unwrap();
+
+        let mut restored = NonceSequence::from_bytes(&sequence.to_bytes()).unwrap();
+        assert_eq!(restored, sequence);
+        assert_ne!(restored.next_nonce().unwrap().as_bytes(), first.as_bytes());
+        assert!(NonceSequence::from_bytes(&[0u8; 8]).is_err());
+    }
+
+    #[test]
+    fn test_rejects_overflow() {
+        let mut state = [0u8; NONCE_SIZE];
+        state[PREFIX_SIZE..].copy_from_slice(&(u64::MAX - 1).to_be_bytes());
+        let mut sequence = NonceSequence::from_bytes(&state).unwrap();
+
+        assert!(sequence.next_nonce().is_ok());
+        assert!(matches!(sequence.next_nonce(), Err(CryptoError::NonceExhausted)));
+        // Stays exhausted instead of wrapping around
+        assert!(sequence.next_nonce().is_err());
+    }
+}
@@ -1,5 +1,4 @@
 use crate::{KEY_SIZE, NONCE_SIZE, SALT_SIZE};
-use crate::error::{CryptoError, Result};
 
 /// Generate a random encryption key
 pub fn generate_key() -> [u8; KEY_SIZE] {
@@ -0,0 +1,145 @@
+use crate::aead::EncryptionKey;
+use crate::error::{CryptoError, Result};
+use crate::TAG_SIZE;
+use chacha20poly1305::{
+    aead::{stream, KeyInit},
+    ChaCha20Poly1305, Key,
+};
+use std::fmt;
+
+/// Size of the random nonce prefix that opens every stream
+pub const STREAM_HEADER_SIZE: usize = 7;
+
+/// Encrypts a message too large to hold at once, one chunk at a time
+///
+/// STREAM construction over ChaCha20-Poly1305: every chunk is sealed under
+/// the stream's random prefix, its position and whether it is the last
+/// one, so chunks that are dropped, reordered, cut off after any chunk but
+/// the last, or moved between streams fail to decrypt. Keep `header` with
+/// the chunks; `StreamDecryptor` needs it.
+pub struct StreamEncryptor {
+    inner: stream::EncryptorBE32<ChaCha20Poly1305>,
+    header: [u8; STREAM_HEADER_SIZE],
+}
+
+impl StreamEncryptor {
+    /// Stream under `key` with a fresh random header
+    pub fn new(key: &EncryptionKey) -> Self {
+        let mut header = [0u8; STREAM_HEADER_SIZE];
+        getrandom::getrandom(&mut header).expect("Failed to generate stream header");
+        let aead = ChaCha20Poly1305::new(Key::from_slice(key.as_bytes()));
+        Self { inner: stream::EncryptorBE32::from_aead(aead, (&header).into()), header }
+    }
+
+    pub fn header(&self) -> [u8; STREAM_HEADER_SIZE] {
+        self.header
+    }
+
+    /// Seal the next chunk; `TAG_SIZE` bytes longer than `chunk`
+    pub fn encrypt_next(&mut self, chunk: &[u8]) -> Result<Vec<u8>> {
+        self.inner.encrypt_next(chunk).map_err(|e| CryptoError::Encryption(e.to_string()))
+    }
+
+    /// Seal the final chunk, ending the stream
+    pub fn encrypt_last(self, chunk: &[u8]) -> Result<Vec<u8>> {
+        self.inner.encrypt_last(chunk).map_err(|e| CryptoError::Encryption(e.to_string()))
+    }
+}
+
+impl fmt::Debug for StreamEncryptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("StreamEncryptor([redacted])")
+    }
+}
+
+/// Opens the chunks of a `StreamEncryptor` stream, in order
+pub struct StreamDecryptor {
+    inner: stream::DecryptorBE32<ChaCha20Poly1305>,
+}
+
+impl StreamDecryptor {
+    /// Decryptor for the stream that `header` opened
+    pub fn new(key: &EncryptionKey, header: &[u8]) -> Result<Self> {
+        let header: &[u8; STREAM_HEADER_SIZE] = header.try_into().map_err(|_| CryptoError::MalformedCiphertext {
+            reason: format!("stream header is {} bytes, not {}", header.len(), STREAM_HEADER_SIZE),
+        })?;
+        let aead = ChaCha20Poly1305::new(Key::from_slice(key.as_bytes()));
+        Ok(Self { inner: stream::DecryptorBE32::from_aead(aead, header.into()) })
+    }
+
+    /// Open the next chunk
+    pub fn decrypt_next(&mut self, chunk: &[u8]) -> Result<Vec<u8>> {
+        check_len(chunk)?;
+        self.inner.decrypt_next(chunk).map_err(|_| CryptoError::Authentication)
+    }
+
+    /// Open the final chunk; fails if it wasn't sealed as the last one
+    pub fn decrypt_last(self, chunk: &[u8]) -> Result<Vec<u8>> {
+        check_len(chunk)?;
+        self.inner.decrypt_last(chunk).map_err(|_| CryptoError::Authentication)
+    }
+}
+
+impl fmt::Debug for StreamDecryptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("StreamDecryptor([redacted])")
+    }
+}
+
+fn check_len(chunk: &[u8]) -> Result<()> {
+    if chunk.len() < TAG_SIZE {
+        return Err(CryptoError::MalformedCiphertext {
+            reason: format!("{} bytes is shorter than the authentication tag", chunk.len()),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seal(key: &EncryptionKey, chunks: &[&[u8]]) -> ([u8; STREAM_HEADER_SIZE], Vec<Vec<u8>>) {
+        let mut encryptor = StreamEncryptor::new(key);
+        let header = encryptor.header();
+        let (last, rest) = chunks.split_last().unwrap();
+        let mut sealed: Vec<_> = rest.iter().map(|chunk| encryptor.encrypt_next(chunk).unwrap()).collect();
+        sealed.push(encryptor.encrypt_last(last).unwrap());
+        (header, sealed)
+    }
+
+    #[test]
+    fn test_chunks_round_trip() {
+        let key = EncryptionKey::generate();
+        let (header, sealed) = seal(&key, &[b"first", b"second", b""]);
+        assert_eq!(sealed[0].len(), 5 + TAG_SIZE);
+
+        let mut decryptor = StreamDecryptor::new(&key, &header).unwrap();
+        assert_eq!(decryptor.decrypt_next(&sealed[0]).unwrap(), b"first");
+        assert_eq!(decryptor.decrypt_next(&sealed[1]).unwrap(), b"second");
+        assert_eq!(decryptor.decrypt_last(&sealed[2]).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_reordered_or_truncated_stream_fails() {
+        let key = EncryptionKey::generate();
+        let (header, sealed) = seal(&key, &[b"a", b"b", b"c"]);
+
+        let mut swapped = StreamDecryptor::new(&key, &header).unwrap();
+        assert!(matches!(swapped.decrypt_next(&sealed[1]), Err(CryptoError::Authentication)));
+
+        // Cut off after the second chunk: it wasn't sealed as the last one
+        let mut truncated = StreamDecryptor::new(&key, &header).unwrap();
+        truncated.decrypt_next(&sealed[0]).unwrap();
+        assert!(matches!(truncated.decrypt_last(&sealed[1]), Err(CryptoError::Authentication)));
+
+        let (other_header, _) = seal(&key, &[b"a"]);
+        let mut spliced = StreamDecryptor::new(&key, &other_header).unwrap();
+        assert!(spliced.decrypt_next(&sealed[0]).is_err());
+
+        assert!(matches!(
+            StreamDecryptor::new(&key, &header[1..]),
+            Err(CryptoError::MalformedCiphertext { .. })
+        ));
+    }
+}
@@ -0,0 +1,535 @@
+use crate::error::IpcError;
+use crate::framing::{read_message, write_message};
+use crate::protocol::{RequestFrame, ResponseFrame, VaultRequest, VaultResponse, SECRET_CHUNK_SIZE, VAULT_LOCKED};
+use crate::{local_socket_name, socket_name, token_path};
+use interprocess::local_socket::tokio::{prelude::*, Stream};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+
+/// How long `send_request` waits for each of the write and the reply
+///
+/// Generous because the daemon may be blocked on an OS keychain prompt.
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Attempts made by `VaultClient::connect` while the daemon may still be starting
+const CONNECT_ATTEMPTS: u32 = 3;
+const CONNECT_INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const CONNECT_MAX_BACKOFF: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+pub enum VaultClientError {
+    ConnectionFailed(String),
+    SendFailed(String),
+    ReceiveFailed(String),
+    SerializationError(String),
+    /// The daemon didn't accept, read or answer within the timeout
+    Timeout(Duration),
+    /// The daemon has no key with this id
+    NotFound(String),
+    /// The daemon answered, but with an error, such as `VAULT_LOCKED`
+    DaemonError(String),
+}
+
+impl VaultClientError {
+    /// Error for a response that isn't the answer the request expects
+    fn from_response(response: VaultResponse) -> Self {
+        match response {
+            VaultResponse::NotFound { key_id } => Self::NotFound(key_id),
+            VaultResponse::Error(message) => Self::DaemonError(message),
+            _ => Self::ReceiveFailed("Unexpected response type".to_string()),
+        }
+    }
+
+    /// True if the request failed only because the vault is locked
+    pub fn is_locked(&self) -> bool {
+        matches!(self, Self::DaemonError(message) if message == VAULT_LOCKED)
+    }
+}
+
+impl fmt::Display for VaultClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ConnectionFailed(msg) => write!(f, "Failed to connect to vault: {}", msg),
+            Self::SendFailed(msg) => write!(f, "Failed to send request: {}", msg),
+            Self::ReceiveFailed(msg) => write!(f, "Failed to receive response: {}", msg),
+            Self::SerializationError(msg) => write!(f, "Serialization error: {}", msg),
+            Self::Timeout(after) => write!(f, "Vault did not respond within {:?}", after),
+            Self::NotFound(key_id) => write!(f, "Key '{}' not found", key_id),
+            Self::DaemonError(msg) => write!(f, "Vault daemon error: {}", msg),
+        }
+    }
+}
+
+/// `unavailable` when the daemon can't be reached, `deadline_exceeded`
+/// when it stopped answering, `not_found` for a missing key,
+/// `failed_precondition` while the vault is locked, and `internal` for
+/// anything else the daemon or the protocol got wrong
+#[cfg(feature = "tonic")]
+impl From<VaultClientError> for tonic::Status {
+    fn from(e: VaultClientError) -> Self {
+        match e {
+            VaultClientError::ConnectionFailed(_) | VaultClientError::SendFailed(_) => {
+                tonic::Status::unavailable(format!("Vault daemon not available: {}", e))
+            }
+            VaultClientError::Timeout(_) => tonic::Status::deadline_exceeded(format!("Vault daemon timed out: {}", e)),
+            VaultClientError::NotFound(_) => tonic::Status::not_found(e.to_string()),
+            _ if e.is_locked() => tonic::Status::failed_precondition("Vault is locked; unlock it and retry"),
+            VaultClientError::ReceiveFailed(_) | VaultClientError::SerializationError(_) | VaultClientError::DaemonError(_) => {
+                tonic::Status::internal(format!("Vault request failed: {}", e))
+            }
+        }
+    }
+}
+
+impl Error for VaultClientError {}
+
+/// Client for the vault daemon
+///
+/// Generic over the transport so tests can run it over an in-memory pipe;
+/// `connect` opens the daemon's local socket.
+pub struct VaultClient<S = Stream> {
+    stream: BufReader<S>,
+    timeout: Duration,
+    // Id of the next request; responses must echo it
+    next_id: u64,
+    // Set when a request was cut off mid-exchange; a late reply could
+    // otherwise be read as the answer to the next request
+    broken: bool,
+}
+
+impl VaultClient<Stream> {
+    /// Connect to the daemon, retrying briefly in case it is still starting
+    pub async fn connect() -> Result<Self, VaultClientError> {
+        with_backoff(CONNECT_ATTEMPTS, CONNECT_INITIAL_BACKOFF, CONNECT_MAX_BACKOFF, Self::connect_once).await
+    }
+
+    /// Single connection attempt, for callers with their own retry policy
+    pub async fn connect_once() -> Result<Self, VaultClientError> {
+        let socket = socket_name();
+        let path = token_path(&socket);
+        // Unreadable until the daemon has started; retried like a refused connect
+        let token = std::fs::read_to_string(&path)
+            .map_err(|e| VaultClientError::ConnectionFailed(format!("Failed to read {}: {}", path.display(), e)))?;
+        let name = local_socket_name(&socket)
+            .map_err(|e| VaultClientError::ConnectionFailed(e.to_string()))?;
+
+        let stream = tokio::time::timeout(DEFAULT_REQUEST_TIMEOUT, Stream::connect(name))
+            .await
+            .map_err(|_| VaultClientError::Timeout(DEFAULT_REQUEST_TIMEOUT))?
+            .map_err(|e| VaultClientError::ConnectionFailed(e.to_string()))?;
+
+        let mut client = Self::from_stream(stream);
+        client.authenticate(token.trim().to_string()).await?;
+        Ok(client)
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> VaultClient<S> {
+    pub fn from_stream(stream: S) -> Self {
+        Self { stream: BufReader::new(stream), timeout: DEFAULT_REQUEST_TIMEOUT, next_id: 1, broken: false }
+    }
+
+    /// Replace `DEFAULT_REQUEST_TIMEOUT`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub async fn send_request(&mut self, request: VaultRequest) -> Result<VaultResponse, VaultClientError> {
+        if self.broken {
+            return Err(VaultClientError::SendFailed("Connection is out of sync after an earlier failure".to_string()));
+        }
+        // Cleared only once the reply is in; timing out or bailing on an
+        // I/O error leaves the connection unusable
+        self.broken = true;
+        let request_id = self.next_id;
+        self.next_id += 1;
+        let frame = RequestFrame { request_id, request };
+
+        match tokio::time::timeout(self.timeout, write_message(self.stream.get_mut(), &frame)).await {
+            Err(_) => return Err(VaultClientError::Timeout(self.timeout)),
+            Ok(Err(IpcError::Serialization(e))) => {
+                // Nothing was written, so the connection is still usable
+                self.broken = false;
+                return Err(VaultClientError::SerializationError(e.to_string()));
+            }
+            Ok(Err(e)) => return Err(VaultClientError::SendFailed(e.to_string())),
+            Ok(Ok(())) => {}
+        }
+
+        match tokio::time::timeout(self.timeout, read_message::<_, ResponseFrame>(&mut self.stream)).await {
+            Err(_) => Err(VaultClientError::Timeout(self.timeout)),
+            Ok(Ok(Some(frame))) if frame.request_id == Some(request_id) => {
+                self.broken = false;
+                Ok(frame.response)
+            }
+            // An error the daemon couldn't tie to a request; it never
+            // carries key material, so it can't be mistaken for an answer
+            Ok(Ok(Some(ResponseFrame { request_id: None, response: VaultResponse::Error(message) }))) => {
+                self.broken = false;
+                Ok(VaultResponse::Error(message))
+            }
+            Ok(Ok(Some(frame))) => {
+                let _ = self.stream.get_mut().shutdown().await;
+                Err(VaultClientError::ReceiveFailed(format!(
+                    "Response to request {:?} arrived while waiting for request {}",
+                    frame.request_id, request_id
+                )))
+            }
+            Ok(Ok(None)) => Err(VaultClientError::ReceiveFailed("Connection closed by vault".to_string())),
+            Ok(Err(IpcError::Serialization(e))) => {
+                // The bad line was consumed whole, so the stream is in sync
+                self.broken = false;
+                Err(VaultClientError::SerializationError(e.to_string()))
+            }
+            Ok(Err(e)) => Err(VaultClientError::ReceiveFailed(e.to_string())),
+        }
+    }
+
+    pub async fn store_key(
+        &mut self,
+        key_id: String,
+        key_data: Vec<u8>,
+        metadata: HashMap<String, String>,
+        expires_at: Option<i64>,
+    ) -> Result<(), VaultClientError> {
+        let response = self.send_request(VaultRequest::StoreKey { key_id, key_data, metadata, expires_at }).await?;
+        match response {
+            VaultResponse::Success => Ok(()),
+            other => Err(VaultClientError::from_response(other)),
+        }
+    }
+
+    pub async fn retrieve_key(&mut self, key_id: String) -> Result<(Vec<u8>, HashMap<String, String>, i64, Option<i64>), VaultClientError> {
+        let response = self.send_request(VaultRequest::RetrieveKey { key_id }).await?;
+        match response {
+            VaultResponse::KeyData { key_data, metadata, created_at, expires_at } => {
+                Ok((key_data, metadata, created_at, expires_at))
+            }
+            other => Err(VaultClientError::from_response(other)),
+        }
+    }
+
+    pub async fn delete_key(&mut self, key_id: String) -> Result<(), VaultClientError> {
+        let response = self.send_request(VaultRequest::DeleteKey { key_id }).await?;
+        match response {
+            VaultResponse::Success => Ok(()),
+            other => Err(VaultClientError::from_response(other)),
+        }
+    }
+
+    pub async fn key_exists(&mut self, key_id: String) -> Result<bool, VaultClientError> {
+        let response = self.send_request(VaultRequest::KeyExists { key_id }).await?;
+        match response {
+            VaultResponse::Exists(exists) => Ok(exists),
+            other => Err(VaultClientError::from_response(other)),
+        }
+    }
+
+    pub async fn list_keys(&mut self) -> Result<Vec<String>, VaultClientError> {
+        let response = self.send_request(VaultRequest::ListKeys).await?;
+        match response {
+            VaultResponse::KeyList(keys) => Ok(keys),
+            other => Err(VaultClientError::from_response(other)),
+        }
+    }
+
+    pub async fn purge_expired(&mut self) -> Result<usize, VaultClientError> {
+        let response = self.send_request(VaultRequest::PurgeExpired).await?;
+        match response {
+            VaultResponse::Purged(count) => Ok(count),
+            other => Err(VaultClientError::from_response(other)),
+        }
+    }
+
+    /// Delete every key under `prefix`, returning how many were deleted
+    pub async fn delete_keys_with_prefix(&mut self, prefix: String) -> Result<usize, VaultClientError> {
+        let response = self.send_request(VaultRequest::DeleteKeysWithPrefix { prefix }).await?;
+        match response {
+            VaultResponse::Purged(count) => Ok(count),
+            other => Err(VaultClientError::from_response(other)),
+        }
+    }
+
+    /// Store the `total_size` bytes read from `secret` under `key_id`, sent
+    /// in `SECRET_CHUNK_SIZE` pieces, calling `progress(done, total)` as
+    /// the daemon takes each one
+    pub async fn store_secret_stream<R: AsyncRead + Unpin>(
+        &mut self,
+        key_id: String,
+        mut secret: R,
+        total_size: u64,
+        metadata: HashMap<String, String>,
+        expires_at: Option<i64>,
+        mut progress: impl FnMut(u64, u64),
+    ) -> Result<(), VaultClientError> {
+        let mut done;
+        let mut request = VaultRequest::StoreSecretStream { key_id, total_size, metadata, expires_at };
+        loop {
+            match self.send_request(request).await? {
+                VaultResponse::Progress { done: stored, total } => {
+                    done = stored;
+                    progress(done, total);
+                }
+                other => return Err(VaultClientError::from_response(other)),
+            }
+            if done >= total_size {
+                return Ok(());
+            }
+
+            let mut data = vec![0; (total_size - done).min(SECRET_CHUNK_SIZE as u64) as usize];
+            secret.read_exact(&mut data).await.map_err(|e| VaultClientError::SendFailed(
+                format!("Secret ended after {} of {} bytes: {}", done, total_size, e)
+            ))?;
+            request = VaultRequest::SecretChunk { data };
+        }
+    }
+
+    /// Write the secret stored under `key_id` with `store_secret_stream` to
+    /// `out`, calling `progress(done, total)` after each piece
+    ///
+    /// Returns its metadata, creation time and expiry.
+    pub async fn retrieve_secret_stream<W: AsyncWrite + Unpin>(
+        &mut self,
+        key_id: String,
+        mut out: W,
+        mut progress: impl FnMut(u64, u64),
+    ) -> Result<(HashMap<String, String>, i64, Option<i64>), VaultClientError> {
+        let response = self.send_request(VaultRequest::RetrieveSecretStream { key_id }).await?;
+        let (total_size, metadata, created_at, expires_at) = match response {
+            VaultResponse::SecretStream { total_size, metadata, created_at, expires_at } => {
+                (total_size, metadata, created_at, expires_at)
+            }
+            other => return Err(VaultClientError::from_response(other)),
+        };
+
+        let mut done = 0;
+        while done < total_size {
+            match self.send_request(VaultRequest::NextSecretChunk).await? {
+                VaultResponse::SecretChunk { data, done: sent } => {
+                    if sent <= done || sent > total_size {
+                        return Err(VaultClientError::ReceiveFailed(format!(
+                            "Secret piece ends at byte {} after {} of {}", sent, done, total_size
+                        )));
+                    }
+                    out.write_all(&data).await.map_err(|e| VaultClientError::ReceiveFailed(e.to_string()))?;
+                    done = sent;
+                    progress(done, total_size);
+                }
+                other => return Err(VaultClientError::from_response(other)),
+            }
+        }
+        out.flush().await.map_err(|e| VaultClientError::ReceiveFailed(e.to_string()))?;
+        Ok((metadata, created_at, expires_at))
+    }
+
+    pub async fn unlock(&mut self, passphrase: String) -> Result<(), VaultClientError> {
+        let response = self.send_request(VaultRequest::Unlock { passphrase }).await?;
+        match response {
+            VaultResponse::Success => Ok(()),
+            other => Err(VaultClientError::from_response(other)),
+        }
+    }
+
+    pub async fn lock(&mut self) -> Result<(), VaultClientError> {
+        let response = self.send_request(VaultRequest::Lock).await?;
+        match response {
+            VaultResponse::Success => Ok(()),
+            other => Err(VaultClientError::from_response(other)),
+        }
+    }
+
+    /// True while the vault is locked
+    pub async fn is_locked(&mut self) -> Result<bool, VaultClientError> {
+        let response = self.send_request(VaultRequest::Status).await?;
+        match response {
+            VaultResponse::Status { locked } => Ok(locked),
+            other => Err(VaultClientError::from_response(other)),
+        }
+    }
+
+    /// Present the daemon's token; `connect` does this already
+    pub async fn authenticate(&mut self, token: String) -> Result<(), VaultClientError> {
+        let response = self.send_request(VaultRequest::Authenticate { token }).await?;
+        match response {
+            VaultResponse::Success => Ok(()),
+            VaultResponse::Error(message) => Err(VaultClientError::ConnectionFailed(message)),
+            _ => Err(VaultClientError::ReceiveFailed("Unexpected response type".to_string())),
+        }
+    }
+
+    pub async fn ping(&mut self) -> Result<(), VaultClientError> {
+        let response = self.send_request(VaultRequest::Ping).await?;
+        match response {
+            VaultResponse::Pong => Ok(()),
+            other => Err(VaultClientError::from_response(other)),
+        }
+    }
+}
+
+/// Run `attempt` up to `attempts` times, sleeping between failures with a
+/// delay starting at `initial` and doubling up to `max`
+pub(crate) async fn with_backoff<T, F, Fut>(
+    attempts: u32,
+    initial: Duration,
+    max: Duration,
+    mut attempt: F,
+) -> Result<T, VaultClientError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, VaultClientError>>,
+{
+    let mut backoff = initial;
+    let mut tried = 1;
+    loop {
+        match attempt().await {
+            Ok(value) => return Ok(value),
+            Err(e) if tried >= attempts => return Err(e),
+            Err(_) => {
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(max);
+                tried += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::DuplexStream;
+
+    const TIMEOUT: Duration = Duration::from_millis(50);
+
+    #[tokio::test]
+    async fn test_silent_server_times_out() {
+        // The server end stays open but never answers
+        let (client, _server) = tokio::io::duplex(4096);
+        let mut client = VaultClient::from_stream(client).with_timeout(TIMEOUT);
+
+        assert!(matches!(client.ping().await, Err(VaultClientError::Timeout(after)) if after == TIMEOUT));
+        // A late reply must not be taken as the answer to the next request
+        assert!(matches!(client.list_keys().await, Err(VaultClientError::SendFailed(_))));
+    }
+
+    /// Answer every request with `Pong`, tagged with the id `tag` picks
+    fn spawn_server(server: DuplexStream, tag: fn(u64) -> Option<u64>) {
+        spawn_server_answering(server, tag, VaultResponse::Pong);
+    }
+
+    fn spawn_server_answering(server: DuplexStream, tag: fn(u64) -> Option<u64>, answer: VaultResponse) {
+        tokio::spawn(async move {
+            let mut server = BufReader::new(server);
+            while let Ok(Some(frame)) = read_message::<_, RequestFrame>(&mut server).await {
+                let response = ResponseFrame { request_id: tag(frame.request_id), response: answer.clone() };
+                if write_message(server.get_mut(), &response).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    #[tokio::test]
+    async fn test_request_ids_count_up() {
+        let (client, server) = tokio::io::duplex(4096);
+        spawn_server(server, Some);
+        let mut client = VaultClient::from_stream(client);
+
+        for _ in 0..3 {
+            client.ping().await.unwrap();
+        }
+        assert_eq!(client.next_id, 4);
+    }
+
+    #[tokio::test]
+    async fn test_mismatched_response_closes_connection() {
+        // A reply left over from an earlier request
+        let (client, server) = tokio::io::duplex(4096);
+        spawn_server(server, |id| Some(id + 100));
+        let mut client = VaultClient::from_stream(client);
+
+        let err = client.ping().await.unwrap_err();
+        assert!(matches!(err, VaultClientError::ReceiveFailed(ref message) if message.contains("waiting for request 1")));
+        assert!(matches!(client.ping().await, Err(VaultClientError::SendFailed(_))));
+    }
+
+    #[tokio::test]
+    async fn test_stalled_write_times_out() {
+        // Buffer too small for the request and nobody reading the other end
+        let (client, _server) = tokio::io::duplex(8);
+        let mut client = VaultClient::from_stream(client).with_timeout(TIMEOUT);
+
+        let result = client.store_key("k".to_string(), vec![0; 64], HashMap::new(), None).await;
+        assert!(matches!(result, Err(VaultClientError::Timeout(_))));
+    }
+
+    #[tokio::test]
+    async fn test_missing_key_told_apart_from_failure() {
+        let (client, server) = tokio::io::duplex(4096);
+        spawn_server_answering(server, Some, VaultResponse::NotFound { key_id: "gone".to_string() });
+        let mut client = VaultClient::from_stream(client);
+        let err = client.retrieve_key("gone".to_string()).await.unwrap_err();
+        assert!(matches!(err, VaultClientError::NotFound(ref key_id) if key_id == "gone"));
+
+        let (client, server) = tokio::io::duplex(4096);
+        let failure = "Failed to retrieve key: Decryption failed".to_string();
+        spawn_server_answering(server, Some, VaultResponse::Error(failure.clone()));
+        let mut client = VaultClient::from_stream(client);
+        let err = client.retrieve_key("broken".to_string()).await.unwrap_err();
+        assert!(matches!(err, VaultClientError::DaemonError(ref message) if *message == failure));
+        assert!(!err.is_locked());
+
+        let (client, server) = tokio::io::duplex(4096);
+        spawn_server_answering(server, Some, VaultResponse::Error(VAULT_LOCKED.to_string()));
+        let mut client = VaultClient::from_stream(client);
+        let err = client.retrieve_key("k".to_string()).await.unwrap_err();
+        assert!(err.is_locked(), "{}", err);
+    }
+
+    #[cfg(feature = "tonic")]
+    #[test]
+    fn test_errors_map_to_status_codes() {
+        use tonic::{Code, Status};
+
+        let code = |e: VaultClientError| Status::from(e).code();
+        assert_eq!(code(VaultClientError::ConnectionFailed("refused".to_string())), Code::Unavailable);
+        assert_eq!(code(VaultClientError::SendFailed("broken pipe".to_string())), Code::Unavailable);
+        assert_eq!(code(VaultClientError::Timeout(TIMEOUT)), Code::DeadlineExceeded);
+        assert_eq!(code(VaultClientError::NotFound("k".to_string())), Code::NotFound);
+        assert_eq!(code(VaultClientError::DaemonError(VAULT_LOCKED.to_string())), Code::FailedPrecondition);
+        assert_eq!(code(VaultClientError::DaemonError("Failed to store key: disk full".to_string())), Code::Internal);
+        assert_eq!(code(VaultClientError::ReceiveFailed("Connection closed by vault".to_string())), Code::Internal);
+        assert_eq!(code(VaultClientError::SerializationError("bad frame".to_string())), Code::Internal);
+    }
+
+    #[tokio::test]
+    async fn test_backoff_retries_until_success_or_limit() {
+        let mut calls = 0;
+        let result = with_backoff(3, Duration::from_millis(1), Duration::from_millis(2), || {
+            calls += 1;
+            let attempt = calls;
+            async move {
+                if attempt < 3 {
+                    Err(VaultClientError::ConnectionFailed("starting".to_string()))
+                } else {
+                    Ok(attempt)
+                }
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 3);
+
+        let mut calls = 0;
+        let result: Result<(), _> = with_backoff(2, Duration::from_millis(1), Duration::from_millis(2), || {
+            calls += 1;
+            async { Err(VaultClientError::ConnectionFailed("down".to_string())) }
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls, 2);
+    }
+}
@@ -0,0 +1,19 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum IpcError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// The line was read completely but isn't a valid message; the
+    /// connection is still in sync and may be reused
+    #[error("Invalid message: {0}")]
+    Serialization(#[from] serde_json::Error),
+
+    /// The peer sent a line longer than the limit; the rest of it is still
+    /// unread, so the connection must be dropped
+    #[error("Message exceeds {limit} bytes")]
+    MessageTooLarge { limit: usize },
+}
+
+pub type Result<T> = std::result::Result<T, IpcError>;
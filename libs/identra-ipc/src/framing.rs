@@ -0,0 +1,175 @@
+use crate::error::{IpcError, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Largest message `read_message` accepts, excluding the newline
+pub const MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+/// Write `message` as one line of JSON and flush
+pub async fn write_message<W, T>(writer: &mut W, message: &T) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+    T: Serialize,
+{
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    writer.write_all(&line).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Read the next line and decode it as `T`
+///
+/// Returns `Ok(None)` when the peer closed the connection cleanly.
+pub async fn read_message<R, T>(reader: &mut R) -> Result<Option<T>>
+where
+    R: AsyncBufRead + Unpin,
+    T: DeserializeOwned,
+{
+    read_message_with_limit(reader, MAX_MESSAGE_SIZE).await
+}
+
+/// `read_message` with a custom size cap
+///
+/// Stops reading once `max_size` bytes arrive without a newline, so a peer
+/// can't make us buffer an unbounded line. The rest of that line is left
+/// unread, so the connection can't be reused after `MessageTooLarge`.
+pub async fn read_message_with_limit<R, T>(reader: &mut R, max_size: usize) -> Result<Option<T>>
+where
+    R: AsyncBufRead + Unpin,
+    T: DeserializeOwned,
+{
+    let mut line = Vec::new();
+    let limit = max_size as u64 + 1;
+    if reader.take(limit).read_until(b'\n', &mut line).await? == 0 {
+        return Ok(None);
+    }
+    if line.last() == Some(&b'\n') {
+        line.pop();
+    }
+    if line.len() > max_size {
+        return Err(IpcError::MessageTooLarge { limit: max_size });
+    }
+    Ok(Some(serde_json::from_slice(&line)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::IpcError;
+    use crate::protocol::{VaultRequest, VaultResponse};
+    use std::collections::HashMap;
+    use tokio::io::BufReader;
+
+    #[tokio::test]
+    async fn test_every_message_round_trips() {
+        let requests = vec![
+            VaultRequest::StoreKey {
+                key_id: "k".into(),
+                key_data: vec![0, 10, 255],
+                metadata: HashMap::from([("line".into(), "has\nnewline".into())]),
+                expires_at: Some(1_700_000_000),
+            },
+            VaultRequest::RetrieveKey { key_id: "k".into() },
+            VaultRequest::DeleteKey { key_id: "k".into() },
+            VaultRequest::KeyExists { key_id: "k".into() },
+            VaultRequest::ListKeys,
+            VaultRequest::PurgeExpired,
+            VaultRequest::DeleteKeysWithPrefix { prefix: "user-1/".into() },
+            VaultRequest::StoreSecretStream {
+                key_id: "big".into(),
+                total_size: 3,
+                metadata: HashMap::new(),
+                expires_at: None,
+            },
+            VaultRequest::SecretChunk { data: vec![1, 2, 3] },
+            VaultRequest::RetrieveSecretStream { key_id: "big".into() },
+            VaultRequest::NextSecretChunk,
+            VaultRequest::Unlock { passphrase: "hunter2".into() },
+            VaultRequest::Lock,
+            VaultRequest::Status,
+            VaultRequest::Ping,
+            VaultRequest::Shutdown,
+        ];
+        let responses = vec![
+            VaultResponse::Success,
+            VaultResponse::KeyData {
+                key_data: vec![1, 2, 3],
+                metadata: HashMap::new(),
+                created_at: 1,
+                expires_at: None,
+            },
+            VaultResponse::KeyList(vec!["a".into(), "b".into()]),
+            VaultResponse::Exists(true),
+            VaultResponse::Purged(3),
+            VaultResponse::NotFound { key_id: "k".into() },
+            VaultResponse::Progress { done: 1, total: 3 },
+            VaultResponse::SecretStream {
+                total_size: 3,
+                metadata: HashMap::new(),
+                created_at: 1,
+                expires_at: Some(2),
+            },
+            VaultResponse::SecretChunk { data: vec![1, 2, 3], done: 3 },
+            VaultResponse::Status { locked: true },
+            VaultResponse::Error("boom".into()),
+            VaultResponse::Pong,
+            VaultResponse::ShuttingDown,
+        ];
+
+        let mut wire = Vec::new();
+        for request in &requests {
+            write_message(&mut wire, request).await.unwrap();
+        }
+        for response in &responses {
+            write_message(&mut wire, response).await.unwrap();
+        }
+        assert_eq!(wire.iter().filter(|b| **b == b'\n').count(), requests.len() + responses.len());
+
+        let mut reader = BufReader::new(wire.as_slice());
+        for request in &requests {
+            let decoded: VaultRequest = read_message(&mut reader).await.unwrap().unwrap();
+            assert_eq!(&decoded, request);
+        }
+        for response in &responses {
+            let decoded: VaultResponse = read_message(&mut reader).await.unwrap().unwrap();
+            assert_eq!(&decoded, response);
+        }
+        assert!(read_message::<_, VaultRequest>(&mut reader).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_line_leaves_stream_in_sync() {
+        let mut wire = b"not json\n".to_vec();
+        write_message(&mut wire, &VaultRequest::Ping).await.unwrap();
+        let mut reader = BufReader::new(wire.as_slice());
+
+        let err = read_message::<_, VaultRequest>(&mut reader).await.unwrap_err();
+        assert!(matches!(err, IpcError::Serialization(_)));
+
+        let next: VaultRequest = read_message(&mut reader).await.unwrap().unwrap();
+        assert_eq!(next, VaultRequest::Ping);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_line_rejected() {
+        let mut wire = vec![b'a'; 64];
+        wire.push(b'\n');
+        let mut reader = BufReader::new(wire.as_slice());
+
+        let err = read_message_with_limit::<_, VaultRequest>(&mut reader, 16).await.unwrap_err();
+        assert!(matches!(err, IpcError::MessageTooLarge { limit: 16 }));
+    }
+
+    #[tokio::test]
+    async fn test_line_at_limit_accepted() {
+        let mut wire = Vec::new();
+        write_message(&mut wire, &VaultRequest::Ping).await.unwrap();
+        let limit = wire.len() - 1;
+        let mut reader = BufReader::new(wire.as_slice());
+
+        let request: VaultRequest = read_message_with_limit(&mut reader, limit).await.unwrap().unwrap();
+        assert_eq!(request, VaultRequest::Ping);
+    }
+}
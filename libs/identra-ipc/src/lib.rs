@@ -0,0 +1,123 @@
+//! Wire protocol between the vault daemon and its clients
+//!
+//! Shared by `vault-daemon` (server), `tunnel-gateway` and `ghost-desktop`
+//! (clients) so all three always agree on the message shapes and framing.
+//!
+//! # Framing
+//!
+//! Every message is one JSON document followed by a single `\n`. serde_json
+//! never emits raw newlines, so a line is always exactly one message.
+//! Requests and responses alternate strictly on a connection: the client
+//! writes one `RequestFrame` line and reads one `ResponseFrame` line before
+//! sending the next request. The response echoes the request's
+//! `request_id`, so a late reply to an abandoned request is detected
+//! instead of being taken for the answer to the next one. Either side may
+//! close the connection between messages. Lines longer than
+//! `MAX_MESSAGE_SIZE` are rejected.
+//!
+//! The first request on every connection must be `Authenticate` with the
+//! token the daemon wrote to `token_path()` when it started; the daemon
+//! answers anything else, or a wrong token, with an error and hangs up.
+//!
+//! Secrets larger than a key are moved in pieces: `StoreSecretStream`
+//! followed by `SecretChunk`s, or `RetrieveSecretStream` followed by
+//! `NextSecretChunk`s, each still one request and one response.
+//!
+//! Enums use serde's default externally tagged representation, e.g.
+//! `{"request_id":7,"request":{"RetrieveKey":{"key_id":"abc"}}}` and
+//! `{"request_id":7,"response":"Pong"}`.
+
+pub mod client;
+pub mod error;
+pub mod framing;
+pub mod pool;
+pub mod protocol;
+
+pub use client::{VaultClient, VaultClientError};
+pub use pool::{PoolConfig, PooledClient, VaultClientPool};
+pub use error::IpcError;
+pub use framing::{read_message, read_message_with_limit, write_message, MAX_MESSAGE_SIZE};
+pub use protocol::{
+    RequestFrame, ResponseFrame, VaultRequest, VaultResponse, MAX_SECRET_SIZE, SECRET_CHUNK_SIZE, VAULT_LOCKED,
+};
+
+/// Named pipe the vault daemon listens on
+#[cfg(windows)]
+pub const PIPE_NAME: &str = "@identra-vault";
+
+/// Socket file the vault daemon listens on, inside `runtime_dir()`
+#[cfg(unix)]
+pub const SOCKET_FILE: &str = "identra-vault.sock";
+
+/// Environment variable overriding the default socket, e.g. to run a second daemon
+pub const SOCKET_ENV: &str = "IDENTRA_VAULT_SOCKET";
+
+/// Socket name to listen on or connect to: `IDENTRA_VAULT_SOCKET` if set,
+/// otherwise `PIPE_NAME` on Windows and `SOCKET_FILE` in `runtime_dir()`
+/// on Unix
+pub fn socket_name() -> String {
+    std::env::var(SOCKET_ENV)
+        .ok()
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(default_socket_name)
+}
+
+#[cfg(windows)]
+fn default_socket_name() -> String {
+    PIPE_NAME.to_string()
+}
+
+#[cfg(unix)]
+fn default_socket_name() -> String {
+    runtime_dir().join(SOCKET_FILE).to_string_lossy().into_owned()
+}
+
+/// Per-user directory for the socket: `$XDG_RUNTIME_DIR`, or
+/// `identra-<uid>` in the temp dir where there is none (e.g. macOS)
+///
+/// Never a world-writable directory itself, so other users can neither
+/// reach the socket nor plant one in its place.
+#[cfg(unix)]
+pub fn runtime_dir() -> std::path::PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        Some(dir) => dir.into(),
+        // SAFETY: getuid has no preconditions and cannot fail
+        None => std::env::temp_dir().join(format!("identra-{}", unsafe { libc::getuid() })),
+    }
+}
+
+/// File holding the token clients of the daemon on `socket` authenticate with
+///
+/// Next to the socket on Unix (`identra-vault.token` in `runtime_dir()`),
+/// and in `%LOCALAPPDATA%\identra` on Windows, where pipes have no
+/// directory. Rewritten with a fresh token each time the daemon starts.
+pub fn token_path(socket: &str) -> std::path::PathBuf {
+    #[cfg(unix)]
+    {
+        std::path::Path::new(socket).with_extension("token")
+    }
+    #[cfg(windows)]
+    {
+        let file = socket.trim_start_matches('@').replace(['\\', '/', ':'], "_");
+        std::env::var_os("LOCALAPPDATA")
+            .map(std::path::PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir)
+            .join("identra")
+            .join(format!("{}.token", file))
+    }
+}
+
+/// `socket_name()` as a local socket name: a file path on Unix, where
+/// permissions apply, and a named pipe on Windows
+pub fn local_socket_name(name: &str) -> std::io::Result<interprocess::local_socket::Name<'_>> {
+    #[cfg(unix)]
+    {
+        use interprocess::local_socket::{GenericFilePath, ToFsName};
+        name.to_fs_name::<GenericFilePath>()
+    }
+    #[cfg(windows)]
+    {
+        use interprocess::local_socket::{GenericNamespaced, ToNsName};
+        name.to_ns_name::<GenericNamespaced>()
+    }
+}
@@ -0,0 +1,279 @@
+use crate::client::{with_backoff, VaultClient, VaultClientError, DEFAULT_REQUEST_TIMEOUT};
+use interprocess::local_socket::tokio::Stream;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+type ConnectFuture<S> = Pin<Box<dyn Future<Output = Result<VaultClient<S>, VaultClientError>> + Send>>;
+type Connector<S> = Box<dyn Fn() -> ConnectFuture<S> + Send + Sync>;
+
+/// Sizing and reconnect policy for `VaultClientPool`
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    /// Most connections open (and handed out) at once
+    pub max_size: usize,
+    /// Connection attempts per `acquire` before giving up
+    pub connect_attempts: u32,
+    /// Delay after the first failed attempt, doubled after each further one
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Per-request timeout of the clients handed out
+    pub request_timeout: Duration,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 4,
+            connect_attempts: 3,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(1),
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+        }
+    }
+}
+
+/// Small pool of warm connections to the vault daemon
+///
+/// Idle connections are checked with a `Ping` before being handed out, so
+/// one left dead by a daemon restart is replaced by a fresh connection
+/// instead of failing the caller's request. Cheap to clone; clones share
+/// the same connections.
+pub struct VaultClientPool<S = Stream> {
+    inner: Arc<PoolInner<S>>,
+}
+
+struct PoolInner<S> {
+    idle: Mutex<Vec<VaultClient<S>>>,
+    permits: Arc<Semaphore>,
+    connect: Connector<S>,
+    config: PoolConfig,
+}
+
+impl<S> Clone for VaultClientPool<S> {
+    fn clone(&self) -> Self {
+        Self { inner: self.inner.clone() }
+    }
+}
+
+impl VaultClientPool<Stream> {
+    /// Pool of connections to the daemon's local socket
+    ///
+    /// Nothing is opened until the first `acquire`.
+    pub fn new(config: PoolConfig) -> Self {
+        Self::with_connector(config, || Box::pin(VaultClient::connect_once()))
+    }
+}
+
+impl<S> VaultClientPool<S>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+{
+    /// Pool opening connections with `connect`, for other transports and tests
+    pub fn with_connector<F>(config: PoolConfig, connect: F) -> Self
+    where
+        F: Fn() -> ConnectFuture<S> + Send + Sync + 'static,
+    {
+        Self {
+            inner: Arc::new(PoolInner {
+                idle: Mutex::new(Vec::new()),
+                permits: Arc::new(Semaphore::new(config.max_size.max(1))),
+                connect: Box::new(connect),
+                config,
+            }),
+        }
+    }
+
+    /// Borrow a live connection, waiting while `max_size` are in use
+    ///
+    /// The connection goes back to the pool when the returned guard drops.
+    pub async fn acquire(&self) -> Result<PooledClient<S>, VaultClientError> {
+        let permit = self.inner.permits.clone()
+            .acquire_owned()
+            .await
+            .expect("pool semaphore is never closed");
+
+        loop {
+            let idle = self.inner.idle.lock().expect("pool mutex poisoned").pop();
+            let Some(mut client) = idle else { break };
+            if client.ping().await.is_ok() {
+                return Ok(self.guard(client, permit));
+            }
+            // Dead or out of sync (e.g. the daemon restarted); drop it
+        }
+
+        let client = self.connect_with_backoff().await?;
+        Ok(self.guard(client, permit))
+    }
+
+    /// Number of idle connections currently held
+    pub fn idle_count(&self) -> usize {
+        self.inner.idle.lock().expect("pool mutex poisoned").len()
+    }
+
+    async fn connect_with_backoff(&self) -> Result<VaultClient<S>, VaultClientError> {
+        let config = &self.inner.config;
+        let client = with_backoff(
+            config.connect_attempts,
+            config.initial_backoff,
+            config.max_backoff,
+            || (self.inner.connect)(),
+        )
+        .await?;
+        Ok(client.with_timeout(config.request_timeout))
+    }
+
+    fn guard(&self, client: VaultClient<S>, permit: OwnedSemaphorePermit) -> PooledClient<S> {
+        PooledClient { client: Some(client), pool: self.inner.clone(), _permit: permit }
+    }
+}
+
+/// A connection borrowed from `VaultClientPool`; derefs to `VaultClient`
+pub struct PooledClient<S = Stream> {
+    client: Option<VaultClient<S>>,
+    pool: Arc<PoolInner<S>>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl<S> Deref for PooledClient<S> {
+    type Target = VaultClient<S>;
+
+    fn deref(&self) -> &Self::Target {
+        self.client.as_ref().expect("client is only taken on drop")
+    }
+}
+
+impl<S> DerefMut for PooledClient<S> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.client.as_mut().expect("client is only taken on drop")
+    }
+}
+
+impl<S> Drop for PooledClient<S> {
+    fn drop(&mut self) {
+        if let (Some(client), Ok(mut idle)) = (self.client.take(), self.pool.idle.lock()) {
+            idle.push(client);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framing::{read_message, write_message};
+    use crate::protocol::{RequestFrame, ResponseFrame, VaultRequest, VaultResponse};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{BufReader, DuplexStream};
+    use tokio::task::JoinHandle;
+
+    /// Stand-in daemon: every connection is served by a task that
+    /// `restart` aborts, as a real daemon restart would close them
+    #[derive(Clone, Default)]
+    struct FakeDaemon {
+        tasks: Arc<Mutex<Vec<JoinHandle<()>>>>,
+        connects: Arc<AtomicUsize>,
+        /// Refuse this many connection attempts before accepting
+        refuse: Arc<AtomicUsize>,
+    }
+
+    impl FakeDaemon {
+        fn pool(&self, config: PoolConfig) -> VaultClientPool<DuplexStream> {
+            let daemon = self.clone();
+            VaultClientPool::with_connector(config, move || {
+                let daemon = daemon.clone();
+                Box::pin(async move { daemon.connect() })
+            })
+        }
+
+        fn connect(&self) -> Result<VaultClient<DuplexStream>, VaultClientError> {
+            self.connects.fetch_add(1, Ordering::SeqCst);
+            if self.refuse.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1)).is_ok() {
+                return Err(VaultClientError::ConnectionFailed("refused".to_string()));
+            }
+
+            let (client, server) = tokio::io::duplex(4096);
+            let task = tokio::spawn(async move {
+                let mut server = BufReader::new(server);
+                while let Ok(Some(frame)) = read_message::<_, RequestFrame>(&mut server).await {
+                    let response = match frame.request {
+                        VaultRequest::Ping => VaultResponse::Pong,
+                        _ => VaultResponse::KeyList(vec!["k".to_string()]),
+                    };
+                    let response = ResponseFrame { request_id: Some(frame.request_id), response };
+                    if write_message(server.get_mut(), &response).await.is_err() {
+                        break;
+                    }
+                }
+            });
+            self.tasks.lock().unwrap().push(task);
+            Ok(VaultClient::from_stream(client))
+        }
+
+        async fn restart(&self) {
+            let tasks: Vec<_> = self.tasks.lock().unwrap().drain(..).collect();
+            for task in tasks {
+                task.abort();
+                let _ = task.await;
+            }
+        }
+    }
+
+    fn fast_config() -> PoolConfig {
+        PoolConfig {
+            max_size: 2,
+            connect_attempts: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(4),
+            request_timeout: Duration::from_millis(200),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reuses_idle_connections() {
+        let daemon = FakeDaemon::default();
+        let pool = daemon.pool(fast_config());
+
+        for _ in 0..3 {
+            let mut client = pool.acquire().await.unwrap();
+            assert_eq!(client.list_keys().await.unwrap(), vec!["k".to_string()]);
+        }
+        assert_eq!(daemon.connects.load(Ordering::SeqCst), 1);
+        assert_eq!(pool.idle_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_recovers_after_daemon_restart() {
+        let daemon = FakeDaemon::default();
+        let pool = daemon.pool(fast_config());
+
+        let mut client = pool.acquire().await.unwrap();
+        client.list_keys().await.unwrap();
+        drop(client);
+
+        // The idle connection dies with the old daemon process
+        daemon.restart().await;
+
+        let mut client = pool.acquire().await.unwrap();
+        assert_eq!(client.list_keys().await.unwrap(), vec!["k".to_string()]);
+        assert_eq!(daemon.connects.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_connect_retries_are_bounded() {
+        let daemon = FakeDaemon::default();
+        let pool = daemon.pool(fast_config());
+
+        // Still starting up: the third attempt gets through
+        daemon.refuse.store(2, Ordering::SeqCst);
+        assert!(pool.acquire().await.is_ok());
+        assert_eq!(daemon.connects.load(Ordering::SeqCst), 3);
+
+        daemon.restart().await;
+        daemon.refuse.store(10, Ordering::SeqCst);
+        assert!(matches!(pool.acquire().await, Err(VaultClientError::ConnectionFailed(_))));
+        assert_eq!(daemon.connects.load(Ordering::SeqCst), 6);
+    }
+}
@@ -0,0 +1,108 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Largest secret `StoreSecretStream` accepts
+pub const MAX_SECRET_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Largest `SecretChunk`; well under `MAX_MESSAGE_SIZE` once JSON-encoded
+pub const SECRET_CHUNK_SIZE: usize = 64 * 1024;
+
+/// `VaultResponse::Error` message for key operations attempted while the
+/// vault is locked, so clients can tell it from other failures
+pub const VAULT_LOCKED: &str = "vault locked";
+
+/// Requests a client can send to the vault daemon
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum VaultRequest {
+    /// Contents of the daemon's `token_path()` file; must be the first
+    /// request on a connection, which is closed if it is anything else
+    Authenticate { token: String },
+    StoreKey {
+        key_id: String,
+        key_data: Vec<u8>,
+        metadata: HashMap<String, String>,
+        expires_at: Option<i64>, // Unix timestamp
+    },
+    RetrieveKey { key_id: String },
+    DeleteKey { key_id: String },
+    KeyExists { key_id: String },
+    ListKeys,
+    PurgeExpired,
+    /// Delete every key whose id starts with `prefix`, e.g. a user's
+    /// `"<user_id>/"` keys when their account is deleted
+    DeleteKeysWithPrefix { prefix: String },
+    /// Start storing a secret too large for `StoreKey` under `key_id`,
+    /// answered with `Progress`. Its `total_size` bytes follow in
+    /// `SecretChunk`s; it replaces any earlier `key_id` once the last arrives.
+    StoreSecretStream {
+        key_id: String,
+        total_size: u64,
+        metadata: HashMap<String, String>,
+        expires_at: Option<i64>,
+    },
+    /// Next piece of the secret being stored on this connection
+    SecretChunk { data: Vec<u8> },
+    /// Start reading a secret stored with `StoreSecretStream`, answered
+    /// with `SecretStream`; its pieces are fetched with `NextSecretChunk`
+    RetrieveSecretStream { key_id: String },
+    NextSecretChunk,
+    /// Derive the master key from `passphrase`; the first unlock sets it
+    Unlock { passphrase: String },
+    Lock,
+    Status,
+    Ping,
+    Shutdown,
+}
+
+/// Responses sent by the vault daemon, one per request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum VaultResponse {
+    Success,
+    KeyData {
+        key_data: Vec<u8>,
+        metadata: HashMap<String, String>,
+        created_at: i64,
+        expires_at: Option<i64>,
+    },
+    KeyList(Vec<String>),
+    Exists(bool),
+    Purged(usize),
+    /// The key asked for isn't in the vault
+    NotFound { key_id: String },
+    /// Bytes of a secret stored so far; all of them once it is stored
+    Progress { done: u64, total: u64 },
+    /// A streamed secret's size and metadata, ahead of its pieces
+    SecretStream {
+        total_size: u64,
+        metadata: HashMap<String, String>,
+        created_at: i64,
+        expires_at: Option<i64>,
+    },
+    /// The next piece of a streamed secret, and the bytes sent with it so far
+    SecretChunk { data: Vec<u8>, done: u64 },
+    Status { locked: bool },
+    Error(String),
+    Pong,
+    ShuttingDown,
+}
+
+/// A request as sent on the wire, tagged with an id its response echoes
+///
+/// Ids only need to be unique per connection; `VaultClient` counts up from 1.
+/// The daemon reads the request as a `serde_json::Value` first so that a
+/// malformed request can still be answered with the right id.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RequestFrame<R = VaultRequest> {
+    pub request_id: u64,
+    pub request: R,
+}
+
+/// A response as sent on the wire
+///
+/// `request_id` is `None` only for errors the daemon can't attribute to a
+/// request, such as an unparseable line or an idle timeout.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResponseFrame {
+    pub request_id: Option<u64>,
+    pub response: VaultResponse,
+}
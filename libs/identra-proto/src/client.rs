@@ -0,0 +1,229 @@
+// Client-side helpers for calling the gateway as a signed-in user
+use crate::auth::{auth_service_client::AuthServiceClient, RefreshTokenRequest, RefreshTokenResponse};
+use crate::memory::memory_service_client::MemoryServiceClient;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tonic::service::interceptor::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::transport::Channel;
+use tonic::{Request, Status};
+
+/// How long before expiry an access token is replaced unless set otherwise
+pub const DEFAULT_REFRESH_BEFORE: Duration = Duration::from_secs(60);
+
+/// Adds `authorization: Bearer <token>` to every outgoing request
+///
+/// Clones share the token, so a `TokenManager` refreshing it updates every
+/// client built with one of its interceptors.
+#[derive(Debug, Clone, Default)]
+pub struct AuthInterceptor {
+    token: Arc<RwLock<String>>,
+}
+
+impl AuthInterceptor {
+    /// Interceptor for a token that is never refreshed
+    pub fn new(token: impl Into<String>) -> Self {
+        Self { token: Arc::new(RwLock::new(token.into())) }
+    }
+
+    fn set(&self, token: String) {
+        *self.token.write().unwrap_or_else(|e| e.into_inner()) = token;
+    }
+}
+
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, mut req: Request<()>) -> Result<Request<()>, Status> {
+        let token = self.token.read().unwrap_or_else(|e| e.into_inner());
+        let value = format!("Bearer {}", token)
+            .parse()
+            .map_err(|_| Status::unauthenticated("Access token isn't valid in metadata"))?;
+        req.metadata_mut().insert("authorization", value);
+        Ok(req)
+    }
+}
+
+/// Tokens from `Login`, `VerifyEmail` or `RefreshToken`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    pub access_token: String,
+    pub refresh_token: String,
+    /// Lifetime of `access_token` from when it was issued
+    pub expires_in: Duration,
+}
+
+/// Exchanges a refresh token for a new session
+#[tonic::async_trait]
+pub trait Refresh: Send + Sync {
+    async fn refresh(&self, refresh_token: &str) -> Result<RefreshTokenResponse, Status>;
+}
+
+#[tonic::async_trait]
+impl Refresh for AuthServiceClient<Channel> {
+    async fn refresh(&self, refresh_token: &str) -> Result<RefreshTokenResponse, Status> {
+        let request = RefreshTokenRequest { refresh_token: refresh_token.to_string() };
+        Ok(self.clone().refresh_token(request).await?.into_inner())
+    }
+}
+
+struct Refreshable {
+    refresh_token: String,
+    expires_at: Instant,
+}
+
+/// Keeps a session's access token fresh for the interceptors it hands out
+///
+/// `ensure_fresh` swaps in a new access token once the current one is
+/// within `refresh_before` of expiring. Concurrent callers wait for a single
+/// refresh, since each refresh token can only be spent once.
+pub struct TokenManager<R = AuthServiceClient<Channel>> {
+    interceptor: AuthInterceptor,
+    state: tokio::sync::Mutex<Refreshable>,
+    refresher: R,
+    refresh_before: Duration,
+}
+
+impl<R: Refresh> TokenManager<R> {
+    pub fn new(session: Session, refresher: R) -> Self {
+        Self {
+            interceptor: AuthInterceptor::new(session.access_token),
+            state: tokio::sync::Mutex::new(Refreshable {
+                refresh_token: session.refresh_token,
+                expires_at: Instant::now() + session.expires_in,
+            }),
+            refresher,
+            refresh_before: DEFAULT_REFRESH_BEFORE,
+        }
+    }
+
+    /// Refresh this long before the access token expires
+    pub fn with_refresh_before(mut self, refresh_before: Duration) -> Self {
+        self.refresh_before = refresh_before;
+        self
+    }
+
+    /// Interceptor attaching the current access token
+    pub fn interceptor(&self) -> AuthInterceptor {
+        self.interceptor.clone()
+    }
+
+    /// Refresh the access token if it expires within `refresh_before`
+    ///
+    /// A refused refresh is `Unauthenticated`: the user has to sign in again.
+    pub async fn ensure_fresh(&self) -> Result<(), Status> {
+        let mut state = self.state.lock().await;
+        if Instant::now() + self.refresh_before < state.expires_at {
+            return Ok(());
+        }
+
+        let refreshed = self.refresher.refresh(&state.refresh_token).await?;
+        if !refreshed.success {
+            return Err(Status::unauthenticated("Session expired; sign in again"));
+        }
+        self.interceptor.set(refreshed.access_token);
+        state.refresh_token = refreshed.refresh_token;
+        state.expires_at = Instant::now() + Duration::from_secs(refreshed.expires_in.max(0) as u64);
+        Ok(())
+    }
+}
+
+/// Gateway clients that sign every request in as one user
+pub struct AuthenticatedClient {
+    channel: Channel,
+    tokens: TokenManager,
+}
+
+impl AuthenticatedClient {
+    /// Client for `session`, refreshed through the gateway's auth service on `channel`
+    pub fn new(channel: Channel, session: Session) -> Self {
+        let tokens = TokenManager::new(session, AuthServiceClient::new(channel.clone()));
+        Self { channel, tokens }
+    }
+
+    pub fn with_refresh_before(mut self, refresh_before: Duration) -> Self {
+        self.tokens = self.tokens.with_refresh_before(refresh_before);
+        self
+    }
+
+    /// Memory service client holding a token good for at least `refresh_before`
+    pub async fn memory(&self) -> Result<MemoryServiceClient<InterceptedService<Channel, AuthInterceptor>>, Status> {
+        self.tokens.ensure_fresh().await?;
+        Ok(MemoryServiceClient::with_interceptor(self.channel.clone(), self.tokens.interceptor()))
+    }
+
+    /// Auth service client for the calls that need the user's token, e.g. `Logout`
+    pub async fn auth(&self) -> Result<AuthServiceClient<InterceptedService<Channel, AuthInterceptor>>, Status> {
+        self.tokens.ensure_fresh().await?;
+        Ok(AuthServiceClient::with_interceptor(self.channel.clone(), self.tokens.interceptor()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Hands out `access-N`/`refresh-N`, checking each refresh token is spent once
+    #[derive(Default)]
+    struct CountingRefresher {
+        spent: Mutex<Vec<String>>,
+    }
+
+    #[tonic::async_trait]
+    impl Refresh for CountingRefresher {
+        async fn refresh(&self, refresh_token: &str) -> Result<RefreshTokenResponse, Status> {
+            let mut spent = self.spent.lock().unwrap();
+            assert!(!spent.iter().any(|t| t == refresh_token), "{} refreshed twice", refresh_token);
+            spent.push(refresh_token.to_string());
+            Ok(RefreshTokenResponse {
+                success: true,
+                access_token: format!("access-{}", spent.len()),
+                expires_in: 3600,
+                refresh_token: format!("refresh-{}", spent.len()),
+            })
+        }
+    }
+
+    fn session(expires_in: Duration) -> Session {
+        Session {
+            access_token: "access-0".to_string(),
+            refresh_token: "refresh-0".to_string(),
+            expires_in,
+        }
+    }
+
+    fn authorization(interceptor: &mut AuthInterceptor) -> String {
+        let req = interceptor.call(Request::new(())).unwrap();
+        req.metadata().get("authorization").unwrap().to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_bearer_token_attached() {
+        let mut interceptor = AuthInterceptor::new("abc.def");
+        assert_eq!(authorization(&mut interceptor), "Bearer abc.def");
+
+        let mut bad = AuthInterceptor::new("line\nbreak");
+        assert_eq!(bad.call(Request::new(())).unwrap_err().code(), tonic::Code::Unauthenticated);
+    }
+
+    #[tokio::test]
+    async fn test_refreshed_once_past_threshold() {
+        let fresh = TokenManager::new(session(Duration::from_secs(600)), CountingRefresher::default());
+        fresh.ensure_fresh().await.unwrap();
+        assert!(fresh.refresher.spent.lock().unwrap().is_empty());
+        assert_eq!(authorization(&mut fresh.interceptor()), "Bearer access-0");
+
+        // Inside the 60 second threshold
+        let expiring = TokenManager::new(session(Duration::from_secs(30)), CountingRefresher::default());
+        let mut interceptor = expiring.interceptor();
+        expiring.ensure_fresh().await.unwrap();
+        expiring.ensure_fresh().await.unwrap();
+        assert_eq!(*expiring.refresher.spent.lock().unwrap(), ["refresh-0"]);
+        assert_eq!(authorization(&mut interceptor), "Bearer access-1", "handed-out interceptors see the new token");
+
+        let eager = TokenManager::new(session(Duration::from_secs(600)), CountingRefresher::default())
+            .with_refresh_before(Duration::from_secs(3600));
+        eager.ensure_fresh().await.unwrap();
+        eager.ensure_fresh().await.unwrap();
+        assert_eq!(*eager.refresher.spent.lock().unwrap(), ["refresh-0", "refresh-1"], "new token is also within the threshold");
+    }
+}
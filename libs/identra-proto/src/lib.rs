@@ -1,3 +1,7 @@
+/// Version of the gateway API these protos describe, reported by
+/// `Health.GetCapabilities`
+pub const API_VERSION: u32 = 1;
+
 // Include generated protobuf code from build.rs
 pub mod health {
     tonic::include_proto!("identra.health.v1");
@@ -15,3 +19,6 @@ pub mod auth {
     tonic::include_proto!("identra.auth");
 }
 
+
+// Bearer-token interceptor and self-refreshing clients
+pub mod client;